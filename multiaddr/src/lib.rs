@@ -429,6 +429,7 @@ macro_rules! multiaddr {
 mod test {
     use super::{Multiaddr, Protocol};
     use parity_multiaddr::{Multiaddr as OtherMultiaddr, Protocol as OtherProtocol};
+    use std::convert::TryFrom;
 
     #[test]
     fn compatibility_test() {
@@ -456,4 +457,32 @@ mod test {
             e => panic!("not expect protocol: {:?}", e),
         }
     }
+
+    #[test]
+    fn identity_peer_id_test() {
+        let address: Multiaddr =
+            "/ip4/127.0.0.1/tcp/8111/p2p/12D3KooWQK1wnefoLrcVHbbnf5tLzbopUd3K3bFAoJpA7YJgL5pV"
+                .parse()
+                .unwrap();
+        assert_eq!(address.to_string().parse::<Multiaddr>().unwrap(), address);
+        assert_eq!(Multiaddr::try_from(address.to_vec()).unwrap(), address);
+    }
+    #[test]
+    fn quic_test() {
+        let address: Multiaddr = "/ip4/127.0.0.1/udp/9000/quic".parse().unwrap();
+        let other: OtherMultiaddr = "/ip4/127.0.0.1/udp/9000/quic".parse().unwrap();
+        assert_eq!(address.to_vec(), other.to_vec());
+        assert_eq!(address.to_string(), "/ip4/127.0.0.1/udp/9000/quic");
+        assert_eq!(address.iter().nth(1), Some(Protocol::Udp(9000)));
+        assert_eq!(address.iter().nth(2), Some(Protocol::Quic));
+    }
+
+    #[test]
+    fn dnsaddr_test() {
+        let address: Multiaddr = "/dnsaddr/bootstrap.libp2p.io".parse().unwrap();
+        let other: OtherMultiaddr = "/dnsaddr/bootstrap.libp2p.io".parse().unwrap();
+        assert_eq!(address.to_vec(), other.to_vec());
+        assert_eq!(address.to_string(), "/dnsaddr/bootstrap.libp2p.io");
+        assert_eq!(Multiaddr::try_from(address.to_vec()).unwrap(), address);
+    }
 }
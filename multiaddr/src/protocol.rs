@@ -11,10 +11,13 @@ use crate::error::Error;
 
 const DNS4: u32 = 0x36;
 const DNS6: u32 = 0x37;
+const DNSADDR: u32 = 0x38;
 const IP4: u32 = 0x04;
 const IP6: u32 = 0x29;
 const P2P: u32 = 0x01a5;
 const TCP: u32 = 0x06;
+const UDP: u32 = 0x0111;
+const QUIC: u32 = 0x01cc;
 const TLS: u32 = 0x01c0;
 const WS: u32 = 0x01dd;
 const WSS: u32 = 0x01de;
@@ -22,16 +25,23 @@ const MEMORY: u32 = 0x0309;
 
 const SHA256_CODE: u16 = 0x12;
 const SHA256_SIZE: u8 = 32;
+const IDENTITY_CODE: u16 = 0x00;
+/// Max length of the data inlined by the identity multihash, such as the ed25519 public key
+const MAX_INLINE_KEY_LENGTH: usize = 42;
 
 /// `Protocol` describes all possible multiaddress protocols.
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub enum Protocol<'a> {
     Dns4(Cow<'a, str>),
     Dns6(Cow<'a, str>),
+    /// Resolved by the TXT records of `_dnsaddr.<domain>` to a set of addresses
+    Dnsaddr(Cow<'a, str>),
     Ip4(Ipv4Addr),
     Ip6(Ipv6Addr),
     P2P(Cow<'a, [u8]>),
     Tcp(u16),
+    Udp(u16),
+    Quic,
     Tls(Cow<'a, str>),
     Ws,
     Wss,
@@ -59,6 +69,10 @@ impl<'a> Protocol<'a> {
                 let s = iter.next().ok_or(Error::InvalidProtocolString)?;
                 Ok(Protocol::Dns6(Cow::Borrowed(s)))
             }
+            "dnsaddr" => {
+                let s = iter.next().ok_or(Error::InvalidProtocolString)?;
+                Ok(Protocol::Dnsaddr(Cow::Borrowed(s)))
+            }
             "ip4" => {
                 let s = iter.next().ok_or(Error::InvalidProtocolString)?;
                 Ok(Protocol::Ip4(Ipv4Addr::from_str(s)?))
@@ -81,6 +95,11 @@ impl<'a> Protocol<'a> {
                 let s = iter.next().ok_or(Error::InvalidProtocolString)?;
                 Ok(Protocol::Tcp(s.parse()?))
             }
+            "udp" => {
+                let s = iter.next().ok_or(Error::InvalidProtocolString)?;
+                Ok(Protocol::Udp(s.parse()?))
+            }
+            "quic" => Ok(Protocol::Quic),
             "ws" => Ok(Protocol::Ws),
             "wss" => Ok(Protocol::Wss),
             "memory" => {
@@ -113,6 +132,14 @@ impl<'a> Protocol<'a> {
                 let (data, rest) = split_header(n, input)?;
                 Ok((Protocol::Dns6(Cow::Borrowed(str::from_utf8(data)?)), rest))
             }
+            DNSADDR => {
+                let (n, input) = decode::usize(input)?;
+                let (data, rest) = split_header(n, input)?;
+                Ok((
+                    Protocol::Dnsaddr(Cow::Borrowed(str::from_utf8(data)?)),
+                    rest,
+                ))
+            }
             IP4 => {
                 let (data, rest) = split_header(4, input)?;
                 Ok((
@@ -152,6 +179,13 @@ impl<'a> Protocol<'a> {
                 let num = rdr.get_u16();
                 Ok((Protocol::Tcp(num), rest))
             }
+            UDP => {
+                let (data, rest) = split_header(2, input)?;
+                let mut rdr = Cursor::new(data);
+                let num = rdr.get_u16();
+                Ok((Protocol::Udp(num), rest))
+            }
+            QUIC => Ok((Protocol::Quic, input)),
             WS => Ok((Protocol::Ws, input)),
             WSS => Ok((Protocol::Wss, input)),
             MEMORY => {
@@ -182,6 +216,12 @@ impl<'a> Protocol<'a> {
                 w.put(encode::usize(bytes.len(), &mut encode::usize_buffer()));
                 w.put(bytes)
             }
+            Protocol::Dnsaddr(s) => {
+                w.put(encode::u32(DNSADDR, &mut buf));
+                let bytes = s.as_bytes();
+                w.put(encode::usize(bytes.len(), &mut encode::usize_buffer()));
+                w.put(bytes)
+            }
             Protocol::Ip4(addr) => {
                 w.put(encode::u32(IP4, &mut buf));
                 w.put(&addr.octets()[..])
@@ -196,6 +236,11 @@ impl<'a> Protocol<'a> {
                 w.put(encode::u32(TCP, &mut buf));
                 w.put_u16(*port)
             }
+            Protocol::Udp(port) => {
+                w.put(encode::u32(UDP, &mut buf));
+                w.put_u16(*port)
+            }
+            Protocol::Quic => w.put(encode::u32(QUIC, &mut buf)),
             Protocol::Tls(s) => {
                 w.put(encode::u32(TLS, &mut buf));
                 let bytes = s.as_bytes();
@@ -221,9 +266,12 @@ impl<'a> Protocol<'a> {
         match self {
             Protocol::Dns4(s) => Protocol::Dns4(Cow::Owned(s.into_owned())),
             Protocol::Dns6(s) => Protocol::Dns6(Cow::Owned(s.into_owned())),
+            Protocol::Dnsaddr(s) => Protocol::Dnsaddr(Cow::Owned(s.into_owned())),
             Protocol::Ip4(addr) => Protocol::Ip4(addr),
             Protocol::Ip6(addr) => Protocol::Ip6(addr),
             Protocol::Tcp(port) => Protocol::Tcp(port),
+            Protocol::Udp(port) => Protocol::Udp(port),
+            Protocol::Quic => Protocol::Quic,
             Protocol::Tls(s) => Protocol::Tls(Cow::Owned(s.into_owned())),
             Protocol::P2P(s) => Protocol::P2P(Cow::Owned(s.into_owned())),
             Protocol::Ws => Protocol::Ws,
@@ -239,10 +287,13 @@ impl<'a> fmt::Display for Protocol<'a> {
         match self {
             Dns4(s) => write!(f, "/dns4/{}", s),
             Dns6(s) => write!(f, "/dns6/{}", s),
+            Dnsaddr(s) => write!(f, "/dnsaddr/{}", s),
             Ip4(addr) => write!(f, "/ip4/{}", addr),
             Ip6(addr) => write!(f, "/ip6/{}", addr),
             P2P(c) => write!(f, "/p2p/{}", bs58::encode(c).into_string()),
             Tcp(port) => write!(f, "/tcp/{}", port),
+            Udp(port) => write!(f, "/udp/{}", port),
+            Quic => write!(f, "/quic"),
             Tls(s) => write!(f, "/tls/{}", s),
             Ws => write!(f, "/ws"),
             Wss => write!(f, "/wss"),
@@ -278,6 +329,16 @@ impl<'a> From<Ipv6Addr> for Protocol<'a> {
 fn check_p2p(data: &[u8]) -> Result<(), Error> {
     let (code, bytes) = unsigned_varint::decode::u16(&data)?;
 
+    if code == IDENTITY_CODE {
+        if bytes.is_empty()
+            || bytes.len() > MAX_INLINE_KEY_LENGTH + 1
+            || bytes[0] as usize != bytes.len() - 1
+        {
+            return Err(Error::UnknownHash);
+        }
+        return Ok(());
+    }
+
     if code != SHA256_CODE {
         return Err(Error::UnknownHash);
     }
@@ -12,6 +12,7 @@ use log::{debug, error, trace, warn};
 use p2p::{
     context::{ProtocolContext, ProtocolContextMutRef, SessionContext},
     multiaddr::{Multiaddr, Protocol},
+    protocol_select::ProtocolInfo,
     secio::PeerId,
     service::SessionType,
     traits::ServiceProtocol,
@@ -85,6 +86,15 @@ pub trait Callback: Clone + Send {
     ) -> MisbehaveResult;
     /// Report misbehavior
     fn misbehave(&mut self, peer: &PeerId, kind: Misbehavior) -> MisbehaveResult;
+    /// Received the agent version and the supported protocols of the remote peer,
+    /// both are empty if the remote runs a version not sending them
+    fn received_protocols(
+        &mut self,
+        _peer: &PeerId,
+        _agent_version: &str,
+        _protocols: Vec<ProtocolInfo>,
+    ) {
+    }
 }
 
 /// Identify protocol
@@ -93,6 +103,7 @@ pub struct IdentifyProtocol<T> {
     remote_infos: HashMap<SessionId, RemoteInfo>,
     secio_enabled: bool,
     global_ip_only: bool,
+    agent_version: String,
 }
 
 impl<T: Callback> IdentifyProtocol<T> {
@@ -102,6 +113,7 @@ impl<T: Callback> IdentifyProtocol<T> {
             remote_infos: HashMap::default(),
             secio_enabled: true,
             global_ip_only: true,
+            agent_version: format!("tentacle-identify/{}", env!("CARGO_PKG_VERSION")),
         }
     }
 
@@ -111,6 +123,12 @@ impl<T: Callback> IdentifyProtocol<T> {
         self
     }
 
+    /// The agent version sent to the remote peers, default is `tentacle-identify/<version>`
+    pub fn agent_version(mut self, agent_version: String) -> Self {
+        self.agent_version = agent_version;
+        self
+    }
+
     fn check_duplicate(&mut self, context: &mut ProtocolContextMutRef) -> MisbehaveResult {
         let session = context.session;
         let info = self
@@ -176,11 +194,22 @@ impl<T: Callback> IdentifyProtocol<T> {
         if multiaddr_to_socketaddr(&observed)
             .map(|socket_addr| socket_addr.ip())
             .filter(|ip_addr| !global_ip_only || is_reachable(*ip_addr))
-            .is_some()
-            && self
-                .callback
-                .add_observed_addr(&info.peer_id, observed.clone(), info.session.ty)
-                .is_disconnect()
+            .is_none()
+        {
+            return MisbehaveResult::Continue;
+        }
+
+        // the service surfaces the address observed by the most peers as the external one
+        if context
+            .report_observed_address(session.id, observed.clone())
+            .is_err()
+        {
+            debug!("report observed address {} failed", observed);
+        }
+        if self
+            .callback
+            .add_observed_addr(&info.peer_id, observed, info.session.ty)
+            .is_disconnect()
         {
             return MisbehaveResult::Disconnect;
         }
@@ -263,8 +292,17 @@ impl<T: Callback> ServiceProtocol for IdentifyProtocol<T> {
             })
             .collect::<Multiaddr>();
 
+        let protocols = context.protocols().values().cloned().collect();
+        let agent_version = self.agent_version.clone();
         let identify = self.callback.identify();
-        let data = IdentifyMessage::new(listen_addrs, observed_addr, identify).encode();
+        let data = IdentifyMessage::new(
+            listen_addrs,
+            observed_addr,
+            identify,
+            agent_version,
+            protocols,
+        )
+        .encode();
         let _ = context.quick_send_message(data);
     }
 
@@ -301,6 +339,13 @@ impl<T: Callback> ServiceProtocol for IdentifyProtocol<T> {
                         .is_disconnect()
                 {
                     let _ = context.disconnect(session.id);
+                } else {
+                    let peer_id = &self.remote_infos[&session.id].peer_id;
+                    self.callback.received_protocols(
+                        peer_id,
+                        &message.agent_version,
+                        message.protocols,
+                    );
                 }
             }
             None => {
@@ -2,7 +2,7 @@ use crate::protocol_mol;
 use molecule::prelude::{Builder, Entity, Reader};
 
 use bytes::Bytes;
-use p2p::multiaddr::Multiaddr;
+use p2p::{multiaddr::Multiaddr, protocol_select::ProtocolInfo};
 
 use std::convert::TryFrom;
 
@@ -11,6 +11,10 @@ pub struct IdentifyMessage<'a> {
     pub(crate) listen_addrs: Vec<Multiaddr>,
     pub(crate) observed_addr: Multiaddr,
     pub(crate) identify: &'a [u8],
+    /// Empty if the remote doesn't send it
+    pub(crate) agent_version: String,
+    /// Empty if the remote doesn't send them
+    pub(crate) protocols: Vec<ProtocolInfo>,
 }
 
 impl<'a> IdentifyMessage<'a> {
@@ -18,11 +22,15 @@ impl<'a> IdentifyMessage<'a> {
         listen_addrs: Vec<Multiaddr>,
         observed_addr: Multiaddr,
         identify: &'a [u8],
+        agent_version: String,
+        protocols: Vec<ProtocolInfo>,
     ) -> Self {
         IdentifyMessage {
             listen_addrs,
             observed_addr,
             identify,
+            agent_version,
+            protocols,
         }
     }
 
@@ -59,10 +67,35 @@ impl<'a> IdentifyMessage<'a> {
             .set(listen_addrs)
             .build();
 
-        protocol_mol::IdentifyMessage::new_builder()
+        let agent_version = protocol_mol::Bytes::new_builder()
+            .set(
+                self.agent_version
+                    .into_bytes()
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
+            )
+            .build();
+        let protocols = protocol_mol::BytesVec::new_builder()
+            .set(
+                self.protocols
+                    .into_iter()
+                    .map(|info| {
+                        protocol_mol::Bytes::new_builder()
+                            .set(info.encode().into_iter().map(Into::into).collect())
+                            .build()
+                    })
+                    .collect(),
+            )
+            .build();
+
+        // the old peers read it as an `IdentifyMessage`, skipping the appended fields
+        protocol_mol::IdentifyMessageV2::new_builder()
             .listen_addrs(listen_addrs)
             .observed_addr(observed_addr)
             .identify(identify)
+            .agent_version(agent_version)
+            .protocols(protocols)
             .build()
             .as_bytes()
     }
@@ -78,10 +111,23 @@ impl<'a> IdentifyMessage<'a> {
             listen_addrs.push(Multiaddr::try_from(addr.bytes().raw_data().to_vec()).ok()?)
         }
 
+        let mut agent_version = String::new();
+        let mut protocols = Vec::new();
+        // the message of the old peers has no appended fields
+        if reader.has_extra_fields() {
+            let reader = protocol_mol::IdentifyMessageV2Reader::from_compatible_slice(data).ok()?;
+            agent_version = String::from_utf8(reader.agent_version().raw_data().to_vec()).ok()?;
+            for info in reader.protocols().iter() {
+                protocols.push(ProtocolInfo::decode(info.raw_data())?);
+            }
+        }
+
         Some(IdentifyMessage {
             identify,
             observed_addr,
             listen_addrs,
+            agent_version,
+            protocols,
         })
     }
 }
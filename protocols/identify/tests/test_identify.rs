@@ -0,0 +1,210 @@
+use futures::StreamExt;
+use std::{
+    sync::mpsc::{channel, Sender},
+    thread,
+    time::Duration,
+};
+
+use p2p::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef, ServiceContext},
+    multiaddr::{Multiaddr, Protocol},
+    protocol_select::ProtocolInfo,
+    secio::{PeerId, SecioKeyPair},
+    service::{ProtocolHandle, Service, ServiceEvent, SessionType, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+};
+use tentacle_identify::{Callback, IdentifyProtocol, MisbehaveResult, Misbehavior};
+
+/// Reports the agent version and the protocol names of the remote
+#[derive(Clone)]
+struct ReportCallback {
+    sender: Sender<(String, Vec<String>)>,
+}
+
+impl Callback for ReportCallback {
+    fn received_identify(
+        &mut self,
+        _context: &mut ProtocolContextMutRef,
+        _identify: &[u8],
+    ) -> MisbehaveResult {
+        MisbehaveResult::Continue
+    }
+    fn identify(&mut self) -> &[u8] {
+        b"identify"
+    }
+    fn local_listen_addrs(&mut self) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+    fn add_remote_listen_addrs(&mut self, _peer: &PeerId, _addrs: Vec<Multiaddr>) {}
+    fn add_observed_addr(
+        &mut self,
+        _peer: &PeerId,
+        _addr: Multiaddr,
+        _ty: SessionType,
+    ) -> MisbehaveResult {
+        MisbehaveResult::Continue
+    }
+    fn misbehave(&mut self, _peer: &PeerId, _kind: Misbehavior) -> MisbehaveResult {
+        MisbehaveResult::Disconnect
+    }
+    fn received_protocols(
+        &mut self,
+        _peer: &PeerId,
+        agent_version: &str,
+        protocols: Vec<ProtocolInfo>,
+    ) {
+        let mut names = protocols
+            .into_iter()
+            .map(|info| info.name)
+            .collect::<Vec<_>>();
+        names.sort();
+        let _res = self.sender.send((agent_version.to_owned(), names));
+    }
+}
+
+struct Idle;
+
+/// Reports the external address elected by the service
+struct ExternalAddress {
+    sender: Sender<Multiaddr>,
+}
+
+impl ServiceHandle for ExternalAddress {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::ExternalAddressChanged { address } = event {
+            let _res = self.sender.send(address);
+        }
+    }
+}
+
+impl ServiceProtocol for Idle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+}
+
+fn create<F>(
+    agent_version: &str,
+    extra: &'static str,
+    sender: Sender<(String, Vec<String>)>,
+    handle: F,
+) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let identify = IdentifyProtocol::new(ReportCallback { sender })
+        .global_ip_only(false)
+        .agent_version(agent_version.to_owned());
+    let identify_meta = MetaBuilder::new()
+        .id(1.into())
+        .name(|_| "/test/identify".to_owned())
+        .service_handle(move || ProtocolHandle::Callback(Box::new(identify)))
+        .build();
+    let extra_meta = MetaBuilder::new()
+        .id(2.into())
+        .name(move |_| extra.to_owned())
+        .service_handle(|| ProtocolHandle::Callback(Box::new(Idle)))
+        .build();
+    ServiceBuilder::default()
+        .insert_protocol(identify_meta)
+        .insert_protocol(extra_meta)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .forever(true)
+        .build(handle)
+}
+
+fn start_service<F>(mut service: Service<F>, listen: Option<Multiaddr>) -> Option<Multiaddr>
+where
+    F: ServiceHandle + Unpin + Send + 'static,
+{
+    let (addr_sender, addr_receiver) = channel::<Multiaddr>();
+    let wait_listen = listen.is_some();
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            if let Some(addr) = listen {
+                let listen_addr = service.listen(addr).await.unwrap();
+                addr_sender.send(listen_addr).unwrap();
+            }
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+    if wait_listen {
+        addr_receiver.recv().ok()
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_identify_exchanges_protocols() {
+    let (listener_sender, listener_receiver) = channel();
+    let listen_addr = start_service(
+        create("listener/1.0", "/test/sync", listener_sender, ()),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (dialer_sender, dialer_receiver) = channel();
+    let service = create("dialer/2.0", "/test/relay", dialer_sender, ());
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    assert_eq!(
+        listener_receiver
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap(),
+        (
+            "dialer/2.0".to_owned(),
+            vec!["/test/identify".to_owned(), "/test/relay".to_owned()]
+        )
+    );
+    assert_eq!(
+        dialer_receiver
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap(),
+        (
+            "listener/1.0".to_owned(),
+            vec!["/test/identify".to_owned(), "/test/sync".to_owned()]
+        )
+    );
+}
+
+#[test]
+fn test_identify_reports_observed_address() {
+    let (listener_sender, _listener_receiver) = channel();
+    let listen_addr = start_service(
+        create("listener/1.0", "/test/sync", listener_sender, ()),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (dialer_sender, _dialer_receiver) = channel();
+    let (address_sender, address_receiver) = channel();
+    let service = create(
+        "dialer/2.0",
+        "/test/relay",
+        dialer_sender,
+        ExternalAddress {
+            sender: address_sender,
+        },
+    );
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    // the listener observes the dialer on its ephemeral port
+    let address = address_receiver
+        .recv_timeout(Duration::from_secs(5))
+        .unwrap();
+    let mut protocols = address.iter();
+    assert_eq!(
+        protocols.next(),
+        Some(Protocol::Ip4("127.0.0.1".parse().unwrap()))
+    );
+    assert!(matches!(protocols.next(), Some(Protocol::Tcp(_))));
+}
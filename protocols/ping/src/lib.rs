@@ -15,6 +15,7 @@ use p2p::{
 use std::{
     collections::HashMap,
     str,
+    sync::{Arc, RwLock},
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
@@ -28,6 +29,40 @@ pub trait Callback {
     fn unexpected_error(&mut self, context: ProtocolContextMutRef);
 }
 
+/// The last round-trip time of each session, shared with the handler that updates it,
+/// so callers can score the peers from outside of the service
+#[derive(Clone, Default)]
+pub struct RttTable {
+    inner: Arc<RwLock<HashMap<SessionId, Duration>>>,
+}
+
+impl RttTable {
+    /// The last round-trip time of the session, none if no pong is received yet
+    pub fn get(&self, id: SessionId) -> Option<Duration> {
+        self.inner
+            .read()
+            .expect("rtt table poisoned")
+            .get(&id)
+            .copied()
+    }
+
+    /// The last round-trip time of all sessions which received a pong
+    pub fn snapshot(&self) -> HashMap<SessionId, Duration> {
+        self.inner.read().expect("rtt table poisoned").clone()
+    }
+
+    fn insert(&self, id: SessionId, rtt: Duration) {
+        self.inner
+            .write()
+            .expect("rtt table poisoned")
+            .insert(id, rtt);
+    }
+
+    fn remove(&self, id: SessionId) {
+        self.inner.write().expect("rtt table poisoned").remove(&id);
+    }
+}
+
 /// Ping protocol handler.
 ///
 /// The interval means that we send ping to peers.
@@ -38,6 +73,7 @@ pub struct PingHandler<T> {
     connected_session_ids: HashMap<SessionId, PingStatus>,
     callback: T,
     unix_epoch: Instant,
+    rtts: RttTable,
 }
 
 impl<T> PingHandler<T>
@@ -58,8 +94,15 @@ where
                         .expect("Convert system time fail"),
                 )
                 .unwrap_or(now),
+            rtts: RttTable::default(),
         }
     }
+
+    /// The last round-trip time of the sessions, take it before the handler is moved
+    /// into the service
+    pub fn rtts(&self) -> RttTable {
+        self.rtts.clone()
+    }
 }
 
 fn nonce(t: &Instant, unix_epoch: Instant) -> u32 {
@@ -137,6 +180,7 @@ where
     fn disconnected(&mut self, context: ProtocolContextMutRef) {
         let session = context.session;
         self.connected_session_ids.remove(&session.id);
+        self.rtts.remove(session.id);
         debug!(
             "proto id [{}] close on session [{}]",
             context.proto_id, session.id
@@ -168,6 +212,7 @@ where
                             if (true, nonce) == (status.processing, status.nonce()) {
                                 status.processing = false;
                                 let ping_time = status.elapsed();
+                                self.rtts.insert(session.id, ping_time);
                                 self.callback.received_pong(context, ping_time);
                                 return;
                             }
@@ -195,6 +240,7 @@ where
                         if ps.processing {
                             None
                         } else {
+                            ps.processing = true;
                             match now {
                                 Some(t) => {
                                     ps.last_ping = t;
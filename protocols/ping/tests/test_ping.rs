@@ -0,0 +1,131 @@
+use futures::StreamExt;
+use std::{
+    sync::mpsc::{channel, Sender},
+    thread,
+    time::Duration,
+};
+
+use p2p::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, TargetProtocol},
+    traits::ServiceProtocol,
+    SessionId,
+};
+use tentacle_ping::{Callback, PingHandler, RttTable};
+
+/// Reports the timed out sessions
+struct TimeoutCallback {
+    sender: Sender<SessionId>,
+}
+
+impl Callback for TimeoutCallback {
+    fn received_ping(&mut self, _context: ProtocolContextMutRef) {}
+    fn received_pong(&mut self, _context: ProtocolContextMutRef, _time: Duration) {}
+    fn timeout(&mut self, _context: &mut ProtocolContext, id: SessionId) {
+        let _res = self.sender.send(id);
+    }
+    fn unexpected_error(&mut self, _context: ProtocolContextMutRef) {}
+}
+
+/// Opens the protocol but never answers a ping
+struct Silent;
+
+impl ServiceProtocol for Silent {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+}
+
+fn ping_meta(sender: Sender<SessionId>) -> (ProtocolMeta, RttTable) {
+    let handler = PingHandler::new(
+        Duration::from_millis(100),
+        Duration::from_millis(500),
+        TimeoutCallback { sender },
+    );
+    let rtts = handler.rtts();
+    let meta = MetaBuilder::new()
+        .id(1.into())
+        .service_handle(move || ProtocolHandle::Callback(Box::new(handler)))
+        .build();
+    (meta, rtts)
+}
+
+fn silent_meta() -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(1.into())
+        .service_handle(|| ProtocolHandle::Callback(Box::new(Silent)))
+        .build()
+}
+
+fn create(meta: ProtocolMeta) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(meta)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .forever(true)
+        .build(())
+}
+
+fn start_service(mut service: Service<()>, listen: Option<Multiaddr>) -> Option<Multiaddr> {
+    let (addr_sender, addr_receiver) = channel::<Multiaddr>();
+    let wait_listen = listen.is_some();
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            if let Some(addr) = listen {
+                let listen_addr = service.listen(addr).await.unwrap();
+                addr_sender.send(listen_addr).unwrap();
+            }
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+    if wait_listen {
+        addr_receiver.recv().ok()
+    } else {
+        None
+    }
+}
+
+/// Connect a pinging service to the remote one, return its rtt table and timeout reports
+fn connect(remote: ProtocolMeta) -> (RttTable, std::sync::mpsc::Receiver<SessionId>) {
+    let listen_addr = start_service(
+        create(remote),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (sender, receiver) = channel();
+    let (meta, rtts) = ping_meta(sender);
+    let service = create(meta);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+    (rtts, receiver)
+}
+
+#[test]
+fn test_silent_peer_timeout() {
+    let (rtts, receiver) = connect(silent_meta());
+
+    let id = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(rtts.get(id), None);
+}
+
+#[test]
+fn test_responsive_peer_rtt() {
+    let (remote_sender, _remote_receiver) = channel();
+    let (remote, _) = ping_meta(remote_sender);
+    let (rtts, receiver) = connect(remote);
+
+    thread::sleep(Duration::from_secs(1));
+    assert!(receiver.try_recv().is_err());
+    let snapshot = rtts.snapshot();
+    assert_eq!(snapshot.len(), 1);
+    assert!(snapshot
+        .values()
+        .all(|rtt| *rtt < Duration::from_millis(500)));
+}
@@ -11,7 +11,7 @@ use std::{
     task::{Context, Poll},
 };
 
-use crate::{crypto::BoxStreamCipher, error::SecioError};
+use crate::{crypto::BoxStreamCipher, error::SecioError, KeyExporter, SecurityParams};
 
 enum RecvBuf {
     Vec(Vec<u8>),
@@ -68,6 +68,8 @@ pub struct SecureStream<T> {
     /// into this buffer so that multiple following 'read' will eventually
     /// get the message correctly
     recv_buf: RecvBuf,
+    exporter: KeyExporter,
+    params: SecurityParams,
 }
 
 impl<T> SecureStream<T>
@@ -80,6 +82,8 @@ where
         decode_cipher: BoxStreamCipher,
         encode_cipher: BoxStreamCipher,
         nonce: Vec<u8>,
+        exporter: KeyExporter,
+        params: SecurityParams,
     ) -> Self {
         let recv_buf = if decode_cipher.is_in_place() {
             RecvBuf::Byte(BytesMut::new())
@@ -92,9 +96,21 @@ where
             encode_cipher,
             nonce,
             recv_buf,
+            exporter,
+            params,
         }
     }
 
+    /// Keying material exporter of this session, see [`KeyExporter`]
+    pub fn exporter(&self) -> &KeyExporter {
+        &self.exporter
+    }
+
+    /// Cipher and digest negotiated by the handshake of this session
+    pub fn security_params(&self) -> SecurityParams {
+        self.params
+    }
+
     /// Decoding data
     #[inline]
     fn decode_buffer(&mut self, mut frame: BytesMut) -> Result<RecvBuf, SecioError> {
@@ -231,7 +247,10 @@ where
 #[cfg(test)]
 mod tests {
     use super::SecureStream;
-    use crate::crypto::{cipher::CipherType, new_stream, CryptoMode};
+    use crate::{
+        crypto::{cipher::CipherType, new_stream, CryptoMode},
+        Digest, KeyExporter, SecurityParams,
+    };
     use bytes::BytesMut;
     use futures::channel;
     use tokio::{
@@ -283,6 +302,11 @@ mod tests {
                 new_stream(cipher, &cipher_key_clone[..key_size], CryptoMode::Decrypt),
                 new_stream(cipher, &cipher_key_clone[..key_size], CryptoMode::Encrypt),
                 nonce2,
+                KeyExporter::new(Digest::Sha256, &[]),
+                SecurityParams {
+                    cipher,
+                    digest: Digest::Sha256,
+                },
             );
 
             let mut data = [0u8; 11];
@@ -298,6 +322,11 @@ mod tests {
                 new_stream(cipher, &cipher_key_clone[..key_size], CryptoMode::Decrypt),
                 new_stream(cipher, &cipher_key_clone[..key_size], CryptoMode::Encrypt),
                 Vec::new(),
+                KeyExporter::new(Digest::Sha256, &[]),
+                SecurityParams {
+                    cipher,
+                    digest: Digest::Sha256,
+                },
             );
 
             let _res = handle.write_all(&data_clone[..]).await;
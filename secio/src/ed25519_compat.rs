@@ -0,0 +1,33 @@
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use std::convert::TryFrom;
+
+pub const SECRET_KEY_SIZE: usize = ed25519_dalek::SECRET_KEY_LENGTH;
+
+fn keypair(secret: &[u8; SECRET_KEY_SIZE]) -> Keypair {
+    let secret = SecretKey::from_bytes(secret).expect("secret key length is right");
+    let public = PublicKey::from(&secret);
+    Keypair { secret, public }
+}
+
+// len = 32
+pub fn pubkey_from_secret(secret: &[u8; SECRET_KEY_SIZE]) -> Vec<u8> {
+    keypair(secret).public.to_bytes().to_vec()
+}
+
+pub fn sign(message: &[u8], secret: &[u8; SECRET_KEY_SIZE]) -> Vec<u8> {
+    keypair(secret).sign(message).to_bytes().to_vec()
+}
+
+pub fn verify(message: &[u8], signature: &[u8], pubkey: &[u8]) -> bool {
+    match (
+        Signature::try_from(signature),
+        PublicKey::from_bytes(pubkey),
+    ) {
+        (Ok(signature), Ok(pubkey)) => pubkey.verify(message, &signature).is_ok(),
+        _ => false,
+    }
+}
+
+pub fn pubkey_is_valid(key: &[u8]) -> bool {
+    PublicKey::from_bytes(key).is_ok()
+}
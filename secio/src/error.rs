@@ -23,6 +23,9 @@ pub enum SecioError {
     /// There is no protocol supported by both the local and remote hosts.
     NoSupportIntersection,
 
+    /// There is no cipher supported by both the local and remote hosts.
+    NoCipherIntersection,
+
     /// The final check of the handshake failed.
     NonceVerificationFailed,
 
@@ -38,6 +41,9 @@ pub enum SecioError {
     /// Failed to parse one of the handshake bincode messages.
     HandshakeParsingFailure,
 
+    /// The length of a handshake message sent by the remote is over the limit.
+    HandshakeMessageTooLarge,
+
     /// The signature of the exchange packet doesn't verify the remote public key.
     SignatureVerificationFailed,
 
@@ -56,11 +62,13 @@ impl PartialEq for SecioError {
             (EphemeralKeyGenerationFailed, EphemeralKeyGenerationFailed)
             | (SecretGenerationFailed, SecretGenerationFailed)
             | (NoSupportIntersection, NoSupportIntersection)
+            | (NoCipherIntersection, NoCipherIntersection)
             | (NonceVerificationFailed, NonceVerificationFailed)
             | (FrameTooShort, FrameTooShort)
             | (HmacNotMatching, HmacNotMatching)
             | (ConnectSelf, ConnectSelf)
             | (HandshakeParsingFailure, HandshakeParsingFailure)
+            | (HandshakeMessageTooLarge, HandshakeMessageTooLarge)
             | (SignatureVerificationFailed, SignatureVerificationFailed)
             | (InvalidMessage, InvalidMessage) => true,
             _ => false,
@@ -111,11 +119,13 @@ impl fmt::Display for SecioError {
             SecioError::EphemeralKeyGenerationFailed => write!(f, "EphemeralKey Generation Failed"),
             SecioError::SecretGenerationFailed => write!(f, "Secret Generation Failed"),
             SecioError::NoSupportIntersection => write!(f, "No Support Intersection"),
+            SecioError::NoCipherIntersection => write!(f, "No Cipher Intersection"),
             SecioError::NonceVerificationFailed => write!(f, "Nonce Verification Failed"),
             SecioError::FrameTooShort => write!(f, "Frame Too Short"),
             SecioError::HmacNotMatching => write!(f, "Hmac Not Matching"),
             SecioError::ConnectSelf => write!(f, "Connect Self"),
             SecioError::HandshakeParsingFailure => write!(f, "Handshake Parsing Failure"),
+            SecioError::HandshakeMessageTooLarge => write!(f, "Handshake Message Too Large"),
             SecioError::InvalidMessage => write!(f, "Invalid Message"),
             SecioError::SignatureVerificationFailed => write!(f, "Signature Verification Failed"),
             SecioError::InvalidProposition(e) => write!(f, "Invalid Proposition: {}", e),
@@ -0,0 +1,90 @@
+use crate::{codec::Hmac, Digest};
+
+/// HKDF salt separating the exporter secret from the keys used by the secure stream
+const EXPORTER_SALT: &[u8] = b"secio exporter";
+
+/// Derives keying material from a finished handshake for use outside of secio
+///
+/// The secret is HKDF-extracted from the shared key material with a dedicated salt,
+/// so the exported values are unrelated to the cipher/mac keys of the stream, and
+/// both sides of the same session derive the same value for the same label.
+///
+/// This is a footgun: anyone holding the exported value can authenticate as part of
+/// this session on whatever protocol uses it. Use distinct labels per purpose and
+/// never send the output over the wire.
+#[derive(Clone)]
+pub struct KeyExporter {
+    digest: Digest,
+    secret: Vec<u8>,
+}
+
+impl KeyExporter {
+    /// HKDF-Extract the exporter secret from the shared key material
+    pub(crate) fn new(digest: Digest, key_material: &[u8]) -> Self {
+        let mut context = Hmac::from_key(digest, EXPORTER_SALT).context();
+        context.update(key_material);
+        KeyExporter {
+            digest,
+            secret: AsRef::<[u8]>::as_ref(&context.sign()).to_vec(),
+        }
+    }
+
+    /// HKDF-Expand the exporter secret with the label, fill the whole output buffer
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is longer than 255 times the hash length of the negotiated digest.
+    pub fn export(&self, label: &[u8], out: &mut [u8]) {
+        let hmac = Hmac::from_key(self.digest, &self.secret);
+        assert!(
+            out.len() <= 255 * hmac.num_bytes(),
+            "exported keying material is too long"
+        );
+
+        let mut last: Vec<u8> = Vec::new();
+        for (index, chunk) in out.chunks_mut(hmac.num_bytes()).enumerate() {
+            let mut context = hmac.context();
+            context.update(&last);
+            context.update(label);
+            context.update(&[index as u8 + 1]);
+            last = AsRef::<[u8]>::as_ref(&context.sign()).to_vec();
+            chunk.copy_from_slice(&last[..chunk.len()]);
+        }
+    }
+}
+
+impl std::fmt::Debug for KeyExporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("KeyExporter")
+            .field("digest", &self.digest)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::KeyExporter;
+    use crate::Digest;
+
+    #[test]
+    fn test_export_by_label() {
+        let exporter = KeyExporter::new(Digest::Sha256, b"key material");
+
+        let mut a = [0u8; 100];
+        let mut b = [0u8; 100];
+        exporter.export(b"label a", &mut a);
+        exporter.export(b"label a", &mut b);
+        assert_eq!(a[..], b[..]);
+
+        let mut short = [0u8; 16];
+        exporter.export(b"label a", &mut short);
+        assert_eq!(short[..], a[..16]);
+
+        exporter.export(b"label b", &mut b);
+        assert_ne!(a[..], b[..]);
+
+        let other = KeyExporter::new(Digest::Sha256, b"other material");
+        other.export(b"label a", &mut b);
+        assert_ne!(a[..], b[..]);
+    }
+}
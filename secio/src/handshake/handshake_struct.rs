@@ -122,6 +122,8 @@ impl Exchange {
 pub enum PublicKey {
     /// Secp256k1
     Secp256k1(Vec<u8>),
+    /// Ed25519
+    Ed25519(Vec<u8>),
 }
 
 impl PublicKey {
@@ -129,6 +131,7 @@ impl PublicKey {
     pub fn inner_ref(&self) -> &[u8] {
         match self {
             PublicKey::Secp256k1(ref key) => key,
+            PublicKey::Ed25519(ref key) => key,
         }
     }
 
@@ -136,6 +139,7 @@ impl PublicKey {
     pub fn inner(self) -> Vec<u8> {
         match self {
             PublicKey::Secp256k1(key) => key,
+            PublicKey::Ed25519(key) => key,
         }
     }
 
@@ -149,15 +153,34 @@ impl PublicKey {
             .map_err(|_| crate::error::SecioError::SecretGenerationFailed)
     }
 
+    /// Creates a ed25519 public key directly from a 32 bytes slice
+    pub fn ed25519_raw_key<K>(key: K) -> Result<Self, crate::error::SecioError>
+    where
+        K: AsRef<[u8]>,
+    {
+        if crate::ed25519_compat::pubkey_is_valid(key.as_ref()) {
+            Ok(PublicKey::Ed25519(key.as_ref().to_vec()))
+        } else {
+            Err(crate::error::SecioError::SecretGenerationFailed)
+        }
+    }
+
     /// Encode with molecule
     pub fn encode(self) -> Bytes {
-        let secp256k1 = handshake_mol::Secp256k1::new_builder()
-            .set(self.inner().into_iter().map(Into::into).collect())
-            .build();
-        let pubkey = handshake_mol::PublicKey::new_builder()
-            .set(secp256k1)
-            .build();
-        pubkey.as_bytes()
+        let builder = handshake_mol::PublicKey::new_builder();
+        let pubkey = match self {
+            PublicKey::Secp256k1(key) => builder.set(
+                handshake_mol::Secp256k1::new_builder()
+                    .set(key.into_iter().map(Into::into).collect())
+                    .build(),
+            ),
+            PublicKey::Ed25519(key) => builder.set(
+                handshake_mol::Ed25519::new_builder()
+                    .set(key.into_iter().map(Into::into).collect())
+                    .build(),
+            ),
+        };
+        pubkey.build().as_bytes()
     }
 
     /// Decode with molecule
@@ -168,6 +191,9 @@ impl PublicKey {
             handshake_mol::PublicKeyUnionReader::Secp256k1(reader) => {
                 Some(PublicKey::Secp256k1(reader.raw_data().to_owned()))
             }
+            handshake_mol::PublicKeyUnionReader::Ed25519(reader) => {
+                Some(PublicKey::Ed25519(reader.raw_data().to_owned()))
+            }
         }
     }
 
@@ -201,6 +227,14 @@ mod tests {
         assert_eq!(raw, PublicKey::decode(&byte.encode()).unwrap())
     }
 
+    #[test]
+    fn decode_encode_ed25519_pubkey() {
+        let raw = SecioKeyPair::ed25519_generated().public_key();
+        let byte = raw.clone();
+
+        assert_eq!(raw, PublicKey::decode(&byte.encode()).unwrap())
+    }
+
     #[test]
     fn decode_encode_propose() {
         let nonce: [u8; 16] = rand::random();
@@ -17,6 +17,7 @@ pub(crate) mod handshake_struct;
 mod procedure;
 
 const MAX_FRAME_SIZE: usize = 1024 * 1024 * 8;
+const MAX_HANDSHAKE_MESSAGE_SIZE: usize = 1024 * 64;
 
 /// Config for Secio
 #[derive(Debug, Clone)]
@@ -26,6 +27,7 @@ pub struct Config {
     pub(crate) ciphers_proposal: Option<String>,
     pub(crate) digests_proposal: Option<String>,
     pub(crate) max_frame_length: usize,
+    pub(crate) max_handshake_message_size: usize,
 }
 
 impl Config {
@@ -37,6 +39,7 @@ impl Config {
             ciphers_proposal: None,
             digests_proposal: None,
             max_frame_length: MAX_FRAME_SIZE,
+            max_handshake_message_size: MAX_HANDSHAKE_MESSAGE_SIZE,
         }
     }
 
@@ -46,6 +49,14 @@ impl Config {
         self
     }
 
+    /// Max length of the messages sent by the remote during the handshake, a larger length
+    /// prefix fails the handshake with `SecioError::HandshakeMessageTooLarge` before the
+    /// message is buffered, default is 64KB
+    pub fn max_handshake_message_size(mut self, size: usize) -> Self {
+        self.max_handshake_message_size = size;
+        self
+    }
+
     /// Override the default set of supported key agreement algorithms.
     pub fn key_agreements<'a, I>(mut self, xs: I) -> Self
     where
@@ -55,7 +66,9 @@ impl Config {
         self
     }
 
-    /// Override the default set of supported ciphers.
+    /// Override the default set of supported ciphers, in the order of preference.
+    ///
+    /// The handshake fails with `SecioError::NoCipherIntersection` if the remote supports none of them.
     pub fn ciphers<'a, I>(mut self, xs: I) -> Self
     where
         I: IntoIterator<Item = &'a CipherType>,
@@ -6,7 +6,7 @@ use futures::{SinkExt, StreamExt};
 use log::{debug, trace};
 use std::{cmp::Ordering, io};
 use tokio::io::{AsyncRead, AsyncWrite};
-use tokio_util::codec::length_delimited::Builder;
+use tokio_util::codec::{length_delimited::Builder, LengthDelimitedCodecError};
 
 use crate::{
     codec::{secure_stream::SecureStream, Hmac},
@@ -17,7 +17,7 @@ use crate::{
         handshake_context::HandshakeContext,
         handshake_struct::{Exchange, PublicKey},
     },
-    EphemeralPublicKey, KeyPairInner,
+    EphemeralPublicKey, KeyExporter, KeyPairInner, SecurityParams,
 };
 use bytes::BytesMut;
 use tokio::io::AsyncWriteExt;
@@ -38,11 +38,13 @@ pub(in crate::handshake) async fn handshake<T>(
 where
     T: AsyncRead + AsyncWrite + Send + 'static + Unpin,
 {
-    // The handshake messages all start with a 4-bytes message length prefix.
+    let max_frame_length = config.max_frame_length;
+    // The handshake messages all start with a 4-bytes message length prefix,
+    // the remote can't make us buffer more than the handshake message size limit
     let mut socket = Builder::new()
         .big_endian()
         .length_field_length(4)
-        .max_frame_length(config.max_frame_length)
+        .max_frame_length(config.max_handshake_message_size)
         .new_framed(socket);
 
     // Generate our nonce.
@@ -59,7 +61,7 @@ where
 
     // Receive the remote's proposition.
     let remote_context = match socket.next().await {
-        Some(p) => local_context.with_remote(p?)?,
+        Some(p) => local_context.with_remote(p.map_err(receive_error)?)?,
         None => {
             let err = io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected eof");
             debug!("unexpected eof while waiting for remote's proposition");
@@ -97,22 +99,25 @@ where
 
         exchanges.epubkey = tmp_pub_key;
 
-        let data_to_sign = crate::sha256_compat::sha256(&data_to_sign);
-        let message = match crate::secp256k1_compat::message_from_slice(data_to_sign.as_ref()) {
-            Ok(msg) => msg,
-            Err(_) => {
-                debug!("message has wrong format");
-                return Err(SecioError::InvalidMessage);
-            }
-        };
-
-        let signature = match ephemeral_context.config.key.inner {
+        exchanges.signature = match ephemeral_context.config.key.inner {
             KeyPairInner::Secp256k1 { ref private } => {
-                crate::secp256k1_compat::sign(&message, private)
+                let data_to_sign = crate::sha256_compat::sha256(&data_to_sign);
+                let message =
+                    match crate::secp256k1_compat::message_from_slice(data_to_sign.as_ref()) {
+                        Ok(msg) => msg,
+                        Err(_) => {
+                            debug!("message has wrong format");
+                            return Err(SecioError::InvalidMessage);
+                        }
+                    };
+                let signature = crate::secp256k1_compat::sign(&message, private);
+                crate::secp256k1_compat::signature_to_vec(signature)
+            }
+            // ed25519 hashes the message itself
+            KeyPairInner::Ed25519 { ref private } => {
+                crate::ed25519_compat::sign(&data_to_sign, private)
             }
         };
-
-        exchanges.signature = crate::secp256k1_compat::signature_to_vec(signature);
         exchanges
     };
     let local_exchanges = exchanges.encode();
@@ -124,7 +129,7 @@ where
 
     // Receive the remote's `Exchange`.
     let raw_exchanges = match socket.next().await {
-        Some(raw) => raw?,
+        Some(raw) => raw.map_err(receive_error)?,
         None => {
             let err = io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected eof");
             debug!("unexpected eof while waiting for remote's proposition");
@@ -150,29 +155,39 @@ where
     data_to_verify.extend_from_slice(&ephemeral_context.state.remote.local.proposition_bytes);
     data_to_verify.extend_from_slice(&remote_exchanges.epubkey);
 
-    let data_to_verify = crate::sha256_compat::sha256(&data_to_verify);
-
-    let message = match crate::secp256k1_compat::message_from_slice(data_to_verify.as_ref()) {
-        Ok(msg) => msg,
-        Err(_) => {
-            debug!("remote's message has wrong format");
-            return Err(SecioError::InvalidMessage);
+    match ephemeral_context.state.remote.public_key {
+        PublicKey::Secp256k1(ref key) => {
+            let data_to_verify = crate::sha256_compat::sha256(&data_to_verify);
+
+            let message = match crate::secp256k1_compat::message_from_slice(data_to_verify.as_ref())
+            {
+                Ok(msg) => msg,
+                Err(_) => {
+                    debug!("remote's message has wrong format");
+                    return Err(SecioError::InvalidMessage);
+                }
+            };
+
+            let signature =
+                crate::secp256k1_compat::signature_from_der(&remote_exchanges.signature);
+            let remote_public_key = crate::secp256k1_compat::pubkey_from_slice(key);
+
+            if let (Ok(signature), Ok(remote_public_key)) = (signature, remote_public_key) {
+                if !crate::secp256k1_compat::verify(&message, &signature, &remote_public_key) {
+                    debug!("failed to verify the remote's signature");
+                    return Err(SecioError::SignatureVerificationFailed);
+                }
+            } else {
+                debug!("remote's secp256k1 signature has wrong format");
+                return Err(SecioError::SignatureVerificationFailed);
+            }
         }
-    };
-
-    let signature = crate::secp256k1_compat::signature_from_der(&remote_exchanges.signature);
-    let remote_public_key = match ephemeral_context.state.remote.public_key {
-        PublicKey::Secp256k1(ref key) => crate::secp256k1_compat::pubkey_from_slice(key),
-    };
-
-    if let (Ok(signature), Ok(remote_public_key)) = (signature, remote_public_key) {
-        if !crate::secp256k1_compat::verify(&message, &signature, &remote_public_key) {
-            debug!("failed to verify the remote's signature");
-            return Err(SecioError::SignatureVerificationFailed);
+        PublicKey::Ed25519(ref key) => {
+            if !crate::ed25519_compat::verify(&data_to_verify, &remote_exchanges.signature, key) {
+                debug!("failed to verify the remote's ed25519 signature");
+                return Err(SecioError::SignatureVerificationFailed);
+            }
         }
-    } else {
-        debug!("remote's secp256k1 signature has wrong format");
-        return Err(SecioError::SignatureVerificationFailed);
     }
 
     trace!("successfully verified the remote's signature");
@@ -235,11 +250,24 @@ where
         iv_size,
     );
 
+    let exporter = KeyExporter::new(
+        pub_ephemeral_context.state.remote.chosen_hash,
+        &key_material,
+    );
+
+    // The frames after the handshake are limited by the max frame length
+    socket.codec_mut().set_max_frame_length(max_frame_length);
+
     let mut secure_stream = SecureStream::new(
         socket,
         decode_cipher,
         encode_cipher,
         pub_ephemeral_context.state.remote.local.nonce.to_vec(),
+        exporter,
+        SecurityParams {
+            cipher: chosen_cipher,
+            digest: pub_ephemeral_context.state.remote.chosen_hash,
+        },
     );
 
     // We send back their nonce to check if the connection works.
@@ -257,6 +285,20 @@ where
     ))
 }
 
+/// The error of receiving a handshake message, distinguish the oversized message from io error
+fn receive_error(err: io::Error) -> SecioError {
+    if err
+        .get_ref()
+        .map(|inner| inner.is::<LengthDelimitedCodecError>())
+        .unwrap_or(false)
+    {
+        debug!("remote's handshake message is too large");
+        SecioError::HandshakeMessageTooLarge
+    } else {
+        err.into()
+    }
+}
+
 /// Custom algorithm translated from reference implementations. Needs to be the same algorithm
 /// amongst all implementations.
 fn stretch_key(hmac: Hmac, result: &mut [u8]) {
@@ -300,10 +342,14 @@ fn generate_stream_cipher_and_hmac(
 #[cfg(test)]
 mod tests {
     use super::stretch_key;
-    use crate::{codec::Hmac, handshake::Config, Digest, SecioKeyPair};
+    use crate::{
+        codec::Hmac, crypto::cipher::CipherType, error::SecioError, handshake::Config, Digest,
+        SecioKeyPair, SecurityParams,
+    };
 
     use bytes::BytesMut;
     use futures::channel;
+    use std::time::Duration;
     use tokio::{
         io::{AsyncReadExt, AsyncWriteExt},
         net::{TcpListener, TcpStream},
@@ -311,7 +357,10 @@ mod tests {
 
     fn handshake_with_self_success(config_1: Config, config_2: Config, data: &'static [u8]) {
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let (sender, receiver) = channel::oneshot::channel::<bytes::BytesMut>();
+        let (sender, receiver) =
+            channel::oneshot::channel::<(bytes::BytesMut, [u8; 32], SecurityParams)>();
+        let (export_sender, export_receiver) =
+            channel::oneshot::channel::<([u8; 32], SecurityParams)>();
         let (addr_sender, addr_receiver) = channel::oneshot::channel::<::std::net::SocketAddr>();
 
         rt.spawn(async move {
@@ -323,6 +372,9 @@ mod tests {
             let mut data = [0u8; 11];
             handle.read_exact(&mut data).await.unwrap();
             handle.write_all(&data).await.unwrap();
+            let mut exported = [0u8; 32];
+            handle.exporter().export(b"test", &mut exported);
+            let _res = export_sender.send((exported, handle.security_params()));
         });
 
         rt.spawn(async move {
@@ -332,12 +384,19 @@ mod tests {
             handle.write_all(data).await.unwrap();
             let mut data = [0u8; 11];
             handle.read_exact(&mut data).await.unwrap();
-            let _res = sender.send(BytesMut::from(&data[..]));
+            let mut exported = [0u8; 32];
+            handle.exporter().export(b"test", &mut exported);
+            let _res = sender.send((
+                BytesMut::from(&data[..]),
+                exported,
+                handle.security_params(),
+            ));
         });
 
         rt.block_on(async move {
-            let received = receiver.await.unwrap();
+            let (received, exported, params) = receiver.await.unwrap();
             assert_eq!(received.to_vec(), data);
+            assert_eq!(export_receiver.await.unwrap(), (exported, params));
         });
     }
 
@@ -348,6 +407,114 @@ mod tests {
         handshake_with_self_success(Config::new(key_1), Config::new(key_2), b"hello world")
     }
 
+    #[test]
+    fn handshake_with_self_success_ed25519_small_data() {
+        let key_1 = SecioKeyPair::ed25519_generated();
+        let key_2 = SecioKeyPair::ed25519_generated();
+        handshake_with_self_success(Config::new(key_1), Config::new(key_2), b"hello world")
+    }
+
+    #[test]
+    fn handshake_between_key_types_success() {
+        let key_1 = SecioKeyPair::ed25519_generated();
+        let key_2 = SecioKeyPair::secp256k1_generated();
+        handshake_with_self_success(Config::new(key_1), Config::new(key_2), b"hello world")
+    }
+
+    #[test]
+    fn handshake_with_preferred_cipher() {
+        let (config_1, config_2) = (
+            Config::new(SecioKeyPair::secp256k1_generated()),
+            Config::new(SecioKeyPair::secp256k1_generated()),
+        );
+        let ciphers = [CipherType::Aes256Gcm, CipherType::Aes128Gcm];
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (res_1, res_2) = rt.block_on(handshake_pair(
+            config_1.ciphers(&ciphers),
+            config_2.ciphers(&ciphers),
+        ));
+        assert_eq!(res_1.unwrap().cipher, CipherType::Aes256Gcm);
+        assert_eq!(res_2.unwrap().cipher, CipherType::Aes256Gcm);
+    }
+
+    #[test]
+    fn handshake_with_disjoint_ciphers_fails() {
+        let config_1 = Config::new(SecioKeyPair::secp256k1_generated())
+            .ciphers(&[CipherType::Aes128Gcm, CipherType::Aes256Gcm]);
+        let config_2 = Config::new(SecioKeyPair::secp256k1_generated())
+            .ciphers(&[CipherType::ChaCha20Poly1305]);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (res_1, res_2) = rt.block_on(handshake_pair(config_1, config_2));
+        assert_eq!(res_1.unwrap_err(), SecioError::NoCipherIntersection);
+        assert_eq!(res_2.unwrap_err(), SecioError::NoCipherIntersection);
+    }
+
+    /// Handshake with a remote sending a length prefix of the size and nothing else
+    async fn handshake_with_length_prefix(config: Config, size: u32) -> Result<(), SecioError> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listener_addr = listener.local_addr().unwrap();
+        let mut connect = TcpStream::connect(&listener_addr).await.unwrap();
+        connect.write_all(&size.to_be_bytes()).await.unwrap();
+
+        let (accepted, _) = listener.accept().await.unwrap();
+        let result = tokio::time::timeout(Duration::from_secs(1), config.handshake(accepted))
+            .await
+            .expect("handshake must abort without waiting for the message");
+        // the connection is kept open until the handshake aborts
+        drop(connect);
+        result.map(|_| ())
+    }
+
+    #[test]
+    fn handshake_with_oversized_message_fails() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        // over the handshake message limit, but under the max frame length
+        let config = Config::new(SecioKeyPair::secp256k1_generated());
+        assert_eq!(
+            rt.block_on(handshake_with_length_prefix(config, 1024 * 1024)),
+            Err(SecioError::HandshakeMessageTooLarge)
+        );
+
+        let config =
+            Config::new(SecioKeyPair::secp256k1_generated()).max_handshake_message_size(1024);
+        assert_eq!(
+            rt.block_on(handshake_with_length_prefix(config, 1025)),
+            Err(SecioError::HandshakeMessageTooLarge)
+        );
+        let config = Config::new(SecioKeyPair::secp256k1_generated());
+        assert_eq!(
+            rt.block_on(handshake_with_length_prefix(config, u32::MAX)),
+            Err(SecioError::HandshakeMessageTooLarge)
+        );
+    }
+
+    /// Handshake over tcp loopback, return the negotiated params of both sides
+    async fn handshake_pair(
+        config_1: Config,
+        config_2: Config,
+    ) -> (
+        Result<SecurityParams, SecioError>,
+        Result<SecurityParams, SecioError>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listener_addr = listener.local_addr().unwrap();
+        let accept = async move {
+            let (connect, _) = listener.accept().await.unwrap();
+            config_1
+                .handshake(connect)
+                .await
+                .map(|(handle, _, _)| handle.security_params())
+        };
+        let dial = async move {
+            let connect = TcpStream::connect(&listener_addr).await.unwrap();
+            config_2
+                .handshake(connect)
+                .await
+                .map(|(handle, _, _)| handle.security_params())
+        };
+        futures::future::join(accept, dial).await
+    }
+
     #[test]
     fn stretch() {
         let mut output = [0u8; 32];
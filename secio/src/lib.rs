@@ -2,16 +2,20 @@
 
 #![deny(missing_docs)]
 use rand::RngCore;
+use std::fmt;
 
-pub use crate::{handshake::handshake_struct::PublicKey, peer_id::PeerId};
+pub use crate::{exporter::KeyExporter, handshake::handshake_struct::PublicKey, peer_id::PeerId};
 
 /// Encrypted and decrypted codec implementation, and stream handle
 pub mod codec;
 /// Symmetric ciphers algorithms
 pub mod crypto;
 mod dh_compat;
+/// A little encapsulation of ed25519
+mod ed25519_compat;
 /// Error type
 pub mod error;
+mod exporter;
 /// Implementation of the handshake process
 pub mod handshake;
 /// Peer id
@@ -58,14 +62,35 @@ impl SecioKeyPair {
         })
     }
 
+    /// Generates a new random ed25519 key pair.
+    pub fn ed25519_generated() -> SecioKeyPair {
+        let mut private = [0; crate::ed25519_compat::SECRET_KEY_SIZE];
+        rand::thread_rng().fill_bytes(&mut private);
+        SecioKeyPair {
+            inner: KeyPairInner::Ed25519 { private },
+        }
+    }
+
+    /// Builds a `SecioKeyPair` from a raw ed25519 32 bytes private key.
+    pub fn ed25519_raw_key<K>(key: K) -> Result<SecioKeyPair, error::SecioError>
+    where
+        K: AsRef<[u8]>,
+    {
+        let key = key.as_ref();
+        if key.len() != crate::ed25519_compat::SECRET_KEY_SIZE {
+            return Err(error::SecioError::SecretGenerationFailed);
+        }
+        let mut private = [0; crate::ed25519_compat::SECRET_KEY_SIZE];
+        private.copy_from_slice(key);
+
+        Ok(SecioKeyPair {
+            inner: KeyPairInner::Ed25519 { private },
+        })
+    }
+
     /// Returns the public key corresponding to this key pair.
     pub fn public_key(&self) -> PublicKey {
-        match self.inner {
-            KeyPairInner::Secp256k1 { ref private } => {
-                let pubkey = crate::secp256k1_compat::from_secret_key(private);
-                PublicKey::Secp256k1(crate::secp256k1_compat::serialize_pubkey(&pubkey))
-            }
-        }
+        self.inner.public_key()
     }
 
     /// Generate Peer id
@@ -74,11 +99,41 @@ impl SecioKeyPair {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 enum KeyPairInner {
     Secp256k1 {
         private: crate::secp256k1_compat::SecretKey,
     },
+    Ed25519 {
+        private: [u8; crate::ed25519_compat::SECRET_KEY_SIZE],
+    },
+}
+
+impl KeyPairInner {
+    fn public_key(&self) -> PublicKey {
+        match self {
+            KeyPairInner::Secp256k1 { private } => {
+                let pubkey = crate::secp256k1_compat::from_secret_key(private);
+                PublicKey::Secp256k1(crate::secp256k1_compat::serialize_pubkey(&pubkey))
+            }
+            KeyPairInner::Ed25519 { private } => {
+                PublicKey::Ed25519(crate::ed25519_compat::pubkey_from_secret(private))
+            }
+        }
+    }
+}
+
+/// Only the public key is printed, the private key must not end up in the logs
+impl fmt::Debug for KeyPairInner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            KeyPairInner::Secp256k1 { .. } => "Secp256k1",
+            KeyPairInner::Ed25519 { .. } => "Ed25519",
+        };
+        f.debug_struct(name)
+            .field("public_key", &self.public_key())
+            .finish()
+    }
 }
 
 /// Possible digest algorithms.
@@ -100,3 +155,33 @@ impl Digest {
         }
     }
 }
+
+/// Algorithms negotiated by the handshake of a session
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SecurityParams {
+    /// Cipher of the secure stream
+    pub cipher: crypto::cipher::CipherType,
+    /// Digest of the hmac which stretches the shared key
+    pub digest: Digest,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SecioKeyPair;
+
+    #[test]
+    fn debug_key_pair_hides_private_key() {
+        let raw = [7u8; 32];
+        let hex: String = raw.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        for key in &[
+            SecioKeyPair::secp256k1_raw_key(raw).unwrap(),
+            SecioKeyPair::ed25519_raw_key(raw).unwrap(),
+        ] {
+            let output = format!("{:?}", key);
+            assert!(!output.contains(&hex));
+            assert!(!output.contains("7, 7, 7"));
+            assert!(output.contains(&format!("{:?}", key.public_key())));
+        }
+    }
+}
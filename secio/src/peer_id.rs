@@ -8,10 +8,16 @@ use crate::handshake::handshake_struct::PublicKey;
 
 const SHA256_CODE: u16 = 0x12;
 const SHA256_SIZE: u8 = 32;
+const IDENTITY_CODE: u16 = 0x00;
+/// The protobuf encoded ed25519 public key of libp2p is at most 42 bytes, so it's inlined
+const MAX_INLINE_KEY_LENGTH: usize = 42;
+/// Protobuf header of a libp2p public key, with `Type` field ed25519 and a 32 bytes `Data` field
+const ED25519_PROTOBUF_HEADER: [u8; 4] = [0x08, 0x01, 0x12, 0x20];
 
 /// Identifier of a peer of the network
 ///
-/// The data is a hash of the public key of the peer
+/// The data is a hash of the public key of the peer, except the ed25519 public key,
+/// which is inlined with the identity multihash as libp2p does
 #[derive(Clone, PartialOrd, PartialEq, Eq, Hash)]
 pub struct PeerId {
     inner: Vec<u8>,
@@ -21,8 +27,14 @@ impl PeerId {
     /// Builds a `PeerId` from a public key.
     #[inline]
     pub fn from_public_key(public_key: &PublicKey) -> Self {
-        let key_inner = public_key.inner_ref();
-        Self::from_seed(&key_inner)
+        match public_key {
+            PublicKey::Secp256k1(key) => Self::from_seed(key),
+            PublicKey::Ed25519(key) => {
+                let mut encoded = ED25519_PROTOBUF_HEADER.to_vec();
+                encoded.extend_from_slice(key);
+                Self::from_identity(&encoded)
+            }
+        }
     }
 
     /// If data is a valid `PeerId`, return `PeerId`, else return error
@@ -33,6 +45,16 @@ impl PeerId {
 
         let (code, bytes) = decode::u16(&data).map_err(|_| Error::InvalidData)?;
 
+        if code == IDENTITY_CODE {
+            if bytes.is_empty()
+                || bytes.len() > MAX_INLINE_KEY_LENGTH + 1
+                || bytes[0] as usize != bytes.len() - 1
+            {
+                return Err(Error::WrongLength);
+            }
+            return Ok(PeerId { inner: data });
+        }
+
         if code != SHA256_CODE {
             return Err(Error::NotSupportHashCode);
         }
@@ -48,6 +70,12 @@ impl PeerId {
         Ok(PeerId { inner: data })
     }
 
+    /// Builds a `PeerId` from the bytes of a custom derivation scheme as is,
+    /// the bytes aren't checked to be a sha256 multihash, so `digest` is not available on it
+    pub fn from_raw_bytes(data: Vec<u8>) -> Self {
+        PeerId { inner: data }
+    }
+
     /// Return a random `PeerId`
     pub fn random() -> Self {
         let mut seed = [0u8; 20];
@@ -73,6 +101,15 @@ impl PeerId {
         PeerId { inner }
     }
 
+    /// Return `PeerId` which inlined the data with the identity multihash
+    fn from_identity(data: &[u8]) -> Self {
+        let mut buf = encode::u16_buffer();
+        let mut inner = encode::u16(IDENTITY_CODE, &mut buf).to_vec();
+        inner.push(data.len() as u8);
+        inner.extend_from_slice(data);
+        PeerId { inner }
+    }
+
     /// Return raw bytes representation of this peer id
     #[inline]
     pub fn as_bytes(&self) -> &[u8] {
@@ -91,7 +128,8 @@ impl PeerId {
         bs58::encode(self.inner.clone()).into_string()
     }
 
-    /// Returns the raw bytes of the hash of this `PeerId`.
+    /// Returns the raw bytes of the hash of this `PeerId`,
+    /// or the inlined data of the identity multihash.
     #[inline]
     pub fn digest(&self) -> &[u8] {
         let (_, bytes) = decode::u16(&self.inner).expect("a invalid digest");
@@ -179,6 +217,27 @@ mod tests {
         assert_eq!(peer_id, second);
     }
 
+    #[test]
+    fn ed25519_peer_id_is_libp2p_compatible() {
+        // test 1 of RFC 8032
+        let key = SecioKeyPair::ed25519_raw_key(
+            &[
+                0x9d, 0x61, 0xb1, 0x9d, 0xef, 0xfd, 0x5a, 0x60, 0xba, 0x84, 0x4a, 0xf4, 0x92, 0xec,
+                0x2c, 0xc4, 0x44, 0x49, 0xc5, 0x69, 0x7b, 0x32, 0x69, 0x19, 0x70, 0x3b, 0xac, 0x03,
+                0x1c, 0xae, 0x7f, 0x60,
+            ][..],
+        )
+        .unwrap();
+        let peer_id = key.peer_id();
+        assert_eq!(
+            peer_id.to_base58(),
+            "12D3KooWQK1wnefoLrcVHbbnf5tLzbopUd3K3bFAoJpA7YJgL5pV"
+        );
+        assert!(peer_id.is_public_key(&key.public_key()));
+        let second: PeerId = peer_id.to_base58().parse().unwrap();
+        assert_eq!(peer_id, second);
+    }
+
     #[test]
     fn peer_id_randomness() {
         let peer_id = PeerId::random();
@@ -165,5 +165,5 @@ pub fn select_cipher(r: Ordering, ours: &str, theirs: &str) -> Result<CipherType
             }
         }
     }
-    Err(SecioError::NoSupportIntersection)
+    Err(SecioError::NoCipherIntersection)
 }
@@ -41,6 +41,14 @@ impl<T> PriorityBuffer<T> {
         self.high_buffer.len() + self.normal_buffer.len()
     }
 
+    pub fn high_len(&self) -> usize {
+        self.high_buffer.len()
+    }
+
+    pub fn normal_len(&self) -> usize {
+        self.normal_buffer.len()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.high_buffer.is_empty() && self.normal_buffer.is_empty()
     }
@@ -109,11 +117,25 @@ impl<T> PriorityBuffer<T> {
         self.high_buffer.clear();
         self.normal_buffer.clear();
     }
+
+    /// Keep only the items that the predicate returns true
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.high_buffer.retain(|item| f(item));
+        self.normal_buffer.retain(|item| f(item));
+    }
 }
 
 pub struct Buffer<T> {
     sender: Sender<T>,
     buffer: VecDeque<T>,
+    /// Whether the buffer is over the lagging threshold
+    lagging: bool,
+    /// Bytes of the items in the buffer, counted by `size_of`
+    bytes: usize,
+    size_of: fn(&T) -> usize,
 }
 
 impl<T> Buffer<T> {
@@ -121,10 +143,35 @@ impl<T> Buffer<T> {
         Buffer {
             sender,
             buffer: VecDeque::default(),
+            lagging: false,
+            bytes: 0,
+            size_of: |_| 0,
         }
     }
 
+    /// Count the bytes of the buffered items with `size_of`
+    pub fn count_bytes(mut self, size_of: fn(&T) -> usize) -> Self {
+        self.bytes = self.buffer.iter().map(size_of).sum();
+        self.size_of = size_of;
+        self
+    }
+
+    /// Bytes of the items in the buffer, zero if they are not counted
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Whether the buffer just went over the threshold, it's true once per crossing,
+    /// then false until the buffer falls back to the threshold
+    pub fn check_lagging(&mut self, threshold: usize) -> bool {
+        let over = self.buffer.len() > threshold;
+        let crossed = over && !self.lagging;
+        self.lagging = over;
+        crossed
+    }
+
     pub fn push(&mut self, item: T) {
+        self.bytes += (self.size_of)(&item);
         self.buffer.push_back(item)
     }
 
@@ -144,10 +191,13 @@ impl<T> Buffer<T> {
 
     pub fn try_send(&mut self, cx: &mut Context) -> SendResult {
         while let Some(event) = self.buffer.pop_front() {
+            let size = (self.size_of)(&event);
+            self.bytes -= size;
             match self.sender.poll_ready(cx) {
                 Poll::Ready(Ok(())) => {
                     if let Err(e) = self.sender.try_send(event) {
                         if e.is_full() {
+                            self.bytes += size;
                             self.buffer.push_front(e.into_inner());
                             return SendResult::Pending;
                         } else {
@@ -157,6 +207,7 @@ impl<T> Buffer<T> {
                     }
                 }
                 Poll::Pending => {
+                    self.bytes += size;
                     self.buffer.push_front(event);
                     return SendResult::Pending;
                 }
@@ -171,6 +222,7 @@ impl<T> Buffer<T> {
     }
 
     pub fn take(&mut self) -> (Sender<T>, VecDeque<T>) {
+        self.bytes = 0;
         (self.sender.clone(), ::std::mem::take(&mut self.buffer))
     }
 
@@ -178,8 +230,14 @@ impl<T> Buffer<T> {
         self.sender.clone()
     }
 
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
     pub fn clear(&mut self) {
-        self.buffer.clear()
+        self.buffer.clear();
+        self.lagging = false;
+        self.bytes = 0;
     }
 }
 
@@ -188,6 +246,9 @@ impl<T> Clone for Buffer<T> {
         Self {
             sender: self.sender.clone(),
             buffer: Default::default(),
+            lagging: false,
+            bytes: 0,
+            size_of: self.size_of,
         }
     }
 }
@@ -268,6 +329,42 @@ mod test {
         assert!(buffer.normal_buffer.is_empty());
     }
 
+    #[test]
+    fn test_priority_buffer_retain() {
+        let (tx, _rx) = priority_channel::<u32>(1);
+        let mut buffer = PriorityBuffer::new(tx);
+
+        buffer.push_high(1);
+        buffer.push_high(2);
+        buffer.push_normal(3);
+        buffer.push_normal(4);
+
+        buffer.retain(|item| item % 2 == 0);
+
+        assert_eq!(buffer.high_buffer, VecDeque::from(vec![2]));
+        assert_eq!(buffer.normal_buffer, VecDeque::from(vec![4]));
+    }
+
+    #[test]
+    fn test_buffer_lagging_once_per_crossing() {
+        let (tx, _rx) = channel::<u32>(1);
+        let mut buffer = Buffer::new(tx);
+
+        buffer.push(1);
+        buffer.push(2);
+        assert!(!buffer.check_lagging(2));
+        buffer.push(3);
+        assert!(buffer.check_lagging(2));
+        buffer.push(4);
+        assert!(!buffer.check_lagging(2));
+
+        buffer.buffer.pop_front();
+        buffer.buffer.pop_front();
+        assert!(!buffer.check_lagging(2));
+        buffer.push(5);
+        assert!(buffer.check_lagging(2));
+    }
+
     #[test]
     fn test_buffer() {
         let (tx, mut rx) = channel::<u32>(1);
@@ -303,4 +400,27 @@ mod test {
 
         assert_eq!(buffer.buffer, VecDeque::from(vec![5]));
     }
+
+    #[test]
+    fn test_buffer_bytes() {
+        let (tx, _rx) = channel::<u32>(1);
+        let mut buffer = Buffer::new(tx).count_bytes(|item| *item as usize);
+
+        for item in 1..=5 {
+            buffer.push(item);
+        }
+        assert_eq!(buffer.bytes(), 15);
+
+        let send = |cx: &mut Context<'_>| -> Poll<()> {
+            buffer.try_send(cx);
+            Poll::Ready(())
+        };
+        block_on(poll_fn(send));
+
+        assert_eq!(buffer.buffer, VecDeque::from(vec![3, 4, 5]));
+        assert_eq!(buffer.bytes(), 12);
+
+        buffer.clear();
+        assert_eq!(buffer.bytes(), 0);
+    }
 }
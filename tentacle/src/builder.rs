@@ -3,17 +3,25 @@ use std::{collections::HashMap, io, sync::Arc, time::Duration};
 use nohash_hasher::IntMap;
 use tokio_util::codec::LengthDelimitedCodec;
 
+#[cfg(feature = "metrics")]
+use crate::metrics::ServiceMetrics;
 #[cfg(feature = "tls")]
 use crate::service::config::TlsConfig;
 use crate::{
-    protocol_select::SelectFn,
-    secio::SecioKeyPair,
+    protocol_select::{NegotiationMode, SelectFn},
+    secio::{crypto::cipher::CipherType, PeerId, SecioKeyPair},
     service::{
-        config::{BlockingFlag, Meta, ServiceConfig},
-        ProtocolHandle, ProtocolMeta, Service,
+        config::{
+            BlockingFlag, HandleClosedPolicy, Meta, RateLimit, ReconnectBackoff, ServiceConfig,
+            TcpSocketConfig,
+        },
+        ProtocolClosePolicy, ProtocolHandle, ProtocolMeta, Service,
     },
-    traits::{Codec, ProtocolSpawn, ServiceHandle, ServiceProtocol, SessionProtocol},
-    utils::multiaddr_to_socketaddr,
+    traits::{
+        Codec, ConnectionGater, PeerIdCodec, PeerStore, ProtocolSpawn, SecurityUpgrade,
+        ServiceHandle, ServiceProtocol, SessionProtocol, StreamMuxer, TxtResolver,
+    },
+    utils::{compress::CompressionAlgo, multiaddr_to_socketaddr},
     yamux::Config,
     ProtocolId,
 };
@@ -54,6 +62,81 @@ impl ServiceBuilder {
         self
     }
 
+    /// Ciphers of secio in the order of preference, such as to disable ChaCha20-Poly1305.
+    /// The handshake with a remote supporting none of them fails with `NoCipherIntersection`
+    ///
+    /// default is AES-128-GCM, AES-256-GCM and ChaCha20-Poly1305
+    pub fn secio_ciphers(mut self, ciphers: Vec<CipherType>) -> Self {
+        self.config.secio_ciphers = Some(ciphers);
+        self
+    }
+
+    /// Use a custom security upgrade instead of secio
+    ///
+    /// If not set, secio will be used when key pair is set
+    pub fn security<S>(mut self, upgrade: S) -> Self
+    where
+        S: SecurityUpgrade + 'static,
+    {
+        self.config.security = Some(Arc::new(upgrade));
+        self
+    }
+
+    /// Use a custom peer id derivation instead of the sha256 multihash of the public key
+    ///
+    /// It's used to identify the remote of a session, to check the peer id of the dialed
+    /// address, and to look up `protocol_allowlist`, so the peer ids passed to the service
+    /// must be derived by the same codec
+    pub fn peer_id_codec<C>(mut self, codec: C) -> Self
+    where
+        C: PeerIdCodec + 'static,
+    {
+        self.config.peer_id_codec = Some(Arc::new(codec));
+        self
+    }
+
+    /// Set the connection gating policy, it can veto the connections on accept, on dial,
+    /// after the security handshake and before the session is opened
+    pub fn connection_gater<G>(mut self, gater: G) -> Self
+    where
+        G: ConnectionGater + 'static,
+    {
+        self.config.gater = Some(Arc::new(gater));
+        self
+    }
+
+    /// Set the address book of the peers, the addresses of the outbound sessions are recorded
+    /// in it, and `ServiceControl::dial_peer` dials the addresses it knows
+    ///
+    /// It's shared, so the caller can keep a handle to persist or inspect the addresses
+    pub fn peer_store(mut self, store: Arc<dyn PeerStore>) -> Self {
+        self.config.peer_store = Some(store);
+        self
+    }
+
+    /// Set the TXT lookup of the `/dnsaddr` addresses, such as a resolver of the custom dns server
+    ///
+    /// The default one uses the system dns config and requires the `dnsaddr` feature,
+    /// without either of them dialing a `/dnsaddr` address is not supported
+    pub fn txt_resolver<R>(mut self, resolver: R) -> Self
+    where
+        R: TxtResolver + 'static,
+    {
+        self.config.txt_resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Use a custom stream multiplexer instead of yamux
+    ///
+    /// If set, `yamux_config` will be ignored
+    pub fn muxer<M>(mut self, muxer: M) -> Self
+    where
+        M: StreamMuxer + 'static,
+    {
+        self.config.muxer = Some(Arc::new(muxer));
+        self
+    }
+
     /// When the service has no tasks, it will be turned off by default.
     /// If you do not want to close service, set it to true.
     pub fn forever(mut self, forever: bool) -> Self {
@@ -69,6 +152,22 @@ impl ServiceBuilder {
         self
     }
 
+    /// Timeout for transport connection establishment on dial
+    ///
+    /// Default is the same as `timeout`
+    pub fn dial_timeout(mut self, timeout: Duration) -> Self {
+        self.config.dial_timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout for transport accept handling on listen, such as ws/tls handshake
+    ///
+    /// Default is the same as `timeout`
+    pub fn listen_timeout(mut self, timeout: Duration) -> Self {
+        self.config.listen_timeout = Some(timeout);
+        self
+    }
+
     /// Yamux config for service
     ///
     /// Panic when max_frame_length < yamux_max_window_size
@@ -91,6 +190,7 @@ impl ServiceBuilder {
                     .max_stream_window_size
         );
         self.config.max_frame_length = size;
+        self.config.session_config.max_frame_length = size;
         self
     }
 
@@ -100,12 +200,73 @@ impl ServiceBuilder {
         self
     }
 
+    /// Block a session once its unsent data reaches the high watermark, the messages to it are
+    /// dropped until the data falls to the low watermark. `ServiceError::SessionBlocked` is
+    /// reported when it's blocked, but the session is kept, unlike going over `set_send_buffer_size`
+    ///
+    /// default is off
+    pub fn session_buffer_watermarks(mut self, high: usize, low: usize) -> Self {
+        assert!(low <= high);
+        self.config.session_buffer_watermarks = Some((high, low));
+        self
+    }
+
     /// Set receive buffer size, default is 24Mb
     pub fn set_recv_buffer_size(mut self, size: usize) -> Self {
         self.config.session_config.recv_buffer_size = size;
         self
     }
 
+    /// Limit the protocol negotiations in progress on each session, default is no limit
+    ///
+    /// The sub streams opened by remote over the limit are dropped without negotiation,
+    /// the protocols opened by local over the limit wait for the former negotiations to finish
+    pub fn max_negotiating_protocols(mut self, number: usize) -> Self {
+        self.config.session_config.max_negotiating_protocols = Some(number);
+        self
+    }
+
+    /// Timeout of the protocol negotiation on each sub stream, default is the same as `timeout`
+    ///
+    /// A sub stream which doesn't finish the negotiation in time is dropped, and reported as
+    /// `ServiceError::ProtocolSelectError`, so peers opening sub streams without negotiating
+    /// can't hold them until the session times out
+    pub fn protocol_select_timeout(mut self, timeout: Duration) -> Self {
+        self.config.session_config.protocol_select_timeout = Some(timeout);
+        self
+    }
+
+    /// Close a session after no protocol message is sent or received on it for the duration,
+    /// default is never
+    ///
+    /// The idle session is reported as `ServiceError::SessionTimeout`, it's checked with
+    /// the traffic of the session every quarter of the duration. Unlike `timeout`, it doesn't
+    /// apply to the handshake
+    pub fn session_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.config.session_config.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Wire format of the protocol negotiation, default is tentacle's own
+    ///
+    /// Use `NegotiationMode::Multistream` to negotiate with libp2p nodes, both sides of a
+    /// session must use the same mode
+    pub fn negotiation_mode(mut self, mode: NegotiationMode) -> Self {
+        self.config.session_config.negotiation_mode = mode;
+        self
+    }
+
+    /// Limit the messages received per second on each session, counted across its protocols,
+    /// default is no limit
+    ///
+    /// Once over the rate, the session stops reading from the network until the next second,
+    /// the messages over the rate stay in the receive window of the sub streams, so a flooding
+    /// peer is slowed down by the backpressure instead of having its messages dropped
+    pub fn max_recv_rate(mut self, messages_per_second: u32) -> Self {
+        self.config.session_config.max_recv_rate = Some(messages_per_second);
+        self
+    }
+
     /// If session is close by remote, did you want to keep unreceived message as more as possible
     /// default is false
     pub fn keep_buffer(mut self, keep: bool) -> Self {
@@ -139,6 +300,241 @@ impl ServiceBuilder {
         self
     }
 
+    /// When the number of events buffered for a protocol handle exceeds the threshold,
+    /// service will output `ServiceError::HandleLagging`, once until the buffer is drained
+    /// to the threshold
+    ///
+    /// Default is None, no check
+    pub fn handle_lagging_threshold(mut self, threshold: usize) -> Self {
+        self.config.handle_lagging_threshold = Some(threshold);
+        self
+    }
+
+    /// What to do when a protocol handle closes unexpectedly, such as the handle panicked,
+    /// the service always outputs `ProtocolHandleErrorKind::AbnormallyClosed` first
+    ///
+    /// Default is `HandleClosedPolicy::Shutdown`, shutdown the whole service
+    pub fn handle_closed_policy(mut self, policy: HandleClosedPolicy) -> Self {
+        self.config.handle_closed_policy = policy;
+        self
+    }
+
+    /// Append every session event and service task handled by the service to the file,
+    /// one per line with a unix timestamp in microseconds, for reproducing ordering bugs
+    ///
+    /// Only for debugging, the lines are written by a background thread, and dropped if it
+    /// falls behind. `service::read_records` reads the trace back, and
+    /// `service::replay_records` feeds its service tasks to another service
+    ///
+    /// Default is None, no record
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn event_recorder<P: AsRef<std::path::Path>>(mut self, path: P) -> Self {
+        self.config.event_recorder = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Measure the time protocol messages spend in the substream write buffer before being
+    /// handed to the session, per protocol, the histograms can be got by `message_latency`
+    /// on the service control
+    ///
+    /// Default is false
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn message_latency(mut self, enable: bool) -> Self {
+        self.config.message_latency = enable;
+        self
+    }
+
+    /// Report one of every `sample` dropped messages as `ServiceError::MessageDropped`,
+    /// such as messages to a closed session or a protocol not open, and lossy sends on a
+    /// blocked session. The first drop is always reported
+    ///
+    /// All drops are counted regardless, see `ServiceControl::dropped_messages`
+    ///
+    /// Default is 0, no report
+    pub fn message_drop_log(mut self, sample: u64) -> Self {
+        self.config.message_drop_sample = sample;
+        self
+    }
+
+    /// Limit the total bytes of the messages held by the write and read buffers of all
+    /// substreams, over the limit, substreams stop reading from the network until the
+    /// buffered messages are consumed, and `ServiceError::MemoryPressure` is reported
+    ///
+    /// Default is unlimited
+    pub fn max_buffer_bytes(mut self, size: usize) -> Self {
+        self.config.max_buffer_bytes = Some(size);
+        self
+    }
+
+    /// Downgrade the protocol version on reconnection to a peer which fails to negotiate it,
+    /// default is off
+    ///
+    /// Once a protocol fails to negotiate with a peer `failures` times, and the last failure
+    /// is within `ttl`, the newest supported version of it is not offered on the next sessions
+    /// with the peer, so the older version is negotiated in mixed-version networks
+    pub fn version_downgrade(mut self, failures: u32, ttl: Duration) -> Self {
+        self.config.version_downgrade = Some((failures, ttl));
+        self
+    }
+
+    /// Limit of the listeners, the listens over it fail with
+    /// `ListenErrorKind::TooManyListeners`, so repeated listens can't exhaust the file descriptors
+    ///
+    /// default is no limit
+    pub fn max_listeners(mut self, number: usize) -> Self {
+        self.config.max_listeners = Some(number);
+        self
+    }
+
+    /// Limit of the inbound handshakes in progress, shared by all listeners. While it's reached
+    /// the listeners stop accepting, the new connections wait in the backlog of the listen
+    /// sockets, so a connection flood can't spawn unbounded handshakes
+    ///
+    /// default is no limit
+    pub fn max_concurrent_handshakes(mut self, number: usize) -> Self {
+        self.config.max_concurrent_handshakes = Some(number);
+        self
+    }
+
+    /// Limit of the opened sessions, inbound and outbound ones together. The sessions over it
+    /// are closed after the handshake with `ServiceError::ConnectionLimit`, and dials are refused
+    /// with it while the limit is reached
+    ///
+    /// Unlike `max_connection_number`, the connections in handshake are not counted
+    ///
+    /// default is no limit
+    pub fn max_connections(mut self, number: usize) -> Self {
+        self.config.max_connections = Some(number);
+        self
+    }
+
+    /// Limit of the opened inbound sessions, the sessions over it are closed after the handshake
+    /// with `ServiceError::ConnectionLimit`
+    ///
+    /// default is no limit
+    pub fn max_inbound(mut self, number: usize) -> Self {
+        self.config.max_inbound = Some(number);
+        self
+    }
+
+    /// Limit of the opened outbound sessions, dials are refused with
+    /// `ServiceError::ConnectionLimit` while it's reached
+    ///
+    /// default is no limit
+    pub fn max_outbound(mut self, number: usize) -> Self {
+        self.config.max_outbound = Some(number);
+        self
+    }
+
+    /// Bandwidth limit of each session, the messages over it are kept in the session buffers
+    /// and sent later, so a session still going over `set_send_buffer_size` is closed.
+    /// The limit of a session can be changed at runtime by `ServiceControl::set_rate_limit`
+    ///
+    /// default is no limit
+    pub fn global_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.config.global_rate_limit = limit;
+        self
+    }
+
+    /// Socket options of the tcp connections, such as disabling the Nagle's algorithm
+    /// for the latency of the small messages, the ws and tls connections over tcp included
+    ///
+    /// default is the system default
+    pub fn tcp_socket_config(mut self, config: TcpSocketConfig) -> Self {
+        self.config.tcp_socket_config = config;
+        self
+    }
+
+    /// Exchange the supported protocols with the remote after the security handshake, they're
+    /// available as `SessionContext::remote_protocols` when the session is opened.
+    ///
+    /// The remote must enable it as well, otherwise the handshake fails.
+    ///
+    /// default is false
+    pub fn exchange_protocols(mut self, enable: bool) -> Self {
+        self.config.exchange_protocols = enable;
+        self
+    }
+
+    /// Backoff of the re-dials of the peers added by `ServiceControl::add_persistent_peer`
+    ///
+    /// default is from 1s doubled up to 60s, with a jitter of 0.2
+    pub fn reconnect_backoff(mut self, backoff: ReconnectBackoff) -> Self {
+        self.config.reconnect_backoff = backoff;
+        self
+    }
+
+    /// Export the metrics of the service into the prometheus registry: the opened sessions,
+    /// the bytes of the protocol messages, the errors reported to the service handle by kind,
+    /// and the events waiting in the buffers of the service
+    ///
+    /// If the metrics are already registered, such as by another service, an error is logged
+    /// and they aren't exported, use a registry with a distinct prefix for each service
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, registry: &prometheus::Registry) -> Self {
+        match ServiceMetrics::new(registry) {
+            Ok(metrics) => self.config.metrics = metrics,
+            Err(err) => log::error!("register the metrics of the service error: {:?}", err),
+        }
+        self
+    }
+
+    /// Limit of the dials in progress, a dial is in progress until its session opens or it fails.
+    /// The dials over the limit are queued, and started by their weight given in
+    /// `ServiceControl::dial_with_priority`, in order for the same weight
+    ///
+    /// default is no limit
+    pub fn max_dial_concurrency(mut self, number: usize) -> Self {
+        self.config.max_dial_concurrency = Some(number);
+        self
+    }
+
+    /// Retry a dial failed with a transient error, such as the connection is refused or timed
+    /// out, up to `retries` times, the delay starts from `delay` and doubles after each retry
+    ///
+    /// The failures before the last retry are not reported, and the `dial_with_result` calls
+    /// keep waiting. The failures of the handshake, such as the peer id mismatch, are not retried
+    ///
+    /// default is no retry
+    pub fn dial_retries(mut self, retries: u32, delay: Duration) -> Self {
+        self.config.dial_retries = Some((retries, delay));
+        self
+    }
+
+    /// Advertise a listen on `0.0.0.0`/`::` as the same listen on each ip of the local interfaces
+    /// of that family, so the addresses in `ServiceContext::listens` and the `update_listens`
+    /// notifications are dialable. Interfaces are enumerated when the listens change, and only
+    /// on unix, the unspecified address is kept if it fails
+    ///
+    /// default is false
+    pub fn expand_unspecified_listens(mut self, enable: bool) -> Self {
+        self.config.expand_unspecified_listens = enable;
+        self
+    }
+
+    /// Only allow the peer to open these protocols on its sessions, other protocols opened by
+    /// it fail to negotiate and the service outputs `ServiceError::ProtocolNotAllowed`
+    ///
+    /// Protocols opened by our side are not limited. Peers not set here, and sessions without
+    /// secio, can open any protocol
+    pub fn protocol_allowlist(mut self, peer_id: PeerId, protocols: Vec<ProtocolId>) -> Self {
+        self.config
+            .protocol_allowlist
+            .insert(peer_id, protocols.into_iter().collect());
+        self
+    }
+
+    /// The max lifetime of a session, the session will be closed gracefully after it
+    ///
+    /// Close time is staggered within the last quarter of the lifetime,
+    /// to avoid sessions opened at the same time being closed at the same time
+    ///
+    /// Default is None, no limit
+    pub fn max_session_lifetime(mut self, lifetime: Duration) -> Self {
+        self.config.max_session_lifetime = Some(lifetime);
+        self
+    }
+
     /// Bind all the outbound connections to the local listening address.
     ///
     /// In this way, any actively connected outbound connection is potentially connectable. Through this setting,
@@ -158,12 +554,27 @@ impl ServiceBuilder {
         self
     }
 
+    /// Originate all the outbound tcp/ws connections from the local address, such as one of
+    /// the interfaces of a multi-homed host.
+    ///
+    /// Unlike tcp bind, the listens aren't affected, and it takes precedence over tcp/ws bind
+    /// on dial. Dialing a target of the other ip family fails with `BindFamilyMismatch`.
+    ///
+    /// Default is None, the system chooses
+    pub fn outbound_bind(mut self, addr: multiaddr::Multiaddr) -> Self {
+        self.config.outbound_bind = multiaddr_to_socketaddr(&addr);
+        self
+    }
+
     /// Clear all protocols
     pub fn clear(&mut self) {
         self.inner.clear();
     }
 
     /// set rustls ServerConfig, default is NoClientAuth
+    ///
+    /// The config is used by the `/tls` addresses. If the service also has a key pair,
+    /// secio runs inside the tls stream, otherwise tls is the only security of the session.
     #[cfg(feature = "tls")]
     pub fn tls_config(mut self, config: TlsConfig) -> Self {
         self.config.tls_config = Some(config);
@@ -204,6 +615,11 @@ pub struct MetaBuilder {
     before_receive: BeforeReceiveFn,
     flag: BlockingFlag,
     spawn: Option<Box<dyn ProtocolSpawn + Send + Sync + 'static>>,
+    close_policy: ProtocolClosePolicy,
+    coalesce: Option<(Duration, usize)>,
+    low_latency: bool,
+    max_message_size: Option<usize>,
+    decompress: bool,
 }
 
 impl MetaBuilder {
@@ -264,7 +680,8 @@ impl MetaBuilder {
         self
     }
 
-    /// Define protocol session handle, default is neither
+    /// Define protocol session handle, default is neither, `ProtocolHandle::Raw` is only valid
+    /// as service handle
     pub fn session_handle<
         T: FnMut() -> ProtocolHandle<Box<dyn SessionProtocol + Send + 'static + Unpin>>
             + Send
@@ -295,7 +712,8 @@ impl MetaBuilder {
         self
     }
 
-    /// Unified processing of messages before they are sent
+    /// Unified processing of messages before they are sent, such as compress,
+    /// the inbound counterpart is `before_receive`
     pub fn before_send<T>(mut self, f: T) -> Self
     where
         T: Fn(bytes::Bytes) -> bytes::Bytes + 'static + Send,
@@ -304,27 +722,94 @@ impl MetaBuilder {
         self
     }
 
-    /// Unified processing of messages before user received
+    /// Unified processing of messages before user received, such as decompress the data
+    /// transformed by the remote `before_send`
+    ///
+    /// Called once on each opened sub stream of the protocol, return None to skip the processing.
+    /// The data that fails to be processed closes the sub stream with the error
     pub fn before_receive<T>(mut self, f: T) -> Self
     where
         T: Fn() -> Option<BeforeReceive> + Send + Sync + 'static,
     {
         self.before_receive = Box::new(f);
+        self.decompress = false;
         self
     }
 
+    /// Compress the messages of the protocol, both sides of it must set this option,
+    /// while the algorithm can be different, see `CompressionAlgo`
+    ///
+    /// It replaces `before_send` and `before_receive`, the decompressed messages are limited
+    /// by `ServiceBuilder::max_frame_length`
+    pub fn compress(mut self, algo: CompressionAlgo) -> Self {
+        self.before_receive = Box::new(|| None);
+        self.decompress = true;
+        self.before_send(move |data| algo.compress(data))
+    }
+
     /// Set a flag to control function behavior
     pub fn flag(mut self, flag: BlockingFlag) -> Self {
         self.flag = flag;
         self
     }
 
+    /// What happens to the queued messages when the protocol is closed,
+    /// default is `ProtocolClosePolicy::DropQueued`
+    pub fn close_policy(mut self, policy: ProtocolClosePolicy) -> Self {
+        self.close_policy = policy;
+        self
+    }
+
+    /// Coalesce the small messages sent to the same session, default is off
+    ///
+    /// The normal priority messages not larger than `max_size` are held by the service
+    /// for up to `delay`, then handed to the session together and written by the codec
+    /// with one flush, so each of them is still a separate frame of the protocol.
+    /// A larger or high priority message isn't held, the messages held before a larger one
+    /// are handed to the session first to keep the order.
+    pub fn coalesce(mut self, delay: Duration, max_size: usize) -> Self {
+        self.coalesce = Some((delay, max_size));
+        self
+    }
+
+    /// Send the messages of the protocol ahead of the queued normal priority ones, default is off
+    ///
+    /// The messages skip the queues of the service tasks and the sessions as the `quick_*`
+    /// sends do, so small time-sensitive messages, such as ping or votes, don't wait behind
+    /// the bulk data of the other protocols. All of them take the same queue, so their order
+    /// is kept. Can't be used with `coalesce`
+    pub fn low_latency(mut self, enable: bool) -> Self {
+        self.low_latency = enable;
+        self
+    }
+
+    /// Max size of the inbound messages of the protocol, default is no limit other than
+    /// the codec and `max_frame_length`
+    ///
+    /// The size is checked on the decoded frame, before `before_receive`, a larger message
+    /// doesn't reach the handle and closes the sub stream with a `ProtocolError`
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = Some(size);
+        self
+    }
+
     /// Combine the configuration of this builder to create a ProtocolMeta
     pub fn build(mut self) -> ProtocolMeta {
+        assert!(!(self.low_latency && self.coalesce.is_some()));
         if self.spawn.is_some() {
             assert!(self.service_handle.is_none());
             assert!((self.session_handle)().is_none());
         }
+        // the raw sub stream is only handed over by the service handle
+        assert!(!(self.session_handle)().is_raw());
+        let (service_handle, raw) = match self.service_handle {
+            ProtocolHandle::Raw(raw) => {
+                assert!(self.spawn.is_none());
+                assert!((self.session_handle)().is_none());
+                (ProtocolHandle::None, Some(raw))
+            }
+            handle => (handle, None),
+        };
         let meta = Meta {
             id: self.id,
             name: self.name,
@@ -333,10 +818,16 @@ impl MetaBuilder {
             select_version: self.select_version,
             before_receive: self.before_receive,
             spawn: self.spawn,
+            raw,
+            close_policy: self.close_policy,
+            coalesce: self.coalesce,
+            low_latency: self.low_latency,
+            max_message_size: self.max_message_size,
+            decompress: self.decompress,
         };
         ProtocolMeta {
             inner: Arc::new(meta),
-            service_handle: self.service_handle,
+            service_handle,
             session_handle: self.session_handle,
             before_send: self.before_send,
             flag: self.flag,
@@ -358,6 +849,11 @@ impl Default for MetaBuilder {
             before_receive: Box::new(|| None),
             flag: BlockingFlag::default(),
             spawn: None,
+            close_policy: ProtocolClosePolicy::default(),
+            coalesce: None,
+            low_latency: false,
+            max_message_size: None,
+            decompress: false,
         }
     }
 }
@@ -172,11 +172,14 @@ impl fmt::Display for TryRecvError {
 /// Priority for send
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum Priority {
+    /// Sent ahead of the normal ones, the same as the `quick_*` send methods
     High,
+    /// Normal
     Normal,
 }
 
 impl Priority {
+    /// Is high priority
     #[inline]
     pub fn is_high(self) -> bool {
         match self {
@@ -1,13 +1,17 @@
 use bytes::Bytes;
 use futures::prelude::*;
+use nohash_hasher::{IntMap, IntSet};
 use std::{
+    any::{Any, TypeId},
     collections::HashMap,
+    fmt,
+    net::SocketAddr,
     ops::{Deref, DerefMut},
     sync::{
         atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc,
     },
-    task::Context,
+    task::{Context, Waker},
     time::Duration,
 };
 
@@ -15,17 +19,33 @@ use crate::{
     buffer::{PriorityBuffer, SendResult},
     channel::{mpsc, mpsc::Priority},
     error::SendErrorKind,
+    flow_control::ByteRateLimit,
+    lock::{Mutex, RwLock},
+    metrics::{DropLog, ServiceMetrics, SessionTraffic, Traffic},
     multiaddr::Multiaddr,
     protocol_select::ProtocolInfo,
-    secio::{PublicKey, SecioKeyPair},
-    service::{event::ServiceTask, ServiceControl, SessionType, TargetProtocol, TargetSession},
+    secio::{KeyExporter, PeerId, PublicKey, SecioKeyPair, SecurityParams},
+    service::{
+        config::ServiceConfig, event::ServiceTask, RateLimit, ServiceControl, SessionType,
+        TargetProtocol, TargetSession, TaskHandle,
+    },
     session::SessionEvent,
+    traits::MuxerControl,
+    yamux::Stats as YamuxStats,
     ProtocolId, SessionId,
 };
 
 pub(crate) struct SessionController {
     pub(crate) buffer: PriorityBuffer<SessionEvent>,
     pub(crate) inner: Arc<SessionContext>,
+    // Abort the lifetime check when the session is removed
+    pub(crate) lifetime_task: Option<TaskHandle>,
+    // Sessions of a higher level are distributed first
+    pub(crate) priority_level: u8,
+    // Over the session buffer watermarks, the messages to it are dropped
+    pub(crate) blocked: bool,
+    // Small messages held by the protocols which coalesce them
+    coalesced: IntMap<ProtocolId, Vec<Bytes>>,
 }
 
 impl SessionController {
@@ -36,6 +56,10 @@ impl SessionController {
         Self {
             buffer: PriorityBuffer::new(event_sender),
             inner,
+            lifetime_task: None,
+            priority_level: 0,
+            blocked: false,
+            coalesced: IntMap::default(),
         }
     }
 
@@ -53,11 +77,105 @@ impl SessionController {
         self.push(priority, message_event)
     }
 
+    /// Hold a small message until `flush_coalesced`, return true if it starts a new batch
+    pub(crate) fn coalesce_message(&mut self, proto_id: ProtocolId, data: Bytes) -> bool {
+        self.inner.incr_pending_data_size(data.len());
+        let batch = self.coalesced.entry(proto_id).or_default();
+        batch.push(data);
+        batch.len() == 1
+    }
+
+    /// Hand the held messages of the protocol to the session in order
+    pub(crate) fn flush_coalesced(&mut self, proto_id: ProtocolId) {
+        if let Some(batch) = self.coalesced.remove(&proto_id) {
+            for data in batch {
+                self.buffer
+                    .push_normal(SessionEvent::ProtocolMessage { proto_id, data })
+            }
+        }
+    }
+
+    /// Drop the queued messages of the protocol
+    pub(crate) fn drop_messages(&mut self, proto_id: ProtocolId) {
+        if let Some(batch) = self.coalesced.remove(&proto_id) {
+            for data in batch {
+                self.inner.decr_pending_data_size(data.len());
+            }
+        }
+        let inner = &self.inner;
+        self.buffer.retain(|event| match event {
+            SessionEvent::ProtocolMessage {
+                proto_id: id, data, ..
+            } if *id == proto_id => {
+                inner.decr_pending_data_size(data.len());
+                false
+            }
+            _ => true,
+        })
+    }
+
     pub(crate) fn try_send(&mut self, cx: &mut Context) -> SendResult {
         self.buffer.try_send(cx)
     }
 }
 
+/// Type-erased map of the application data attached to a session, one value per type,
+/// such as a peer score or a group tag
+///
+/// The service doesn't interpret it, the data lives until the session context is dropped.
+#[derive(Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    /// Insert the value, return the previous one of the same type
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|prev| prev.downcast().ok().map(|prev| *prev))
+    }
+
+    /// Get the value of the type
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// Get the mutable value of the type
+    pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    /// Remove the value of the type
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok().map(|value| *value))
+    }
+
+    /// Number of the values
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Whether there is no value
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
+
 /// Session context, contains basic information about the current connection
 #[derive(Clone, Debug)]
 pub struct SessionContext {
@@ -70,16 +188,39 @@ pub struct SessionContext {
     // TODO: use reference?
     /// Remote public key
     pub remote_pubkey: Option<PublicKey>,
+    /// Remote peer id, derived by the peer id codec of the service if set
+    remote_peer_id: Option<PeerId>,
+    /// Local socket address of outbound session, which source port the remote sees us on
+    ///
+    /// None on inbound session or the transport can't provide it, such as tls/memory
+    pub local_address: Option<SocketAddr>,
+    #[cfg_attr(not(feature = "danger-exporter"), allow(dead_code))]
+    exporter: Option<KeyExporter>,
+    security_params: Option<SecurityParams>,
     pub(crate) closed: Arc<AtomicBool>,
     pending_data_size: Arc<AtomicUsize>,
+    dropped_messages: Arc<AtomicUsize>,
+    opened_protocols: Arc<RwLock<IntMap<ProtocolId, String>>>,
+    pub(crate) upload_limit: Arc<ByteRateLimit>,
+    pub(crate) download_limit: Arc<ByteRateLimit>,
+    pub(crate) traffic: Arc<Traffic>,
+    muxer: Arc<RwLock<Option<Arc<dyn MuxerControl>>>>,
+    extensions: Arc<RwLock<Extensions>>,
+    remote_protocols: Option<Arc<Vec<ProtocolInfo>>>,
+    /// Protocols whose inbound reads are paused, and the waker of their sub streams
+    read_paused: Arc<Mutex<IntMap<ProtocolId, Option<Waker>>>>,
 }
 
 impl SessionContext {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         id: SessionId,
         address: Multiaddr,
         ty: SessionType,
         remote_pubkey: Option<PublicKey>,
+        exporter: Option<KeyExporter>,
+        security_params: Option<SecurityParams>,
+        local_address: Option<SocketAddr>,
         closed: Arc<AtomicBool>,
         pending_data_size: Arc<AtomicUsize>,
     ) -> SessionContext {
@@ -87,9 +228,114 @@ impl SessionContext {
             id,
             address,
             ty,
+            remote_peer_id: remote_pubkey.as_ref().map(PublicKey::peer_id),
             remote_pubkey,
+            local_address,
+            exporter,
+            security_params,
             closed,
             pending_data_size,
+            dropped_messages: Arc::new(AtomicUsize::new(0)),
+            opened_protocols: Arc::new(RwLock::new(IntMap::default())),
+            upload_limit: Arc::new(ByteRateLimit::new(None)),
+            download_limit: Arc::new(ByteRateLimit::new(None)),
+            traffic: Arc::new(Traffic::default()),
+            muxer: Arc::new(RwLock::new(None)),
+            extensions: Arc::new(RwLock::new(Extensions::default())),
+            remote_protocols: None,
+            read_paused: Arc::new(Mutex::new(IntMap::default())),
+        }
+    }
+
+    /// Derive the remote peer id by the configured codec instead of the default one
+    pub(crate) fn derive_peer_id(mut self, config: &ServiceConfig) -> Self {
+        self.remote_peer_id = self.remote_pubkey.as_ref().map(|key| config.peer_id(key));
+        self
+    }
+
+    /// Count the traffic of this session into the metrics of the service as well
+    pub(crate) fn export_traffic(mut self, metrics: ServiceMetrics) -> Self {
+        self.traffic = Arc::new(Traffic::new(metrics));
+        self
+    }
+
+    pub(crate) fn remote_protocols_exchanged(
+        mut self,
+        protocols: Option<Vec<ProtocolInfo>>,
+    ) -> Self {
+        self.remote_protocols = protocols.map(Arc::new);
+        self
+    }
+
+    /// Protocols supported by the remote, known at open time, so the protocols to open can be
+    /// decided before any of them is opened
+    ///
+    /// None if `ServiceBuilder::exchange_protocols` isn't enabled, or the connection brings
+    /// its own muxer, such as quic
+    pub fn remote_protocols(&self) -> Option<&[ProtocolInfo]> {
+        self.remote_protocols
+            .as_ref()
+            .map(|protocols| protocols.as_slice())
+    }
+
+    /// Bytes of the protocol messages sent and received on this session
+    pub fn traffic(&self) -> SessionTraffic {
+        self.traffic.snapshot()
+    }
+
+    pub(crate) fn set_muxer(&self, muxer: Arc<dyn MuxerControl>) {
+        *self.muxer.write() = Some(muxer);
+    }
+
+    /// Flow control state of the yamux session: the open substreams, and the send/receive
+    /// windows which move as the substreams consume and return the window credits
+    ///
+    /// A zero send window with an empty write buffer means the remote doesn't return the
+    /// credits, while a full write buffer with free window is the application backpressure.
+    ///
+    /// Return None if the session uses a custom multiplexer.
+    pub fn yamux_stats(&self) -> Option<YamuxStats> {
+        self.muxer
+            .read()
+            .as_ref()
+            .and_then(|muxer| muxer.yamux_stats())
+    }
+
+    /// Bandwidth limit of the session
+    pub fn rate_limit(&self) -> RateLimit {
+        RateLimit {
+            upload: self.upload_limit.rate(),
+            download: self.download_limit.rate(),
+        }
+    }
+
+    pub(crate) fn set_rate_limit(&self, limit: RateLimit) {
+        self.upload_limit.set_rate(limit.upload);
+        self.download_limit.set_rate(limit.download);
+    }
+
+    pub(crate) fn set_read_paused(&self, proto_id: ProtocolId, paused: bool) {
+        let mut read_paused = self.read_paused.lock();
+        if paused {
+            read_paused.entry(proto_id).or_insert(None);
+        } else if let Some(Some(waker)) = read_paused.remove(&proto_id) {
+            waker.wake();
+        }
+    }
+
+    /// Whether reading the inbound messages of the protocol is paused
+    pub fn read_paused(&self, proto_id: ProtocolId) -> bool {
+        self.read_paused.lock().contains_key(&proto_id)
+    }
+
+    /// Return true if paused, and the sub stream will be woken up on resume
+    pub(crate) fn poll_read_paused(&self, proto_id: ProtocolId, cx: &mut Context) -> bool {
+        match self.read_paused.lock().get_mut(&proto_id) {
+            Some(waker) => {
+                *waker = Some(cx.waker().clone());
+                true
+            }
+            None => false,
         }
     }
 
@@ -113,6 +359,146 @@ impl SessionContext {
     pub fn pending_data_size(&self) -> usize {
         self.pending_data_size.load(Ordering::Acquire)
     }
+
+    pub(crate) fn incr_dropped_messages(&self) {
+        self.dropped_messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Export keying material of the secure session, derived by HKDF from the handshake
+    /// secret and the caller's label, both sides of the session get the same value
+    ///
+    /// Danger: this is not the session key, but whoever holds the output can act as
+    /// this session on the protocol using it. Use a distinct label for each purpose and
+    /// keep the output secret.
+    ///
+    /// Return None if the session has no secio upgrade, e.g. no key pair or custom security.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is longer than 255 times the hash length of the negotiated digest.
+    #[cfg(feature = "danger-exporter")]
+    pub fn export_keying_material(&self, label: &[u8], len: usize) -> Option<Vec<u8>> {
+        self.exporter.as_ref().map(|exporter| {
+            let mut out = vec![0; len];
+            exporter.export(label, &mut out);
+            out
+        })
+    }
+
+    /// Cipher and digest negotiated by the secure session, for auditing the security of
+    /// the live sessions
+    ///
+    /// Return None if the session has no secio upgrade, e.g. no key pair or custom security
+    /// which doesn't report them.
+    pub fn security_params(&self) -> Option<SecurityParams> {
+        self.security_params
+    }
+
+    /// Number of messages dropped by lossy send or the session buffer watermarks because
+    /// the session was blocked
+    pub fn dropped_messages(&self) -> usize {
+        self.dropped_messages.load(Ordering::Relaxed)
+    }
+
+    // Update when protocol stream open/close on session, None means close
+    pub(crate) fn set_protocol_open(&self, proto_id: ProtocolId, version: Option<String>) {
+        let mut opened = self.opened_protocols.write();
+        match version {
+            Some(version) => {
+                opened.insert(proto_id, version);
+            }
+            None => {
+                opened.remove(&proto_id);
+            }
+        }
+    }
+
+    pub(crate) fn clear_opened_protocols(&self) {
+        self.opened_protocols.write().clear();
+    }
+
+    /// Whether the protocol is open on this session
+    pub fn is_protocol_open(&self, proto_id: ProtocolId) -> bool {
+        !self.closed() && self.opened_protocols.read().contains_key(&proto_id)
+    }
+
+    pub(crate) fn opened_protocol_count(&self) -> usize {
+        self.opened_protocols.read().len()
+    }
+
+    /// The negotiated version of the protocol on this session, None if it is not open
+    pub fn protocol_version(&self, proto_id: ProtocolId) -> Option<String> {
+        if self.closed() {
+            return None;
+        }
+        self.opened_protocols.read().get(&proto_id).cloned()
+    }
+
+    /// Attach the application data to this session, return the previous value of the same type
+    pub fn insert_extension<T: Send + Sync + 'static>(&self, value: T) -> Option<T> {
+        self.extensions.write().insert(value)
+    }
+
+    /// A copy of the application data of the type attached to this session
+    pub fn extension<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.extensions.read().get::<T>().cloned()
+    }
+
+    /// Detach the application data of the type from this session
+    pub fn remove_extension<T: Send + Sync + 'static>(&self) -> Option<T> {
+        self.extensions.write().remove()
+    }
+
+    /// Read or update the application data attached to this session under the lock
+    pub fn with_extensions<R>(&self, f: impl FnOnce(&mut Extensions) -> R) -> R {
+        f(&mut self.extensions.write())
+    }
+
+    /// A plain copy of the session info, serializable with the `serde` feature
+    pub fn to_snapshot(&self) -> SessionSnapshot {
+        let mut protocols = self
+            .opened_protocols
+            .read()
+            .iter()
+            .map(|(id, version)| (id.value(), version.clone()))
+            .collect::<Vec<_>>();
+        protocols.sort();
+        SessionSnapshot {
+            id: self.id.value(),
+            address: self.address.to_string(),
+            peer_id: self.remote_peer_id.as_ref().map(PeerId::to_base58),
+            inbound: self.ty.is_inbound(),
+            local_address: self.local_address,
+            closed: self.closed(),
+            pending_data_size: self.pending_data_size(),
+            dropped_messages: self.dropped_messages(),
+            protocols,
+        }
+    }
+}
+
+/// Snapshot of a session context, see `SessionContext::to_snapshot`
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SessionSnapshot {
+    /// Session id
+    pub id: usize,
+    /// Remote address
+    pub address: String,
+    /// Remote peer id in base58, None if the session is not encrypted
+    pub peer_id: Option<String>,
+    /// Whether the session is opened by remote
+    pub inbound: bool,
+    /// Local socket address of outbound session
+    pub local_address: Option<SocketAddr>,
+    /// Whether the session is closed
+    pub closed: bool,
+    /// Bytes of the messages wait to be sent
+    pub pending_data_size: usize,
+    /// Count of the messages dropped on this session
+    pub dropped_messages: usize,
+    /// Opened protocol ids and their negotiated versions, ordered by id
+    pub protocols: Vec<(usize, String)>,
 }
 
 type Result = std::result::Result<(), SendErrorKind>;
@@ -131,11 +517,21 @@ impl ServiceContext {
     pub(crate) fn new(
         task_sender: mpsc::Sender<ServiceTask>,
         proto_infos: HashMap<ProtocolId, ProtocolInfo>,
+        low_latency: IntSet<ProtocolId>,
+        flush_on_close: IntSet<ProtocolId>,
         key_pair: Option<SecioKeyPair>,
         closed: Arc<AtomicBool>,
+        drop_log: Arc<DropLog>,
     ) -> Self {
         ServiceContext {
-            inner: ServiceControl::new(task_sender, proto_infos, closed),
+            inner: ServiceControl::new(
+                task_sender,
+                proto_infos,
+                low_latency,
+                flush_on_close,
+                closed,
+                drop_log,
+            ),
             key_pair,
             listens: Vec::new(),
         }
@@ -153,12 +549,108 @@ impl ServiceContext {
         self.inner.dial(address, target)
     }
 
+    /// Initiate a connection request to address with a weight, the higher weight dials
+    /// are started first when the dial concurrency is limited
+    #[inline]
+    pub fn dial_with_priority(
+        &self,
+        address: Multiaddr,
+        target: TargetProtocol,
+        weight: u8,
+    ) -> Result {
+        self.inner.dial_with_priority(address, target, weight)
+    }
+
+    /// Dial the addresses of the peer known by the peer store one by one
+    #[inline]
+    pub fn dial_peer(&self, peer_id: PeerId, target: TargetProtocol) -> Result {
+        self.inner.dial_peer(peer_id, target)
+    }
+
+    /// Dial the addresses of the peer concurrently, keep the first one to connect
+    #[inline]
+    pub fn dial_addrs(
+        &self,
+        peer_id: PeerId,
+        addrs: Vec<Multiaddr>,
+        target: TargetProtocol,
+    ) -> Result {
+        self.inner.dial_addrs(peer_id, addrs, target)
+    }
+
+    /// Dial the address and keep it connected, re-dial it when the session closes
+    #[inline]
+    pub fn add_persistent_peer(&self, address: Multiaddr, target: TargetProtocol) -> Result {
+        self.inner.add_persistent_peer(address, target)
+    }
+
+    /// Stop re-dialing the persistent address
+    #[inline]
+    pub fn remove_persistent_peer(&self, address: Multiaddr) -> Result {
+        self.inner.remove_persistent_peer(address)
+    }
+
+    /// Initiate a connection request to address, the remote must match the expected peer id
+    #[inline]
+    pub fn dial_with_peer_id(
+        &self,
+        address: Multiaddr,
+        peer_id: PeerId,
+        target: TargetProtocol,
+    ) -> Result {
+        self.inner.dial_with_peer_id(address, peer_id, target)
+    }
+
     /// Disconnect a connection
     #[inline]
     pub fn disconnect(&self, session_id: SessionId) -> Result {
         self.inner.disconnect(session_id)
     }
 
+    /// Disconnect the session of the peer, do nothing if the peer isn't connected
+    #[inline]
+    pub fn disconnect_by_peer_id(&self, peer_id: PeerId) -> Result {
+        self.inner.disconnect_by_peer_id(peer_id)
+    }
+
+    /// Report our address observed by the remote of the session, the address reported by
+    /// the most sessions is surfaced by `ServiceEvent::ExternalAddressChanged`
+    #[inline]
+    pub fn report_observed_address(&self, session_id: SessionId, address: Multiaddr) -> Result {
+        self.inner.report_observed_address(session_id, address)
+    }
+
+    /// Ban the peer for the duration
+    #[inline]
+    pub fn ban_peer(&self, peer_id: PeerId, duration: Duration) -> Result {
+        self.inner.ban_peer(peer_id, duration)
+    }
+
+    /// Lift the ban of the peer
+    #[inline]
+    pub fn unban_peer(&self, peer_id: PeerId) -> Result {
+        self.inner.unban_peer(peer_id)
+    }
+
+    /// Change the bandwidth limit of the session
+    #[inline]
+    pub fn set_rate_limit(&self, session_id: SessionId, limit: RateLimit) -> Result {
+        self.inner.set_rate_limit(session_id, limit)
+    }
+
+    /// Stop reading the inbound messages of the protocol on the session, the remote stalls
+    /// once the stream window is used up
+    #[inline]
+    pub fn pause_protocol_read(&self, session_id: SessionId, proto_id: ProtocolId) -> Result {
+        self.inner.pause_protocol_read(session_id, proto_id)
+    }
+
+    /// Resume reading the inbound messages of the protocol on the session
+    #[inline]
+    pub fn resume_protocol_read(&self, session_id: SessionId, proto_id: ProtocolId) -> Result {
+        self.inner.resume_protocol_read(session_id, proto_id)
+    }
+
     /// Send message
     #[inline]
     pub fn send_message_to(
@@ -181,6 +673,32 @@ impl ServiceContext {
         self.inner.quick_send_message_to(session_id, proto_id, data)
     }
 
+    /// Send message only if the protocol is open on the session,
+    /// otherwise return `SendErrorKind::ProtocolNotOpen`
+    #[inline]
+    pub fn checked_send_message_to(
+        &self,
+        session: &SessionContext,
+        proto_id: ProtocolId,
+        data: Bytes,
+    ) -> Result {
+        self.inner.checked_send_message_to(session, proto_id, data)
+    }
+
+    /// Send message unless the session pending data size already reaches `max_pending`,
+    /// return whether the message was sent
+    #[inline]
+    pub fn send_message_lossy(
+        &self,
+        session: &SessionContext,
+        proto_id: ProtocolId,
+        data: Bytes,
+        max_pending: usize,
+    ) -> std::result::Result<bool, SendErrorKind> {
+        self.inner
+            .send_message_lossy(session, proto_id, data, max_pending)
+    }
+
     /// Send data to the specified protocol for the specified sessions.
     #[inline]
     pub fn filter_broadcast(
@@ -192,6 +710,18 @@ impl ServiceContext {
         self.inner.filter_broadcast(session_ids, proto_id, data)
     }
 
+    /// Send data to the sessions which negotiated a version of the protocol not lower than
+    /// `min_version`
+    #[inline]
+    pub fn broadcast_version(
+        &self,
+        proto_id: ProtocolId,
+        min_version: &str,
+        data: Bytes,
+    ) -> Result {
+        self.inner.broadcast_version(proto_id, min_version, data)
+    }
+
     /// Send data to the specified protocol for the specified sessions on quick channel.
     #[inline]
     pub fn quick_filter_broadcast(
@@ -213,6 +743,17 @@ impl ServiceContext {
         self.inner.future_task(task)
     }
 
+    /// Send a future task which can be aborted by the returned handle
+    ///
+    /// Drop the handle will also abort the task
+    #[inline]
+    pub fn spawn_cancellable<T>(&self, task: T) -> std::result::Result<TaskHandle, SendErrorKind>
+    where
+        T: Future<Output = ()> + 'static + Send,
+    {
+        self.inner.spawn_cancellable(task)
+    }
+
     /// Try open a protocol
     ///
     /// If the protocol has been open, do nothing
@@ -237,6 +778,18 @@ impl ServiceContext {
         self.inner.close_protocol(session_id, proto_id)
     }
 
+    /// Abort a protocol abnormally, see `ServiceControl::reset_protocol`
+    #[inline]
+    pub fn reset_protocol(&self, session_id: SessionId, proto_id: ProtocolId) -> Result {
+        self.inner.reset_protocol(session_id, proto_id)
+    }
+
+    /// Cancel the negotiation of a protocol, see `ServiceControl::cancel_protocol_open`
+    #[inline]
+    pub fn cancel_protocol_open(&self, session_id: SessionId, proto_id: ProtocolId) -> Result {
+        self.inner.cancel_protocol_open(session_id, proto_id)
+    }
+
     /// Get the internal channel sender side handle
     #[inline]
     pub fn control(&self) -> &ServiceControl {
@@ -245,8 +798,8 @@ impl ServiceContext {
 
     /// Get service protocol message, Map(ID, Name), but can't modify
     #[inline]
-    pub fn protocols(&self) -> &Arc<HashMap<ProtocolId, ProtocolInfo>> {
-        &self.inner.proto_infos
+    pub fn protocols(&self) -> Arc<HashMap<ProtocolId, ProtocolInfo>> {
+        self.inner.protocols()
     }
 
     /// Get the key pair of self
@@ -305,6 +858,21 @@ impl ServiceContext {
             .remove_session_notify(session_id, proto_id, token)
     }
 
+    /// Fire the service notify of the token once now, apart from its timer
+    pub fn service_notify_now(&self, proto_id: ProtocolId, token: u64) -> Result {
+        self.inner.service_notify_now(proto_id, token)
+    }
+
+    /// Fire the session notify of the token once now, apart from its timer
+    pub fn session_notify_now(
+        &self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+        token: u64,
+    ) -> Result {
+        self.inner.session_notify_now(session_id, proto_id, token)
+    }
+
     /// Close service.
     ///
     /// Order:
@@ -345,6 +913,13 @@ impl ProtocolContext {
         }
     }
 
+    /// Fire the `notify` of the service level handle of this protocol once now,
+    /// the token doesn't need to be set, and its timer is not affected
+    #[inline]
+    pub fn notify_now(&self, token: u64) -> Result {
+        self.inner.service_notify_now(self.proto_id, token)
+    }
+
     #[inline]
     pub(crate) fn as_mut<'a, 'b: 'a>(
         &'b mut self,
@@ -375,6 +950,19 @@ impl<'a> ProtocolContextMutRef<'a> {
         self.inner.send_message_to(self.session.id, proto_id, data)
     }
 
+    /// Send message to current protocol current session unless the session is blocked,
+    /// return whether the message was sent
+    #[inline]
+    pub fn send_message_lossy(
+        &self,
+        data: Bytes,
+        max_pending: usize,
+    ) -> std::result::Result<bool, SendErrorKind> {
+        let proto_id = self.proto_id();
+        self.inner
+            .send_message_lossy(self.session, proto_id, data, max_pending)
+    }
+
     /// Send message to current protocol current session on quick channel
     #[inline]
     pub fn quick_send_message(&self, data: Bytes) -> Result {
@@ -383,11 +971,49 @@ impl<'a> ProtocolContextMutRef<'a> {
             .quick_send_message_to(self.session.id, proto_id, data)
     }
 
+    /// Reply to the session and protocol which the current callback fired for
+    #[inline]
+    pub fn reply(&self, data: Bytes) -> Result {
+        self.reply_with_priority(data, Priority::Normal)
+    }
+
+    /// Reply to the session and protocol which the current callback fired for with the priority
+    #[inline]
+    pub fn reply_with_priority(&self, data: Bytes, priority: Priority) -> Result {
+        match priority {
+            Priority::High => self.quick_send_message(data),
+            Priority::Normal => self.send_message(data),
+        }
+    }
+
+    /// Abort the protocol of current session abnormally, the remote sees a reset
+    /// instead of a graceful close
+    #[inline]
+    pub fn reset(&self, proto_id: ProtocolId) -> Result {
+        self.inner.reset_protocol(self.session.id, proto_id)
+    }
+
+    /// Cancel the in-flight negotiation of the protocol opened on current session, such as
+    /// the one the remote never responds to, see `ServiceControl::cancel_protocol_open`
+    #[inline]
+    pub fn cancel_open(&self, proto_id: ProtocolId) -> Result {
+        self.inner.cancel_protocol_open(self.session.id, proto_id)
+    }
+
     /// Protocol id
     #[inline]
     pub fn proto_id(&self) -> ProtocolId {
         self.inner.proto_id
     }
+
+    /// Reborrow for a shorter lifetime
+    #[inline]
+    pub(crate) fn reborrow(&mut self) -> ProtocolContextMutRef<'_> {
+        ProtocolContextMutRef {
+            inner: self.inner,
+            session: self.session,
+        }
+    }
 }
 
 impl Deref for ProtocolContext {
@@ -421,3 +1047,44 @@ impl<'a> DerefMut for ProtocolContextMutRef<'a> {
         &mut self.inner
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::SessionContext;
+    use crate::{secio::SecioKeyPair, service::SessionType};
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc,
+    };
+
+    #[test]
+    fn test_session_snapshot() {
+        let key = SecioKeyPair::secp256k1_generated();
+        let context = SessionContext::new(
+            1.into(),
+            "/ip4/127.0.0.1/tcp/1337".parse().unwrap(),
+            SessionType::Inbound,
+            Some(key.public_key()),
+            None,
+            None,
+            None,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicUsize::new(0)),
+        );
+        context.set_protocol_open(2.into(), Some("2.0".to_owned()));
+        context.set_protocol_open(1.into(), Some("1.0".to_owned()));
+        context.incr_pending_data_size(10);
+
+        let snapshot = context.to_snapshot();
+        assert_eq!(snapshot.id, 1);
+        assert_eq!(snapshot.address, "/ip4/127.0.0.1/tcp/1337");
+        assert_eq!(snapshot.peer_id, Some(key.peer_id().to_base58()));
+        assert!(snapshot.inbound);
+        assert!(!snapshot.closed);
+        assert_eq!(snapshot.pending_data_size, 10);
+        assert_eq!(
+            snapshot.protocols,
+            vec![(1, "1.0".to_owned()), (2, "2.0".to_owned())]
+        );
+    }
+}
@@ -1,6 +1,12 @@
-use crate::{secio::error::SecioError, SessionId};
+use crate::{
+    secio::{error::SecioError, PeerId},
+    ProtocolId, SessionId,
+};
 use multiaddr::Multiaddr;
-use std::io::Error as IOError;
+use std::{
+    io::{Error as IOError, ErrorKind},
+    net::SocketAddr,
+};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -9,16 +15,47 @@ pub enum TransportErrorKind {
     /// IO error
     #[error("transport io error: `{0:?}`")]
     Io(#[from] IOError),
+    /// Tcp connection can't be established, such as refused, unreachable or connect timeout
+    #[error("transport connect error: `{0:?}`")]
+    Connect(IOError),
     /// Protocol not support
     #[error("multiaddr `{0:?}` is not supported")]
     NotSupported(Multiaddr),
     /// Dns resolver error
     #[error("can not resolve `{0:?}`, io error: `{1:?}`")]
     DnsResolverError(Multiaddr, IOError),
+    /// The local bind address and the dialed target aren't of the same ip family
+    #[error("bind address `{0}` mismatches the family of the target `{1}`")]
+    BindFamilyMismatch(SocketAddr, SocketAddr),
     /// Tls error
     #[error("tls setting error: `{0:?}`")]
     #[cfg(feature = "tls")]
     TlsError(String),
+    /// Quic endpoint or certificate error
+    #[error("quic error: `{0}`")]
+    #[cfg(feature = "quic")]
+    QuicError(String),
+    /// Websocket handshake failed, such as the remote is not a websocket server
+    #[error("websocket handshake error: `{0}`")]
+    #[cfg(feature = "ws")]
+    WsHandshake(String),
+}
+
+impl TransportErrorKind {
+    /// Whether the dial may succeed if it's tried again, such as the connection is refused
+    /// or timed out
+    pub fn is_transient(&self) -> bool {
+        match self {
+            TransportErrorKind::Io(err) | TransportErrorKind::Connect(err) => matches!(
+                err.kind(),
+                ErrorKind::ConnectionRefused
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::TimedOut
+            ),
+            _ => false,
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -47,9 +84,39 @@ pub enum DialerErrorKind {
     /// Handshake error
     #[error("handshake error: `{0:?}`")]
     HandshakeError(HandshakeErrorKind),
+    /// Connected, but the handshake did not finish in time
+    #[error("handshake timeout: `{0}`")]
+    HandshakeTimeout(String),
     /// Transport error
     #[error("transport error: `{0:?}`")]
     TransportError(TransportErrorKind),
+    /// Connection can't be established, such as refused, unreachable or connect timeout
+    #[error("connect failed: `{0:?}`")]
+    ConnectFailed(IOError),
+    /// Vetoed by the connection gater
+    #[error("vetoed by the connection gater")]
+    Gated,
+    /// The peer is banned by `ServiceControl::ban_peer`
+    #[error("peer `{0:?}` is banned")]
+    Banned(PeerId),
+}
+
+impl From<TransportErrorKind> for DialerErrorKind {
+    fn from(error: TransportErrorKind) -> Self {
+        match error {
+            TransportErrorKind::Connect(error) => DialerErrorKind::ConnectFailed(error),
+            error => DialerErrorKind::TransportError(error),
+        }
+    }
+}
+
+impl From<HandshakeErrorKind> for DialerErrorKind {
+    fn from(error: HandshakeErrorKind) -> Self {
+        match error {
+            HandshakeErrorKind::Timeout(error) => DialerErrorKind::HandshakeTimeout(error),
+            error => DialerErrorKind::HandshakeError(error),
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -61,6 +128,12 @@ pub enum HandshakeErrorKind {
     /// Secio error
     #[error("secio error: `{0:?}`")]
     SecioError(SecioError),
+    /// Custom security upgrade error
+    #[error("upgrade error: `{0:?}`")]
+    Upgrade(IOError),
+    /// The protocols exchange failed, such as the remote doesn't enable it
+    #[error("protocol exchange error: `{0:?}`")]
+    ProtocolExchange(IOError),
 }
 
 #[derive(Error, Debug)]
@@ -75,6 +148,15 @@ pub enum ListenErrorKind {
     /// Transport error
     #[error("transport error: `{0:?}`")]
     TransportError(TransportErrorKind),
+    /// The number of listeners reached the limit
+    #[error("too many listeners, limit: `{0}`")]
+    TooManyListeners(usize),
+    /// The inbound session is vetoed by the connection gater
+    #[error("vetoed by the connection gater")]
+    Gated,
+    /// The inbound session is of a peer banned by `ServiceControl::ban_peer`
+    #[error("peer `{0:?}` is banned")]
+    Banned(PeerId),
 }
 
 #[derive(Error, Debug)]
@@ -86,4 +168,96 @@ pub enum SendErrorKind {
     /// The operation needs to block to complete, but the blocking operation was requested to not occur.
     #[error("would block")]
     WouldBlock,
+    /// The protocol is not open on the session
+    #[error("protocol not open")]
+    ProtocolNotOpen,
+}
+
+#[derive(Error, Debug)]
+/// Error of `ServiceControl::dial_with_result`
+pub enum DialError {
+    /// The dial task could not be sent to the service
+    #[error("send dial task error: `{0}`")]
+    Send(#[from] SendErrorKind),
+    /// The dial failed, the same error is also reported to the service handle
+    #[error("dial failed: {0}")]
+    Failed(DialFailure),
+    /// The service shut down before the dial finished
+    #[error("service shut down")]
+    Shutdown,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+/// Why a dial of `ServiceControl::dial_with_result` failed,
+/// the cloneable summary of the error reported to the service handle
+pub enum DialFailure {
+    /// IO error
+    #[error("dialler io error: `{0:?}`")]
+    IoError(ErrorKind),
+    /// When dial remote, peer id does not match
+    #[error("peer id not match")]
+    PeerIdNotMatch,
+    /// Connected to the connected peer
+    #[error("repeated connection, sessio id: `{0:?}`")]
+    RepeatedConnection(SessionId),
+    /// Handshake error
+    #[error("handshake error: `{0}`")]
+    HandshakeError(String),
+    /// Connected, but the handshake did not finish in time
+    #[error("handshake timeout: `{0}`")]
+    HandshakeTimeout(String),
+    /// Transport error
+    #[error("transport error: `{0}`")]
+    TransportError(String),
+    /// Connection can't be established, such as refused, unreachable or connect timeout
+    #[error("connect failed: `{0:?}`")]
+    ConnectFailed(ErrorKind),
+    /// Vetoed by the connection gater
+    #[error("vetoed by the connection gater")]
+    Gated,
+    /// The peer is banned by `ServiceControl::ban_peer`
+    #[error("peer `{0:?}` is banned")]
+    Banned(PeerId),
+    /// The outbound connection limit is reached
+    #[error("outbound connection limit `{0}` reached")]
+    ConnectionLimit(usize),
+}
+
+impl From<&DialerErrorKind> for DialFailure {
+    fn from(error: &DialerErrorKind) -> Self {
+        match error {
+            DialerErrorKind::IoError(error) => DialFailure::IoError(error.kind()),
+            DialerErrorKind::PeerIdNotMatch => DialFailure::PeerIdNotMatch,
+            DialerErrorKind::RepeatedConnection(id) => DialFailure::RepeatedConnection(*id),
+            DialerErrorKind::HandshakeError(error) => {
+                DialFailure::HandshakeError(error.to_string())
+            }
+            DialerErrorKind::HandshakeTimeout(error) => {
+                DialFailure::HandshakeTimeout(error.clone())
+            }
+            DialerErrorKind::TransportError(error) => {
+                DialFailure::TransportError(error.to_string())
+            }
+            DialerErrorKind::ConnectFailed(error) => DialFailure::ConnectFailed(error.kind()),
+            DialerErrorKind::Gated => DialFailure::Gated,
+            DialerErrorKind::Banned(peer_id) => DialFailure::Banned(peer_id.clone()),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+/// Error of `ServiceControl::register_protocol`
+pub enum RegisterError {
+    /// The register task could not be sent to the service
+    #[error("send register task error: `{0}`")]
+    Send(#[from] SendErrorKind),
+    /// A protocol with the same id is already registered
+    #[error("protocol id `{0}` already registered")]
+    DuplicateId(ProtocolId),
+    /// A protocol with the same name is already registered
+    #[error("protocol name `{0}` already registered")]
+    DuplicateName(String),
+    /// The service shut down before the protocol is registered
+    #[error("service shut down")]
+    Shutdown,
 }
@@ -0,0 +1,301 @@
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use crate::{lock::Mutex, service::ServiceControl};
+
+/// Bytes held by the buffers of all substreams, once over the limit, the substreams
+/// stop reading from the network until enough buffered data is consumed
+pub(crate) struct MemoryBudget {
+    used: AtomicUsize,
+    limit: usize,
+    exceeded: AtomicBool,
+    /// Substreams waiting for the budget to go below the limit
+    wakers: Mutex<Vec<Waker>>,
+    control: ServiceControl,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize, control: ServiceControl) -> Self {
+        MemoryBudget {
+            used: AtomicUsize::new(0),
+            limit,
+            exceeded: AtomicBool::new(false),
+            wakers: Mutex::new(Vec::new()),
+            control,
+        }
+    }
+
+    /// Hold some bytes, report the memory pressure to the service when it goes over the limit
+    pub fn acquire(&self, size: usize) {
+        let used = self.used.fetch_add(size, Ordering::SeqCst) + size;
+        if used > self.limit && !self.exceeded.swap(true, Ordering::SeqCst) {
+            self.control.memory_pressure(used, self.limit);
+        }
+    }
+
+    /// Release some bytes, wake up the waiting substreams when it goes below the limit
+    pub fn release(&self, size: usize) {
+        let used = self.used.fetch_sub(size, Ordering::SeqCst) - size;
+        if used <= self.limit && self.exceeded.swap(false, Ordering::SeqCst) {
+            for waker in self.wakers.lock().drain(..) {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Ready if the budget is not over the limit, otherwise wait for release
+    pub fn poll_available(&self, cx: &mut Context) -> Poll<()> {
+        if self.used() <= self.limit {
+            return Poll::Ready(());
+        }
+        {
+            let mut wakers = self.wakers.lock();
+            if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+        }
+        // released between the check and the registration
+        if self.used() <= self.limit {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+}
+
+/// Messages received by all substreams of a session in each second, once over the rate,
+/// the substreams stop reading from the network until the next second
+pub(crate) struct RecvRateLimit {
+    rate: u32,
+    /// Start of the current second and the messages received in it
+    window: Mutex<(Instant, u32)>,
+}
+
+impl RecvRateLimit {
+    pub fn new(rate: u32) -> Self {
+        RecvRateLimit {
+            rate,
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Ok if a message can be received now, otherwise the time to wait for the next second
+    pub fn check(&self) -> Result<(), Duration> {
+        let mut window = self.window.lock();
+        let elapsed = window.0.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            *window = (Instant::now(), 0);
+            return Ok(());
+        }
+        if window.1 < self.rate {
+            Ok(())
+        } else {
+            Err(Duration::from_secs(1) - elapsed)
+        }
+    }
+
+    /// Count a received message
+    pub fn record(&self) {
+        self.window.lock().1 += 1;
+    }
+}
+
+/// Token bucket of the bytes sent or received by a session, holds one second of the rate.
+/// A message larger than the tokens left is let through and paid back by a longer wait,
+/// so the average stays at the rate
+///
+/// The bucket is only created once a limit is set, so an unlimited session never reads
+/// the clock, which panics on wasm
+#[derive(Debug)]
+pub(crate) struct ByteRateLimit {
+    /// Bytes per second, 0 is no limit
+    rate: AtomicU64,
+    /// Last refill time and the tokens left, negative while in debt, none while no limit
+    bucket: Mutex<Option<(Instant, f64)>>,
+}
+
+impl ByteRateLimit {
+    pub fn new(rate: Option<u64>) -> Self {
+        let limit = ByteRateLimit {
+            rate: AtomicU64::new(0),
+            bucket: Mutex::new(None),
+        };
+        limit.set_rate(rate);
+        limit
+    }
+
+    pub fn rate(&self) -> Option<u64> {
+        Some(self.rate.load(Ordering::SeqCst)).filter(|rate| *rate != 0)
+    }
+
+    /// Change the rate at runtime, the tokens left are kept up to the new burst
+    /// and so is the debt, changing the limit doesn't reset the throttling
+    pub fn set_rate(&self, rate: Option<u64>) {
+        let mut bucket = self.bucket.lock();
+        let rate = match rate.filter(|rate| *rate != 0) {
+            Some(rate) => rate,
+            None => {
+                self.rate.store(0, Ordering::SeqCst);
+                *bucket = None;
+                return;
+            }
+        };
+        let now = Instant::now();
+        let tokens = match (*bucket, self.rate()) {
+            (Some((last, tokens)), Some(old)) => {
+                tokens + now.saturating_duration_since(last).as_secs_f64() * old as f64
+            }
+            // Nothing is taken while there is no limit, it starts full
+            _ => f64::INFINITY,
+        };
+        self.rate.store(rate, Ordering::SeqCst);
+        *bucket = Some((now, tokens.min(rate as f64)));
+    }
+
+    /// Ok if bytes can be transferred now, otherwise the time to wait for the refill
+    pub fn check(&self) -> Result<(), Duration> {
+        let rate = match self.rate() {
+            Some(rate) => rate as f64,
+            None => return Ok(()),
+        };
+        let mut bucket = self.bucket.lock();
+        let (last, tokens) = match bucket.as_mut() {
+            Some(bucket) => bucket,
+            None => return Ok(()),
+        };
+        let now = Instant::now();
+        let refill = now.saturating_duration_since(*last).as_secs_f64() * rate;
+        *last = now;
+        *tokens = (*tokens + refill).min(rate);
+        if *tokens >= 0.0 {
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(-*tokens / rate))
+        }
+    }
+
+    /// Take the tokens of the transferred bytes
+    pub fn record(&self, size: usize) {
+        if let Some((_, tokens)) = self.bucket.lock().as_mut() {
+            *tokens -= size as f64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ByteRateLimit, MemoryBudget, RecvRateLimit};
+    use crate::{
+        channel::mpsc,
+        metrics::DropLog,
+        service::{event::ServiceTask, ServiceControl},
+    };
+    use futures::{task::noop_waker, FutureExt, StreamExt};
+    use std::{
+        sync::{atomic::AtomicBool, Arc},
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    #[test]
+    fn test_memory_budget() {
+        let (sender, mut receiver) = mpsc::channel(8);
+        let control = ServiceControl::new(
+            sender,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(DropLog::new(0)),
+        );
+        let budget = MemoryBudget::new(10, control);
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        budget.acquire(10);
+        assert_eq!(budget.poll_available(&mut cx), Poll::Ready(()));
+        assert!(receiver.next().now_or_never().is_none());
+
+        // only the first time over the limit is reported
+        budget.acquire(5);
+        budget.acquire(5);
+        assert_eq!(budget.used(), 20);
+        assert_eq!(budget.poll_available(&mut cx), Poll::Pending);
+        assert!(matches!(
+            receiver.next().now_or_never(),
+            Some(Some((
+                _,
+                ServiceTask::MemoryPressure {
+                    used: 15,
+                    limit: 10
+                }
+            )))
+        ));
+        assert!(receiver.next().now_or_never().is_none());
+
+        budget.release(10);
+        assert_eq!(budget.poll_available(&mut cx), Poll::Ready(()));
+        budget.acquire(1);
+        assert!(receiver.next().now_or_never().is_some());
+    }
+
+    #[test]
+    fn test_recv_rate_limit() {
+        let limit = RecvRateLimit::new(2);
+
+        assert!(limit.check().is_ok());
+        limit.record();
+        limit.record();
+        let wait = limit.check().unwrap_err();
+        assert!(wait <= Duration::from_secs(1));
+
+        // a new second
+        std::thread::sleep(wait);
+        assert!(limit.check().is_ok());
+    }
+
+    #[test]
+    fn test_byte_rate_limit() {
+        let limit = ByteRateLimit::new(None);
+        limit.record(usize::MAX);
+        assert!(limit.check().is_ok());
+        // no clock is read without a limit
+        assert!(limit.bucket.lock().is_none());
+
+        limit.set_rate(Some(1000));
+        // one second of burst, then in debt for the large message
+        limit.record(600);
+        assert!(limit.check().is_ok());
+        limit.record(900);
+        let wait = limit.check().unwrap_err();
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+
+        // the debt is kept by a new limit
+        limit.set_rate(Some(2000));
+        let wait = limit.check().unwrap_err();
+        assert!(wait <= Duration::from_millis(250));
+
+        std::thread::sleep(wait);
+        assert!(limit.check().is_ok());
+
+        // the tokens are capped at the new burst
+        limit.set_rate(Some(1000));
+        std::thread::sleep(Duration::from_millis(200));
+        limit.set_rate(Some(100));
+        limit.record(150);
+        assert!(limit.check().is_err());
+
+        limit.set_rate(None);
+        assert_eq!(limit.rate(), None);
+        assert!(limit.bucket.lock().is_none());
+        limit.record(usize::MAX);
+        assert!(limit.check().is_ok());
+    }
+}
@@ -70,6 +70,18 @@
 //! 6. Run [`Service`] just like other stream, maybe keep a [`Control`] on some place which want to
 //!    communicate with background [`Service`]
 //!
+//! ### Message order
+//!
+//! For one protocol on one session, what the remote handle sees keeps the order of the local side:
+//! - `connected` comes before any `received`, and `disconnected` comes last
+//! - messages of the same priority are received in the sending order, a high priority message
+//!   may overtake the normal ones sent before it
+//! - with `ProtocolClosePolicy::Flush`, the messages sent before `close_protocol` are all received
+//!   before `disconnected`, with `ProtocolClosePolicy::DropQueued` the ones not yet sent are dropped,
+//!   so the remote receives a prefix of them
+//!
+//! The messages sent before the protocol is open are dropped.
+//!
 //! ### Feature flags
 //! Tentacle uses the Feature flag to enable some optional functions and split the smallest dependencies
 //! for use as much as possible. Users can choose the dependencies they want according to their needs.
@@ -124,7 +136,11 @@ pub mod builder;
 pub mod context;
 /// Error
 pub mod error;
+/// Flow control of the substreams, by the memory held and the rates of a session
+pub(crate) mod flow_control;
 pub(crate) mod lock;
+/// Metrics collected by the service
+pub mod metrics;
 /// Protocol handle callback stream
 pub(crate) mod protocol_handle_stream;
 /// Protocol select
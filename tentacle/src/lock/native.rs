@@ -35,6 +35,7 @@ impl<T> Mutex<T> {
 /// from its api
 /// In Tentacle, lock is mainly used to implement priority queue channel,
 /// and there is no panic scenario after lock
+#[derive(Debug)]
 pub struct RwLock<T: ?Sized>(sync::RwLock<T>);
 
 impl<T> RwLock<T> {
@@ -0,0 +1,313 @@
+use nohash_hasher::IntMap;
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::{lock::RwLock, ProtocolId};
+
+mod exporter;
+
+pub(crate) use exporter::ServiceMetrics;
+
+/// Upper bounds of the latency buckets, the last bucket collects everything above them
+const LATENCY_BOUNDS: [Duration; 10] = [
+    Duration::from_micros(50),
+    Duration::from_micros(100),
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+];
+
+/// A snapshot of a latency histogram
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    /// Upper bound (inclusive) and count of each bucket, `None` bound means unbounded
+    pub buckets: Vec<(Option<Duration>, u64)>,
+    /// Total count of records
+    pub count: u64,
+    /// Sum of all records
+    pub sum: Duration,
+}
+
+/// Lock free histogram with fixed buckets
+pub(crate) struct LatencyHistogram {
+    buckets: [AtomicU64; LATENCY_BOUNDS.len() + 1],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        LatencyHistogram {
+            buckets: Default::default(),
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, latency: Duration) {
+        let index = LATENCY_BOUNDS
+            .iter()
+            .position(|bound| latency <= *bound)
+            .unwrap_or(LATENCY_BOUNDS.len());
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            buckets: LATENCY_BOUNDS
+                .iter()
+                .map(|bound| Some(*bound))
+                .chain(::std::iter::once(None))
+                .zip(self.buckets.iter())
+                .map(|(bound, count)| (bound, count.load(Ordering::Relaxed)))
+                .collect(),
+            count: self.count.load(Ordering::Relaxed),
+            sum: Duration::from_micros(self.sum_micros.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Time of protocol messages spent from entering the substream write buffer
+/// to being handed to the underlying session, per protocol
+pub(crate) struct MessageLatency {
+    inner: IntMap<ProtocolId, LatencyHistogram>,
+}
+
+impl MessageLatency {
+    pub fn new(protocols: impl Iterator<Item = ProtocolId>) -> Self {
+        MessageLatency {
+            inner: protocols
+                .map(|proto_id| (proto_id, LatencyHistogram::new()))
+                .collect(),
+        }
+    }
+
+    pub fn record(&self, proto_id: ProtocolId, latency: Duration) {
+        if let Some(histogram) = self.inner.get(&proto_id) {
+            histogram.record(latency)
+        }
+    }
+
+    pub fn snapshot(&self) -> HashMap<ProtocolId, HistogramSnapshot> {
+        self.inner
+            .iter()
+            .map(|(proto_id, histogram)| (*proto_id, histogram.snapshot()))
+            .collect()
+    }
+}
+
+/// Count dropped messages of the whole service, and sample them for reporting
+/// so a flood of drops doesn't flood the service handle
+pub(crate) struct DropLog {
+    total: AtomicU64,
+    /// Report one of every `sample_every` drops, 0 means never report
+    sample_every: u64,
+}
+
+impl DropLog {
+    pub fn new(sample_every: u64) -> Self {
+        DropLog {
+            total: AtomicU64::new(0),
+            sample_every,
+        }
+    }
+
+    /// Count a drop, return whether it should be reported, the first drop is always reported
+    pub fn record(&self) -> bool {
+        let previous = self.total.fetch_add(1, Ordering::Relaxed);
+        self.sample_every != 0 && previous % self.sample_every == 0
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+}
+
+/// Bytes of the protocol messages sent and received
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrafficCount {
+    /// Bytes sent
+    pub sent: u64,
+    /// Bytes received
+    pub received: u64,
+}
+
+/// A snapshot of the traffic of a session
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SessionTraffic {
+    /// Traffic of all protocols
+    pub total: TrafficCount,
+    /// Traffic of each protocol opened on the session, kept after it's closed
+    pub protocols: HashMap<ProtocolId, TrafficCount>,
+}
+
+#[derive(Debug, Default)]
+struct TrafficCounter {
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+impl TrafficCounter {
+    fn add(&self, sent: u64, received: u64) {
+        self.sent.fetch_add(sent, Ordering::Relaxed);
+        self.received.fetch_add(received, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> TrafficCount {
+        TrafficCount {
+            sent: self.sent.load(Ordering::Relaxed),
+            received: self.received.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Bytes sent and received by the substreams of a session, lives as long as the session
+#[derive(Debug, Default)]
+pub(crate) struct Traffic {
+    total: TrafficCounter,
+    protocols: RwLock<IntMap<ProtocolId, TrafficCounter>>,
+    /// Also counted into the bytes of the service
+    service: ServiceMetrics,
+}
+
+impl Traffic {
+    pub fn new(service: ServiceMetrics) -> Self {
+        Traffic {
+            service,
+            ..Default::default()
+        }
+    }
+
+    pub fn record_sent(&self, proto_id: ProtocolId, size: usize) {
+        self.record(proto_id, size as u64, 0)
+    }
+
+    pub fn record_received(&self, proto_id: ProtocolId, size: usize) {
+        self.record(proto_id, 0, size as u64)
+    }
+
+    fn record(&self, proto_id: ProtocolId, sent: u64, received: u64) {
+        self.total.add(sent, received);
+        self.service.traffic(sent, received);
+        if let Some(counter) = self.protocols.read().get(&proto_id) {
+            counter.add(sent, received);
+            return;
+        }
+        self.protocols
+            .write()
+            .entry(proto_id)
+            .or_default()
+            .add(sent, received);
+    }
+
+    /// Bytes sent and received by all protocols
+    pub fn total(&self) -> u64 {
+        let total = self.total.snapshot();
+        total.sent + total.received
+    }
+
+    pub fn snapshot(&self) -> SessionTraffic {
+        SessionTraffic {
+            total: self.total.snapshot(),
+            protocols: self
+                .protocols
+                .read()
+                .iter()
+                .map(|(proto_id, counter)| (*proto_id, counter.snapshot()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DropLog, LatencyHistogram, MessageLatency, Traffic, TrafficCount, LATENCY_BOUNDS};
+    use crate::ProtocolId;
+    use std::time::Duration;
+
+    #[test]
+    fn test_histogram_bucket() {
+        let histogram = LatencyHistogram::new();
+        histogram.record(Duration::from_micros(10));
+        histogram.record(Duration::from_micros(50));
+        histogram.record(Duration::from_millis(3));
+        histogram.record(Duration::from_secs(5));
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 4);
+        assert_eq!(snapshot.sum, Duration::from_micros(5_003_060));
+        assert_eq!(snapshot.buckets.len(), LATENCY_BOUNDS.len() + 1);
+        assert_eq!(snapshot.buckets[0], (Some(Duration::from_micros(50)), 2));
+        assert_eq!(snapshot.buckets[4], (Some(Duration::from_millis(5)), 1));
+        assert_eq!(snapshot.buckets[LATENCY_BOUNDS.len()], (None, 1));
+        assert_eq!(
+            snapshot.buckets.iter().map(|(_, count)| count).sum::<u64>(),
+            snapshot.count
+        );
+    }
+
+    #[test]
+    fn test_unknown_protocol_ignored() {
+        let latency = MessageLatency::new(vec![1.into()].into_iter());
+        latency.record(1.into(), Duration::from_millis(1));
+        latency.record(2.into(), Duration::from_millis(1));
+
+        let snapshot = latency.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[&1.into()].count, 1);
+    }
+
+    #[test]
+    fn test_drop_log_sample() {
+        let log = DropLog::new(3);
+        let reported: Vec<bool> = (0..7).map(|_| log.record()).collect();
+        assert_eq!(reported, vec![true, false, false, true, false, false, true]);
+        assert_eq!(log.total(), 7);
+
+        let log = DropLog::new(0);
+        assert!(!(0..5).any(|_| log.record()));
+        assert_eq!(log.total(), 5);
+    }
+
+    #[test]
+    fn test_traffic() {
+        let traffic = Traffic::default();
+        traffic.record_sent(1.into(), 10);
+        traffic.record_sent(1.into(), 5);
+        traffic.record_received(2.into(), 7);
+
+        let snapshot = traffic.snapshot();
+        assert_eq!(
+            snapshot.total,
+            TrafficCount {
+                sent: 15,
+                received: 7
+            }
+        );
+        assert_eq!(
+            snapshot.protocols[&ProtocolId::from(1)],
+            TrafficCount {
+                sent: 15,
+                received: 0
+            }
+        );
+        assert_eq!(
+            snapshot.protocols[&ProtocolId::from(2)],
+            TrafficCount {
+                sent: 0,
+                received: 7
+            }
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! Export the service metrics into a prometheus registry, registered by `ServiceBuilder::metrics`
+//! with the `metrics` feature
+
+#[cfg(feature = "metrics")]
+use prometheus::{IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry};
+use std::fmt;
+#[cfg(feature = "metrics")]
+use std::sync::Arc;
+
+use crate::service::SessionType;
+
+/// The registered metrics
+#[cfg(feature = "metrics")]
+struct Exported {
+    sessions: IntGauge,
+    opened_sessions: IntCounterVec,
+    sent_bytes: IntCounter,
+    received_bytes: IntCounter,
+    errors: IntCounterVec,
+    buffers: IntGaugeVec,
+}
+
+#[cfg(feature = "metrics")]
+impl Exported {
+    fn new(registry: &Registry) -> Result<Self, prometheus::Error> {
+        let exported = Exported {
+            sessions: IntGauge::new("tentacle_sessions", "Number of the opened sessions")?,
+            opened_sessions: IntCounterVec::new(
+                Opts::new(
+                    "tentacle_opened_sessions_total",
+                    "Sessions opened, by direction",
+                ),
+                &["direction"],
+            )?,
+            sent_bytes: IntCounter::new(
+                "tentacle_sent_bytes_total",
+                "Bytes of the protocol messages sent",
+            )?,
+            received_bytes: IntCounter::new(
+                "tentacle_received_bytes_total",
+                "Bytes of the protocol messages received",
+            )?,
+            errors: IntCounterVec::new(
+                Opts::new(
+                    "tentacle_errors_total",
+                    "Errors reported to the service handle, by kind",
+                ),
+                &["kind"],
+            )?,
+            buffers: IntGaugeVec::new(
+                Opts::new(
+                    "tentacle_buffer_len",
+                    "Events waiting in the buffers of the service",
+                ),
+                &["buffer"],
+            )?,
+        };
+        registry.register(Box::new(exported.sessions.clone()))?;
+        registry.register(Box::new(exported.opened_sessions.clone()))?;
+        registry.register(Box::new(exported.sent_bytes.clone()))?;
+        registry.register(Box::new(exported.received_bytes.clone()))?;
+        registry.register(Box::new(exported.errors.clone()))?;
+        registry.register(Box::new(exported.buffers.clone()))?;
+        Ok(exported)
+    }
+}
+
+/// Metrics of the service, does nothing unless registered by `ServiceBuilder::metrics`
+#[derive(Clone, Default)]
+pub(crate) struct ServiceMetrics {
+    #[cfg(feature = "metrics")]
+    inner: Option<Arc<Exported>>,
+}
+
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+impl ServiceMetrics {
+    /// Register the metrics into the registry
+    #[cfg(feature = "metrics")]
+    pub fn new(registry: &Registry) -> Result<Self, prometheus::Error> {
+        Ok(ServiceMetrics {
+            inner: Some(Arc::new(Exported::new(registry)?)),
+        })
+    }
+
+    /// Whether the metrics are registered, skip collecting the numbers if not
+    #[cfg(feature = "metrics")]
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Whether the metrics are registered, skip collecting the numbers if not
+    #[cfg(not(feature = "metrics"))]
+    pub fn is_enabled(&self) -> bool {
+        false
+    }
+
+    pub fn session_open(&self, ty: SessionType) {
+        #[cfg(feature = "metrics")]
+        if let Some(ref inner) = self.inner {
+            inner.sessions.inc();
+            let direction = if ty.is_outbound() {
+                "outbound"
+            } else {
+                "inbound"
+            };
+            inner.opened_sessions.with_label_values(&[direction]).inc();
+        }
+    }
+
+    pub fn session_close(&self) {
+        #[cfg(feature = "metrics")]
+        if let Some(ref inner) = self.inner {
+            inner.sessions.dec();
+        }
+    }
+
+    pub fn traffic(&self, sent: u64, received: u64) {
+        #[cfg(feature = "metrics")]
+        if let Some(ref inner) = self.inner {
+            inner.sent_bytes.inc_by(sent);
+            inner.received_bytes.inc_by(received);
+        }
+    }
+
+    /// Count an error reported to the service handle, such as `dial` or `handshake`
+    pub fn error(&self, kind: &str) {
+        #[cfg(feature = "metrics")]
+        if let Some(ref inner) = self.inner {
+            inner.errors.with_label_values(&[kind]).inc();
+        }
+    }
+
+    /// Set the number of the events waiting in the buffer, such as `write` or `read_service`
+    pub fn buffer_len(&self, buffer: &str, len: usize) {
+        #[cfg(feature = "metrics")]
+        if let Some(ref inner) = self.inner {
+            inner.buffers.with_label_values(&[buffer]).set(len as i64);
+        }
+    }
+}
+
+impl fmt::Debug for ServiceMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ServiceMetrics")
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
@@ -51,6 +51,13 @@ pub enum ServiceProtocolEvent {
         /// Data
         data: bytes::Bytes,
     },
+    /// Coalesced protocol data of the same session
+    ReceivedBatch {
+        /// Session id
+        id: SessionId,
+        /// Data
+        data: Vec<bytes::Bytes>,
+    },
     SetNotify {
         /// Timer interval
         interval: Duration,
@@ -63,11 +70,18 @@ pub enum ServiceProtocolEvent {
     Notify {
         token: u64,
     },
+    /// Fire the notify once, without the timer
+    NotifyNow {
+        token: u64,
+    },
     Update {
         listen_addrs: Vec<Multiaddr>,
     },
 }
 
+/// Max number of messages coalesced in one `received_batch` call
+const MAX_RECEIVED_BATCH: usize = 64;
+
 enum CurrentTask {
     Idle,
     Run(Option<SessionId>),
@@ -102,6 +116,8 @@ pub struct ServiceProtocolStream<T> {
     future_task_sender: mpsc::Sender<BoxedFutureTask>,
     flag: BlockingFlag,
     need_poll: bool,
+    /// The event taken out when coalesce received messages
+    pending_event: Option<ServiceProtocolEvent>,
 }
 
 impl<T> ServiceProtocolStream<T>
@@ -131,9 +147,36 @@ where
             future_task_sender,
             flag,
             need_poll: true,
+            pending_event: None,
         }
     }
 
+    /// Coalesce the queued messages of the same session behind the received one,
+    /// all messages are delivered by `received_batch`
+    fn coalesce_received(
+        &mut self,
+        cx: &mut Context,
+        id: SessionId,
+        data: bytes::Bytes,
+    ) -> ServiceProtocolEvent {
+        let mut batch = vec![data];
+        while batch.len() < MAX_RECEIVED_BATCH {
+            match Pin::new(&mut self.receiver).as_mut().poll_next(cx) {
+                Poll::Ready(Some(ServiceProtocolEvent::Received { id: next_id, data }))
+                    if next_id == id =>
+                {
+                    batch.push(data)
+                }
+                Poll::Ready(Some(event)) => {
+                    self.pending_event = Some(event);
+                    break;
+                }
+                _ => break,
+            }
+        }
+        ServiceProtocolEvent::ReceivedBatch { id, data: batch }
+    }
+
     #[inline]
     pub fn handle_event(&mut self, event: ServiceProtocolEvent) {
         use self::ServiceProtocolEvent::*;
@@ -197,6 +240,19 @@ where
                     }
                 }
             }
+            ReceivedBatch { id, data } => {
+                self.current_task.run_with_id(id);
+                if let Some(session) = self.sessions.get(&id).cloned() {
+                    if !session.closed.load(Ordering::SeqCst)
+                        && !self.shutdown.load(Ordering::SeqCst)
+                    {
+                        block_in_place(self.flag.received(), || {
+                            self.handle
+                                .received_batch(self.handle_context.as_mut(&session), data)
+                        });
+                    }
+                }
+            }
             Notify { token } => {
                 self.current_task.run();
                 block_in_place(self.flag.notify(), || {
@@ -204,6 +260,12 @@ where
                 });
                 self.set_notify(token);
             }
+            NotifyNow { token } => {
+                self.current_task.run();
+                block_in_place(self.flag.notify(), || {
+                    self.handle.notify(&mut self.handle_context, token)
+                });
+            }
             SetNotify { interval, token } => {
                 self.current_task.run();
                 self.notify.entry(token).or_insert(interval);
@@ -295,7 +357,17 @@ where
             return Poll::Ready(None);
         }
 
-        let mut is_pending = match Pin::new(&mut self.receiver).as_mut().poll_next(cx) {
+        let event = match self.pending_event.take() {
+            Some(event) => Poll::Ready(Some(event)),
+            None => Pin::new(&mut self.receiver).as_mut().poll_next(cx),
+        };
+
+        let mut is_pending = match event {
+            Poll::Ready(Some(ServiceProtocolEvent::Received { id, data })) => {
+                let event = self.coalesce_received(cx, id, data);
+                self.handle_event(event);
+                false
+            }
             Poll::Ready(Some(event)) => {
                 self.handle_event(event);
                 false
@@ -356,6 +428,10 @@ pub enum SessionProtocolEvent {
     Notify {
         token: u64,
     },
+    /// Fire the notify once, without the timer
+    NotifyNow {
+        token: u64,
+    },
     SetNotify {
         /// Timer interval
         interval: Duration,
@@ -463,6 +539,10 @@ where
                 });
                 self.set_notify(token);
             }
+            NotifyNow { token } => block_in_place(self.flag.notify(), || {
+                self.handle
+                    .notify(self.handle_context.as_mut(&self.context), token)
+            }),
             SetNotify { token, interval } => {
                 self.notify.entry(token).or_insert(interval);
                 self.set_notify(token);
@@ -12,10 +12,27 @@ use tokio_util::codec::{length_delimited::LengthDelimitedCodec, Framed};
 #[allow(clippy::all)]
 #[allow(dead_code)]
 mod protocol_select_mol;
+pub(crate) mod multistream;
 
 /// Function for protocol version select
 pub type SelectFn<T> = Box<dyn Fn(&[T], &[T]) -> Option<T> + Send + 'static>;
 
+/// Wire format of the protocol negotiation on the sub streams
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NegotiationMode {
+    /// Tentacle's own scheme, the protocol info is encoded with molecule
+    Tentacle,
+    /// multistream-select 1.0 of libp2p, the protocol info maps onto the paths
+    /// `<name>/<version>`, which are proposed one by one from the newest version
+    Multistream,
+}
+
+impl Default for NegotiationMode {
+    fn default() -> Self {
+        NegotiationMode::Tentacle
+    }
+}
+
 /// Protocol Info
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct ProtocolInfo {
@@ -0,0 +1,319 @@
+//! Negotiation compatible with multistream-select 1.0, the one of libp2p
+//!
+//! Every message is an unsigned varint length prefix followed by the content and a newline,
+//! the protocol of tentacle maps onto the path `<name>/<version>`.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::prelude::*;
+use log::{debug, trace};
+use std::{collections::HashMap, io};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{
+    length_delimited::LengthDelimitedCodec, Decoder, Encoder, Framed, FramedParts,
+};
+
+use super::{select_version, ProtocolInfo, SelectFn};
+
+/// Header of multistream-select 1.0
+const HEADER: &[u8] = b"/multistream/1.0.0";
+/// The listener doesn't support the proposed protocol
+const NOT_AVAILABLE: &[u8] = b"na";
+/// The dialer asks for the protocols the listener supports
+const LIST: &[u8] = b"ls";
+/// Max length of the received message, protocol paths are short
+const MAX_MESSAGE_LEN: usize = 1024;
+
+/// Varint length prefixed and newline terminated messages
+struct MultistreamCodec;
+
+impl Encoder<Bytes> for MultistreamCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        put_message(&item, dst);
+        Ok(())
+    }
+}
+
+impl Decoder for MultistreamCodec {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let (len, prefix) = match get_varint(src)? {
+            Some(res) => res,
+            None => return Ok(None),
+        };
+        if len > MAX_MESSAGE_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "multistream message too long",
+            ));
+        }
+        if src.len() < prefix + len {
+            src.reserve(prefix + len - src.len());
+            return Ok(None);
+        }
+        src.advance(prefix);
+        let mut message = src.split_to(len).freeze();
+        if message.last() != Some(&b'\n') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "multistream message without newline",
+            ));
+        }
+        message.truncate(len - 1);
+        Ok(Some(message))
+    }
+}
+
+/// Write the length prefix, the content and the newline
+fn put_message(content: &[u8], dst: &mut BytesMut) {
+    put_varint(content.len() + 1, dst);
+    dst.reserve(content.len() + 1);
+    dst.put_slice(content);
+    dst.put_u8(b'\n');
+}
+
+fn put_varint(mut n: usize, dst: &mut BytesMut) {
+    while n >= 0x80 {
+        dst.put_u8((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    dst.put_u8(n as u8);
+}
+
+/// Return the varint and its length in bytes, none if incomplete
+fn get_varint(src: &[u8]) -> Result<Option<(usize, usize)>, io::Error> {
+    let mut n = 0usize;
+    for (i, byte) in src.iter().enumerate() {
+        // a message length never needs more than 3 bytes
+        if i >= 3 {
+            break;
+        }
+        n |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some((n, i + 1)));
+        }
+    }
+    if src.len() >= 3 {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid multistream length prefix",
+        ))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The path of a version of the protocol
+fn path(name: &str, version: &str) -> String {
+    format!("{}/{}", name, version)
+}
+
+/// The socket after negotiation is framed as the protocol messages of tentacle,
+/// the data already buffered is kept
+fn into_length_delimited<T>(
+    socket: Framed<T, MultistreamCodec>,
+) -> Framed<T, LengthDelimitedCodec> {
+    let parts = socket.into_parts();
+    let mut new_parts = FramedParts::new::<Bytes>(parts.io, LengthDelimitedCodec::new());
+    new_parts.read_buf = parts.read_buf;
+    new_parts.write_buf = parts.write_buf;
+    Framed::from_parts(new_parts)
+}
+
+async fn recv<T: AsyncWrite + AsyncRead + Send + Unpin>(
+    socket: &mut Framed<T, MultistreamCodec>,
+) -> Result<Bytes, io::Error> {
+    match socket.next().await {
+        Some(message) => {
+            let message = message?;
+            trace!("multistream recv(len={}): {:?}", message.len(), message);
+            Ok(message)
+        }
+        None => {
+            debug!("multistream unexpected eof during negotiation");
+            Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "unexpected eof",
+            ))
+        }
+    }
+}
+
+async fn recv_header<T: AsyncWrite + AsyncRead + Send + Unpin>(
+    socket: &mut Framed<T, MultistreamCodec>,
+) -> Result<(), io::Error> {
+    if recv(socket).await? == HEADER {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unsupported multistream version",
+        ))
+    }
+}
+
+/// Propose the versions of the protocol from the newest one, until the listener accepts one.
+///
+/// Return the same as `client_select`, the version is none if the listener accepts none of them.
+pub(crate) async fn client_select<T: AsyncWrite + AsyncRead + Send + Unpin>(
+    handle: T,
+    proto_info: ProtocolInfo,
+) -> Result<(Framed<T, LengthDelimitedCodec>, String, Option<String>), io::Error> {
+    let mut socket = Framed::new(handle, MultistreamCodec);
+    let mut versions = proto_info.support_versions;
+    versions.sort();
+
+    socket.feed(Bytes::from_static(HEADER)).await?;
+    let mut header_received = false;
+    while let Some(version) = versions.pop() {
+        let proposal = path(&proto_info.name, &version);
+        socket.send(Bytes::from(proposal.clone())).await?;
+        if !header_received {
+            recv_header(&mut socket).await?;
+            header_received = true;
+        }
+
+        let response = recv(&mut socket).await?;
+        if response == proposal.as_bytes() {
+            return Ok((
+                into_length_delimited(socket),
+                proto_info.name,
+                Some(version),
+            ));
+        } else if response != NOT_AVAILABLE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected multistream response",
+            ));
+        }
+    }
+
+    Ok((into_length_delimited(socket), proto_info.name, None))
+}
+
+/// Answer the proposals of the dialer until one is supported, also answer the `ls` request.
+///
+/// Return the same as `server_select`.
+pub(crate) async fn server_select<T: AsyncWrite + AsyncRead + Send + Unpin>(
+    handle: T,
+    mut proto_infos: HashMap<String, (ProtocolInfo, Option<SelectFn<String>>)>,
+) -> Result<(Framed<T, LengthDelimitedCodec>, String, Option<String>), io::Error> {
+    let mut socket = Framed::new(handle, MultistreamCodec);
+
+    recv_header(&mut socket).await?;
+    socket.send(Bytes::from_static(HEADER)).await?;
+
+    loop {
+        let message = recv(&mut socket).await?;
+        if message == LIST {
+            let mut list = BytesMut::new();
+            for (info, _) in proto_infos.values() {
+                for version in info.support_versions.iter() {
+                    put_message(path(&info.name, version).as_bytes(), &mut list);
+                }
+            }
+            // the codec terminates the list with the single newline covered by its length
+            socket.send(list.freeze()).await?;
+            continue;
+        }
+
+        let selected = std::str::from_utf8(&message).ok().and_then(|proposal| {
+            proto_infos.iter().find_map(|(name, (info, select))| {
+                let version = proposal.strip_prefix(name.as_str())?.strip_prefix('/')?;
+                let remote = [version.to_owned()];
+                select
+                    .as_ref()
+                    .map(|f| f(&info.support_versions, &remote))
+                    .unwrap_or_else(|| select_version(&info.support_versions, &remote))
+                    .map(|version| (name.clone(), version))
+            })
+        });
+
+        match selected {
+            Some((name, version)) => {
+                socket.send(message).await?;
+                proto_infos.remove(&name);
+                return Ok((into_length_delimited(socket), name, Some(version)));
+            }
+            None => socket.send(Bytes::from_static(NOT_AVAILABLE)).await?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{client_select, server_select};
+    use crate::protocol_select::ProtocolInfo;
+    use std::collections::HashMap;
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+    const HEADER: &[u8] = b"\x13/multistream/1.0.0\n";
+
+    fn ping_info(versions: &[&str]) -> ProtocolInfo {
+        ProtocolInfo::new(
+            "/ipfs/ping",
+            versions.iter().map(ToString::to_string).collect(),
+        )
+    }
+
+    /// Feed the recorded bytes of the remote, return what the local sent
+    async fn exchange(
+        remote: &mut tokio::io::DuplexStream,
+        input: &[u8],
+        expected_len: usize,
+    ) -> Vec<u8> {
+        remote.write_all(input).await.unwrap();
+        let mut output = vec![0; expected_len];
+        remote.read_exact(&mut output).await.unwrap();
+        output
+    }
+
+    #[test]
+    fn test_dialer_against_recorded_listener() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (local, mut remote) = duplex(1024);
+            let task = tokio::spawn(client_select(local, ping_info(&["1.0.0", "2.0.0"])));
+
+            // the listener doesn't support 2.0.0, accepts 1.0.0
+            let mut listener = HEADER.to_vec();
+            listener.extend_from_slice(b"\x03na\n\x11/ipfs/ping/1.0.0\n");
+            let mut dialer = HEADER.to_vec();
+            dialer.extend_from_slice(b"\x11/ipfs/ping/2.0.0\n\x11/ipfs/ping/1.0.0\n");
+
+            let output = exchange(&mut remote, &listener, dialer.len()).await;
+            assert_eq!(output, dialer);
+
+            let (_, name, version) = task.await.unwrap().unwrap();
+            assert_eq!(name, "/ipfs/ping");
+            assert_eq!(version, Some("1.0.0".to_owned()));
+        })
+    }
+
+    #[test]
+    fn test_listener_against_recorded_dialer() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (local, mut remote) = duplex(1024);
+            let mut infos = HashMap::new();
+            infos.insert("/ipfs/ping".to_owned(), (ping_info(&["1.0.0"]), None));
+            let task = tokio::spawn(server_select(local, infos));
+
+            let mut dialer = HEADER.to_vec();
+            dialer.extend_from_slice(b"\x03ls\n\x0b/unknown/1\n\x11/ipfs/ping/1.0.0\n");
+            let mut listener = HEADER.to_vec();
+            listener.extend_from_slice(b"\x13\x11/ipfs/ping/1.0.0\n\n");
+            listener.extend_from_slice(b"\x03na\n\x11/ipfs/ping/1.0.0\n");
+
+            let output = exchange(&mut remote, &dialer, listener.len()).await;
+            assert_eq!(output, listener);
+
+            let (_, name, version) = task.await.unwrap().unwrap();
+            assert_eq!(name, "/ipfs/ping");
+            assert_eq!(version, Some("1.0.0".to_owned()));
+        })
+    }
+}
@@ -98,6 +98,10 @@ mod os {
         pub fn peer_addr(&self) -> io::Result<SocketAddr> {
             self.0.get_ref().peer_addr()
         }
+
+        pub fn local_addr(&self) -> io::Result<SocketAddr> {
+            self.0.get_ref().local_addr()
+        }
     }
 
     impl AsyncRead for TcpStream {
@@ -153,6 +157,13 @@ mod os {
         Ok(TcpListener::new(AsyncListener::from(listen), addr))
     }
 
+    pub(crate) fn set_socket_options(
+        stream: &TcpStream,
+        config: &crate::service::TcpSocketConfig,
+    ) -> io::Result<()> {
+        crate::runtime::apply_socket_options(socket2::SockRef::from(stream.0.get_ref()), config)
+    }
+
     pub(crate) async fn connect(
         addr: SocketAddr,
         bind_addr: Option<SocketAddr>,
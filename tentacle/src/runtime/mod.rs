@@ -64,6 +64,30 @@ use std::{
 };
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
+/// Apply the socket options to a connected tcp socket
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    any(feature = "tokio-runtime", feature = "async-runtime")
+))]
+fn apply_socket_options(
+    socket: socket2::SockRef,
+    config: &crate::service::TcpSocketConfig,
+) -> io::Result<()> {
+    if config.nodelay {
+        socket.set_nodelay(true)?;
+    }
+    if let Some(interval) = config.keepalive {
+        socket.set_tcp_keepalive(&socket2::TcpKeepalive::new().with_time(interval))?;
+    }
+    if let Some(size) = config.send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+    }
+    if let Some(size) = config.recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+    }
+    Ok(())
+}
+
 /// Compact tokio to future
 pub struct CompatStream<T>(T);
 
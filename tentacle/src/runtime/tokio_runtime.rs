@@ -66,6 +66,13 @@ pub(crate) fn reuse_listen(addr: SocketAddr) -> io::Result<TcpListener> {
     socket.listen(1024)
 }
 
+pub(crate) fn set_socket_options(
+    stream: &TcpStream,
+    config: &crate::service::TcpSocketConfig,
+) -> io::Result<()> {
+    super::apply_socket_options(socket2::SockRef::from(stream), config)
+}
+
 pub(crate) async fn connect(
     addr: SocketAddr,
     bind_addr: Option<SocketAddr>,
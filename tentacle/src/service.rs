@@ -1,65 +1,90 @@
 use futures::{
-    channel::mpsc,
+    channel::{mpsc, oneshot},
     prelude::*,
     stream::{FusedStream, StreamExt},
 };
 use log::{debug, error, log_enabled, trace, warn};
-use nohash_hasher::IntMap;
+use nohash_hasher::{IntMap, IntSet};
 use std::{
     borrow::Cow,
-    collections::{HashMap, HashSet},
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    net::SocketAddr,
     pin::Pin,
     sync::{
         atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc,
     },
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 use tokio::io::{AsyncRead, AsyncWrite};
 
-#[cfg(not(target_arch = "wasm32"))]
-use crate::service::helper::Listener;
 use crate::{
     buffer::{Buffer, SendResult},
-    channel::{mpsc as priority_mpsc, mpsc::Priority},
+    channel::mpsc as priority_mpsc,
     context::{ServiceContext, SessionContext, SessionController},
-    error::{DialerErrorKind, ListenErrorKind, ProtocolHandleErrorKind, TransportErrorKind},
+    error::{
+        DialError, DialFailure, DialerErrorKind, ListenErrorKind, ProtocolHandleErrorKind,
+        RegisterError, TransportErrorKind,
+    },
+    flow_control::MemoryBudget,
+    metrics::{DropLog, MessageLatency},
     multiaddr::{Multiaddr, Protocol},
     protocol_handle_stream::{
         ServiceProtocolEvent, ServiceProtocolStream, SessionProtocolEvent, SessionProtocolStream,
     },
     protocol_select::ProtocolInfo,
-    secio::{PublicKey, SecioKeyPair},
+    secio::{KeyExporter, PeerId, PublicKey, SecioKeyPair, SecurityParams},
     service::{
         config::{ServiceConfig, State},
-        event::ServiceTask,
+        event::{DialResultSender, ServiceTask},
         future_task::{BoxedFutureTask, FutureTaskManager},
-        helper::{HandshakeContext, Source},
+        helper::{
+            AddrRace, HandshakeContext, PendingDial, PersistentPeer, PersistentTarget, Pushed,
+            Source,
+        },
     },
     session::{Session, SessionEvent, SessionMeta},
-    traits::ServiceHandle,
+    traits::{ServiceHandle, StreamMuxer},
     transports::{MultiIncoming, MultiTransport, Transport},
-    utils::extract_peer_id,
     yamux::Config as YamuxConfig,
     ProtocolId, SessionId,
 };
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    service::helper::{HandshakeLimit, Listener},
+    utils::{dns::DnsResolver, multiaddr_to_socketaddr, socketaddr_to_multiaddr},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 pub(crate) mod config;
 mod control;
 pub(crate) mod event;
 pub(crate) mod future_task;
 mod helper;
+#[cfg(not(target_arch = "wasm32"))]
+mod recorder;
 
+pub use crate::channel::Priority;
 pub use crate::service::{
-    config::{BlockingFlag, ProtocolHandle, ProtocolMeta, TargetProtocol, TargetSession},
+    config::{
+        BlockingFlag, HandleClosedPolicy, ProtocolClosePolicy, ProtocolHandle, ProtocolMeta,
+        RateLimit, ReconnectBackoff, TargetProtocol, TargetSession, TcpSocketConfig,
+    },
     control::{ServiceAsyncControl, ServiceControl},
-    event::{ServiceError, ServiceEvent},
-    helper::SessionType,
+    event::{
+        DropReason, SelectErrorCause, ServiceError, ServiceEvent, ServiceState, SessionBufferStats,
+    },
+    future_task::TaskHandle,
+    helper::{SecioUpgrade, SessionType, YamuxMuxer},
 };
 use bytes::Bytes;
 
 #[cfg(feature = "tls")]
 pub use crate::service::config::TlsConfig;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::service::recorder::{read_records, replay_records, RecordedEvent};
 
 /// Received from user, aggregate mode
 pub(crate) const RECEIVED_BUFFER_SIZE: usize = 2048;
@@ -75,15 +100,51 @@ pub struct Service<T> {
     protocol_configs: IntMap<ProtocolId, ProtocolMeta>,
 
     sessions: IntMap<SessionId, SessionController>,
+    /// Order the sessions are distributed to, boosted sessions first, the higher level the earlier
+    session_order: Vec<(u8, SessionId)>,
 
     multi_transport: MultiTransport,
 
     listens: HashSet<Multiaddr>,
+    /// Number of the listens which the advertised listen addresses were built from
+    advertised_listens: usize,
 
     #[cfg(all(not(target_arch = "wasm32"), feature = "upnp"))]
     igd_client: Option<crate::upnp::IgdClient>,
 
     dial_protocols: HashMap<Multiaddr, TargetProtocol>,
+    /// Number of the outbound connections in progress, limited by the dial concurrency
+    dialing: usize,
+    /// Dials over the dial concurrency limit, and the sequence of the next one
+    pending_dials: BinaryHeap<PendingDial>,
+    next_dial_seq: u64,
+    /// Expected remote peer id of the dialing address, verified on session open
+    dial_peer_ids: HashMap<Multiaddr, PeerId>,
+    /// Dialing address -> the `dial_with_result` calls waiting for it
+    dial_results: HashMap<Multiaddr, Vec<DialResultSender>>,
+    /// Dialing address of a peer from the peer store -> the peer and its addresses left to try
+    peer_dials: HashMap<Multiaddr, (PeerId, VecDeque<Multiaddr>)>,
+    /// Addresses kept connected, re-dialed with backoff
+    persistent_peers: HashMap<Multiaddr, PersistentPeer>,
+    /// Peers dialed by `dial_addrs` -> the race of their addresses
+    addr_races: HashMap<PeerId, AddrRace>,
+    /// Racing address -> its peer, the cancelled ones are kept until their dials end
+    race_addrs: HashMap<Multiaddr, PeerId>,
+    /// Retries done of the dialing addresses failed with transient errors
+    dial_retries: HashMap<Multiaddr, u32>,
+    /// Session of each connected peer id, repeated connections are rejected so there is only one
+    peer_sessions: HashMap<PeerId, SessionId>,
+    /// Banned peers, the generations of their bans and the timers to lift them
+    banned_peers: HashMap<PeerId, (u64, TaskHandle)>,
+    /// Generation of the next ban, tells a stale expiry from the one of the current ban
+    next_ban_generation: u64,
+    /// Negotiation failures of the protocols with each peer and the time of the last one,
+    /// used to downgrade the protocols on the next sessions
+    select_failures: HashMap<(PeerId, ProtocolId), (u32, Instant)>,
+    /// Our address observed by the remote of each session, one vote per session
+    observed_addrs: HashMap<SessionId, Multiaddr>,
+    /// The observed address reported by the most sessions
+    external_address: Option<Multiaddr>,
     config: ServiceConfig,
     /// service state
     state: State,
@@ -116,6 +177,18 @@ pub struct Service<T> {
 
     shutdown: Arc<AtomicBool>,
 
+    /// Record session events and service tasks for debugging
+    #[cfg(not(target_arch = "wasm32"))]
+    recorder: Option<recorder::EventRecorder>,
+
+    /// Per protocol latency of messages in substream write buffer
+    message_latency: Option<Arc<MessageLatency>>,
+    /// Bytes held by the buffers of all substreams
+    memory_budget: Option<Arc<MemoryBudget>>,
+    /// Inbound handshakes in progress, shared by the listeners
+    #[cfg(not(target_arch = "wasm32"))]
+    handshake_limit: Option<Arc<HandshakeLimit>>,
+
     wait_handle: Vec<(
         Option<futures::channel::oneshot::Sender<()>>,
         crate::runtime::JoinHandle<()>,
@@ -132,7 +205,7 @@ where
         handle: T,
         key_pair: Option<SecioKeyPair>,
         forever: bool,
-        config: ServiceConfig,
+        mut config: ServiceConfig,
     ) -> Self {
         let (session_event_sender, session_event_receiver) = mpsc::channel(RECEIVED_SIZE);
         let (task_sender, task_receiver) = priority_mpsc::channel(RECEIVED_BUFFER_SIZE);
@@ -143,8 +216,56 @@ where
                 (meta.id(), proto_info)
             })
             .collect();
+        let low_latency = protocol_configs
+            .values()
+            .filter(|meta| meta.inner.low_latency)
+            .map(ProtocolMeta::id)
+            .collect();
+        let flush_on_close = protocol_configs
+            .values()
+            .filter(|meta| meta.inner.close_policy == ProtocolClosePolicy::Flush)
+            .map(ProtocolMeta::id)
+            .collect();
         let (future_task_sender, future_task_receiver) = mpsc::channel(SEND_SIZE);
         let shutdown = Arc::new(AtomicBool::new(false));
+        if config.security.is_none() {
+            if let Some(ref key_pair) = key_pair {
+                let mut upgrade = SecioUpgrade::new(key_pair.clone(), config.max_frame_length);
+                if let Some(ref ciphers) = config.secio_ciphers {
+                    upgrade = upgrade.ciphers(ciphers.clone());
+                }
+                config.security = Some(Arc::new(upgrade));
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        let recorder = config.event_recorder.as_ref().and_then(|path| {
+            recorder::EventRecorder::new(path)
+                .map_err(|err| error!("open event recorder {:?} error: {:?}", path, err))
+                .ok()
+        });
+        let message_latency = if config.message_latency {
+            Some(Arc::new(MessageLatency::new(
+                protocol_configs.keys().cloned(),
+            )))
+        } else {
+            None
+        };
+        let service_context = ServiceContext::new(
+            task_sender,
+            proto_infos,
+            low_latency,
+            flush_on_close,
+            key_pair,
+            shutdown.clone(),
+            Arc::new(DropLog::new(config.message_drop_sample)),
+        );
+        #[cfg(not(target_arch = "wasm32"))]
+        let handshake_limit = config
+            .max_concurrent_handshakes
+            .map(|limit| Arc::new(HandshakeLimit::new(limit)));
+        let memory_budget = config
+            .max_buffer_bytes
+            .map(|limit| Arc::new(MemoryBudget::new(limit, service_context.control().clone())));
         #[cfg(all(not(target_arch = "wasm32"), feature = "upnp"))]
         let igd_client = if config.upnp {
             crate::upnp::IgdClient::new()
@@ -158,7 +279,12 @@ where
             handle,
             multi_transport: {
                 #[allow(clippy::let_and_return)]
-                let transport = MultiTransport::new(config.timeout).tcp_bind(config.tcp_bind_addr);
+                let transport =
+                    MultiTransport::with_timeouts(config.dial_timeout(), config.listen_timeout())
+                        .tcp_bind(config.tcp_bind_addr)
+                        .outbound_bind(config.outbound_bind)
+                        .tcp_socket_config(config.tcp_socket_config)
+                        .txt_resolver(config.txt_resolver.clone());
                 #[cfg(feature = "ws")]
                 let transport = transport.ws_bind(config.ws_bind_addr);
                 #[cfg(feature = "tls")]
@@ -171,25 +297,44 @@ where
                 shutdown.clone(),
             )),
             sessions: HashMap::default(),
+            session_order: Vec::new(),
             service_proto_handles: HashMap::default(),
             session_proto_handles: HashMap::default(),
             listens: HashSet::new(),
+            advertised_listens: 0,
             #[cfg(all(not(target_arch = "wasm32"), feature = "upnp"))]
             igd_client,
             dial_protocols: HashMap::default(),
+            dialing: 0,
+            pending_dials: BinaryHeap::new(),
+            next_dial_seq: 0,
+            dial_peer_ids: HashMap::default(),
+            dial_results: HashMap::default(),
+            peer_dials: HashMap::default(),
+            persistent_peers: HashMap::default(),
+            dial_retries: HashMap::default(),
+            addr_races: HashMap::default(),
+            race_addrs: HashMap::default(),
+            peer_sessions: HashMap::default(),
+            banned_peers: HashMap::default(),
+            next_ban_generation: 0,
+            select_failures: HashMap::default(),
+            observed_addrs: HashMap::default(),
+            external_address: None,
             state: State::new(forever),
             next_session: SessionId::default(),
             session_event_sender,
             session_event_receiver,
-            service_context: ServiceContext::new(
-                task_sender,
-                proto_infos,
-                key_pair,
-                shutdown.clone(),
-            ),
+            service_context,
             config,
             service_task_receiver: task_receiver,
             shutdown,
+            #[cfg(not(target_arch = "wasm32"))]
+            recorder,
+            message_latency,
+            memory_budget,
+            #[cfg(not(target_arch = "wasm32"))]
+            handshake_limit,
             wait_handle: Vec::new(),
         }
     }
@@ -216,54 +361,139 @@ where
                     .max_stream_window_size
         );
         self.config.max_frame_length = size;
+        self.config.session_config.max_frame_length = size;
         self
     }
 
     /// Listen on the given address.
     ///
     /// Return really listen multiaddr, but if use `/dns4/localhost/tcp/80`,
-    /// it will return original value, and create a future task to DNS resolver later.
+    /// the domain is resolved first and every resolved address is listened separately,
+    /// each of them emits a `ListenStarted` event followed by a `ListenResolved` event,
+    /// the first one is returned.
+    ///
+    /// The addresses over the listeners limit are not listened, and if none of them is,
+    /// an io error is returned.
     pub async fn listen(&mut self, address: Multiaddr) -> Result<Multiaddr> {
-        let listen_future = self.multi_transport.clone().listen(address.clone())?;
-
         #[cfg(target_arch = "wasm32")]
-        unreachable!();
+        {
+            let _ignore = address;
+            unreachable!();
+        }
 
         #[cfg(not(target_arch = "wasm32"))]
-        match listen_future.await {
-            Ok((addr, incoming)) => {
-                let listen_address = addr.clone();
+        {
+            let (original, addresses) = match DnsResolver::new(address.clone()) {
+                Some(resolver) => {
+                    let addresses =
+                        resolver
+                            .resolve_all()
+                            .await
+                            .map_err(|(address, io_error)| {
+                                TransportErrorKind::DnsResolverError(address, io_error)
+                            })?;
+                    (Some(address), addresses)
+                }
+                None => (None, vec![address]),
+            };
+
+            // bind all the addresses before starting any listener, on error the bound ones
+            // are dropped with the list, which closes them instead of leaking them
+            let mut bound = Vec::with_capacity(addresses.len());
+            for address in addresses {
+                if self.reached_max_listeners(bound.len()) {
+                    self.too_many_listeners(address);
+                    break;
+                }
+                bound.push(self.multi_transport.clone().listen(address)?.await?);
+            }
 
+            let mut first = None;
+            for (listen_address, incoming) in bound {
                 self.handle.handle_event(
                     &mut self.service_context,
                     ServiceEvent::ListenStarted {
                         address: listen_address.clone(),
                     },
                 );
+                if let Some(ref original) = original {
+                    self.handle.handle_event(
+                        &mut self.service_context,
+                        ServiceEvent::ListenResolved {
+                            original: original.clone(),
+                            address: listen_address.clone(),
+                        },
+                    );
+                }
                 #[cfg(feature = "upnp")]
                 if let Some(client) = self.igd_client.as_mut() {
                     client.register(&listen_address)
                 }
                 self.listens.insert(listen_address.clone());
 
-                self.spawn_listener(incoming, listen_address);
-
-                Ok(addr)
+                self.spawn_listener(incoming, listen_address.clone());
+                first.get_or_insert(listen_address);
             }
-            Err(err) => Err(err),
+
+            first.ok_or_else(|| {
+                TransportErrorKind::Io(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "too many listeners",
+                ))
+            })
         }
     }
 
+    /// Listen on the unspecified addresses of both IPv6 and IPv4 with the same tcp port.
+    ///
+    /// IPv6 is listened first and its port is reused by IPv4, so port 0 gives the same
+    /// random port to both. On the systems where the IPv6 socket is dual-stack by default,
+    /// it also accepts the IPv4 connections.
+    ///
+    /// If IPv6 is unavailable, its error is reported by `ServiceError::ListenError` and only
+    /// IPv4 is listened. Return the listened addresses, each of them emits a `ListenStarted` event.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn listen_dual_stack(&mut self, port: u16) -> Result<Vec<Multiaddr>> {
+        let mut addresses = Vec::with_capacity(2);
+
+        let ipv6 = socketaddr_to_multiaddr(SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), port));
+        let port = match self.listen(ipv6.clone()).await {
+            Ok(address) => {
+                let port = multiaddr_to_socketaddr(&address)
+                    .map(|addr| addr.port())
+                    .unwrap_or(port);
+                addresses.push(address);
+                port
+            }
+            Err(error) => {
+                debug!("listen on {} error: {}, fallback to ipv4 only", ipv6, error);
+                self.report_error(ServiceError::ListenError {
+                    address: ipv6,
+                    error: ListenErrorKind::TransportError(error),
+                });
+                port
+            }
+        };
+
+        let ipv4 = socketaddr_to_multiaddr(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port));
+        addresses.push(self.listen(ipv4).await?);
+
+        Ok(addresses)
+    }
+
     #[cfg(not(target_arch = "wasm32"))]
     fn spawn_listener(&mut self, incoming: MultiIncoming, listen_address: Multiaddr) {
         let listener = Listener {
             inner: incoming,
-            key_pair: self.service_context.key_pair().cloned(),
+            security: self.config.security.clone(),
+            protocols: self.exchanged_protocols(),
+            gater: self.config.gater.clone(),
             event_sender: self.session_event_sender.clone(),
-            max_frame_length: self.config.max_frame_length,
             timeout: self.config.timeout,
             listen_addr: listen_address,
             future_task_sender: self.future_task_sender.clone_sender(),
+            accept_switch: self.service_context.control().accept_switch.clone(),
+            handshake_limit: self.handshake_limit.clone(),
         };
         let mut sender = self.future_task_sender.clone_sender();
         crate::runtime::spawn(async move {
@@ -277,7 +507,32 @@ where
     }
 
     /// Use by inner
-    fn listen_inner(&mut self, address: Multiaddr) -> Result<()> {
+    /// Listen on the address, the original is the dns address it's resolved from
+    fn listen_inner(&mut self, address: Multiaddr, original: Option<Multiaddr>) -> Result<()> {
+        // A domain may resolve to multiple ips, resolve it first and listen on each of them
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(resolver) = DnsResolver::new(address.clone()) {
+            let mut sender = self.session_event_sender.clone();
+            let task = async move {
+                let event = match resolver.resolve_all().await {
+                    Ok(addresses) => SessionEvent::ListenResolved {
+                        original: address,
+                        addresses,
+                    },
+                    Err((address, io_error)) => SessionEvent::ListenError {
+                        address: address.clone(),
+                        error: TransportErrorKind::DnsResolverError(address, io_error),
+                    },
+                };
+                if let Err(err) = sender.send(event).await {
+                    error!("Listen address resolve result send back error: {:?}", err);
+                }
+            };
+            self.future_task_sender.push(Box::pin(task));
+            self.state.increase();
+            return Ok(());
+        }
+
         let listen_future = self.multi_transport.clone().listen(address.clone())?;
 
         #[cfg(not(target_arch = "wasm32"))]
@@ -289,6 +544,7 @@ where
                     Ok((addr, incoming)) => SessionEvent::ListenStart {
                         listen_address: addr,
                         incoming,
+                        original,
                     },
                     Err(error) => SessionEvent::ListenError { address, error },
                 };
@@ -305,12 +561,40 @@ where
 
     /// Dial the given address, doesn't actually make a request, just generate a future
     pub async fn dial(&mut self, address: Multiaddr, target: TargetProtocol) -> Result<&mut Self> {
+        if !self.intercept_dial(&address) {
+            return Err(TransportErrorKind::Io(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "vetoed by the connection gater",
+            )));
+        }
+        if self.dial_banned(&address, None) {
+            return Err(TransportErrorKind::Io(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "the peer is banned",
+            )));
+        }
+        if let Some(limit) = self.reached_connection_limit(SessionType::Outbound) {
+            return Err(TransportErrorKind::Io(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("reached the outbound connection limit {}", limit),
+            )));
+        }
         let dial_future = self.multi_transport.clone().dial(address.clone())?;
 
         match dial_future.await {
-            Ok((addr, incoming)) => {
-                self.handshake(incoming, SessionType::Outbound, addr, None);
+            Ok((addr, mut incoming)) => {
+                let local_address = incoming.local_addr();
+                let muxer = incoming.muxer();
+                self.handshake(
+                    incoming,
+                    muxer,
+                    SessionType::Outbound,
+                    addr,
+                    None,
+                    local_address,
+                );
                 self.dial_protocols.insert(address, target);
+                self.dialing += 1;
                 self.state.increase();
                 Ok(self)
             }
@@ -320,27 +604,47 @@ where
 
     /// Use by inner
     #[inline(always)]
-    fn dial_inner(&mut self, address: Multiaddr, target: TargetProtocol) -> Result<()> {
+    fn dial_inner(
+        &mut self,
+        address: Multiaddr,
+        target: TargetProtocol,
+        peer_id: Option<PeerId>,
+    ) -> Result<()> {
+        let task = self.dial_future(address, target, peer_id)?;
+        self.future_task_sender.push(Box::pin(task));
+        Ok(())
+    }
+
+    /// Count the dial in and create the task of it, the result is sent back as a session event
+    fn dial_future(
+        &mut self,
+        address: Multiaddr,
+        target: TargetProtocol,
+        peer_id: Option<PeerId>,
+    ) -> Result<impl Future<Output = ()> + Send + 'static> {
         self.dial_protocols.insert(address.clone(), target);
+        if let Some(peer_id) = peer_id {
+            self.dial_peer_ids.insert(address.clone(), peer_id);
+        }
         let dial_future = self.multi_transport.clone().dial(address.clone())?;
 
-        let key_pair = self.service_context.key_pair().cloned();
+        let security = self.config.security.clone();
         let timeout = self.config.timeout;
-        let max_frame_length = self.config.max_frame_length;
 
         let mut sender = self.session_event_sender.clone();
         let task = async move {
             let result = dial_future.await;
 
             match result {
-                Ok((addr, incoming)) => {
+                Ok((addr, mut incoming)) => {
                     HandshakeContext {
                         ty: SessionType::Outbound,
                         remote_address: addr,
                         listen_address: None,
-                        key_pair,
+                        local_address: incoming.local_addr(),
+                        muxer: incoming.muxer(),
+                        security,
                         event_sender: sender,
-                        max_frame_length,
                         timeout,
                     }
                     .handshake(incoming)
@@ -357,9 +661,458 @@ where
             };
         };
 
+        self.dialing += 1;
+        self.state.increase();
+        Ok(task)
+    }
+
+    /// Ask the connection gater whether to dial the address, output the error if vetoed
+    fn intercept_dial(&mut self, address: &Multiaddr) -> bool {
+        match self.config.gater {
+            Some(ref gater) if !gater.intercept_dial(address) => {
+                self.handle_dial_error(ServiceError::DialerError {
+                    address: address.clone(),
+                    error: DialerErrorKind::Gated,
+                });
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Refuse the dial if the peer of it is banned, output the error if refused
+    fn dial_banned(&mut self, address: &Multiaddr, peer_id: Option<&PeerId>) -> bool {
+        let peer_id = match peer_id
+            .cloned()
+            .or_else(|| self.config.extract_peer_id(address))
+        {
+            Some(peer_id) if self.banned_peers.contains_key(&peer_id) => peer_id,
+            _ => return false,
+        };
+        debug!("dial {} is refused, peer {:?} is banned", address, peer_id);
+        self.handle_dial_error(ServiceError::DialerError {
+            address: address.clone(),
+            error: DialerErrorKind::Banned(peer_id),
+        });
+        true
+    }
+
+    /// Ban the peer and disconnect its session, the ban is lifted when the duration is reached
+    fn ban_peer(&mut self, cx: &mut Context, peer_id: PeerId, duration: Duration) {
+        let mut sender = self.service_context.control().task_sender.clone();
+        let task_peer_id = peer_id.clone();
+        let generation = self.next_ban_generation;
+        self.next_ban_generation = self.next_ban_generation.wrapping_add(1);
+        let (handle, task) = TaskHandle::new(async move {
+            crate::runtime::delay_for(duration).await;
+            let task = ServiceTask::BanExpired {
+                peer_id: task_peer_id,
+                generation,
+            };
+            if sender.send(task).await.is_err() {
+                trace!("ban expired send err")
+            }
+        });
+        // the timer of the former ban is aborted on drop, an expiry it has already
+        // sent is ignored by the generation
+        self.banned_peers
+            .insert(peer_id.clone(), (generation, handle));
+        self.future_task_sender.push(task);
+
+        if let Some(session_id) = self.peer_sessions.get(&peer_id).copied() {
+            self.session_close(cx, session_id, Source::External)
+        }
+    }
+
+    /// Refuse the dial if the outbound connection limit is reached
+    fn check_dial_limit(&mut self, address: &Multiaddr) -> bool {
+        match self.reached_connection_limit(SessionType::Outbound) {
+            Some(limit) => {
+                self.handle_dial_error(ServiceError::ConnectionLimit {
+                    ty: SessionType::Outbound,
+                    address: address.clone(),
+                    limit,
+                });
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Dial the address unless it is being dialed, vetoed, banned or over the limit
+    fn dial_task(
+        &mut self,
+        address: Multiaddr,
+        target: TargetProtocol,
+        peer_id: Option<PeerId>,
+        weight: u8,
+    ) {
+        if !self.dial_protocols.contains_key(&address)
+            && self.intercept_dial(&address)
+            && !self.dial_banned(&address, peer_id.as_ref())
+            && self.check_dial_limit(&address)
+        {
+            self.dial_or_queue(address, target, peer_id, weight);
+        }
+    }
+
+    /// Dial now or queue it if the dial concurrency limit is reached
+    fn dial_or_queue(
+        &mut self,
+        address: Multiaddr,
+        target: TargetProtocol,
+        peer_id: Option<PeerId>,
+        weight: u8,
+    ) {
+        if self
+            .config
+            .max_dial_concurrency
+            .map(|max| self.dialing >= max)
+            .unwrap_or(false)
+        {
+            // occupy the address, so it isn't dialed twice
+            self.dial_protocols.insert(address.clone(), target);
+            self.pending_dials.push(PendingDial {
+                weight,
+                seq: self.next_dial_seq,
+                address,
+                peer_id,
+            });
+            self.next_dial_seq += 1;
+            return;
+        }
+        if let Err(e) = self.dial_inner(address.clone(), target, peer_id) {
+            let target = self.dial_protocols.remove(&address);
+            self.dial_peer_ids.remove(&address);
+            self.handle_dial_error(ServiceError::DialerError {
+                address: address.clone(),
+                error: DialerErrorKind::TransportError(e),
+            });
+            self.dial_next_peer_addr(&address, target);
+            self.reconnect_later(&address);
+        }
+    }
+
+    /// Dial the addresses of the peer one by one, the next one is dialed when the former fails
+    fn dial_peer_addrs(
+        &mut self,
+        peer_id: PeerId,
+        mut addrs: VecDeque<Multiaddr>,
+        target: TargetProtocol,
+    ) {
+        if self.peer_sessions.contains_key(&peer_id) {
+            debug!("peer {:?} is connected, skip the dial", peer_id);
+            return;
+        }
+        if self.banned_peers.contains_key(&peer_id) {
+            debug!("peer {:?} is banned, skip the dial", peer_id);
+            return;
+        }
+        while let Some(address) = addrs.pop_front() {
+            if self.dial_protocols.contains_key(&address) || !self.intercept_dial(&address) {
+                continue;
+            }
+            if !self.check_dial_limit(&address) {
+                return;
+            }
+            self.peer_dials
+                .insert(address.clone(), (peer_id.clone(), addrs));
+            self.dial_or_queue(address, target, Some(peer_id), 0);
+            return;
+        }
+        debug!("no address of peer {:?} left to dial", peer_id);
+    }
+
+    /// The dial of an address from the peer store failed, dial the next address of the peer
+    fn dial_next_peer_addr(&mut self, address: &Multiaddr, target: Option<TargetProtocol>) {
+        if let (Some((peer_id, addrs)), Some(target)) = (self.peer_dials.remove(address), target) {
+            self.dial_peer_addrs(peer_id, addrs, target)
+        }
+    }
+
+    /// Dial the addresses of the peer concurrently, the dials can be cancelled by the race
+    fn dial_addrs(&mut self, peer_id: PeerId, addrs: Vec<Multiaddr>, target: TargetProtocol) {
+        if self.peer_sessions.contains_key(&peer_id) {
+            debug!("peer {:?} is connected, skip the dial", peer_id);
+            return;
+        }
+        if self.banned_peers.contains_key(&peer_id) {
+            debug!("peer {:?} is banned, skip the dial", peer_id);
+            return;
+        }
+        if self.addr_races.contains_key(&peer_id) {
+            debug!("addresses of peer {:?} are being dialed", peer_id);
+            return;
+        }
+        let mut race = AddrRace {
+            target,
+            dials: HashMap::default(),
+            errors: Vec::new(),
+        };
+        for address in addrs {
+            if self.dial_protocols.contains_key(&address)
+                || race.dials.contains_key(&address)
+                || !self.intercept_dial(&address)
+            {
+                continue;
+            }
+            if let Some(limit) = self.reached_connection_limit(SessionType::Outbound) {
+                race.errors.push(ServiceError::ConnectionLimit {
+                    ty: SessionType::Outbound,
+                    address,
+                    limit,
+                });
+                continue;
+            }
+            // the target is taken from the race by the winner
+            match self.dial_future(address.clone(), TargetProtocol::All, Some(peer_id.clone())) {
+                Ok(dial) => {
+                    let (signal, cancel) = oneshot::channel();
+                    let mut sender = self.session_event_sender.clone();
+                    let dial_address = address.clone();
+                    let task = async move {
+                        if let future::Either::Right(_) =
+                            future::select(Box::pin(dial), cancel).await
+                        {
+                            // the dial is counted in, end it as a failure
+                            let error = TransportErrorKind::Io(std::io::Error::new(
+                                std::io::ErrorKind::Interrupted,
+                                "dial cancelled",
+                            ));
+                            let event = SessionEvent::DialError {
+                                address: dial_address,
+                                error,
+                            };
+                            if sender.send(event).await.is_err() {
+                                trace!("dial cancel send err")
+                            }
+                        }
+                    };
+                    self.future_task_sender.push(Box::pin(task));
+                    self.race_addrs.insert(address.clone(), peer_id.clone());
+                    race.dials.insert(address, signal);
+                }
+                Err(e) => {
+                    self.dial_protocols.remove(&address);
+                    self.dial_peer_ids.remove(&address);
+                    race.errors.push(ServiceError::DialerError {
+                        address,
+                        error: DialerErrorKind::TransportError(e),
+                    });
+                }
+            }
+        }
+        if !race.dials.is_empty() {
+            self.addr_races.insert(peer_id, race);
+        } else if !race.errors.is_empty() {
+            self.report_error(ServiceError::DialAddrsError {
+                peer_id,
+                errors: race.errors,
+            });
+        } else {
+            debug!("no address of peer {:?} left to dial", peer_id);
+        }
+    }
+
+    /// An address of the race finished the handshake, cancel the dials of the others
+    /// and give back the target of the race
+    fn win_addr_race(&mut self, address: &Multiaddr) -> Option<TargetProtocol> {
+        let peer_id = self.race_addrs.remove(address)?;
+        let mut race = self.addr_races.remove(&peer_id)?;
+        race.dials.remove(address);
+        for (address, signal) in race.dials {
+            debug!(
+                "cancel the dial of {}, peer {:?} is connected",
+                address, peer_id
+            );
+            let _ignore = signal.send(());
+        }
+        Some(race.target)
+    }
+
+    /// Collect the dial failure of an address of the race, the failures are output as one
+    /// when all of them fail, give back the error if it doesn't belong to a race
+    fn addr_race_failed(&mut self, error: ServiceError) -> Option<ServiceError> {
+        let address = match error {
+            ServiceError::DialerError { ref address, .. }
+            | ServiceError::ConnectionLimit {
+                ty: SessionType::Outbound,
+                ref address,
+                ..
+            } => address,
+            _ => return Some(error),
+        };
+        let peer_id = match self.race_addrs.remove(address) {
+            Some(peer_id) => peer_id,
+            None => return Some(error),
+        };
+        let race = match self.addr_races.get_mut(&peer_id) {
+            Some(race) => race,
+            // cancelled or lost the race
+            None => return None,
+        };
+        race.dials.remove(address);
+        race.errors.push(error);
+        if !race.dials.is_empty() {
+            return None;
+        }
+        self.addr_races
+            .remove(&peer_id)
+            .map(|race| ServiceError::DialAddrsError {
+                peer_id,
+                errors: race.errors,
+            })
+    }
+
+    /// Dial the persistent address if it isn't connected or dialing
+    fn reconnect(&mut self, address: Multiaddr) {
+        let target = match self.persistent_peers.get(&address) {
+            Some(peer) if peer.session.is_none() => peer.target.target(),
+            _ => return,
+        };
+        if self.dial_protocols.contains_key(&address) {
+            return;
+        }
+        if self.intercept_dial(&address)
+            && !self.dial_banned(&address, None)
+            && self.check_dial_limit(&address)
+        {
+            self.dial_or_queue(address, target, None, 0);
+        } else {
+            self.reconnect_later(&address);
+        }
+    }
+
+    /// Re-dial the persistent address when its backoff is reached
+    fn reconnect_later(&mut self, address: &Multiaddr) {
+        if self.state == State::PreShutdown {
+            return;
+        }
+        let backoff = self.config.reconnect_backoff;
+        let peer = match self.persistent_peers.get_mut(address) {
+            Some(peer) => peer,
+            None => return,
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let random = rand::random::<f64>();
+        #[cfg(target_arch = "wasm32")]
+        let random = js_sys::Math::random();
+        let delay = backoff.delay(peer.attempts, random);
+        peer.attempts = peer.attempts.saturating_add(1);
+        peer.session = None;
+        debug!("re-dial persistent address {} after {:?}", address, delay);
+
+        let mut sender = self.service_context.control().task_sender.clone();
+        let address = address.clone();
+        let (handle, task) = TaskHandle::new(async move {
+            crate::runtime::delay_for(delay).await;
+            let task = ServiceTask::Reconnect { address };
+            if sender.send(task).await.is_err() {
+                trace!("reconnect send err")
+            }
+        });
+        // the former one is aborted on drop
+        peer.retry = Some(handle);
+        self.future_task_sender.push(task);
+    }
+
+    /// Retry the dial failed with a transient error after the delay,
+    /// give back the target if no retry is left
+    fn retry_dial(
+        &mut self,
+        address: &Multiaddr,
+        target: TargetProtocol,
+        peer_id: Option<PeerId>,
+    ) -> Option<TargetProtocol> {
+        let (retries, delay) = match self.config.dial_retries {
+            Some(retries) if self.state != State::PreShutdown => retries,
+            _ => return Some(target),
+        };
+        let attempts = self.dial_retries.entry(address.clone()).or_insert(0);
+        if *attempts >= retries {
+            return Some(target);
+        }
+        let delay = delay * 2u32.pow((*attempts).min(16));
+        *attempts += 1;
+        debug!("retry dial {} after {:?}", address, delay);
+
+        let mut sender = self.service_context.control().task_sender.clone();
+        let address = address.clone();
+        let task = async move {
+            crate::runtime::delay_for(delay).await;
+            let task = ServiceTask::RetryDial {
+                address,
+                target,
+                peer_id,
+            };
+            if sender.send(task).await.is_err() {
+                trace!("retry dial send err")
+            }
+        };
         self.future_task_sender.push(Box::pin(task));
+        // Counted until the retry fires, so the service doesn't shut down in the meantime
         self.state.increase();
-        Ok(())
+        None
+    }
+
+    /// Complete the `dial_with_result` calls waiting for the address
+    fn complete_dial_results(
+        &mut self,
+        address: &Multiaddr,
+        result: std::result::Result<SessionId, DialFailure>,
+    ) {
+        for sender in self.dial_results.remove(address).unwrap_or_default() {
+            let _ignore = sender.send(result.clone().map_err(DialError::Failed));
+        }
+    }
+
+    /// Output the error to the service handle, and count it in the metrics
+    fn report_error(&mut self, error: ServiceError) {
+        self.config.metrics.error(error.kind());
+        self.handle.handle_error(&mut self.service_context, error);
+    }
+
+    /// Output the error, a dial error also completes the `dial_with_result` calls of the address
+    fn handle_dial_error(&mut self, error: ServiceError) {
+        match error {
+            ServiceError::DialerError {
+                ref address,
+                ref error,
+            } if self.dial_results.contains_key(address) => {
+                self.complete_dial_results(address, Err(error.into()))
+            }
+            ServiceError::ConnectionLimit {
+                ty: SessionType::Outbound,
+                ref address,
+                limit,
+            } if self.dial_results.contains_key(address) => {
+                self.complete_dial_results(address, Err(DialFailure::ConnectionLimit(limit)))
+            }
+            _ => (),
+        }
+        if let Some(error) = self.addr_race_failed(error) {
+            self.report_error(error);
+        }
+    }
+
+    /// A dial finished, start the queued ones up to the dial concurrency limit
+    fn dial_finished(&mut self) {
+        self.dialing = self.dialing.saturating_sub(1);
+        while self
+            .config
+            .max_dial_concurrency
+            .map(|max| self.dialing < max)
+            .unwrap_or(true)
+        {
+            match self.pending_dials.pop() {
+                Some(dial) => {
+                    if let Some(target) = self.dial_protocols.remove(&dial.address) {
+                        self.dial_or_queue(dial.address, target, dial.peer_id, dial.weight)
+                    }
+                }
+                None => break,
+            }
+        }
     }
 
     /// Get service control, control can send tasks externally to the runtime inside
@@ -367,6 +1120,20 @@ where
         self.service_context.control()
     }
 
+    /// Spawn the service on the runtime and drive it to completion,
+    /// return the handle of the spawned task and the service control
+    ///
+    /// Must be called inside the runtime. Listen before it, the rest can be done by the control.
+    /// The `Stream` impl is left for the users who want to drive the service by themselves
+    pub fn run(self) -> (crate::runtime::JoinHandle<()>, ServiceControl)
+    where
+        T: Send + 'static,
+    {
+        let control = self.control().clone();
+        let handle = crate::runtime::spawn(self.for_each(|_| future::ready(())));
+        (handle, control)
+    }
+
     /// Distribute event to sessions
     #[inline]
     fn distribute_to_session(&mut self, cx: &mut Context) {
@@ -374,20 +1141,16 @@ where
             return;
         }
 
-        for control in self
-            .sessions
-            .values_mut()
-            .filter(|control| !control.buffer.is_empty())
-        {
+        // Not changed by the loop, the sessions are closed on their close events
+        let session_order = ::std::mem::take(&mut self.session_order);
+        for &(_, id) in session_order.iter() {
+            let control = match self.sessions.get_mut(&id) {
+                Some(control) if !control.buffer.is_empty() => control,
+                _ => continue,
+            };
             if let SendResult::Pending = control.try_send(cx) {
                 if control.inner.pending_data_size() > self.config.session_config.send_buffer_size {
-                    self.handle.handle_error(
-                        &mut self.service_context,
-                        ServiceError::SessionBlocked {
-                            session_context: control.inner.clone(),
-                        },
-                    );
-
+                    let session_context = control.inner.clone();
                     warn!(
                         "session {:?} unable to send message, \
                          user allow buffer size: {}, \
@@ -402,9 +1165,22 @@ where
                     let id = control.inner.id;
                     control.push(Priority::High, SessionEvent::SessionClose { id });
                     control.try_send(cx);
+                    self.report_error(ServiceError::SessionBlocked { session_context });
                 }
             }
         }
+        self.session_order = session_order;
+    }
+
+    /// Place the session in the distribution order, after the sessions of the same level
+    fn order_session(&mut self, id: SessionId, level: u8) {
+        self.session_order.retain(|(_, session)| *session != id);
+        let index = self
+            .session_order
+            .iter()
+            .position(|(other, _)| *other < level)
+            .unwrap_or_else(|| self.session_order.len());
+        self.session_order.insert(index, (level, id));
     }
 
     /// Distribute event to user level
@@ -414,6 +1190,8 @@ where
             return;
         }
         let mut error = false;
+        let mut errors = Vec::new();
+        let lagging_threshold = self.config.handle_lagging_threshold;
 
         for (proto_id, buffer) in self
             .service_proto_handles
@@ -423,24 +1201,26 @@ where
             match buffer.try_send(cx) {
                 SendResult::Pending => {
                     let error = ProtocolHandleErrorKind::Block(None);
-                    self.handle.handle_error(
-                        &mut self.service_context,
-                        ServiceError::ProtocolHandleError {
-                            proto_id: *proto_id,
-                            error,
-                        },
-                    );
+                    errors.push(ServiceError::ProtocolHandleError {
+                        proto_id: *proto_id,
+                        error,
+                    });
                 }
                 SendResult::Ok => (),
                 SendResult::Disconnect => {
                     error = true;
-                    self.handle.handle_error(
-                        &mut self.service_context,
-                        ServiceError::ProtocolHandleError {
-                            proto_id: *proto_id,
-                            error: ProtocolHandleErrorKind::AbnormallyClosed(None),
-                        },
-                    );
+                    errors.push(ServiceError::ProtocolHandleError {
+                        proto_id: *proto_id,
+                        error: ProtocolHandleErrorKind::AbnormallyClosed(None),
+                    });
+                }
+            }
+            if let Some(threshold) = lagging_threshold {
+                if buffer.check_lagging(threshold) {
+                    errors.push(ServiceError::HandleLagging {
+                        proto_id: *proto_id,
+                        buffered: buffer.len(),
+                    });
                 }
             }
         }
@@ -453,31 +1233,52 @@ where
             match buffer.try_send(cx) {
                 SendResult::Pending => {
                     let error = ProtocolHandleErrorKind::Block(Some(*session_id));
-                    self.handle.handle_error(
-                        &mut self.service_context,
-                        ServiceError::ProtocolHandleError {
-                            proto_id: *proto_id,
-                            error,
-                        },
-                    );
+                    errors.push(ServiceError::ProtocolHandleError {
+                        proto_id: *proto_id,
+                        error,
+                    });
                 }
                 SendResult::Ok => (),
                 SendResult::Disconnect => {
                     error = true;
-                    self.handle.handle_error(
-                        &mut self.service_context,
-                        ServiceError::ProtocolHandleError {
-                            proto_id: *proto_id,
-                            error: ProtocolHandleErrorKind::AbnormallyClosed(Some(*session_id)),
-                        },
-                    )
+                    errors.push(ServiceError::ProtocolHandleError {
+                        proto_id: *proto_id,
+                        error: ProtocolHandleErrorKind::AbnormallyClosed(Some(*session_id)),
+                    })
+                }
+            }
+            if let Some(threshold) = lagging_threshold {
+                if buffer.check_lagging(threshold) {
+                    errors.push(ServiceError::HandleLagging {
+                        proto_id: *proto_id,
+                        buffered: buffer.len(),
+                    });
                 }
             }
         }
 
+        for error in errors {
+            self.report_error(error);
+        }
         if error {
+            self.handle_closed(cx);
+        }
+    }
+
+    /// Some protocol handles closed unexpectedly, such as handle panic
+    fn handle_closed(&mut self, cx: &mut Context) {
+        match self.config.handle_closed_policy {
             // if handle panic, close service
-            self.handle_service_task(cx, ServiceTask::Shutdown(false), Priority::High);
+            HandleClosedPolicy::Shutdown => {
+                self.handle_service_task(cx, ServiceTask::Shutdown(false), Priority::High)
+            }
+            // only give up the closed handles, the events sent to them later are dropped
+            HandleClosedPolicy::DropProtocol => {
+                self.service_proto_handles
+                    .retain(|_, buffer| !buffer.is_closed());
+                self.session_proto_handles
+                    .retain(|_, buffer| !buffer.is_closed());
+            }
         }
     }
 
@@ -490,38 +1291,50 @@ where
         Option<futures::channel::oneshot::Sender<()>>,
         crate::runtime::JoinHandle<()>,
     )> {
-        let mut handles = Vec::new();
-        for (proto_id, meta) in self.protocol_configs.iter_mut() {
-            if let ProtocolHandle::Callback(handle) = meta.session_handle() {
-                if let Some(session_control) = self.sessions.get(&id) {
-                    debug!("init session [{}] level proto [{}] handle", id, proto_id);
-                    let (sender, receiver) = mpsc::channel(RECEIVED_SIZE);
-                    self.session_proto_handles
-                        .insert((id, *proto_id), Buffer::new(sender));
+        let ids = self.protocol_configs.keys().copied().collect::<Vec<_>>();
+        ids.into_iter()
+            .filter_map(|proto_id| self.session_handle_open(id, proto_id))
+            .collect()
+    }
 
-                    let stream = SessionProtocolStream::new(
-                        handle,
-                        self.service_context.clone_self(),
-                        Arc::clone(&session_control.inner),
-                        receiver,
-                        (*proto_id, meta.blocking_flag()),
-                        self.session_event_sender.clone(),
-                        (
-                            self.shutdown.clone(),
-                            self.future_task_sender.clone_sender(),
-                        ),
-                    );
-                    let (sender, receiver) = futures::channel::oneshot::channel();
-                    let handle = crate::runtime::spawn(async move {
-                        future::select(stream.for_each(|_| future::ready(())), receiver).await;
-                    });
-                    handles.push((Some(sender), handle));
-                }
-            } else {
-                debug!("can't find proto [{}] session handle", proto_id);
-            }
+    /// Spawn the session level handle of the protocol on the session
+    fn session_handle_open(
+        &mut self,
+        id: SessionId,
+        proto_id: ProtocolId,
+    ) -> Option<(
+        Option<futures::channel::oneshot::Sender<()>>,
+        crate::runtime::JoinHandle<()>,
+    )> {
+        let meta = self.protocol_configs.get_mut(&proto_id)?;
+        if let ProtocolHandle::Callback(handle) = meta.session_handle() {
+            let session_control = self.sessions.get(&id)?;
+            debug!("init session [{}] level proto [{}] handle", id, proto_id);
+            let (sender, receiver) = mpsc::channel(RECEIVED_SIZE);
+            self.session_proto_handles
+                .insert((id, proto_id), Buffer::new(sender));
+
+            let stream = SessionProtocolStream::new(
+                handle,
+                self.service_context.clone_self(),
+                Arc::clone(&session_control.inner),
+                receiver,
+                (proto_id, meta.blocking_flag()),
+                self.session_event_sender.clone(),
+                (
+                    self.shutdown.clone(),
+                    self.future_task_sender.clone_sender(),
+                ),
+            );
+            let (sender, receiver) = futures::channel::oneshot::channel();
+            let handle = crate::runtime::spawn(async move {
+                future::select(stream.for_each(|_| future::ready(())), receiver).await;
+            });
+            Some((Some(sender), handle))
+        } else {
+            debug!("can't find proto [{}] session handle", proto_id);
+            None
         }
-        handles
     }
 
     fn handle_message(
@@ -536,13 +1349,29 @@ where
             Some(function) => function(data),
             None => data,
         };
+        let (coalesce, priority) = match self.protocol_configs.get(&proto_id) {
+            // Ahead of the normal priority messages, still in order within the protocol
+            Some(meta) if meta.inner.low_latency => (None, Priority::High),
+            Some(meta) => (meta.inner.coalesce, priority),
+            None => (None, priority),
+        };
+        let watermarks = self.config.session_buffer_watermarks;
+        let mut pushed = Vec::new();
+        let mut push = |id: SessionId, control: &mut SessionController, data: Bytes| {
+            pushed.push((
+                id,
+                Self::push_message(control, proto_id, priority, data, coalesce, watermarks),
+            ))
+        };
 
         match target {
             // Send data to the specified protocol for the specified session.
             TargetSession::Single(id) => {
                 if let Some(control) = self.sessions.get_mut(&id) {
-                    control.push_message(proto_id, priority, data);
+                    push(id, control, data);
                     control.try_send(cx);
+                } else {
+                    self.message_dropped(id, proto_id, DropReason::SessionNotFound)
                 }
             }
             // Send data to the specified protocol for the specified sessions.
@@ -557,9 +1386,61 @@ where
                         proto_id,
                         data.len()
                     );
-                    control.push_message(proto_id, priority, data.clone());
+                    push(*id, control, data.clone());
                     control.try_send(cx);
                 }),
+            // Send data to the specified protocol for the sessions matching their context.
+            TargetSession::FilterContext(filter) => self
+                .sessions
+                .iter_mut()
+                .filter(|(_, control)| filter(&control.inner))
+                .for_each(|(id, control)| {
+                    debug!(
+                        "send message to session [{}], proto [{}], data len: {}",
+                        id,
+                        proto_id,
+                        data.len()
+                    );
+                    push(*id, control, data.clone());
+                    control.try_send(cx);
+                }),
+            // Send data to the sessions which negotiated a high enough version of the protocol.
+            TargetSession::MinVersion(min_version) => {
+                for (id, control) in self.sessions.iter_mut() {
+                    match control.inner.protocol_version(proto_id) {
+                        Some(version) if version >= min_version => {
+                            debug!(
+                                "send message to session [{}], proto [{}] version [{}], data len: {}",
+                                id,
+                                proto_id,
+                                version,
+                                data.len()
+                            );
+                            push(*id, control, data.clone());
+                            control.try_send(cx);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            // Broadcast data for a specified protocol, skipping the excluded sessions.
+            TargetSession::Except(excluded) => {
+                debug!(
+                    "broadcast message, peer count: {}, excluded: {:?}, proto_id: {}, data len: {}",
+                    self.sessions.len(),
+                    excluded,
+                    proto_id,
+                    data.len()
+                );
+                for (id, control) in self
+                    .sessions
+                    .iter_mut()
+                    .filter(|(id, _)| !excluded.contains(id))
+                {
+                    push(*id, control, data.clone());
+                    control.try_send(cx);
+                }
+            }
             // Broadcast data for a specified protocol.
             TargetSession::All => {
                 debug!(
@@ -568,12 +1449,103 @@ where
                     proto_id,
                     data.len()
                 );
-                for control in self.sessions.values_mut() {
-                    control.push_message(proto_id, priority, data.clone());
+                for (id, control) in self.sessions.iter_mut() {
+                    push(*id, control, data.clone());
                     control.try_send(cx);
                 }
             }
         }
+
+        for (id, pushed) in pushed {
+            match pushed {
+                Pushed::Queued => (),
+                Pushed::Batch(delay) => self.flush_coalesced_later(id, proto_id, delay),
+                Pushed::Blocked => {
+                    if let Some(control) = self.sessions.get(&id) {
+                        let session_context = control.inner.clone();
+                        self.report_error(ServiceError::SessionBlocked { session_context });
+                    }
+                    self.message_dropped(id, proto_id, DropReason::SessionBlocked)
+                }
+                Pushed::Dropped => self.message_dropped(id, proto_id, DropReason::SessionBlocked),
+            }
+        }
+    }
+
+    /// Push the message to the session, or hold it if the protocol coalesces the small messages,
+    /// or drop it if the unsent data of the session is over the watermarks
+    fn push_message(
+        control: &mut SessionController,
+        proto_id: ProtocolId,
+        priority: Priority,
+        data: Bytes,
+        coalesce: Option<(Duration, usize)>,
+        watermarks: Option<(usize, usize)>,
+    ) -> Pushed {
+        if let Some((high, low)) = watermarks {
+            let pending = control.inner.pending_data_size();
+            let reached = !control.blocked && pending >= high;
+            // Blocked from the high watermark until the data falls to the low one
+            control.blocked = if control.blocked {
+                pending > low
+            } else {
+                reached
+            };
+            if control.blocked {
+                control.inner.incr_dropped_messages();
+                return if reached {
+                    Pushed::Blocked
+                } else {
+                    Pushed::Dropped
+                };
+            }
+        }
+        match coalesce {
+            Some((delay, max_size)) if !priority.is_high() => {
+                if data.len() <= max_size {
+                    return if control.coalesce_message(proto_id, data) {
+                        Pushed::Batch(delay)
+                    } else {
+                        Pushed::Queued
+                    };
+                }
+                // Keep the order with the messages held before it
+                control.flush_coalesced(proto_id);
+                control.push_message(proto_id, priority, data);
+                Pushed::Queued
+            }
+            _ => {
+                control.push_message(proto_id, priority, data);
+                Pushed::Queued
+            }
+        }
+    }
+
+    /// Hand the coalesced messages to the session when the delay is reached
+    fn flush_coalesced_later(&mut self, id: SessionId, proto_id: ProtocolId, delay: Duration) {
+        let mut sender = self.service_context.control().task_sender.clone();
+        let task = async move {
+            crate::runtime::delay_for(delay).await;
+            let task = ServiceTask::FlushCoalesced {
+                session_id: id,
+                proto_id,
+            };
+            if sender.send(task).await.is_err() {
+                trace!("session [{}] flush coalesced send err", id)
+            }
+        };
+        self.future_task_sender.push(Box::pin(task));
+    }
+
+    /// Count a message dropped by the service, and report it if it's sampled
+    fn message_dropped(&mut self, id: SessionId, proto_id: ProtocolId, reason: DropReason) {
+        if self.service_context.control().drop_log.record() {
+            self.report_error(ServiceError::MessageDropped {
+                id,
+                proto_id,
+                reason,
+            })
+        }
     }
 
     /// Handshake
@@ -581,9 +1553,11 @@ where
     fn handshake<H>(
         &mut self,
         socket: H,
+        muxer: Option<Arc<dyn StreamMuxer>>,
         ty: SessionType,
         remote_address: Multiaddr,
         listen_address: Option<Multiaddr>,
+        local_address: Option<SocketAddr>,
     ) where
         H: AsyncRead + AsyncWrite + Send + 'static + Unpin,
     {
@@ -591,9 +1565,11 @@ where
             ty,
             remote_address,
             listen_address,
-            key_pair: self.service_context.key_pair().cloned(),
+            local_address,
+            security: self.config.security.clone(),
+            muxer,
+            protocols: self.exchanged_protocols(),
             event_sender: self.session_event_sender.clone(),
-            max_frame_length: self.config.max_frame_length,
             timeout: self.config.timeout,
         }
         .handshake(socket);
@@ -611,6 +1587,15 @@ where
         });
     }
 
+    /// The local protocols to exchange in the handshake, if enabled
+    fn exchanged_protocols(&self) -> Option<Arc<HashMap<ProtocolId, ProtocolInfo>>> {
+        if self.config.exchange_protocols {
+            Some(self.service_context.protocols())
+        } else {
+            None
+        }
+    }
+
     fn generate_next_session(&mut self) {
         loop {
             self.next_session = self.next_session.wrapping_add(1);
@@ -620,6 +1605,22 @@ where
         }
     }
 
+    /// Whether the listeners limit is reached, counting the pending ones not yet listened
+    fn reached_max_listeners(&self, pending: usize) -> bool {
+        self.config
+            .max_listeners
+            .map(|max| self.listens.len() + pending >= max)
+            .unwrap_or(false)
+    }
+
+    /// Output the listen error of the listeners limit
+    fn too_many_listeners(&mut self, address: Multiaddr) {
+        self.report_error(ServiceError::ListenError {
+            address,
+            error: ListenErrorKind::TooManyListeners(self.config.max_listeners.unwrap_or_default()),
+        });
+    }
+
     fn reached_max_connection_limit(&self) -> bool {
         self.sessions
             .len()
@@ -628,16 +1629,122 @@ where
             .unwrap_or_default()
     }
 
+    /// Close the handshaked connection over `max_connection_number` and output the error
+    fn session_over_max_connection<H>(
+        &mut self,
+        cx: &mut Context,
+        mut handle: H,
+        address: Multiaddr,
+        ty: SessionType,
+    ) where
+        H: AsyncRead + AsyncWrite + Unpin,
+    {
+        debug!("session with {} is over the max connection number", address);
+        if let Poll::Ready(Err(e)) = Pin::new(&mut handle).poll_shutdown(cx) {
+            trace!("handle poll shutdown err {}", e)
+        }
+        if ty.is_outbound() {
+            self.dial_protocols.remove(&address);
+            self.dial_peer_ids.remove(&address);
+            self.peer_dials.remove(&address);
+            self.dial_retries.remove(&address);
+        }
+        self.handle_dial_error(ServiceError::ConnectionLimit {
+            ty,
+            address: address.clone(),
+            limit: self.config.max_connection_number,
+        });
+        if ty.is_outbound() {
+            self.reconnect_later(&address);
+        }
+    }
+
+    /// The connection limit reached by a new session of this type, if any
+    fn reached_connection_limit(&self, ty: SessionType) -> Option<usize> {
+        let type_limit = if ty.is_outbound() {
+            self.config.max_outbound
+        } else {
+            self.config.max_inbound
+        };
+        type_limit
+            .filter(|limit| {
+                self.sessions
+                    .values()
+                    .filter(|session| session.inner.ty == ty)
+                    .count()
+                    >= *limit
+            })
+            .or_else(|| {
+                self.config
+                    .max_connections
+                    .filter(|limit| self.sessions.len() >= *limit)
+            })
+    }
+
+    /// Close the session when its lifetime is reached,
+    /// the deadline is staggered within the last quarter of the lifetime
+    fn session_lifetime_check(&mut self, id: SessionId, lifetime: Duration) -> TaskHandle {
+        // spread the consecutive session ids over [0, 1) by the fibonacci hashing,
+        // so the stagger needs no rng, which isn't available on wasm
+        let stagger = (id.value() as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 11;
+        let deadline = lifetime - (lifetime / 4).mul_f64(stagger as f64 / (1u64 << 53) as f64);
+        let control = self.service_context.control().clone();
+        let (handle, task) = TaskHandle::new(async move {
+            crate::runtime::delay_for(deadline).await;
+            debug!("session [{}] reached max lifetime, close it", id);
+            if control.disconnect(id).is_err() {
+                trace!("session [{}] lifetime close send err", id)
+            }
+        });
+        self.future_task_sender.push(task);
+        handle
+    }
+
+    /// Close the connection vetoed by the connection gater and output the error
+    fn session_gated<H>(
+        &mut self,
+        cx: &mut Context,
+        handle: &mut H,
+        ty: SessionType,
+        address: Multiaddr,
+        listen_addr: Option<Multiaddr>,
+    ) where
+        H: AsyncRead + AsyncWrite + Unpin,
+    {
+        debug!("session with {} is vetoed by the gater", address);
+        if let Poll::Ready(Err(e)) = Pin::new(handle).poll_shutdown(cx) {
+            trace!("handle poll shutdown err {}", e)
+        }
+        let error = if ty.is_outbound() {
+            ServiceError::DialerError {
+                address,
+                error: DialerErrorKind::Gated,
+            }
+        } else {
+            ServiceError::ListenError {
+                address: listen_addr.expect("listen address must exist"),
+                error: ListenErrorKind::Gated,
+            }
+        };
+        self.handle_dial_error(error);
+    }
+
     /// Session open
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     fn session_open<H>(
         &mut self,
         cx: &mut Context,
         mut handle: H,
+        muxer: Option<Arc<dyn StreamMuxer>>,
         remote_pubkey: Option<PublicKey>,
+        exporter: Option<KeyExporter>,
+        security_params: Option<SecurityParams>,
         mut address: Multiaddr,
         ty: SessionType,
         listen_addr: Option<Multiaddr>,
+        local_address: Option<SocketAddr>,
+        remote_protocols: Option<Vec<ProtocolInfo>>,
     ) where
         H: AsyncRead + AsyncWrite + Send + 'static + Unpin,
     {
@@ -645,54 +1752,134 @@ where
             .dial_protocols
             .remove(&address)
             .unwrap_or(TargetProtocol::All);
+        self.dial_retries.remove(&address);
+        let expected_peer_id = self.dial_peer_ids.remove(&address);
+        let peer_dial = self.peer_dials.remove(&address);
+        // the address may be changed below, keep the dialed one
+        let persistent_address = if ty.is_outbound() && self.persistent_peers.contains_key(&address)
+        {
+            Some(address.clone())
+        } else {
+            None
+        };
+        let dialed_address = if ty.is_outbound() && self.dial_results.contains_key(&address) {
+            Some(address.clone())
+        } else {
+            None
+        };
+        let race_address = if ty.is_outbound() && self.race_addrs.contains_key(&address) {
+            Some(address.clone())
+        } else {
+            None
+        };
+        if let Some(ref gater) = self.config.gater {
+            let peer_id = remote_pubkey.as_ref().map(|key| self.config.peer_id(key));
+            if !gater.intercept_secured(ty, &address, peer_id.as_ref()) {
+                self.session_gated(cx, &mut handle, ty, address, listen_addr);
+                if let Some(address) = persistent_address {
+                    self.reconnect_later(&address);
+                }
+                return;
+            }
+        }
+        if let Some(peer_id) = remote_pubkey
+            .as_ref()
+            .map(|key| self.config.peer_id(key))
+            .filter(|peer_id| self.banned_peers.contains_key(peer_id))
+        {
+            debug!(
+                "session with {} is refused, peer {:?} is banned",
+                address, peer_id
+            );
+            if let Poll::Ready(Err(e)) = Pin::new(&mut handle).poll_shutdown(cx) {
+                trace!("handle poll shutdown err {}", e)
+            }
+            let error = if ty.is_outbound() {
+                ServiceError::DialerError {
+                    address,
+                    error: DialerErrorKind::Banned(peer_id),
+                }
+            } else {
+                ServiceError::ListenError {
+                    address: listen_addr.expect("listen address must exist"),
+                    error: ListenErrorKind::Banned(peer_id),
+                }
+            };
+            self.handle_dial_error(error);
+            if let Some(address) = persistent_address {
+                self.reconnect_later(&address);
+            }
+            return;
+        }
+        if let Some(limit) = self.reached_connection_limit(ty) {
+            debug!(
+                "session with {} is over the connection limit {}",
+                address, limit
+            );
+            if let Poll::Ready(Err(e)) = Pin::new(&mut handle).poll_shutdown(cx) {
+                trace!("handle poll shutdown err {}", e)
+            }
+            self.handle_dial_error(ServiceError::ConnectionLimit { ty, address, limit });
+            if let Some(address) = persistent_address {
+                self.reconnect_later(&address);
+            }
+            return;
+        }
         if let Some(ref key) = remote_pubkey {
             // If the public key exists, the connection has been established
             // and then the useless connection needs to be closed.
-            match self
-                .sessions
-                .values()
-                .find(|&context| context.inner.remote_pubkey.as_ref() == Some(key))
-            {
-                Some(context) => {
+            match self.peer_sessions.get(&self.config.peer_id(key)) {
+                Some(id) => {
                     trace!("Connected to the connected node");
                     if let Poll::Ready(Err(e)) = Pin::new(&mut handle).poll_shutdown(cx) {
                         trace!("handle poll shutdown err {}", e)
                     }
                     if ty.is_outbound() {
-                        self.handle.handle_error(
-                            &mut self.service_context,
-                            ServiceError::DialerError {
-                                error: DialerErrorKind::RepeatedConnection(context.inner.id),
-                                address,
-                            },
-                        );
+                        self.handle_dial_error(ServiceError::DialerError {
+                            error: DialerErrorKind::RepeatedConnection(*id),
+                            address,
+                        });
                     } else {
-                        self.handle.handle_error(
-                            &mut self.service_context,
-                            ServiceError::ListenError {
-                                error: ListenErrorKind::RepeatedConnection(context.inner.id),
-                                address: listen_addr.expect("listen address must exist"),
-                            },
-                        );
+                        self.report_error(ServiceError::ListenError {
+                            error: ListenErrorKind::RepeatedConnection(*id),
+                            address: listen_addr.expect("listen address must exist"),
+                        });
+                    }
+                    if let Some(address) = persistent_address {
+                        self.reconnect_later(&address);
                     }
                     return;
                 }
                 None => {
                     // if peer id doesn't match return an error
-                    if let Some(peer_id) = extract_peer_id(&address) {
-                        if key.peer_id() != peer_id {
-                            trace!("Peer id not match");
-                            self.handle.handle_error(
-                                &mut self.service_context,
-                                ServiceError::DialerError {
-                                    error: DialerErrorKind::PeerIdNotMatch,
-                                    address,
-                                },
-                            );
-                            return;
+                    let remote_peer_id = self.config.peer_id(key);
+                    let embedded_peer_id = self.config.extract_peer_id(&address);
+                    let not_match = embedded_peer_id
+                        .iter()
+                        .chain(expected_peer_id.iter())
+                        .any(|peer_id| peer_id != &remote_peer_id);
+                    if not_match {
+                        trace!("Peer id not match");
+                        if let (Some(store), Some(peer_id)) =
+                            (self.config.peer_store.as_ref(), expected_peer_id.as_ref())
+                        {
+                            store.remove(peer_id, &address);
                         }
-                    } else {
-                        address.push(Protocol::P2P(Cow::Owned(key.peer_id().into_bytes())))
+                        self.handle_dial_error(ServiceError::DialerError {
+                            error: DialerErrorKind::PeerIdNotMatch,
+                            address,
+                        });
+                        // the address is another peer now, try the next one
+                        if let Some((peer_id, addrs)) = peer_dial {
+                            self.dial_peer_addrs(peer_id, addrs, target);
+                        }
+                        if let Some(address) = persistent_address {
+                            self.reconnect_later(&address);
+                        }
+                        return;
+                    }
+                    if embedded_peer_id.is_none() {
+                        address.push(Protocol::P2P(Cow::Owned(remote_peer_id.into_bytes())))
                     }
                 }
             }
@@ -702,24 +1889,83 @@ where
 
         let session_closed = Arc::new(AtomicBool::new(false));
         let pending_data_size = Arc::new(AtomicUsize::new(0));
+        let session_context = SessionContext::new(
+            self.next_session,
+            address,
+            ty,
+            remote_pubkey,
+            exporter,
+            security_params,
+            local_address,
+            session_closed,
+            pending_data_size,
+        )
+        .derive_peer_id(&self.config)
+        .export_traffic(self.config.metrics.clone())
+        .remote_protocols_exchanged(remote_protocols);
+        session_context.set_rate_limit(self.config.global_rate_limit);
+        if let Some(ref gater) = self.config.gater {
+            if !gater.intercept_upgraded(&session_context) {
+                if let Some(address) = dialed_address {
+                    self.complete_dial_results(&address, Err(DialFailure::Gated));
+                }
+                // a racing address is reported as dialed, so the race goes on
+                let address = race_address.unwrap_or_else(|| session_context.address.clone());
+                self.session_gated(cx, &mut handle, ty, address, listen_addr);
+                // re-dial the address as dialed, not the one with the peer id appended
+                if let Some(address) = persistent_address {
+                    self.reconnect_later(&address);
+                }
+                return;
+            }
+        }
+
+        if let Some(peer) = persistent_address
+            .as_ref()
+            .and_then(|address| self.persistent_peers.get_mut(address))
+        {
+            peer.attempts = 0;
+            peer.session = Some(self.next_session);
+        }
+
+        // the first address of the race to open the session wins
+        let target = match race_address.and_then(|address| self.win_addr_race(&address)) {
+            Some(target) => target,
+            None => target,
+        };
+
         let (service_event_sender, service_event_receiver) = priority_mpsc::channel(SEND_SIZE);
-        let session_control = SessionController::new(
-            service_event_sender.clone(),
-            Arc::new(SessionContext::new(
-                self.next_session,
-                address,
-                ty,
-                remote_pubkey,
-                session_closed,
-                pending_data_size,
-            )),
-        );
+        let mut session_control =
+            SessionController::new(service_event_sender.clone(), Arc::new(session_context));
+
+        if let Some(lifetime) = self.config.max_session_lifetime {
+            session_control.lifetime_task =
+                Some(self.session_lifetime_check(self.next_session, lifetime));
+        }
 
         let session_context = session_control.inner.clone();
 
+        if let Some(ref key) = session_context.remote_pubkey {
+            let peer_id = self.config.peer_id(key);
+            if let (Some(store), SessionType::Outbound) =
+                (self.config.peer_store.as_ref(), session_context.ty)
+            {
+                store.add_addr(peer_id.clone(), session_context.address.clone());
+            }
+            self.peer_sessions.insert(peer_id, session_context.id);
+        }
+
+        self.service_context
+            .control()
+            .sessions
+            .write()
+            .insert(session_context.id, session_context.clone());
+
         // must insert here, otherwise, the session protocol handle cannot be opened
+        self.order_session(session_control.inner.id, session_control.priority_level);
         self.sessions
             .insert(session_control.inner.id, session_control);
+        self.config.metrics.session_open(ty);
 
         // Open all session protocol handles
         let handles = self.session_handles_open(self.next_session);
@@ -741,7 +1987,22 @@ where
         .protocol_by_name(by_name)
         .protocol_by_id(by_id)
         .config(self.config.session_config)
+        .muxer(muxer.or_else(|| self.config.muxer.clone()))
+        .allowed_protocols(
+            session_context
+                .remote_pubkey
+                .as_ref()
+                .and_then(|key| {
+                    self.config
+                        .protocol_allowlist
+                        .get(&self.config.peer_id(key))
+                })
+                .cloned(),
+        )
+        .downgraded_protocols(self.downgraded_protocols(&session_context))
         .keep_buffer(self.config.keep_buffer)
+        .message_latency(self.message_latency.clone())
+        .memory_budget(self.memory_budget.clone())
         .service_proto_senders(self.service_proto_handles.clone())
         .session_senders(
             self.session_proto_handles
@@ -782,6 +2043,18 @@ where
                     .iter()
                     .filter(|(id, _)| filter(id))
                     .for_each(|(_, meta)| session.open_proto_stream(&meta.name())),
+                TargetProtocol::Fallback(proto_ids) => session.open_proto_stream_with_fallback(
+                    proto_ids
+                        .iter()
+                        .filter_map(|id| self.protocol_configs.get(id))
+                        .map(ProtocolMeta::name)
+                        .collect(),
+                ),
+                TargetProtocol::Version(proto_id, version) => {
+                    if let Some(meta) = self.protocol_configs.get(&proto_id) {
+                        session.open_proto_stream_with_version(&meta.name(), version);
+                    }
+                }
             }
         }
 
@@ -791,6 +2064,9 @@ where
             &mut self.service_context,
             ServiceEvent::SessionOpen { session_context },
         );
+        if let Some(address) = dialed_address {
+            self.complete_dial_results(&address, Ok(self.next_session));
+        }
     }
 
     /// Close the specified session, clean up the handle
@@ -811,6 +2087,23 @@ where
         self.session_proto_handles.retain(|key, _| id != key.0);
 
         if let Some(session_control) = self.sessions.remove(&id) {
+            self.session_order.retain(|(_, session)| *session != id);
+            self.config.metrics.session_close();
+            self.service_context.control().sessions.write().remove(&id);
+            if let Some(ref key) = session_control.inner.remote_pubkey {
+                self.peer_sessions.remove(&self.config.peer_id(key));
+            }
+            if self.observed_addrs.remove(&id).is_some() {
+                self.update_external_address();
+            }
+            if let Some(address) = self
+                .persistent_peers
+                .iter()
+                .find(|(_, peer)| peer.session == Some(id))
+                .map(|(address, _)| address.clone())
+            {
+                self.reconnect_later(&address);
+            }
             // Service handle processing flow
             self.handle.handle_event(
                 &mut self.service_context,
@@ -821,21 +2114,149 @@ where
         }
     }
 
+    /// Elect the observed address reported by the most sessions as the external address,
+    /// the current one is kept on a tie
+    fn update_external_address(&mut self) {
+        let mut votes: HashMap<&Multiaddr, usize> = HashMap::default();
+        for address in self.observed_addrs.values() {
+            *votes.entry(address).or_default() += 1;
+        }
+        let current = self
+            .external_address
+            .as_ref()
+            .and_then(|address| votes.get(address))
+            .copied()
+            .unwrap_or(0);
+        let elected = votes
+            .into_iter()
+            .filter(|(_, count)| *count > current)
+            .max_by(|(a, x), (b, y)| x.cmp(y).then_with(|| b.to_vec().cmp(&a.to_vec())))
+            .map(|(address, _)| address.clone());
+
+        if let Some(address) = elected {
+            debug!("external address changed to {}", address);
+            self.external_address = Some(address.clone());
+            self.handle.handle_event(
+                &mut self.service_context,
+                ServiceEvent::ExternalAddressChanged { address },
+            );
+        }
+    }
+
     /// Open the handle corresponding to the protocol
     #[inline]
-    fn protocol_open(&mut self, cx: &mut Context, id: SessionId, proto_id: ProtocolId) {
+    fn protocol_open(
+        &mut self,
+        cx: &mut Context,
+        id: SessionId,
+        proto_id: ProtocolId,
+        version: Option<String>,
+    ) {
         if let Some(control) = self.sessions.get_mut(&id) {
-            control.push(Priority::High, SessionEvent::ProtocolOpen { proto_id });
+            control.push(
+                Priority::High,
+                SessionEvent::ProtocolOpen { proto_id, version },
+            );
             debug!("try open session [{}] proto [{}]", id, proto_id);
             control.try_send(cx);
         }
     }
 
+    /// Set the numbers of the events waiting in the buffers of the service to the metrics
+    fn export_buffer_lens(&self) {
+        let metrics = &self.config.metrics;
+        metrics.buffer_len("pending_task", self.future_task_sender.len());
+        metrics.buffer_len(
+            "write",
+            self.sessions
+                .values()
+                .map(|item| item.buffer.len())
+                .sum::<usize>(),
+        );
+        metrics.buffer_len(
+            "read_service",
+            self.service_proto_handles
+                .values()
+                .map(Buffer::len)
+                .sum::<usize>(),
+        );
+        metrics.buffer_len(
+            "read_session",
+            self.session_proto_handles
+                .values()
+                .map(Buffer::len)
+                .sum::<usize>(),
+        );
+    }
+
+    /// Count a failed negotiation of the protocol with the peer of the session
+    fn record_select_failure(&mut self, session_context: &SessionContext, proto_name: &str) {
+        let ttl = match self.config.version_downgrade {
+            Some((_, ttl)) => ttl,
+            None => return,
+        };
+        let peer_id = match session_context.remote_pubkey {
+            Some(ref key) => self.config.peer_id(key),
+            None => return,
+        };
+        let proto_id = match self
+            .protocol_configs
+            .iter()
+            .find(|(_, meta)| meta.name() == proto_name)
+        {
+            Some((proto_id, _)) => *proto_id,
+            None => return,
+        };
+        let failure = self
+            .select_failures
+            .entry((peer_id, proto_id))
+            .or_insert((0, Instant::now()));
+        // the failures out of ttl are forgotten
+        if failure.1.elapsed() > ttl {
+            failure.0 = 0;
+        }
+        *failure = (failure.0 + 1, Instant::now());
+    }
+
+    /// Protocols which failed to negotiate with the peer of the session too many times
+    fn downgraded_protocols(&mut self, session_context: &SessionContext) -> IntSet<ProtocolId> {
+        let (failures, ttl) = match self.config.version_downgrade {
+            Some(downgrade) => downgrade,
+            None => return IntSet::default(),
+        };
+        self.select_failures
+            .retain(|_, (_, last)| last.elapsed() <= ttl);
+        let peer_id = match session_context.remote_pubkey {
+            Some(ref key) => self.config.peer_id(key),
+            None => return IntSet::default(),
+        };
+        self.select_failures
+            .iter()
+            .filter(|((id, _), (count, _))| id == &peer_id && *count >= failures)
+            .map(|((_, proto_id), _)| *proto_id)
+            .collect()
+    }
+
     /// Protocol stream is closed, clean up data
     #[inline]
     fn protocol_close(&mut self, cx: &mut Context, session_id: SessionId, proto_id: ProtocolId) {
+        let policy = self
+            .protocol_configs
+            .get(&proto_id)
+            .map(|meta| meta.inner.close_policy)
+            .unwrap_or_default();
         if let Some(control) = self.sessions.get_mut(&session_id) {
-            control.push(Priority::High, SessionEvent::ProtocolClose { proto_id });
+            match policy {
+                ProtocolClosePolicy::DropQueued => {
+                    control.drop_messages(proto_id);
+                    control.push(Priority::High, SessionEvent::ProtocolClose { proto_id });
+                }
+                // Queued behind the messages sent before it
+                ProtocolClosePolicy::Flush => {
+                    control.flush_coalesced(proto_id);
+                    control.push(Priority::Normal, SessionEvent::ProtocolClose { proto_id })
+                }
+            }
             debug!("try close session [{}] proto [{}]", session_id, proto_id);
             control.try_send(cx);
         }
@@ -852,37 +2273,93 @@ where
     }
 
     fn init_proto_handles(&mut self) {
-        for (proto_id, meta) in self.protocol_configs.iter_mut() {
-            if let ProtocolHandle::Callback(handle) = meta.service_handle() {
-                debug!("init service level [{}] proto handle", proto_id);
-                let (sender, receiver) = mpsc::channel(RECEIVED_SIZE);
-                self.service_proto_handles
-                    .insert(*proto_id, Buffer::new(sender));
-
-                let mut stream = ServiceProtocolStream::new(
-                    handle,
-                    self.service_context.clone_self(),
-                    receiver,
-                    (*proto_id, meta.blocking_flag()),
-                    self.session_event_sender.clone(),
-                    (
-                        self.shutdown.clone(),
-                        self.future_task_sender.clone_sender(),
-                    ),
+        let ids = self.protocol_configs.keys().copied().collect::<Vec<_>>();
+        for proto_id in ids {
+            self.handle_open(proto_id);
+        }
+    }
+
+    /// Spawn the service level handle of the protocol and set up its before send function
+    fn handle_open(&mut self, proto_id: ProtocolId) {
+        let meta = match self.protocol_configs.get_mut(&proto_id) {
+            Some(meta) => meta,
+            None => return,
+        };
+        if let ProtocolHandle::Callback(handle) = meta.service_handle() {
+            debug!("init service level [{}] proto handle", proto_id);
+            let (sender, receiver) = mpsc::channel(RECEIVED_SIZE);
+            self.service_proto_handles
+                .insert(proto_id, Buffer::new(sender));
+
+            let mut stream = ServiceProtocolStream::new(
+                handle,
+                self.service_context.clone_self(),
+                receiver,
+                (proto_id, meta.blocking_flag()),
+                self.session_event_sender.clone(),
+                (
+                    self.shutdown.clone(),
+                    self.future_task_sender.clone_sender(),
+                ),
+            );
+            stream.handle_event(ServiceProtocolEvent::Init);
+            let (sender, receiver) = futures::channel::oneshot::channel();
+            let handle = crate::runtime::spawn(async move {
+                future::select(stream.for_each(|_| future::ready(())), receiver).await;
+            });
+            self.wait_handle.push((Some(sender), handle));
+        } else {
+            debug!("can't find proto [{}] service handle", proto_id);
+        }
+        if let Some(function) = meta.before_send.take() {
+            self.before_sends.insert(proto_id, function);
+        }
+    }
+
+    /// Register a protocol on the running service, and push it to the open sessions
+    fn register_protocol(
+        &mut self,
+        cx: &mut Context,
+        meta: ProtocolMeta,
+    ) -> std::result::Result<(), RegisterError> {
+        let proto_id = meta.id();
+        if self.protocol_configs.contains_key(&proto_id) {
+            return Err(RegisterError::DuplicateId(proto_id));
+        }
+        let name = meta.name();
+        if self
+            .protocol_configs
+            .values()
+            .any(|registered| registered.name() == name)
+        {
+            return Err(RegisterError::DuplicateName(name));
+        }
+        debug!("register proto [{}] {}", proto_id, name);
+        self.service_context.control().insert_protocol(
+            proto_id,
+            ProtocolInfo::new(&name, meta.support_versions()),
+            meta.inner.low_latency,
+            meta.inner.close_policy,
+        );
+        let inner = meta.inner.clone();
+        self.protocol_configs.insert(proto_id, meta);
+        self.handle_open(proto_id);
+
+        let ids = self.sessions.keys().copied().collect::<Vec<_>>();
+        for id in ids {
+            let handle = self.session_handle_open(id, proto_id);
+            if let Some(control) = self.sessions.get_mut(&id) {
+                control.push(
+                    Priority::High,
+                    SessionEvent::ProtocolRegistered {
+                        meta: inner.clone(),
+                        handle,
+                    },
                 );
-                stream.handle_event(ServiceProtocolEvent::Init);
-                let (sender, receiver) = futures::channel::oneshot::channel();
-                let handle = crate::runtime::spawn(async move {
-                    future::select(stream.for_each(|_| future::ready(())), receiver).await;
-                });
-                self.wait_handle.push((Some(sender), handle));
-            } else {
-                debug!("can't find proto [{}] service handle", proto_id);
-            }
-            if let Some(function) = meta.before_send.take() {
-                self.before_sends.insert(*proto_id, function);
+                control.try_send(cx);
             }
         }
+        Ok(())
     }
 
     /// When listen update, call here
@@ -893,10 +2370,22 @@ where
         if let Some(client) = self.igd_client.as_mut() {
             client.process_only_leases_support()
         }
-        if self.listens.len() == self.service_context.listens().len() {
+        if self.listens.len() == self.advertised_listens {
             return;
         }
-        let new_listens = self.listens.iter().cloned().collect::<Vec<Multiaddr>>();
+        self.advertised_listens = self.listens.len();
+        let new_listens = if self.config.expand_unspecified_listens {
+            let ips = crate::utils::interfaces::local_ip_addrs().unwrap_or_else(|err| {
+                debug!("enumerate local interfaces failed: {}", err);
+                Vec::new()
+            });
+            self.listens
+                .iter()
+                .flat_map(|address| crate::utils::expand_unspecified(address, &ips))
+                .collect::<Vec<Multiaddr>>()
+        } else {
+            self.listens.iter().cloned().collect::<Vec<Multiaddr>>()
+        };
         self.service_context.update_listens(new_listens.clone());
 
         for buffer in self.service_proto_handles.values_mut() {
@@ -916,81 +2405,134 @@ where
 
     /// Handling various events uploaded by the session
     fn handle_session_event(&mut self, cx: &mut Context, event: SessionEvent) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record("session", &event);
+        }
         match event {
             SessionEvent::SessionClose { id } => self.session_close(cx, id, Source::Internal),
             SessionEvent::HandshakeSuccess {
                 handle,
+                muxer,
                 public_key,
+                exporter,
+                security_params,
                 address,
                 ty,
                 listen_address,
+                local_address,
+                remote_protocols,
             } => {
                 if ty.is_outbound() {
                     self.state.decrease();
+                    self.dial_finished();
                 }
                 if !self.reached_max_connection_limit() {
-                    self.session_open(cx, handle, public_key, address, ty, listen_address);
+                    self.session_open(
+                        cx,
+                        handle,
+                        muxer,
+                        public_key,
+                        exporter,
+                        security_params,
+                        address,
+                        ty,
+                        listen_address,
+                        local_address,
+                        remote_protocols,
+                    );
+                } else {
+                    self.session_over_max_connection(cx, handle, address, ty);
                 }
             }
             SessionEvent::HandshakeError { ty, error, address } => {
                 if ty.is_outbound() {
                     self.state.decrease();
-                    self.dial_protocols.remove(&address);
-                    self.handle.handle_error(
-                        &mut self.service_context,
-                        ServiceError::DialerError {
-                            address,
-                            error: DialerErrorKind::HandshakeError(error),
-                        },
-                    )
+                    let target = self.dial_protocols.remove(&address);
+                    self.dial_peer_ids.remove(&address);
+                    self.dial_retries.remove(&address);
+                    self.dial_finished();
+                    self.handle_dial_error(ServiceError::DialerError {
+                        address: address.clone(),
+                        error: error.into(),
+                    });
+                    self.dial_next_peer_addr(&address, target);
+                    self.reconnect_later(&address);
                 }
             }
             SessionEvent::ProtocolMessage { .. }
             | SessionEvent::ProtocolOpen { .. }
-            | SessionEvent::ProtocolClose { .. } => unreachable!(),
-            SessionEvent::ProtocolSelectError { id, proto_name } => {
+            | SessionEvent::ProtocolClose { .. }
+            | SessionEvent::ProtocolReset { .. } => unreachable!(),
+            SessionEvent::ProtocolNotAllowed { id, proto_id } => {
                 if let Some(session_control) = self.sessions.get(&id) {
-                    self.handle.handle_error(
-                        &mut self.service_context,
-                        ServiceError::ProtocolSelectError {
-                            proto_name,
-                            session_context: Arc::clone(&session_control.inner),
-                        },
-                    )
+                    let session_context = Arc::clone(&session_control.inner);
+                    self.report_error(ServiceError::ProtocolNotAllowed {
+                        proto_id,
+                        session_context,
+                    })
+                }
+            }
+            SessionEvent::ProtocolSelectError {
+                id,
+                proto_name,
+                cause,
+            } => {
+                if let (Some(session_control), Some(name), SelectErrorCause::Failed) =
+                    (self.sessions.get(&id), proto_name.as_ref(), cause)
+                {
+                    let session_context = session_control.inner.clone();
+                    self.record_select_failure(&session_context, name);
+                }
+                if let Some(session_control) = self.sessions.get(&id) {
+                    let session_context = Arc::clone(&session_control.inner);
+                    self.report_error(ServiceError::ProtocolSelectError {
+                        proto_name,
+                        cause,
+                        session_context,
+                    })
                 }
             }
             SessionEvent::ProtocolError {
                 id,
                 proto_id,
                 error,
-            } => self.handle.handle_error(
-                &mut self.service_context,
-                ServiceError::ProtocolError {
-                    id,
-                    proto_id,
-                    error,
-                },
-            ),
+            } => self.report_error(ServiceError::ProtocolError {
+                id,
+                proto_id,
+                error,
+            }),
             SessionEvent::DialError { address, error } => {
                 self.state.decrease();
-                self.dial_protocols.remove(&address);
-                self.handle.handle_error(
-                    &mut self.service_context,
-                    ServiceError::DialerError {
-                        address,
-                        error: DialerErrorKind::TransportError(error),
-                    },
-                )
+                let target = self.dial_protocols.remove(&address);
+                let peer_id = self.dial_peer_ids.remove(&address);
+                self.dial_finished();
+                let target = match target {
+                    Some(target)
+                        if error.is_transient() && !self.race_addrs.contains_key(&address) =>
+                    {
+                        match self.retry_dial(&address, target, peer_id) {
+                            Some(target) => Some(target),
+                            // retry later, the failure isn't reported
+                            None => return,
+                        }
+                    }
+                    target => target,
+                };
+                self.dial_retries.remove(&address);
+                self.handle_dial_error(ServiceError::DialerError {
+                    address: address.clone(),
+                    error: error.into(),
+                });
+                self.dial_next_peer_addr(&address, target);
+                self.reconnect_later(&address);
             }
             #[cfg(not(target_arch = "wasm32"))]
             SessionEvent::ListenError { address, error } => {
-                self.handle.handle_error(
-                    &mut self.service_context,
-                    ServiceError::ListenError {
-                        address: address.clone(),
-                        error: ListenErrorKind::TransportError(error),
-                    },
-                );
+                self.report_error(ServiceError::ListenError {
+                    address: address.clone(),
+                    error: ListenErrorKind::TransportError(error),
+                });
                 if self.listens.remove(&address) {
                     #[cfg(feature = "upnp")]
                     if let Some(ref mut client) = self.igd_client {
@@ -1008,38 +2550,47 @@ where
             }
             SessionEvent::SessionTimeout { id } => {
                 if let Some(session_control) = self.sessions.get(&id) {
-                    self.handle.handle_error(
-                        &mut self.service_context,
-                        ServiceError::SessionTimeout {
-                            session_context: Arc::clone(&session_control.inner),
-                        },
-                    )
+                    let session_context = Arc::clone(&session_control.inner);
+                    self.report_error(ServiceError::SessionTimeout { session_context })
                 }
             }
             SessionEvent::MuxerError { id, error } => {
                 if let Some(session_control) = self.sessions.get(&id) {
-                    self.handle.handle_error(
-                        &mut self.service_context,
-                        ServiceError::MuxerError {
-                            session_context: Arc::clone(&session_control.inner),
-                            error,
-                        },
-                    )
+                    let session_context = Arc::clone(&session_control.inner);
+                    self.report_error(ServiceError::MuxerError {
+                        session_context,
+                        error,
+                    })
                 }
             }
             #[cfg(not(target_arch = "wasm32"))]
             SessionEvent::ListenStart {
                 listen_address,
                 incoming,
+                original,
             } => {
+                self.state.decrease();
+                if self.reached_max_listeners(0) {
+                    // drop the listener
+                    self.too_many_listeners(listen_address);
+                    return;
+                }
                 self.handle.handle_event(
                     &mut self.service_context,
                     ServiceEvent::ListenStarted {
                         address: listen_address.clone(),
                     },
                 );
+                if let Some(original) = original {
+                    self.handle.handle_event(
+                        &mut self.service_context,
+                        ServiceEvent::ListenResolved {
+                            original,
+                            address: listen_address.clone(),
+                        },
+                    );
+                }
                 self.listens.insert(listen_address.clone());
-                self.state.decrease();
                 self.try_update_listens(cx);
                 #[cfg(feature = "upnp")]
                 if let Some(client) = self.igd_client.as_mut() {
@@ -1047,13 +2598,24 @@ where
                 }
                 self.spawn_listener(incoming, listen_address);
             }
+            #[cfg(not(target_arch = "wasm32"))]
+            SessionEvent::ListenResolved {
+                original,
+                addresses,
+            } => {
+                self.state.decrease();
+                for address in addresses {
+                    if let Err(error) = self.listen_inner(address.clone(), Some(original.clone())) {
+                        self.report_error(ServiceError::ListenError {
+                            address,
+                            error: ListenErrorKind::TransportError(error),
+                        });
+                    }
+                }
+            }
             SessionEvent::ProtocolHandleError { error, proto_id } => {
-                self.handle.handle_error(
-                    &mut self.service_context,
-                    ServiceError::ProtocolHandleError { error, proto_id },
-                );
-                // if handle panic, close service
-                self.handle_service_task(cx, ServiceTask::Shutdown(false), Priority::High);
+                self.report_error(ServiceError::ProtocolHandleError { error, proto_id });
+                self.handle_closed(cx);
             }
             _ => (),
         }
@@ -1062,6 +2624,10 @@ where
     /// Handling various tasks sent externally
     #[allow(clippy::needless_collect)]
     fn handle_service_task(&mut self, cx: &mut Context, event: ServiceTask, priority: Priority) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record("task", &event);
+        }
         match event {
             ServiceTask::ProtocolMessage {
                 target,
@@ -1070,35 +2636,113 @@ where
             } => {
                 self.handle_message(cx, target, proto_id, priority, data);
             }
-            ServiceTask::Dial { address, target } => {
-                if !self.dial_protocols.contains_key(&address) {
-                    if let Err(e) = self.dial_inner(address.clone(), target) {
-                        self.handle.handle_error(
-                            &mut self.service_context,
-                            ServiceError::DialerError {
-                                address,
-                                error: DialerErrorKind::TransportError(e),
-                            },
-                        );
+            ServiceTask::Dial {
+                address,
+                target,
+                peer_id,
+                weight,
+            } => self.dial_task(address, target, peer_id, weight),
+            ServiceTask::DialWithResult {
+                address,
+                target,
+                sender,
+            } => {
+                self.dial_results
+                    .entry(address.clone())
+                    .or_default()
+                    .push(sender);
+                self.dial_task(address, target, None, 0)
+            }
+            ServiceTask::DialPeer { peer_id, target } => {
+                let addrs = self
+                    .config
+                    .peer_store
+                    .as_ref()
+                    .map(|store| store.addrs(&peer_id))
+                    .unwrap_or_default();
+                self.dial_peer_addrs(peer_id, addrs.into(), target)
+            }
+            ServiceTask::AddPersistentPeer { address, target } => {
+                let target = PersistentTarget::new(target, self.protocol_configs.keys().copied());
+                match self.persistent_peers.get_mut(&address) {
+                    Some(peer) => peer.target = target,
+                    None => {
+                        self.persistent_peers
+                            .insert(address.clone(), PersistentPeer::new(target));
+                        self.reconnect(address)
                     }
                 }
             }
+            ServiceTask::RemovePersistentPeer { address } => {
+                // the scheduled re-dial is aborted on drop
+                self.persistent_peers.remove(&address);
+            }
+            ServiceTask::Reconnect { address } => self.reconnect(address),
+            ServiceTask::DialAddrs {
+                peer_id,
+                addrs,
+                target,
+            } => self.dial_addrs(peer_id, addrs, target),
+            ServiceTask::RetryDial {
+                address,
+                target,
+                peer_id,
+            } => {
+                self.state.decrease();
+                self.dial_task(address, target, peer_id, 0)
+            }
             ServiceTask::Listen { address } => {
-                if !self.listens.contains(&address) {
-                    if let Err(e) = self.listen_inner(address.clone()) {
-                        self.handle.handle_error(
-                            &mut self.service_context,
-                            ServiceError::ListenError {
-                                address,
-                                error: ListenErrorKind::TransportError(e),
-                            },
-                        );
+                if self.reached_max_listeners(0) {
+                    self.too_many_listeners(address);
+                } else if !self.listens.contains(&address) {
+                    if let Err(e) = self.listen_inner(address.clone(), None) {
+                        self.report_error(ServiceError::ListenError {
+                            address,
+                            error: ListenErrorKind::TransportError(e),
+                        });
                     }
                 }
             }
             ServiceTask::Disconnect { session_id } => {
                 self.session_close(cx, session_id, Source::External)
             }
+            ServiceTask::SetRateLimit { session_id, limit } => {
+                if let Some(session) = self.sessions.get(&session_id) {
+                    session.inner.set_rate_limit(limit)
+                }
+            }
+            ServiceTask::SetReadPaused {
+                session_id,
+                proto_id,
+                paused,
+            } => {
+                if let Some(session) = self.sessions.get(&session_id) {
+                    session.inner.set_read_paused(proto_id, paused)
+                }
+            }
+            ServiceTask::DisconnectPeer { peer_id } => {
+                if let Some(session_id) = self.peer_sessions.get(&peer_id).copied() {
+                    self.session_close(cx, session_id, Source::External)
+                }
+            }
+            ServiceTask::BanPeer { peer_id, duration } => self.ban_peer(cx, peer_id, duration),
+            ServiceTask::UnbanPeer { peer_id } => {
+                // the timer is aborted on drop
+                self.banned_peers.remove(&peer_id);
+            }
+            ServiceTask::BanExpired {
+                peer_id,
+                generation,
+            } => {
+                // the peer may be banned again after the expiry was sent
+                if self
+                    .banned_peers
+                    .get(&peer_id)
+                    .map_or(false, |(current, _)| *current == generation)
+                {
+                    self.banned_peers.remove(&peer_id);
+                }
+            }
             ServiceTask::FutureTask { task } => {
                 self.send_future_task(cx, task);
             }
@@ -1141,6 +2785,22 @@ where
                     buffer.try_send(cx);
                 }
             }
+            ServiceTask::ProtocolNotifyNow { proto_id, token } => {
+                if let Some(buffer) = self.service_proto_handles.get_mut(&proto_id) {
+                    buffer.push(ServiceProtocolEvent::NotifyNow { token });
+                    buffer.try_send(cx);
+                }
+            }
+            ServiceTask::ProtocolSessionNotifyNow {
+                session_id,
+                proto_id,
+                token,
+            } => {
+                if let Some(buffer) = self.session_proto_handles.get_mut(&(session_id, proto_id)) {
+                    buffer.push(SessionProtocolEvent::NotifyNow { token });
+                    buffer.try_send(cx);
+                }
+            }
             ServiceTask::ProtocolOpen { session_id, target } => match target {
                 TargetProtocol::All => {
                     // Borrowed check attack
@@ -1148,21 +2808,148 @@ where
                     {
                         let ids = self.protocol_configs.keys().copied().collect::<Vec<_>>();
                         ids.into_iter()
-                            .for_each(|id| self.protocol_open(cx, session_id, id));
+                            .for_each(|id| self.protocol_open(cx, session_id, id, None));
                     }
                 }
-                TargetProtocol::Single(id) => self.protocol_open(cx, session_id, id),
+                TargetProtocol::Single(id) => self.protocol_open(cx, session_id, id, None),
                 TargetProtocol::Filter(filter) => {
                     let ids = self.protocol_configs.keys().copied().collect::<Vec<_>>();
                     ids.into_iter()
                         .filter(filter)
-                        .for_each(|id| self.protocol_open(cx, session_id, id))
+                        .for_each(|id| self.protocol_open(cx, session_id, id, None))
+                }
+                TargetProtocol::Fallback(proto_ids) => {
+                    if let Some(control) = self.sessions.get_mut(&session_id) {
+                        control.push(
+                            Priority::High,
+                            SessionEvent::ProtocolOpenFallback { proto_ids },
+                        );
+                        debug!("try open session [{}] fallback protos", session_id);
+                        control.try_send(cx);
+                    }
+                }
+                TargetProtocol::Version(id, version) => {
+                    self.protocol_open(cx, session_id, id, Some(version))
                 }
             },
             ServiceTask::ProtocolClose {
                 session_id,
                 proto_id,
             } => self.protocol_close(cx, session_id, proto_id),
+            ServiceTask::ProtocolReset {
+                session_id,
+                proto_id,
+            } => {
+                if let Some(control) = self.sessions.get_mut(&session_id) {
+                    control.push(Priority::High, SessionEvent::ProtocolReset { proto_id });
+                    debug!("try reset session [{}] proto [{}]", session_id, proto_id);
+                    control.try_send(cx);
+                }
+            }
+            ServiceTask::ProtocolCancelOpen {
+                session_id,
+                proto_id,
+            } => {
+                if let Some(control) = self.sessions.get_mut(&session_id) {
+                    control.push(
+                        Priority::High,
+                        SessionEvent::ProtocolCancelOpen { proto_id },
+                    );
+                    debug!(
+                        "try cancel open session [{}] proto [{}]",
+                        session_id, proto_id
+                    );
+                    control.try_send(cx);
+                }
+            }
+            ServiceTask::FlushCoalesced {
+                session_id,
+                proto_id,
+            } => {
+                if let Some(control) = self.sessions.get_mut(&session_id) {
+                    control.flush_coalesced(proto_id);
+                    control.try_send(cx);
+                }
+            }
+            ServiceTask::SetSessionPriority { session_id, level } => {
+                if let Some(control) = self.sessions.get_mut(&session_id) {
+                    control.priority_level = level;
+                    self.order_session(session_id, level);
+                }
+            }
+            ServiceTask::RegisterProtocol { meta, sender } => {
+                let result = self.register_protocol(cx, meta);
+                if sender.send(result).is_err() {
+                    trace!("register protocol result send back err")
+                }
+            }
+            ServiceTask::ObservedAddress {
+                session_id,
+                address,
+            } => {
+                if self.sessions.contains_key(&session_id) {
+                    self.observed_addrs.insert(session_id, address);
+                    self.update_external_address();
+                }
+            }
+            ServiceTask::IsConnected { peer_id, sender } => {
+                if sender
+                    .send(self.peer_sessions.contains_key(&peer_id))
+                    .is_err()
+                {
+                    trace!("peer [{:?}] connected state send back err", peer_id)
+                }
+            }
+            ServiceTask::SessionBufferStats { session_id, sender } => {
+                let stats = self
+                    .sessions
+                    .get(&session_id)
+                    .map(|control| SessionBufferStats {
+                        high_write_buf: control.buffer.high_len(),
+                        write_buf: control.buffer.normal_len(),
+                        read_session_buf: self
+                            .session_proto_handles
+                            .iter()
+                            .filter(|((id, _), _)| *id == session_id)
+                            .map(|(_, buffer)| buffer.len())
+                            .sum(),
+                    });
+                if sender.send(stats).is_err() {
+                    trace!("session [{}] buffer stats send back err", session_id)
+                }
+            }
+            ServiceTask::MessageDropped {
+                session_id,
+                proto_id,
+                reason,
+            } => self.report_error(ServiceError::MessageDropped {
+                id: session_id,
+                proto_id,
+                reason,
+            }),
+            ServiceTask::MemoryPressure { used, limit } => {
+                self.report_error(ServiceError::MemoryPressure { used, limit })
+            }
+            ServiceTask::MessageLatency { sender } => {
+                let snapshot = self
+                    .message_latency
+                    .as_ref()
+                    .map(|latency| latency.snapshot())
+                    .unwrap_or_default();
+                if sender.send(snapshot).is_err() {
+                    trace!("message latency send back err")
+                }
+            }
+            ServiceTask::TotalSubstreamCount { sender } => {
+                let count = self
+                    .sessions
+                    .values()
+                    .map(|control| control.inner.opened_protocol_count())
+                    .sum();
+                if sender.send(count).is_err() {
+                    trace!("total substream count send back err")
+                }
+            }
             ServiceTask::Shutdown(quick) => {
                 self.state.pre_shutdown();
 
@@ -1178,6 +2965,8 @@ where
                     client.clear()
                 };
                 self.future_task_sender.clear();
+                // the waiting `dial_with_result` calls get the shutdown error
+                self.dial_results.clear();
 
                 let sessions = self.sessions.keys().cloned().collect::<Vec<SessionId>>();
 
@@ -1260,6 +3049,7 @@ where
         }
 
         if self.wait_handle.is_empty() {
+            self.service_context.control().shutdown_signal.finish();
             Poll::Ready(None)
         } else {
             Poll::Pending
@@ -1267,6 +3057,12 @@ where
     }
 }
 
+impl<T> Drop for Service<T> {
+    fn drop(&mut self) {
+        self.service_context.control().shutdown_signal.finish();
+    }
+}
+
 impl<T> Stream for Service<T>
 where
     T: ServiceHandle + Unpin,
@@ -1291,6 +3087,21 @@ where
             });
             self.wait_handle.push((Some(sender), handle));
             self.init_proto_handles();
+
+            #[cfg(all(not(target_arch = "wasm32"), feature = "upnp"))]
+            if self.config.upnp {
+                let gateway = self
+                    .igd_client
+                    .as_ref()
+                    .map(crate::upnp::IgdClient::gateway);
+                self.handle.handle_event(
+                    &mut self.service_context,
+                    ServiceEvent::UpnpStatus {
+                        available: gateway.is_some(),
+                        gateway,
+                    },
+                );
+            }
         }
 
         self.flush_buffer(cx);
@@ -1317,6 +3128,10 @@ where
             return self.wait_handle_poll(cx);
         }
 
+        if self.config.metrics.is_enabled() {
+            self.export_buffer_lens();
+        }
+
         if log_enabled!(target: "tentacle", log::Level::Debug) {
             debug!(
                 "listens count: {}, state: {:?}, sessions count: {}, \
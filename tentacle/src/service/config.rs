@@ -2,11 +2,23 @@
 use crate::utils::multiaddr_to_socketaddr;
 use crate::{
     builder::{BeforeReceiveFn, CodecFn, NameFn, SelectVersionFn, SessionHandleFn},
-    traits::{Codec, ProtocolSpawn, ServiceProtocol, SessionProtocol},
+    context::SessionContext,
+    metrics::ServiceMetrics,
+    multiaddr::{Multiaddr, Protocol},
+    protocol_select::NegotiationMode,
+    secio::{crypto::cipher::CipherType, PeerId, PublicKey},
+    traits::{
+        Codec, ConnectionGater, PeerIdCodec, PeerStore, ProtocolSpawn, RawProtocol,
+        SecurityUpgrade, ServiceProtocol, SessionProtocol, StreamMuxer, TxtResolver,
+    },
+    utils::extract_peer_id,
     yamux::config::Config as YamuxConfig,
     ProtocolId, SessionId,
 };
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use nohash_hasher::IntSet;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
 #[cfg(feature = "tls")]
 use tokio_rustls::rustls::{ClientConfig, ServerConfig};
 
@@ -15,34 +27,151 @@ const MAX_BUF_SIZE: usize = 24 * 1024 * 1024;
 
 pub(crate) struct ServiceConfig {
     pub timeout: Duration,
+    pub dial_timeout: Option<Duration>,
+    pub listen_timeout: Option<Duration>,
     pub session_config: SessionConfig,
     pub max_frame_length: usize,
     pub keep_buffer: bool,
     #[cfg(all(not(target_arch = "wasm32"), feature = "upnp"))]
     pub upnp: bool,
     pub max_connection_number: usize,
+    pub handle_lagging_threshold: Option<usize>,
+    pub handle_closed_policy: HandleClosedPolicy,
+    pub protocol_allowlist: HashMap<PeerId, IntSet<ProtocolId>>,
+    pub max_session_lifetime: Option<Duration>,
+    pub security: Option<Arc<dyn SecurityUpgrade>>,
+    pub muxer: Option<Arc<dyn StreamMuxer>>,
+    pub peer_id_codec: Option<Arc<dyn PeerIdCodec>>,
+    pub gater: Option<Arc<dyn ConnectionGater>>,
+    pub peer_store: Option<Arc<dyn PeerStore>>,
+    /// TXT lookup of the `/dnsaddr` addresses, replaces the system one
+    pub txt_resolver: Option<Arc<dyn TxtResolver>>,
+    /// Ciphers of secio in the order of preference, default is all of them
+    pub secio_ciphers: Option<Vec<CipherType>>,
     pub tcp_bind_addr: Option<SocketAddr>,
     #[cfg(feature = "ws")]
     pub ws_bind_addr: Option<SocketAddr>,
+    /// The source address of the outbound tcp/ws connections
+    pub outbound_bind: Option<SocketAddr>,
     #[cfg(feature = "tls")]
     pub tls_config: Option<TlsConfig>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub event_recorder: Option<PathBuf>,
+    pub message_latency: bool,
+    pub message_drop_sample: u64,
+    pub max_buffer_bytes: Option<usize>,
+    /// Unsent data size of a session to block it, and to resume it, the high and low ones
+    pub session_buffer_watermarks: Option<(usize, usize)>,
+    /// Failures of a protocol negotiation with a peer to downgrade it, and how long they're kept
+    pub version_downgrade: Option<(u32, Duration)>,
+    /// Advertise the unspecified listen addresses as the addresses of the local interfaces
+    pub expand_unspecified_listens: bool,
+    /// Limit of the dials in progress, the others are queued by their weight
+    pub max_dial_concurrency: Option<usize>,
+    /// Retries of a dial failed with a transient error, and the delay of the first retry
+    pub dial_retries: Option<(u32, Duration)>,
+    /// Limit of the listeners
+    pub max_listeners: Option<usize>,
+    /// Limit of the inbound handshakes in progress, the listeners stop accepting while reached
+    pub max_concurrent_handshakes: Option<usize>,
+    /// Limit of the opened sessions, both inbound and outbound
+    pub max_connections: Option<usize>,
+    /// Limit of the opened inbound sessions
+    pub max_inbound: Option<usize>,
+    /// Limit of the opened outbound sessions
+    pub max_outbound: Option<usize>,
+    /// Bandwidth limit of each session, can be changed at runtime by `ServiceControl::set_rate_limit`
+    pub global_rate_limit: RateLimit,
+    /// Socket options of the tcp connections
+    pub tcp_socket_config: TcpSocketConfig,
+    /// Exchange the supported protocols with the remote in the handshake
+    pub exchange_protocols: bool,
+    /// Delays between the re-dials of the persistent peers
+    pub reconnect_backoff: ReconnectBackoff,
+    /// Exported by the `metrics` feature
+    pub metrics: ServiceMetrics,
+}
+
+impl ServiceConfig {
+    /// Timeout of the transport dial, default is `timeout`
+    pub fn dial_timeout(&self) -> Duration {
+        self.dial_timeout.unwrap_or(self.timeout)
+    }
+
+    /// Timeout of the transport listen accept, default is `timeout`
+    pub fn listen_timeout(&self) -> Duration {
+        self.listen_timeout.unwrap_or(self.timeout)
+    }
+
+    /// Peer id of the public key, derived by the custom codec if set
+    pub fn peer_id(&self, key: &PublicKey) -> PeerId {
+        match self.peer_id_codec {
+            Some(ref codec) => codec.peer_id(key),
+            None => key.peer_id(),
+        }
+    }
+
+    /// Peer id in the address, decoded by the custom codec if set
+    pub fn extract_peer_id(&self, address: &Multiaddr) -> Option<PeerId> {
+        match self.peer_id_codec {
+            Some(ref codec) => address.iter().find_map(|proto| match proto {
+                Protocol::P2P(raw_bytes) => codec.decode(&raw_bytes),
+                _ => None,
+            }),
+            None => extract_peer_id(address),
+        }
+    }
 }
 
 impl Default for ServiceConfig {
     fn default() -> Self {
         ServiceConfig {
             timeout: Duration::from_secs(10),
+            dial_timeout: None,
+            listen_timeout: None,
             session_config: SessionConfig::default(),
             max_frame_length: 1024 * 1024 * 8,
             keep_buffer: false,
             #[cfg(all(not(target_arch = "wasm32"), feature = "upnp"))]
             upnp: false,
             max_connection_number: 65535,
+            handle_lagging_threshold: None,
+            handle_closed_policy: HandleClosedPolicy::default(),
+            protocol_allowlist: HashMap::default(),
+            max_session_lifetime: None,
+            security: None,
+            muxer: None,
+            peer_id_codec: None,
+            gater: None,
+            peer_store: None,
+            txt_resolver: None,
+            secio_ciphers: None,
             tcp_bind_addr: None,
             #[cfg(feature = "ws")]
             ws_bind_addr: None,
+            outbound_bind: None,
             #[cfg(feature = "tls")]
             tls_config: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            event_recorder: None,
+            message_latency: false,
+            message_drop_sample: 0,
+            max_buffer_bytes: None,
+            session_buffer_watermarks: None,
+            version_downgrade: None,
+            expand_unspecified_listens: false,
+            max_dial_concurrency: None,
+            dial_retries: None,
+            max_listeners: None,
+            max_concurrent_handshakes: None,
+            max_connections: None,
+            max_inbound: None,
+            max_outbound: None,
+            global_rate_limit: RateLimit::default(),
+            tcp_socket_config: TcpSocketConfig::default(),
+            exchange_protocols: false,
+            reconnect_backoff: ReconnectBackoff::default(),
+            metrics: ServiceMetrics::default(),
         }
     }
 }
@@ -54,6 +183,18 @@ pub(crate) struct SessionConfig {
     pub send_buffer_size: usize,
     /// default is 24Mb
     pub recv_buffer_size: usize,
+    /// Limit of the protocol negotiations in progress, default is no limit
+    pub max_negotiating_protocols: Option<usize>,
+    /// Limit of the messages received per second, default is no limit
+    pub max_recv_rate: Option<u32>,
+    /// Timeout of the protocol negotiation on a sub stream, default is the session timeout
+    pub protocol_select_timeout: Option<Duration>,
+    /// Wire format of the protocol negotiation, default is tentacle's own
+    pub negotiation_mode: NegotiationMode,
+    /// Close the session without traffic for it, default is never
+    pub idle_timeout: Option<Duration>,
+    /// Max frame length, also the limit of the decompressed messages, default is 8Mb
+    pub max_frame_length: usize,
 }
 
 impl SessionConfig {
@@ -76,6 +217,12 @@ impl Default for SessionConfig {
             recv_buffer_size: MAX_BUF_SIZE,
             send_buffer_size: MAX_BUF_SIZE,
             yamux_config: YamuxConfig::default(),
+            max_negotiating_protocols: None,
+            max_recv_rate: None,
+            protocol_select_timeout: None,
+            negotiation_mode: NegotiationMode::default(),
+            idle_timeout: None,
+            max_frame_length: 1024 * 1024 * 8,
         }
     }
 }
@@ -112,6 +259,101 @@ impl TlsConfig {
     }
 }
 
+/// What the service does when a protocol handle closes unexpectedly, such as the handle panicked
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HandleClosedPolicy {
+    /// Shutdown the whole service
+    Shutdown,
+    /// Drop the events of that protocol handle and keep the rest of the service running
+    DropProtocol,
+}
+
+impl Default for HandleClosedPolicy {
+    fn default() -> Self {
+        HandleClosedPolicy::Shutdown
+    }
+}
+
+/// What happens to the queued messages of a protocol when it is closed
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ProtocolClosePolicy {
+    /// Drop the queued messages of the protocol and close it immediately
+    DropQueued,
+    /// Send the messages queued before the close, then close the protocol
+    Flush,
+}
+
+impl Default for ProtocolClosePolicy {
+    fn default() -> Self {
+        ProtocolClosePolicy::DropQueued
+    }
+}
+
+/// Bandwidth limit of a session in bytes per second, none is no limit
+///
+/// Messages over the limit wait in the session buffers, in the order of their priority,
+/// and reading from the network pauses until the limit allows it again
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct RateLimit {
+    /// Limit of the bytes sent
+    pub upload: Option<u64>,
+    /// Limit of the bytes received
+    pub download: Option<u64>,
+}
+
+/// Socket options of the tcp connections, applied to both the dialed and the accepted ones
+///
+/// None leaves the option as the system default
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct TcpSocketConfig {
+    /// Disable the Nagle's algorithm, so the small messages are sent without delay
+    pub nodelay: bool,
+    /// Enable the keepalive, probing the idle connection after the interval
+    pub keepalive: Option<Duration>,
+    /// Size of the send buffer of the socket
+    pub send_buffer_size: Option<usize>,
+    /// Size of the receive buffer of the socket
+    pub recv_buffer_size: Option<usize>,
+}
+
+/// Exponential backoff of the re-dials of a persistent peer
+///
+/// The delay doubles from `base_delay` on every failed attempt up to `max_delay`,
+/// then is reduced by a random fraction up to `jitter`, so the peers don't re-dial at once
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReconnectBackoff {
+    /// Delay of the first re-dial, default is 1s
+    pub base_delay: Duration,
+    /// Upper bound of the delay, default is 60s
+    pub max_delay: Duration,
+    /// Fraction of the delay randomized, between 0 and 1, default is 0.2
+    pub jitter: f64,
+}
+
+impl ReconnectBackoff {
+    /// Delay of the re-dial after the given number of failed attempts,
+    /// `random` in [0, 1) picks the fraction of the jitter
+    pub(crate) fn delay(&self, attempts: u32, random: f64) -> Duration {
+        let delay = self
+            .base_delay
+            .checked_mul(1 << attempts.min(31))
+            .map(|delay| delay.min(self.max_delay))
+            .unwrap_or(self.max_delay);
+        let jitter = self.jitter.max(0.0).min(1.0) * random.max(0.0).min(1.0);
+        delay - delay.mul_f64(jitter)
+    }
+}
+
+impl Default for ReconnectBackoff {
+    fn default() -> Self {
+        ReconnectBackoff {
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            jitter: 0.2,
+        }
+    }
+}
+
 /// When dial, specify which protocol want to open
 pub enum TargetProtocol {
     /// Try open all protocol
@@ -120,6 +362,12 @@ pub enum TargetProtocol {
     Single(ProtocolId),
     /// Try open some protocol, if return true, open it
     Filter(Box<dyn Fn(&ProtocolId) -> bool + Send>),
+    /// Try open the protocols in order, if the remote fails to negotiate the former,
+    /// try the next one, until one of them is opened
+    Fallback(Vec<ProtocolId>),
+    /// Try open one protocol at the version, if the remote doesn't support it,
+    /// the highest common version is opened, `connected` reports the opened one
+    Version(ProtocolId, String),
 }
 
 impl From<ProtocolId> for TargetProtocol {
@@ -142,6 +390,15 @@ pub enum TargetSession {
     Single(SessionId),
     /// Try send to some session, if return true, send to it
     Filter(Box<dyn Fn(&SessionId) -> bool + Send>),
+    /// Try send to some session by its context, if return true, send to it,
+    /// such as only to the inbound sessions
+    FilterContext(Box<dyn Fn(&SessionContext) -> bool + Send>),
+    /// Try send to the sessions whose negotiated version of the protocol is not lower than it,
+    /// versions are compared as strings, the same as version selection
+    MinVersion(String),
+    /// Try broadcast to all sessions except the specified ones,
+    /// such as all but the peer the message comes from
+    Except(Vec<SessionId>),
 }
 
 impl From<SessionId> for TargetSession {
@@ -240,6 +497,16 @@ pub(crate) struct Meta {
     pub(crate) select_version: SelectVersionFn,
     pub(crate) before_receive: BeforeReceiveFn,
     pub(crate) spawn: Option<Box<dyn ProtocolSpawn + Send + Sync + 'static>>,
+    pub(crate) raw: Option<Box<dyn RawProtocol + Send + Sync + 'static>>,
+    pub(crate) close_policy: ProtocolClosePolicy,
+    /// The delay and the max size of the coalesced messages
+    pub(crate) coalesce: Option<(Duration, usize)>,
+    /// Messages are sent with high priority
+    pub(crate) low_latency: bool,
+    /// Max size of the inbound messages
+    pub(crate) max_message_size: Option<usize>,
+    /// The inbound messages are decompressed, see `MetaBuilder::compress`
+    pub(crate) decompress: bool,
 }
 
 /// Protocol handle Contains four modes, each of which has a corresponding behavior,
@@ -252,6 +519,10 @@ pub enum ProtocolHandle<T: Sized> {
     /// behaviors, each has its own advantages and disadvantages, please carefully consider.
     /// This is also the recommended way to use this crate.
     Callback(T),
+    /// Raw handle: The opened sub stream is handed over to `RawProtocol` directly,
+    /// bypassing the read/write buffering of service, and lose the backpressure/accounting of it.
+    /// Only valid as service handle, and mutually exclusive with session handle.
+    Raw(Box<dyn RawProtocol + Send + Sync + 'static>),
 }
 
 impl<T> ProtocolHandle<T> {
@@ -266,6 +537,12 @@ impl<T> ProtocolHandle<T> {
     pub fn is_none(&self) -> bool {
         matches!(self, ProtocolHandle::None)
     }
+
+    /// Returns true if the enum is a raw value.
+    #[inline]
+    pub fn is_raw(&self) -> bool {
+        matches!(self, ProtocolHandle::Raw(_))
+    }
 }
 
 /// Control whether the protocol handle method requires blocking to run
@@ -415,7 +692,8 @@ impl State {
 
 #[cfg(test)]
 mod test {
-    use super::{BlockingFlag, State};
+    use super::{BlockingFlag, ReconnectBackoff, State};
+    use std::time::Duration;
 
     #[test]
     fn test_state_no_forever() {
@@ -476,4 +754,26 @@ mod test {
         assert_eq!(p.received(), false);
         assert_eq!(p.notify(), false);
     }
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let backoff = ReconnectBackoff {
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.5,
+        };
+
+        // doubles on every attempt up to the max delay
+        assert_eq!(backoff.delay(0, 0.0), Duration::from_secs(1));
+        assert_eq!(backoff.delay(2, 0.0), Duration::from_secs(4));
+        assert_eq!(backoff.delay(4, 0.0), Duration::from_secs(10));
+        assert_eq!(
+            backoff.delay(u32::max_value(), 0.0),
+            Duration::from_secs(10)
+        );
+
+        // the jitter reduces the delay by up to its fraction
+        assert_eq!(backoff.delay(2, 0.5), Duration::from_secs(3));
+        assert_eq!(backoff.delay(2, 1.0), Duration::from_secs(2));
+    }
 }
@@ -1,4 +1,4 @@
-use futures::prelude::*;
+use futures::{channel::oneshot, prelude::*};
 
 use std::time::Duration;
 use std::{
@@ -8,13 +8,24 @@ use std::{
 
 use crate::{
     channel::{mpsc, QuickSinkExt},
-    error::SendErrorKind,
+    context::SessionContext,
+    error::{DialError, RegisterError, SendErrorKind},
+    lock::RwLock,
+    metrics::{DropLog, HistogramSnapshot, SessionTraffic},
     multiaddr::Multiaddr,
     protocol_select::ProtocolInfo,
-    service::{event::ServiceTask, TargetProtocol, TargetSession},
+    secio::PeerId,
+    service::{
+        event::{DropReason, ServiceState, ServiceTask, SessionBufferStats},
+        future_task::TaskHandle,
+        helper::{AcceptSwitch, ShutdownSignal},
+        ProtocolClosePolicy, ProtocolMeta, RateLimit, TargetProtocol, TargetSession,
+    },
+    yamux::Stats as YamuxStats,
     ProtocolId, SessionId,
 };
 use bytes::Bytes;
+use nohash_hasher::{IntMap, IntSet};
 use std::sync::atomic::AtomicBool;
 
 type Result = std::result::Result<(), SendErrorKind>;
@@ -23,8 +34,17 @@ type Result = std::result::Result<(), SendErrorKind>;
 #[derive(Clone)]
 pub struct ServiceControl {
     pub(crate) task_sender: mpsc::Sender<ServiceTask>,
-    pub(crate) proto_infos: Arc<HashMap<ProtocolId, ProtocolInfo>>,
+    /// Registered protocols, replaced as a whole when a protocol is registered at runtime
+    pub(crate) proto_infos: Arc<RwLock<Arc<HashMap<ProtocolId, ProtocolInfo>>>>,
+    /// Protocols marked low latency, their messages are sent on the quick channel
+    low_latency: Arc<RwLock<IntSet<ProtocolId>>>,
+    /// Protocols closed with `ProtocolClosePolicy::Flush`, their close follows the messages
+    flush_on_close: Arc<RwLock<IntSet<ProtocolId>>>,
     closed: Arc<AtomicBool>,
+    pub(crate) accept_switch: Arc<AcceptSwitch>,
+    pub(crate) shutdown_signal: Arc<ShutdownSignal>,
+    pub(crate) drop_log: Arc<DropLog>,
+    pub(crate) sessions: Arc<RwLock<IntMap<SessionId, Arc<SessionContext>>>>,
 }
 
 impl ServiceControl {
@@ -32,15 +52,65 @@ impl ServiceControl {
     pub(crate) fn new(
         task_sender: mpsc::Sender<ServiceTask>,
         proto_infos: HashMap<ProtocolId, ProtocolInfo>,
+        low_latency: IntSet<ProtocolId>,
+        flush_on_close: IntSet<ProtocolId>,
         closed: Arc<AtomicBool>,
+        drop_log: Arc<DropLog>,
     ) -> Self {
         ServiceControl {
             task_sender,
-            proto_infos: Arc::new(proto_infos),
+            proto_infos: Arc::new(RwLock::new(Arc::new(proto_infos))),
+            low_latency: Arc::new(RwLock::new(low_latency)),
+            flush_on_close: Arc::new(RwLock::new(flush_on_close)),
             closed,
+            accept_switch: Arc::new(AcceptSwitch::default()),
+            shutdown_signal: Arc::new(ShutdownSignal::default()),
+            drop_log,
+            sessions: Arc::new(RwLock::new(IntMap::default())),
         }
     }
 
+    /// Count a dropped message, and report it to the service if it's sampled
+    pub(crate) fn message_dropped(
+        &self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+        reason: DropReason,
+    ) {
+        if self.drop_log.record() {
+            let _ignore = self.quick_send(ServiceTask::MessageDropped {
+                session_id,
+                proto_id,
+                reason,
+            });
+        }
+    }
+
+    /// Add a protocol registered at runtime
+    pub(crate) fn insert_protocol(
+        &self,
+        proto_id: ProtocolId,
+        info: ProtocolInfo,
+        low_latency: bool,
+        close_policy: ProtocolClosePolicy,
+    ) {
+        let mut proto_infos = self.proto_infos.write();
+        let mut infos = HashMap::clone(&proto_infos);
+        infos.insert(proto_id, info);
+        *proto_infos = Arc::new(infos);
+        if low_latency {
+            self.low_latency.write().insert(proto_id);
+        }
+        if close_policy == ProtocolClosePolicy::Flush {
+            self.flush_on_close.write().insert(proto_id);
+        }
+    }
+
+    /// Report that the buffers went over the memory limit
+    pub(crate) fn memory_pressure(&self, used: usize, limit: usize) {
+        let _ignore = self.quick_send(ServiceTask::MemoryPressure { used, limit });
+    }
+
     /// Send raw event
     pub(crate) fn send(&self, event: ServiceTask) -> Result {
         if self.closed.load(Ordering::SeqCst) {
@@ -71,12 +141,22 @@ impl ServiceControl {
     }
 
     /// Get service protocol message, Map(ID, Name), but can't modify
+    ///
+    /// It's a snapshot, including the protocols registered by `register_protocol` so far
     #[inline]
-    pub fn protocols(&self) -> &Arc<HashMap<ProtocolId, ProtocolInfo>> {
-        &self.proto_infos
+    pub fn protocols(&self) -> Arc<HashMap<ProtocolId, ProtocolInfo>> {
+        Arc::clone(&self.proto_infos.read())
+    }
+
+    /// Get the id, name and supported versions of the registered protocols, ordered by id
+    pub fn registered_protocols(&self) -> Vec<(ProtocolId, String, Vec<String>)> {
+        registered_protocols(&self.protocols())
     }
 
     /// Create a new listener
+    ///
+    /// A dns address is resolved and every resolved address is listened separately, each of
+    /// them emits a `ListenStarted` event followed by a `ListenResolved` event
     #[inline]
     pub fn listen(&self, address: Multiaddr) -> Result {
         self.quick_send(ServiceTask::Listen { address })
@@ -85,7 +165,107 @@ impl ServiceControl {
     /// Initiate a connection request to address
     #[inline]
     pub fn dial(&self, address: Multiaddr, target: TargetProtocol) -> Result {
-        self.quick_send(ServiceTask::Dial { address, target })
+        self.quick_send(ServiceTask::Dial {
+            address,
+            target,
+            peer_id: None,
+            weight: 0,
+        })
+    }
+
+    /// Initiate a connection request to address and wait for the id of the opened session
+    ///
+    /// The dial error is also output to the service handle, and the shutdown of the service
+    /// before the dial finished returns `DialError::Shutdown`
+    pub async fn dial_with_result(
+        &self,
+        address: Multiaddr,
+        target: TargetProtocol,
+    ) -> std::result::Result<SessionId, DialError> {
+        let (sender, receiver) = oneshot::channel();
+        self.quick_send(ServiceTask::DialWithResult {
+            address,
+            target,
+            sender,
+        })?;
+        receiver.await.unwrap_or(Err(DialError::Shutdown))
+    }
+
+    /// Initiate a connection request to address with a weight
+    ///
+    /// When the dial concurrency is limited by `ServiceBuilder::max_dial_concurrency`, the
+    /// queued dials of a higher weight are started first. `dial` is weight 0
+    #[inline]
+    pub fn dial_with_priority(
+        &self,
+        address: Multiaddr,
+        target: TargetProtocol,
+        weight: u8,
+    ) -> Result {
+        self.quick_send(ServiceTask::Dial {
+            address,
+            target,
+            peer_id: None,
+            weight,
+        })
+    }
+
+    /// Dial the addresses of the peer known by `ServiceBuilder::peer_store` one by one,
+    /// until one of them connects, do nothing if the peer is connected or has no address
+    #[inline]
+    pub fn dial_peer(&self, peer_id: PeerId, target: TargetProtocol) -> Result {
+        self.quick_send(ServiceTask::DialPeer { peer_id, target })
+    }
+
+    /// Dial the addresses of the peer concurrently, the first one to finish the handshake
+    /// is kept and the dials of the others are cancelled
+    ///
+    /// The failures of the addresses are collected, and reported as one
+    /// `ServiceError::DialAddrsError` when all of them fail. The dials aren't queued by
+    /// `ServiceBuilder::max_dial_concurrency`, do nothing if the peer is connected
+    #[inline]
+    pub fn dial_addrs(
+        &self,
+        peer_id: PeerId,
+        addrs: Vec<Multiaddr>,
+        target: TargetProtocol,
+    ) -> Result {
+        self.quick_send(ServiceTask::DialAddrs {
+            peer_id,
+            addrs,
+            target,
+        })
+    }
+
+    /// Dial the address and keep it connected, when its session closes or the dial fails,
+    /// it's re-dialed with the backoff set by `ServiceBuilder::reconnect_backoff`
+    #[inline]
+    pub fn add_persistent_peer(&self, address: Multiaddr, target: TargetProtocol) -> Result {
+        self.quick_send(ServiceTask::AddPersistentPeer { address, target })
+    }
+
+    /// Stop re-dialing the persistent address, the opened session is kept
+    #[inline]
+    pub fn remove_persistent_peer(&self, address: Multiaddr) -> Result {
+        self.quick_send(ServiceTask::RemovePersistentPeer { address })
+    }
+
+    /// Initiate a connection request to address, the remote must match the expected peer id
+    ///
+    /// The address doesn't need to contain `/p2p/<id>`, the peer id will be verified on session open
+    #[inline]
+    pub fn dial_with_peer_id(
+        &self,
+        address: Multiaddr,
+        peer_id: PeerId,
+        target: TargetProtocol,
+    ) -> Result {
+        self.quick_send(ServiceTask::Dial {
+            address,
+            target,
+            peer_id: Some(peer_id),
+            weight: 0,
+        })
     }
 
     /// Disconnect a connection
@@ -94,6 +274,59 @@ impl ServiceControl {
         self.quick_send(ServiceTask::Disconnect { session_id })
     }
 
+    /// Disconnect the session of the peer, do nothing if the peer isn't connected
+    ///
+    /// The peer id is the one of `SessionContext::remote_pubkey`, so sessions without secio
+    /// can't be disconnected by it
+    #[inline]
+    pub fn disconnect_by_peer_id(&self, peer_id: PeerId) -> Result {
+        self.quick_send(ServiceTask::DisconnectPeer { peer_id })
+    }
+
+    /// Ban the peer for the duration, its session is disconnected, its inbound connections
+    /// are refused and it isn't dialed until the ban expires or `unban_peer` is called
+    ///
+    /// Banning a banned peer again restarts the duration
+    #[inline]
+    pub fn ban_peer(&self, peer_id: PeerId, duration: Duration) -> Result {
+        self.quick_send(ServiceTask::BanPeer { peer_id, duration })
+    }
+
+    /// Lift the ban of the peer, do nothing if it isn't banned
+    #[inline]
+    pub fn unban_peer(&self, peer_id: PeerId) -> Result {
+        self.quick_send(ServiceTask::UnbanPeer { peer_id })
+    }
+
+    /// Change the bandwidth limit of the session, overrides `ServiceBuilder::global_rate_limit`
+    /// for it, do nothing if the session isn't found
+    #[inline]
+    pub fn set_rate_limit(&self, session_id: SessionId, limit: RateLimit) -> Result {
+        self.quick_send(ServiceTask::SetRateLimit { session_id, limit })
+    }
+
+    /// Stop reading the inbound messages of the protocol on the session, do nothing if the
+    /// session isn't found
+    ///
+    /// The messages are left unread in the multiplexer instead of being buffered, so the
+    /// remote stalls once the stream window is used up, until `resume_protocol_read`
+    pub fn pause_protocol_read(&self, session_id: SessionId, proto_id: ProtocolId) -> Result {
+        self.quick_send(ServiceTask::SetReadPaused {
+            session_id,
+            proto_id,
+            paused: true,
+        })
+    }
+
+    /// Resume reading the inbound messages of the protocol paused by `pause_protocol_read`
+    pub fn resume_protocol_read(&self, session_id: SessionId, proto_id: ProtocolId) -> Result {
+        self.quick_send(ServiceTask::SetReadPaused {
+            session_id,
+            proto_id,
+            paused: false,
+        })
+    }
+
     /// Send message
     #[inline]
     pub fn send_message_to(
@@ -116,6 +349,45 @@ impl ServiceControl {
         self.quick_filter_broadcast(TargetSession::Single(session_id), proto_id, data)
     }
 
+    /// Send message only if the protocol is open on the session,
+    /// otherwise return `SendErrorKind::ProtocolNotOpen`
+    ///
+    /// The protocol may still close before the message arrives, in which case it is dropped
+    #[inline]
+    pub fn checked_send_message_to(
+        &self,
+        session: &SessionContext,
+        proto_id: ProtocolId,
+        data: Bytes,
+    ) -> Result {
+        if !session.is_protocol_open(proto_id) {
+            return Err(SendErrorKind::ProtocolNotOpen);
+        }
+        self.send_message_to(session.id, proto_id, data)
+    }
+
+    /// Send message unless the session is blocked, that is, its pending data size
+    /// already reaches `max_pending`, in which case the message is dropped and counted
+    /// on `SessionContext::dropped_messages`
+    ///
+    /// Return whether the message was sent, for the real-time data that prefer to drop rather than queue
+    #[inline]
+    pub fn send_message_lossy(
+        &self,
+        session: &SessionContext,
+        proto_id: ProtocolId,
+        data: Bytes,
+        max_pending: usize,
+    ) -> std::result::Result<bool, SendErrorKind> {
+        if session.pending_data_size() >= max_pending {
+            session.incr_dropped_messages();
+            self.message_dropped(session.id, proto_id, DropReason::SessionBlocked);
+            return Ok(false);
+        }
+        self.send_message_to(session.id, proto_id, data)
+            .map(|_| true)
+    }
+
     /// Send data to the specified protocol for the specified sessions.
     #[inline]
     pub fn filter_broadcast(
@@ -124,11 +396,32 @@ impl ServiceControl {
         proto_id: ProtocolId,
         data: Bytes,
     ) -> Result {
-        self.send(ServiceTask::ProtocolMessage {
+        let task = ServiceTask::ProtocolMessage {
             target,
             proto_id,
             data,
-        })
+        };
+        if self.low_latency.read().contains(&proto_id) {
+            self.quick_send(task)
+        } else {
+            self.send(task)
+        }
+    }
+
+    /// Send data to the sessions which negotiated a version of the protocol not lower than
+    /// `min_version`, sessions that don't open the protocol are skipped
+    #[inline]
+    pub fn broadcast_version(
+        &self,
+        proto_id: ProtocolId,
+        min_version: &str,
+        data: Bytes,
+    ) -> Result {
+        self.filter_broadcast(
+            TargetSession::MinVersion(min_version.to_owned()),
+            proto_id,
+            data,
+        )
     }
 
     /// Send data to the specified protocol for the specified sessions on quick channel.
@@ -157,6 +450,18 @@ impl ServiceControl {
         })
     }
 
+    /// Send a future task which can be aborted by the returned handle
+    ///
+    /// Drop the handle will also abort the task
+    pub fn spawn_cancellable<T>(&self, task: T) -> std::result::Result<TaskHandle, SendErrorKind>
+    where
+        T: Future<Output = ()> + 'static + Send,
+    {
+        let (handle, task) = TaskHandle::new(task);
+        self.send(ServiceTask::FutureTask { task })?;
+        Ok(handle)
+    }
+
     /// Try open a protocol
     ///
     /// If the protocol has been open, do nothing
@@ -176,17 +481,76 @@ impl ServiceControl {
         self.quick_send(ServiceTask::ProtocolOpen { session_id, target })
     }
 
-    /// Try close a protocol
+    /// Try close a protocol, the queued messages are handled by the `ProtocolClosePolicy`
+    /// of the protocol, with `ProtocolClosePolicy::Flush` the close follows the messages sent before it
     ///
     /// If the protocol has been closed, do nothing
     #[inline]
     pub fn close_protocol(&self, session_id: SessionId, proto_id: ProtocolId) -> Result {
-        self.quick_send(ServiceTask::ProtocolClose {
+        let task = ServiceTask::ProtocolClose {
+            session_id,
+            proto_id,
+        };
+        if self.flush_on_close.read().contains(&proto_id) {
+            self.send(task)
+        } else {
+            self.quick_send(task)
+        }
+    }
+
+    /// Abort a protocol abnormally, the sub stream is reset rather than closed gracefully,
+    /// the remote sees a `ServiceError::ProtocolError` with `ConnectionReset` before `disconnected`
+    ///
+    /// The buffered messages not sent yet are discarded. If the protocol has been closed, do nothing
+    #[inline]
+    pub fn reset_protocol(&self, session_id: SessionId, proto_id: ProtocolId) -> Result {
+        self.quick_send(ServiceTask::ProtocolReset {
             session_id,
             proto_id,
         })
     }
 
+    /// Cancel the negotiation of a protocol opened by local which is still in flight,
+    /// or waiting for a negotiation slot, `ServiceError::ProtocolSelectError` is reported
+    /// with `SelectErrorCause::Cancelled`
+    ///
+    /// If the negotiation has finished, do nothing
+    #[inline]
+    pub fn cancel_protocol_open(&self, session_id: SessionId, proto_id: ProtocolId) -> Result {
+        self.quick_send(ServiceTask::ProtocolCancelOpen {
+            session_id,
+            proto_id,
+        })
+    }
+
+    /// Boost the session, messages to the sessions of a higher level are handed to their
+    /// sessions ahead of the others, regardless of the message priority. 0 means no boost,
+    /// which is the default
+    #[inline]
+    pub fn set_session_priority(&self, session_id: SessionId, level: u8) -> Result {
+        self.quick_send(ServiceTask::SetSessionPriority { session_id, level })
+    }
+
+    /// Register a protocol on the running service, it's listed in `protocols` and can be opened
+    /// on the open sessions and the ones opened after it
+    pub async fn register_protocol(
+        &self,
+        meta: ProtocolMeta,
+    ) -> std::result::Result<(), RegisterError> {
+        let (sender, receiver) = oneshot::channel();
+        self.send(ServiceTask::RegisterProtocol { meta, sender })?;
+        receiver.await.unwrap_or(Err(RegisterError::Shutdown))
+    }
+
+    /// Report our address observed by the remote of the session, the address reported by
+    /// the most sessions is surfaced by `ServiceEvent::ExternalAddressChanged`
+    pub fn report_observed_address(&self, session_id: SessionId, address: Multiaddr) -> Result {
+        self.send(ServiceTask::ObservedAddress {
+            session_id,
+            address,
+        })
+    }
+
     /// Set a service notify token
     pub fn set_service_notify(
         &self,
@@ -236,6 +600,25 @@ impl ServiceControl {
         })
     }
 
+    /// Fire the service notify of the token once now, apart from its timer
+    pub fn service_notify_now(&self, proto_id: ProtocolId, token: u64) -> Result {
+        self.send(ServiceTask::ProtocolNotifyNow { proto_id, token })
+    }
+
+    /// Fire the session notify of the token once now, apart from its timer
+    pub fn session_notify_now(
+        &self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+        token: u64,
+    ) -> Result {
+        self.send(ServiceTask::ProtocolSessionNotifyNow {
+            session_id,
+            proto_id,
+            token,
+        })
+    }
+
     /// Close service
     ///
     /// Order:
@@ -251,6 +634,112 @@ impl ServiceControl {
     pub fn shutdown(&self) -> Result {
         self.quick_send(ServiceTask::Shutdown(true))
     }
+
+    /// Wait for the service stream to end after `close` or `shutdown`,
+    /// also resolved if the service is dropped
+    pub fn wait_shutdown(&self) -> impl Future<Output = ()> {
+        self.shutdown_signal.wait()
+    }
+
+    /// Get the buffered event counts of a session, None if session not found
+    pub async fn session_buffer_stats(
+        &self,
+        session_id: SessionId,
+    ) -> std::result::Result<Option<SessionBufferStats>, SendErrorKind> {
+        let (sender, receiver) = oneshot::channel();
+        self.quick_send(ServiceTask::SessionBufferStats { session_id, sender })?;
+        receiver.await.map_err(|_| SendErrorKind::BrokenPipe)
+    }
+
+    /// Whether there is a session connected to the peer
+    pub async fn is_connected(&self, peer_id: PeerId) -> std::result::Result<bool, SendErrorKind> {
+        let (sender, receiver) = oneshot::channel();
+        self.quick_send(ServiceTask::IsConnected { peer_id, sender })?;
+        receiver.await.map_err(|_| SendErrorKind::BrokenPipe)
+    }
+
+    /// Get the number of open protocol substreams across all sessions
+    pub async fn total_substream_count(&self) -> std::result::Result<usize, SendErrorKind> {
+        let (sender, receiver) = oneshot::channel();
+        self.quick_send(ServiceTask::TotalSubstreamCount { sender })?;
+        receiver.await.map_err(|_| SendErrorKind::BrokenPipe)
+    }
+
+    /// Get the per protocol latency histograms of messages from entering the substream write
+    /// buffer to being handed to the session, empty if `message_latency` is not enabled
+    pub async fn message_latency(
+        &self,
+    ) -> std::result::Result<HashMap<ProtocolId, HistogramSnapshot>, SendErrorKind> {
+        let (sender, receiver) = oneshot::channel();
+        self.quick_send(ServiceTask::MessageLatency { sender })?;
+        receiver.await.map_err(|_| SendErrorKind::BrokenPipe)
+    }
+
+    /// Stop accepting new inbound connections, listeners and existing sessions are kept
+    pub fn pause_accept(&self) {
+        self.accept_switch.pause()
+    }
+
+    /// Resume accepting new inbound connections
+    pub fn resume_accept(&self) {
+        self.accept_switch.resume()
+    }
+
+    /// Snapshot of the service state, such as whether accepting is paused
+    pub fn state(&self) -> ServiceState {
+        ServiceState {
+            closed: self.closed.load(Ordering::SeqCst),
+            accept_paused: self.accept_switch.is_paused(),
+            session_count: self.session_count(),
+        }
+    }
+
+    /// Total number of messages dropped, by every reason of `DropReason`
+    pub fn dropped_messages(&self) -> u64 {
+        self.drop_log.total()
+    }
+
+    /// Number of the currently opened sessions
+    pub fn session_count(&self) -> usize {
+        self.sessions.read().len()
+    }
+
+    /// Snapshot of the currently opened sessions, ordered by session id
+    pub fn sessions(&self) -> Vec<Arc<SessionContext>> {
+        let mut sessions = self.sessions.read().values().cloned().collect::<Vec<_>>();
+        sessions.sort_by_key(|context| context.id);
+        sessions
+    }
+
+    /// Bytes of the protocol messages sent and received on the session,
+    /// none if the session isn't opened
+    pub fn session_traffic(&self, session_id: SessionId) -> Option<SessionTraffic> {
+        self.sessions
+            .read()
+            .get(&session_id)
+            .map(|context| context.traffic())
+    }
+
+    /// Flow control state of the yamux session, see `SessionContext::yamux_stats`,
+    /// none if the session isn't opened or uses a custom multiplexer
+    pub fn yamux_stats(&self, session_id: SessionId) -> Option<YamuxStats> {
+        self.sessions
+            .read()
+            .get(&session_id)
+            .and_then(|context| context.yamux_stats())
+    }
+
+    /// A copy of the application data of the type attached to the session,
+    /// see `SessionContext::insert_extension`, none if the session isn't opened
+    pub fn session_extension<T: Clone + Send + Sync + 'static>(
+        &self,
+        session_id: SessionId,
+    ) -> Option<T> {
+        self.sessions
+            .read()
+            .get(&session_id)
+            .and_then(|context| context.extension())
+    }
 }
 
 impl From<ServiceControl> for ServiceAsyncControl {
@@ -258,7 +747,13 @@ impl From<ServiceControl> for ServiceAsyncControl {
         ServiceAsyncControl {
             task_sender: control.task_sender,
             proto_infos: control.proto_infos,
+            low_latency: control.low_latency,
+            flush_on_close: control.flush_on_close,
             closed: control.closed,
+            accept_switch: control.accept_switch,
+            shutdown_signal: control.shutdown_signal,
+            drop_log: control.drop_log,
+            sessions: control.sessions,
         }
     }
 }
@@ -268,7 +763,13 @@ impl From<ServiceAsyncControl> for ServiceControl {
         ServiceControl {
             task_sender: control.task_sender,
             proto_infos: control.proto_infos,
+            low_latency: control.low_latency,
+            flush_on_close: control.flush_on_close,
             closed: control.closed,
+            accept_switch: control.accept_switch,
+            shutdown_signal: control.shutdown_signal,
+            drop_log: control.drop_log,
+            sessions: control.sessions,
         }
     }
 }
@@ -277,8 +778,14 @@ impl From<ServiceAsyncControl> for ServiceControl {
 #[derive(Clone)]
 pub struct ServiceAsyncControl {
     task_sender: mpsc::Sender<ServiceTask>,
-    proto_infos: Arc<HashMap<ProtocolId, ProtocolInfo>>,
+    proto_infos: Arc<RwLock<Arc<HashMap<ProtocolId, ProtocolInfo>>>>,
+    low_latency: Arc<RwLock<IntSet<ProtocolId>>>,
+    flush_on_close: Arc<RwLock<IntSet<ProtocolId>>>,
     closed: Arc<AtomicBool>,
+    accept_switch: Arc<AcceptSwitch>,
+    shutdown_signal: Arc<ShutdownSignal>,
+    drop_log: Arc<DropLog>,
+    sessions: Arc<RwLock<IntMap<SessionId, Arc<SessionContext>>>>,
 }
 
 impl ServiceAsyncControl {
@@ -306,9 +813,16 @@ impl ServiceAsyncControl {
     }
 
     /// Get service protocol message, Map(ID, Name), but can't modify
+    ///
+    /// It's a snapshot, including the protocols registered by `register_protocol` so far
     #[inline]
-    pub fn protocols(&self) -> &Arc<HashMap<ProtocolId, ProtocolInfo>> {
-        &self.proto_infos
+    pub fn protocols(&self) -> Arc<HashMap<ProtocolId, ProtocolInfo>> {
+        Arc::clone(&self.proto_infos.read())
+    }
+
+    /// Get the id, name and supported versions of the registered protocols, ordered by id
+    pub fn registered_protocols(&self) -> Vec<(ProtocolId, String, Vec<String>)> {
+        registered_protocols(&self.protocols())
     }
 
     /// Create a new listener
@@ -320,7 +834,119 @@ impl ServiceAsyncControl {
     /// Initiate a connection request to address
     #[inline]
     pub async fn dial(&mut self, address: Multiaddr, target: TargetProtocol) -> Result {
-        self.quick_send(ServiceTask::Dial { address, target }).await
+        self.quick_send(ServiceTask::Dial {
+            address,
+            target,
+            peer_id: None,
+            weight: 0,
+        })
+        .await
+    }
+
+    /// Initiate a connection request to address and wait for the id of the opened session
+    ///
+    /// The dial error is also output to the service handle, and the shutdown of the service
+    /// before the dial finished returns `DialError::Shutdown`
+    pub async fn dial_with_result(
+        &mut self,
+        address: Multiaddr,
+        target: TargetProtocol,
+    ) -> std::result::Result<SessionId, DialError> {
+        let (sender, receiver) = oneshot::channel();
+        self.quick_send(ServiceTask::DialWithResult {
+            address,
+            target,
+            sender,
+        })
+        .await?;
+        receiver.await.unwrap_or(Err(DialError::Shutdown))
+    }
+
+    /// Initiate a connection request to address with a weight
+    ///
+    /// When the dial concurrency is limited by `ServiceBuilder::max_dial_concurrency`, the
+    /// queued dials of a higher weight are started first. `dial` is weight 0
+    #[inline]
+    pub async fn dial_with_priority(
+        &mut self,
+        address: Multiaddr,
+        target: TargetProtocol,
+        weight: u8,
+    ) -> Result {
+        self.quick_send(ServiceTask::Dial {
+            address,
+            target,
+            peer_id: None,
+            weight,
+        })
+        .await
+    }
+
+    /// Dial the addresses of the peer known by `ServiceBuilder::peer_store` one by one,
+    /// until one of them connects, do nothing if the peer is connected or has no address
+    #[inline]
+    pub async fn dial_peer(&mut self, peer_id: PeerId, target: TargetProtocol) -> Result {
+        self.quick_send(ServiceTask::DialPeer { peer_id, target })
+            .await
+    }
+
+    /// Dial the addresses of the peer concurrently, the first one to finish the handshake
+    /// is kept and the dials of the others are cancelled
+    ///
+    /// The failures of the addresses are collected, and reported as one
+    /// `ServiceError::DialAddrsError` when all of them fail. The dials aren't queued by
+    /// `ServiceBuilder::max_dial_concurrency`, do nothing if the peer is connected
+    #[inline]
+    pub async fn dial_addrs(
+        &mut self,
+        peer_id: PeerId,
+        addrs: Vec<Multiaddr>,
+        target: TargetProtocol,
+    ) -> Result {
+        self.quick_send(ServiceTask::DialAddrs {
+            peer_id,
+            addrs,
+            target,
+        })
+        .await
+    }
+
+    /// Dial the address and keep it connected, when its session closes or the dial fails,
+    /// it's re-dialed with the backoff set by `ServiceBuilder::reconnect_backoff`
+    #[inline]
+    pub async fn add_persistent_peer(
+        &mut self,
+        address: Multiaddr,
+        target: TargetProtocol,
+    ) -> Result {
+        self.quick_send(ServiceTask::AddPersistentPeer { address, target })
+            .await
+    }
+
+    /// Stop re-dialing the persistent address, the opened session is kept
+    #[inline]
+    pub async fn remove_persistent_peer(&mut self, address: Multiaddr) -> Result {
+        self.quick_send(ServiceTask::RemovePersistentPeer { address })
+            .await
+    }
+
+    /// Initiate a connection request to address, the remote must match the expected peer id
+    ///
+    /// The address doesn't need to contain `/p2p/<id>`, the peer id will be verified on session open
+    #[inline]
+    pub async fn dial_with_peer_id(
+        &mut self,
+        address: Multiaddr,
+        peer_id: PeerId,
+        target: TargetProtocol,
+    ) -> Result {
+        self.quick_send(ServiceTask::Dial {
+            address,
+            target,
+            peer_id: Some(peer_id),
+            weight: 0,
+        })
+        .await
     }
 
     /// Disconnect a connection
@@ -330,6 +956,72 @@ impl ServiceAsyncControl {
             .await
     }
 
+    /// Disconnect the session of the peer, do nothing if the peer isn't connected
+    ///
+    /// The peer id is the one of `SessionContext::remote_pubkey`, so sessions without secio
+    /// can't be disconnected by it
+    #[inline]
+    pub async fn disconnect_by_peer_id(&mut self, peer_id: PeerId) -> Result {
+        self.quick_send(ServiceTask::DisconnectPeer { peer_id })
+            .await
+    }
+
+    /// Ban the peer for the duration, its session is disconnected, its inbound connections
+    /// are refused and it isn't dialed until the ban expires or `unban_peer` is called
+    ///
+    /// Banning a banned peer again restarts the duration
+    #[inline]
+    pub async fn ban_peer(&mut self, peer_id: PeerId, duration: Duration) -> Result {
+        self.quick_send(ServiceTask::BanPeer { peer_id, duration })
+            .await
+    }
+
+    /// Lift the ban of the peer, do nothing if it isn't banned
+    #[inline]
+    pub async fn unban_peer(&mut self, peer_id: PeerId) -> Result {
+        self.quick_send(ServiceTask::UnbanPeer { peer_id }).await
+    }
+
+    /// Change the bandwidth limit of the session, overrides `ServiceBuilder::global_rate_limit`
+    /// for it, do nothing if the session isn't found
+    #[inline]
+    pub async fn set_rate_limit(&mut self, session_id: SessionId, limit: RateLimit) -> Result {
+        self.quick_send(ServiceTask::SetRateLimit { session_id, limit })
+            .await
+    }
+
+    /// Stop reading the inbound messages of the protocol on the session, do nothing if the
+    /// session isn't found
+    ///
+    /// The messages are left unread in the multiplexer instead of being buffered, so the
+    /// remote stalls once the stream window is used up, until `resume_protocol_read`
+    pub async fn pause_protocol_read(
+        &mut self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+    ) -> Result {
+        self.quick_send(ServiceTask::SetReadPaused {
+            session_id,
+            proto_id,
+            paused: true,
+        })
+        .await
+    }
+
+    /// Resume reading the inbound messages of the protocol paused by `pause_protocol_read`
+    pub async fn resume_protocol_read(
+        &mut self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+    ) -> Result {
+        self.quick_send(ServiceTask::SetReadPaused {
+            session_id,
+            proto_id,
+            paused: false,
+        })
+        .await
+    }
+
     /// Send message
     #[inline]
     pub async fn send_message_to(
@@ -362,11 +1054,32 @@ impl ServiceAsyncControl {
         proto_id: ProtocolId,
         data: Bytes,
     ) -> Result {
-        self.send(ServiceTask::ProtocolMessage {
+        let task = ServiceTask::ProtocolMessage {
             target,
             proto_id,
             data,
-        })
+        };
+        if self.low_latency.read().contains(&proto_id) {
+            self.quick_send(task).await
+        } else {
+            self.send(task).await
+        }
+    }
+
+    /// Send data to the sessions which negotiated a version of the protocol not lower than
+    /// `min_version`, sessions that don't open the protocol are skipped
+    #[inline]
+    pub async fn broadcast_version(
+        &mut self,
+        proto_id: ProtocolId,
+        min_version: &str,
+        data: Bytes,
+    ) -> Result {
+        self.filter_broadcast(
+            TargetSession::MinVersion(min_version.to_owned()),
+            proto_id,
+            data,
+        )
         .await
     }
 
@@ -398,6 +1111,21 @@ impl ServiceAsyncControl {
         .await
     }
 
+    /// Send a future task which can be aborted by the returned handle
+    ///
+    /// Drop the handle will also abort the task
+    pub async fn spawn_cancellable<T>(
+        &mut self,
+        task: T,
+    ) -> std::result::Result<TaskHandle, SendErrorKind>
+    where
+        T: Future<Output = ()> + 'static + Send,
+    {
+        let (handle, task) = TaskHandle::new(task);
+        self.send(ServiceTask::FutureTask { task }).await?;
+        Ok(handle)
+    }
+
     /// Try open a protocol
     ///
     /// If the protocol has been open, do nothing
@@ -423,18 +1151,89 @@ impl ServiceAsyncControl {
             .await
     }
 
-    /// Try close a protocol
+    /// Try close a protocol, the queued messages are handled by the `ProtocolClosePolicy`
+    /// of the protocol, with `ProtocolClosePolicy::Flush` the close follows the messages sent before it
     ///
     /// If the protocol has been closed, do nothing
     #[inline]
     pub async fn close_protocol(&mut self, session_id: SessionId, proto_id: ProtocolId) -> Result {
-        self.quick_send(ServiceTask::ProtocolClose {
+        let task = ServiceTask::ProtocolClose {
+            session_id,
+            proto_id,
+        };
+        if self.flush_on_close.read().contains(&proto_id) {
+            self.send(task).await
+        } else {
+            self.quick_send(task).await
+        }
+    }
+
+    /// Abort a protocol abnormally, the sub stream is reset rather than closed gracefully,
+    /// the remote sees a `ServiceError::ProtocolError` with `ConnectionReset` before `disconnected`
+    ///
+    /// The buffered messages not sent yet are discarded. If the protocol has been closed, do nothing
+    #[inline]
+    pub async fn reset_protocol(&mut self, session_id: SessionId, proto_id: ProtocolId) -> Result {
+        self.quick_send(ServiceTask::ProtocolReset {
+            session_id,
+            proto_id,
+        })
+        .await
+    }
+
+    /// Cancel the negotiation of a protocol opened by local which is still in flight,
+    /// or waiting for a negotiation slot, `ServiceError::ProtocolSelectError` is reported
+    /// with `SelectErrorCause::Cancelled`
+    ///
+    /// If the negotiation has finished, do nothing
+    #[inline]
+    pub async fn cancel_protocol_open(
+        &mut self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+    ) -> Result {
+        self.quick_send(ServiceTask::ProtocolCancelOpen {
             session_id,
             proto_id,
         })
         .await
     }
 
+    /// Boost the session, messages to the sessions of a higher level are handed to their
+    /// sessions ahead of the others, regardless of the message priority. 0 means no boost,
+    /// which is the default
+    #[inline]
+    pub async fn set_session_priority(&mut self, session_id: SessionId, level: u8) -> Result {
+        self.quick_send(ServiceTask::SetSessionPriority { session_id, level })
+            .await
+    }
+
+    /// Register a protocol on the running service, it's listed in `protocols` and can be opened
+    /// on the open sessions and the ones opened after it
+    pub async fn register_protocol(
+        &mut self,
+        meta: ProtocolMeta,
+    ) -> std::result::Result<(), RegisterError> {
+        let (sender, receiver) = oneshot::channel();
+        self.send(ServiceTask::RegisterProtocol { meta, sender })
+            .await?;
+        receiver.await.unwrap_or(Err(RegisterError::Shutdown))
+    }
+
+    /// Report our address observed by the remote of the session, the address reported by
+    /// the most sessions is surfaced by `ServiceEvent::ExternalAddressChanged`
+    pub async fn report_observed_address(
+        &mut self,
+        session_id: SessionId,
+        address: Multiaddr,
+    ) -> Result {
+        self.send(ServiceTask::ObservedAddress {
+            session_id,
+            address,
+        })
+        .await
+    }
+
     /// Set a service notify token
     pub async fn set_service_notify(
         &mut self,
@@ -488,6 +1287,27 @@ impl ServiceAsyncControl {
         .await
     }
 
+    /// Fire the service notify of the token once now, apart from its timer
+    pub async fn service_notify_now(&mut self, proto_id: ProtocolId, token: u64) -> Result {
+        self.send(ServiceTask::ProtocolNotifyNow { proto_id, token })
+            .await
+    }
+
+    /// Fire the session notify of the token once now, apart from its timer
+    pub async fn session_notify_now(
+        &mut self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+        token: u64,
+    ) -> Result {
+        self.send(ServiceTask::ProtocolSessionNotifyNow {
+            session_id,
+            proto_id,
+            token,
+        })
+        .await
+    }
+
     /// Close service
     ///
     /// Order:
@@ -503,4 +1323,264 @@ impl ServiceAsyncControl {
     pub async fn shutdown(&mut self) -> Result {
         self.quick_send(ServiceTask::Shutdown(true)).await
     }
+
+    /// Wait for the service stream to end after `close` or `shutdown`,
+    /// also resolved if the service is dropped
+    pub fn wait_shutdown(&self) -> impl Future<Output = ()> {
+        self.shutdown_signal.wait()
+    }
+
+    /// Get the buffered event counts of a session, None if session not found
+    pub async fn session_buffer_stats(
+        &mut self,
+        session_id: SessionId,
+    ) -> std::result::Result<Option<SessionBufferStats>, SendErrorKind> {
+        let (sender, receiver) = oneshot::channel();
+        self.quick_send(ServiceTask::SessionBufferStats { session_id, sender })
+            .await?;
+        receiver.await.map_err(|_| SendErrorKind::BrokenPipe)
+    }
+
+    /// Whether there is a session connected to the peer
+    pub async fn is_connected(
+        &mut self,
+        peer_id: PeerId,
+    ) -> std::result::Result<bool, SendErrorKind> {
+        let (sender, receiver) = oneshot::channel();
+        self.quick_send(ServiceTask::IsConnected { peer_id, sender })
+            .await?;
+        receiver.await.map_err(|_| SendErrorKind::BrokenPipe)
+    }
+
+    /// Get the number of open protocol substreams across all sessions
+    pub async fn total_substream_count(&mut self) -> std::result::Result<usize, SendErrorKind> {
+        let (sender, receiver) = oneshot::channel();
+        self.quick_send(ServiceTask::TotalSubstreamCount { sender })
+            .await?;
+        receiver.await.map_err(|_| SendErrorKind::BrokenPipe)
+    }
+
+    /// Get the per protocol latency histograms of messages from entering the substream write
+    /// buffer to being handed to the session, empty if `message_latency` is not enabled
+    pub async fn message_latency(
+        &mut self,
+    ) -> std::result::Result<HashMap<ProtocolId, HistogramSnapshot>, SendErrorKind> {
+        let (sender, receiver) = oneshot::channel();
+        self.quick_send(ServiceTask::MessageLatency { sender })
+            .await?;
+        receiver.await.map_err(|_| SendErrorKind::BrokenPipe)
+    }
+
+    /// Stop accepting new inbound connections, listeners and existing sessions are kept
+    pub fn pause_accept(&self) {
+        self.accept_switch.pause()
+    }
+
+    /// Resume accepting new inbound connections
+    pub fn resume_accept(&self) {
+        self.accept_switch.resume()
+    }
+
+    /// Snapshot of the service state, such as whether accepting is paused
+    pub fn state(&self) -> ServiceState {
+        ServiceState {
+            closed: self.closed.load(Ordering::SeqCst),
+            accept_paused: self.accept_switch.is_paused(),
+            session_count: self.session_count(),
+        }
+    }
+
+    /// Total number of messages dropped, by every reason of `DropReason`
+    pub fn dropped_messages(&self) -> u64 {
+        self.drop_log.total()
+    }
+
+    /// Number of the currently opened sessions
+    pub fn session_count(&self) -> usize {
+        self.sessions.read().len()
+    }
+
+    /// Snapshot of the currently opened sessions, ordered by session id
+    pub fn sessions(&self) -> Vec<Arc<SessionContext>> {
+        let mut sessions = self.sessions.read().values().cloned().collect::<Vec<_>>();
+        sessions.sort_by_key(|context| context.id);
+        sessions
+    }
+
+    /// Bytes of the protocol messages sent and received on the session,
+    /// none if the session isn't opened
+    pub fn session_traffic(&self, session_id: SessionId) -> Option<SessionTraffic> {
+        self.sessions
+            .read()
+            .get(&session_id)
+            .map(|context| context.traffic())
+    }
+
+    /// Flow control state of the yamux session, see `SessionContext::yamux_stats`,
+    /// none if the session isn't opened or uses a custom multiplexer
+    pub fn yamux_stats(&self, session_id: SessionId) -> Option<YamuxStats> {
+        self.sessions
+            .read()
+            .get(&session_id)
+            .and_then(|context| context.yamux_stats())
+    }
+
+    /// A copy of the application data of the type attached to the session,
+    /// see `SessionContext::insert_extension`, none if the session isn't opened
+    pub fn session_extension<T: Clone + Send + Sync + 'static>(
+        &self,
+        session_id: SessionId,
+    ) -> Option<T> {
+        self.sessions
+            .read()
+            .get(&session_id)
+            .and_then(|context| context.extension())
+    }
+}
+
+fn registered_protocols(
+    proto_infos: &HashMap<ProtocolId, ProtocolInfo>,
+) -> Vec<(ProtocolId, String, Vec<String>)> {
+    let mut protocols = proto_infos
+        .iter()
+        .map(|(id, info)| (*id, info.name.clone(), info.support_versions.clone()))
+        .collect::<Vec<_>>();
+    protocols.sort_by_key(|(id, _, _)| *id);
+    protocols
+}
+
+#[cfg(test)]
+mod test {
+    use super::ServiceControl;
+    use crate::{
+        channel::mpsc, context::SessionContext, error::SendErrorKind, metrics::DropLog,
+        multiaddr::Multiaddr, protocol_select::ProtocolInfo, service::SessionType,
+    };
+    use bytes::Bytes;
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn test_send_message_lossy() {
+        let (sender, _receiver) = mpsc::channel(8);
+        let control = ServiceControl::new(
+            sender,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(DropLog::new(0)),
+        );
+        let session = SessionContext::new(
+            0.into(),
+            "/ip4/127.0.0.1/tcp/1337".parse::<Multiaddr>().unwrap(),
+            SessionType::Outbound,
+            None,
+            None,
+            None,
+            None,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicUsize::new(0)),
+        );
+
+        assert!(matches!(
+            control.send_message_lossy(&session, 1.into(), Bytes::from("a"), 10),
+            Ok(true)
+        ));
+        session.incr_pending_data_size(10);
+        assert!(matches!(
+            control.send_message_lossy(&session, 1.into(), Bytes::from("b"), 10),
+            Ok(false)
+        ));
+        assert_eq!(session.dropped_messages(), 1);
+        assert_eq!(control.dropped_messages(), 1);
+    }
+
+    #[test]
+    fn test_checked_send_message_to() {
+        let (sender, _receiver) = mpsc::channel(8);
+        let control = ServiceControl::new(
+            sender,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(DropLog::new(0)),
+        );
+        let closed = Arc::new(AtomicBool::new(false));
+        let session = SessionContext::new(
+            0.into(),
+            "/ip4/127.0.0.1/tcp/1337".parse::<Multiaddr>().unwrap(),
+            SessionType::Outbound,
+            None,
+            None,
+            None,
+            None,
+            closed.clone(),
+            Arc::new(AtomicUsize::new(0)),
+        );
+
+        assert!(!session.is_protocol_open(1.into()));
+        assert!(matches!(
+            control.checked_send_message_to(&session, 1.into(), Bytes::from("a")),
+            Err(SendErrorKind::ProtocolNotOpen)
+        ));
+
+        session.set_protocol_open(1.into(), Some("1.0".to_owned()));
+        assert!(session.is_protocol_open(1.into()));
+        assert!(control
+            .checked_send_message_to(&session, 1.into(), Bytes::from("b"))
+            .is_ok());
+        // the other protocols are still not open
+        assert!(matches!(
+            control.checked_send_message_to(&session, 2.into(), Bytes::from("c")),
+            Err(SendErrorKind::ProtocolNotOpen)
+        ));
+
+        // nothing is open on a closed session
+        closed.store(true, Ordering::SeqCst);
+        assert!(!session.is_protocol_open(1.into()));
+        assert!(matches!(
+            control.checked_send_message_to(&session, 1.into(), Bytes::from("d")),
+            Err(SendErrorKind::ProtocolNotOpen)
+        ));
+    }
+
+    #[test]
+    fn test_registered_protocols() {
+        let (sender, _receiver) = mpsc::channel(8);
+        let control = ServiceControl::new(
+            sender,
+            vec![
+                (
+                    2.into(),
+                    ProtocolInfo::new("/p2p/b", vec!["1.0".to_owned()]),
+                ),
+                (
+                    1.into(),
+                    ProtocolInfo::new("/p2p/a", vec!["1.0".to_owned(), "2.0".to_owned()]),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            Default::default(),
+            Default::default(),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(DropLog::new(0)),
+        );
+
+        assert_eq!(
+            control.registered_protocols(),
+            vec![
+                (
+                    1.into(),
+                    "/p2p/a".to_owned(),
+                    vec!["1.0".to_owned(), "2.0".to_owned()]
+                ),
+                (2.into(), "/p2p/b".to_owned(), vec!["1.0".to_owned()]),
+            ]
+        );
+    }
 }
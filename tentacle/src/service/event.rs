@@ -1,15 +1,43 @@
+use std::collections::HashMap;
 use std::fmt;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
 use crate::{
     context::SessionContext,
-    error::{DialerErrorKind, ListenErrorKind, ProtocolHandleErrorKind},
+    error::{DialError, DialerErrorKind, ListenErrorKind, ProtocolHandleErrorKind, RegisterError},
+    metrics::HistogramSnapshot,
     multiaddr::Multiaddr,
-    service::{future_task::BoxedFutureTask, TargetProtocol, TargetSession},
+    secio::PeerId,
+    service::{
+        future_task::BoxedFutureTask, ProtocolMeta, RateLimit, SessionType, TargetProtocol,
+        TargetSession,
+    },
     ProtocolId, SessionId,
 };
 use bytes::Bytes;
+use futures::channel::oneshot;
+
+/// The reason of a dropped message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    /// The target session was closed or not found
+    SessionNotFound,
+    /// The protocol is not open on the session
+    ProtocolNotOpen,
+    /// The session was blocked on lossy send, or by the session buffer watermarks
+    SessionBlocked,
+}
+
+/// The cause of a failed protocol negotiation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectErrorCause {
+    /// The remote doesn't support the protocol, or the negotiation met a timeout or net problem
+    Failed,
+    /// The negotiation opened by local was cancelled by `cancel_protocol_open`
+    Cancelled,
+}
 
 /// Error generated by the Service
 #[derive(Debug)]
@@ -29,13 +57,42 @@ pub enum ServiceError {
         error: ListenErrorKind,
     },
     /// Protocol select fail
+    ///
+    /// Non exhaustive, so the fields can grow without breaking the users who match on it
+    #[non_exhaustive]
     ProtocolSelectError {
         /// Protocol name, if none, timeout or other net problem,
         /// if Some, don't support this proto
         proto_name: Option<String>,
+        /// Why the negotiation failed
+        cause: SelectErrorCause,
+        /// Session context
+        session_context: Arc<SessionContext>,
+    },
+    /// The remote opened a protocol which is not in its allowlist, the open was rejected
+    ProtocolNotAllowed {
+        /// Protocol id
+        proto_id: ProtocolId,
         /// Session context
         session_context: Arc<SessionContext>,
     },
+    /// A message was dropped, only a sample of drops is reported, see `ServiceBuilder::message_drop_log`
+    MessageDropped {
+        /// Session id
+        id: SessionId,
+        /// Protocol id
+        proto_id: ProtocolId,
+        /// Why the message was dropped
+        reason: DropReason,
+    },
+    /// The bytes held by the buffers of all sessions went over the limit, reading is paused until
+    /// they are consumed, see `ServiceBuilder::max_buffer_bytes`
+    MemoryPressure {
+        /// Bytes held
+        used: usize,
+        /// The limit
+        limit: usize,
+    },
     /// Protocol error during interaction
     ProtocolError {
         /// Session id
@@ -47,6 +104,8 @@ pub enum ServiceError {
     },
     /// After initializing the connection, the session does not open any protocol,
     /// suspected fd attack
+    ///
+    /// Also reported when the session is closed by `ServiceBuilder::session_idle_timeout`
     SessionTimeout {
         /// Session context
         session_context: Arc<SessionContext>,
@@ -71,10 +130,71 @@ pub enum ServiceError {
     /// it may cause oom, so this session will be kill by tentacle
     ///
     /// Judging condition: unsent message size > send buffer size set by the user, default 24m
+    ///
+    /// With `ServiceBuilder::session_buffer_watermarks`, it's also reported when the unsent
+    /// message size reaches the high watermark, then the session is kept but the messages to it
+    /// are dropped until the size falls to the low watermark
     SessionBlocked {
         /// Session context
         session_context: Arc<SessionContext>,
     },
+    /// Protocol handle can't keep up with the events sent to it,
+    /// the number of buffered events exceeds the threshold set by the user
+    ///
+    /// It's reported when the number crosses the threshold, and again only after it falls
+    /// back to the threshold
+    HandleLagging {
+        /// Protocol id
+        proto_id: ProtocolId,
+        /// Buffered event count
+        buffered: usize,
+    },
+    /// The connection limit of the session type, or the total one, is reached,
+    /// the session is closed or the dial is refused
+    ConnectionLimit {
+        /// Session type
+        ty: SessionType,
+        /// Remote address
+        address: Multiaddr,
+        /// The limit reached
+        limit: usize,
+    },
+    /// All the addresses raced by `ServiceControl::dial_addrs` failed
+    DialAddrsError {
+        /// Peer id
+        peer_id: PeerId,
+        /// The `DialerError` or `ConnectionLimit` of each address
+        errors: Vec<ServiceError>,
+    },
+}
+
+impl ServiceError {
+    /// Kind of the error, the label of it in the metrics
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            ServiceError::DialerError {
+                error: DialerErrorKind::HandshakeError(_),
+                ..
+            }
+            | ServiceError::DialerError {
+                error: DialerErrorKind::HandshakeTimeout(_),
+                ..
+            } => "handshake",
+            ServiceError::DialerError { .. } | ServiceError::DialAddrsError { .. } => "dial",
+            ServiceError::ListenError { .. } => "listen",
+            ServiceError::ProtocolSelectError { .. } => "protocol_select",
+            ServiceError::ProtocolNotAllowed { .. } => "protocol_not_allowed",
+            ServiceError::MessageDropped { .. } => "message_dropped",
+            ServiceError::MemoryPressure { .. } => "memory_pressure",
+            ServiceError::ProtocolError { .. } => "protocol",
+            ServiceError::SessionTimeout { .. } => "session_timeout",
+            ServiceError::MuxerError { .. } => "muxer",
+            ServiceError::ProtocolHandleError { .. } => "protocol_handle",
+            ServiceError::SessionBlocked { .. } => "session_blocked",
+            ServiceError::HandleLagging { .. } => "handle_lagging",
+            ServiceError::ConnectionLimit { .. } => "connection_limit",
+        }
+    }
 }
 
 /// Event generated by the Service
@@ -100,8 +220,56 @@ pub enum ServiceEvent {
         /// Listen address
         address: Multiaddr,
     },
+    /// Listen on a dns address started on one of the addresses it's resolved to,
+    /// emitted after the `ListenStarted` of the resolved address
+    ListenResolved {
+        /// The dns address to listen
+        original: Multiaddr,
+        /// Listen address
+        address: Multiaddr,
+    },
+    /// The result of the UPnP gateway discovery, emitted once on start when upnp is enabled
+    UpnpStatus {
+        /// Whether a usable gateway is found, if not, the ports need to be forwarded manually
+        available: bool,
+        /// Gateway address
+        gateway: Option<SocketAddr>,
+    },
+    /// The address reported by the most sessions through `report_observed_address` changed,
+    /// it's the best guess of how the remote peers see us, useful when UPnP is unavailable
+    ExternalAddressChanged {
+        /// External address
+        address: Multiaddr,
+    },
+}
+
+/// Buffered event counts of a session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionBufferStats {
+    /// Events wait to be sent to the session on high priority
+    pub high_write_buf: usize,
+    /// Events wait to be sent to the session on normal priority
+    pub write_buf: usize,
+    /// Events wait to be sent to the session protocol handles of the session
+    pub read_session_buf: usize,
 }
 
+/// Snapshot of the service state, see `ServiceControl::state`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServiceState {
+    /// The service is shut down, or shutting down
+    pub closed: bool,
+    /// Accepting new inbound connections is paused by `ServiceControl::pause_accept`
+    pub accept_paused: bool,
+    /// Number of the currently opened sessions
+    pub session_count: usize,
+}
+
+/// Sender of the result of `ServiceControl::dial_with_result`
+pub(crate) type DialResultSender = oneshot::Sender<Result<SessionId, DialError>>;
+
+pub(crate) type RegisterResultSender = oneshot::Sender<Result<(), RegisterError>>;
+
 /// Task received by the Service.
 ///
 /// An instruction that the outside world can send to the service
@@ -130,6 +298,48 @@ pub(crate) enum ServiceTask {
         /// protocol id
         proto_id: ProtocolId,
     },
+    /// Reset specify protocol
+    ProtocolReset {
+        /// Session id
+        session_id: SessionId,
+        /// protocol id
+        proto_id: ProtocolId,
+    },
+    /// Cancel the negotiation of a protocol opened by local
+    ProtocolCancelOpen {
+        /// Session id
+        session_id: SessionId,
+        /// protocol id
+        proto_id: ProtocolId,
+    },
+    /// Set the priority level of a session
+    SetSessionPriority {
+        /// Session id
+        session_id: SessionId,
+        /// Priority level, 0 means no boost
+        level: u8,
+    },
+    /// Register a protocol on the running service
+    RegisterProtocol {
+        /// Protocol meta
+        meta: ProtocolMeta,
+        /// Register result sender
+        sender: RegisterResultSender,
+    },
+    /// Our address observed by the remote of a session
+    ObservedAddress {
+        /// Session id
+        session_id: SessionId,
+        /// Observed address
+        address: Multiaddr,
+    },
+    /// Hand the coalesced messages of a protocol to the session
+    FlushCoalesced {
+        /// Session id
+        session_id: SessionId,
+        /// Protocol id
+        proto_id: ProtocolId,
+    },
     /// Set service notify task
     SetProtocolNotify {
         /// Protocol id
@@ -166,6 +376,22 @@ pub(crate) enum ServiceTask {
         /// The timer token
         token: u64,
     },
+    /// Fire the service notify once now
+    ProtocolNotifyNow {
+        /// Protocol id
+        proto_id: ProtocolId,
+        /// The timer token
+        token: u64,
+    },
+    /// Fire the session notify once now
+    ProtocolSessionNotifyNow {
+        /// Session id
+        session_id: SessionId,
+        /// Protocol id
+        proto_id: ProtocolId,
+        /// The timer token
+        token: u64,
+    },
     /// Future task
     FutureTask {
         /// Future
@@ -176,18 +402,153 @@ pub(crate) enum ServiceTask {
         /// Session id
         session_id: SessionId,
     },
+    /// Disconnect the session of the peer, if it's connected
+    DisconnectPeer {
+        /// Peer id
+        peer_id: PeerId,
+    },
+    /// Refuse the connections of the peer for a duration
+    BanPeer {
+        /// Peer id
+        peer_id: PeerId,
+        /// How long the ban lasts
+        duration: Duration,
+    },
+    /// Lift the ban of the peer
+    UnbanPeer {
+        /// Peer id
+        peer_id: PeerId,
+    },
+    /// The duration of a ban is reached, lift it if the peer isn't banned again since
+    BanExpired {
+        /// Peer id
+        peer_id: PeerId,
+        /// Generation of the expired ban
+        generation: u64,
+    },
+    /// Change the bandwidth limit of a session
+    SetRateLimit {
+        /// Session id
+        session_id: SessionId,
+        /// The new limit
+        limit: RateLimit,
+    },
+    /// Pause or resume reading the inbound messages of a protocol on a session
+    SetReadPaused {
+        /// Session id
+        session_id: SessionId,
+        /// Protocol id
+        proto_id: ProtocolId,
+        /// Pause or resume
+        paused: bool,
+    },
     /// Dial task
     Dial {
         /// Remote address
         address: Multiaddr,
         /// Dial protocols
         target: TargetProtocol,
+        /// Expected remote peer id, verified on session open
+        peer_id: Option<PeerId>,
+        /// Dials of a higher weight are started first when the dial concurrency is limited
+        weight: u8,
+    },
+    /// Dial task, the sender receives the opened session id or the dial error
+    DialWithResult {
+        /// Remote address
+        address: Multiaddr,
+        /// Dial protocols
+        target: TargetProtocol,
+        /// Dial result sender
+        sender: DialResultSender,
+    },
+    /// Dial the known addresses of the peer in the peer store
+    DialPeer {
+        /// Peer id
+        peer_id: PeerId,
+        /// Dial protocols
+        target: TargetProtocol,
+    },
+    /// Dial the addresses of the peer concurrently, keep the first one to finish the handshake
+    DialAddrs {
+        /// Peer id
+        peer_id: PeerId,
+        /// Addresses to race
+        addrs: Vec<Multiaddr>,
+        /// Dial protocols
+        target: TargetProtocol,
+    },
+    /// Keep the address connected, re-dial it when its session closes or the dial fails
+    AddPersistentPeer {
+        /// Remote address
+        address: Multiaddr,
+        /// Dial protocols
+        target: TargetProtocol,
+    },
+    /// Stop re-dialing the persistent address
+    RemovePersistentPeer {
+        /// Remote address
+        address: Multiaddr,
+    },
+    /// The backoff of the persistent address is reached, re-dial it
+    Reconnect {
+        /// Remote address
+        address: Multiaddr,
+    },
+    /// The delay of a dial failed with a transient error is reached, retry it
+    RetryDial {
+        /// Remote address
+        address: Multiaddr,
+        /// Dial protocols
+        target: TargetProtocol,
+        /// Expected remote peer id
+        peer_id: Option<PeerId>,
     },
     /// Listen task
     Listen {
         /// Listen address
         address: Multiaddr,
     },
+    /// Get session buffer stats
+    SessionBufferStats {
+        /// Session id
+        session_id: SessionId,
+        /// Send back the stats, None if session not found
+        sender: oneshot::Sender<Option<SessionBufferStats>>,
+    },
+    /// Whether the peer is currently connected
+    IsConnected {
+        /// Peer id
+        peer_id: PeerId,
+        /// Send back the result
+        sender: oneshot::Sender<bool>,
+    },
+    /// Get the number of open substreams of all sessions
+    TotalSubstreamCount {
+        /// Send back the count
+        sender: oneshot::Sender<usize>,
+    },
+    /// A message was dropped outside the service, report it
+    MessageDropped {
+        /// Session id
+        session_id: SessionId,
+        /// Protocol id
+        proto_id: ProtocolId,
+        /// Why the message was dropped
+        reason: DropReason,
+    },
+    /// The buffers went over the memory limit, report it
+    MemoryPressure {
+        /// Bytes held
+        used: usize,
+        /// The limit
+        limit: usize,
+    },
+    /// Get the message latency histograms of all protocols
+    MessageLatency {
+        /// Send back the histograms, empty if not enabled
+        sender: oneshot::Sender<HashMap<ProtocolId, HistogramSnapshot>>,
+    },
     /// Shutdown service
     Shutdown(bool),
 }
@@ -225,15 +586,104 @@ impl fmt::Debug for ServiceTask {
                 "remove protocol({}) session({}) notify({})",
                 proto_id, session_id, token
             ),
+            ProtocolNotifyNow { proto_id, token } => {
+                write!(f, "protocol({}) notify({}) now", proto_id, token)
+            }
+            ProtocolSessionNotifyNow {
+                session_id,
+                proto_id,
+                token,
+            } => write!(
+                f,
+                "protocol({}) session({}) notify({}) now",
+                proto_id, session_id, token
+            ),
             FutureTask { .. } => write!(f, "Future task"),
             Disconnect { session_id } => write!(f, "Disconnect session [{}]", session_id),
+            DisconnectPeer { peer_id } => write!(f, "Disconnect peer [{:?}]", peer_id),
+            BanPeer { peer_id, duration } => {
+                write!(f, "Ban peer [{:?}] for {:?}", peer_id, duration)
+            }
+            UnbanPeer { peer_id } => write!(f, "Unban peer [{:?}]", peer_id),
+            BanExpired { peer_id, .. } => write!(f, "Ban of peer [{:?}] expired", peer_id),
+            SetRateLimit { session_id, limit } => {
+                write!(f, "Set session [{}] rate limit: {:?}", session_id, limit)
+            }
+            SetReadPaused {
+                session_id,
+                proto_id,
+                paused,
+            } => write!(
+                f,
+                "Set session [{}] proto [{}] read paused: {}",
+                session_id, proto_id, paused
+            ),
             Dial { address, .. } => write!(f, "Dial address: {}", address),
+            DialWithResult { address, .. } => {
+                write!(f, "Dial address with result: {}", address)
+            }
+            DialPeer { peer_id, .. } => write!(f, "Dial peer [{:?}]", peer_id),
+            DialAddrs { peer_id, addrs, .. } => {
+                write!(f, "Dial peer [{:?}] addresses: {:?}", peer_id, addrs)
+            }
+            AddPersistentPeer { address, .. } => write!(f, "Add persistent peer: {}", address),
+            RemovePersistentPeer { address } => {
+                write!(f, "Remove persistent peer: {}", address)
+            }
+            Reconnect { address } => write!(f, "Reconnect address: {}", address),
+            RetryDial { address, .. } => write!(f, "Retry dial address: {}", address),
             Listen { address } => write!(f, "Listen address: {}", address),
             ProtocolOpen { session_id, .. } => write!(f, "Open session [{}] proto", session_id),
             ProtocolClose {
                 session_id,
                 proto_id,
             } => write!(f, "Close session [{}] proto [{}]", session_id, proto_id),
+            ProtocolReset {
+                session_id,
+                proto_id,
+            } => write!(f, "Reset session [{}] proto [{}]", session_id, proto_id),
+            ProtocolCancelOpen {
+                session_id,
+                proto_id,
+            } => write!(
+                f,
+                "Cancel open session [{}] proto [{}]",
+                session_id, proto_id
+            ),
+            SetSessionPriority { session_id, level } => {
+                write!(f, "Set session [{}] priority level: {}", session_id, level)
+            }
+            RegisterProtocol { meta, .. } => write!(f, "Register proto [{}]", meta.id()),
+            ObservedAddress {
+                session_id,
+                address,
+            } => write!(f, "Session [{}] observed address: {}", session_id, address),
+            FlushCoalesced {
+                session_id,
+                proto_id,
+            } => write!(
+                f,
+                "Flush session [{}] proto [{}] coalesced messages",
+                session_id, proto_id
+            ),
+            IsConnected { peer_id, .. } => write!(f, "Is peer [{:?}] connected", peer_id),
+            SessionBufferStats { session_id, .. } => {
+                write!(f, "Get session [{}] buffer stats", session_id)
+            }
+            TotalSubstreamCount { .. } => write!(f, "Get total substream count"),
+            MessageLatency { .. } => write!(f, "Get message latency"),
+            MessageDropped {
+                session_id,
+                proto_id,
+                reason,
+            } => write!(
+                f,
+                "Session [{}] proto [{}] message dropped: {:?}",
+                session_id, proto_id, reason
+            ),
+            MemoryPressure { used, limit } => {
+                write!(f, "Memory pressure, used: {}, limit: {}", used, limit)
+            }
             Shutdown(_) => write!(f, "Try close service"),
         }
     }
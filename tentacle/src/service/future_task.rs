@@ -22,6 +22,56 @@ pub(crate) type BoxedFutureTask = Pin<Box<dyn Future<Output = ()> + 'static + Se
 #[cfg(target_arch = "wasm32")]
 pub(crate) type BoxedFutureTask = Pin<Box<dyn Future<Output = ()> + 'static>>;
 
+/// Handle of a cancellable future task
+///
+/// The task will be aborted when the handle is dropped or canceled
+pub struct TaskHandle {
+    signal: Option<oneshot::Sender<()>>,
+}
+
+impl TaskHandle {
+    pub(crate) fn new<T>(task: T) -> (TaskHandle, BoxedFutureTask)
+    where
+        T: Future<Output = ()> + 'static + Send,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let task = Box::pin(async move {
+            future::select(Box::pin(task), receiver).await;
+        });
+        (
+            TaskHandle {
+                signal: Some(sender),
+            },
+            task,
+        )
+    }
+
+    /// Abort the task
+    pub fn cancel(mut self) {
+        self.stop()
+    }
+
+    /// The task has finished or been aborted
+    pub fn is_finished(&self) -> bool {
+        self.signal
+            .as_ref()
+            .map(oneshot::Sender::is_canceled)
+            .unwrap_or(true)
+    }
+
+    fn stop(&mut self) {
+        if let Some(sender) = self.signal.take() {
+            let _ignore = sender.send(());
+        }
+    }
+}
+
+impl Drop for TaskHandle {
+    fn drop(&mut self) {
+        self.stop()
+    }
+}
+
 /// A future task manager
 pub(crate) struct FutureTaskManager {
     signals: IntMap<FutureTaskId, oneshot::Sender<()>>,
@@ -140,7 +190,7 @@ impl Stream for FutureTaskManager {
 
 #[cfg(test)]
 mod test {
-    use super::{Arc, AtomicBool, BoxedFutureTask, FutureTaskManager, Ordering};
+    use super::{Arc, AtomicBool, BoxedFutureTask, FutureTaskManager, Ordering, TaskHandle};
 
     use crate::runtime::delay_for;
     use futures::{channel::mpsc::channel, stream::pending, SinkExt, StreamExt};
@@ -230,4 +280,60 @@ mod test {
 
         handle.join().unwrap()
     }
+
+    #[test]
+    fn test_cancel_task_handle() {
+        let (sender, receiver) = channel(128);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let mut manager = FutureTaskManager::new(receiver, shutdown);
+        let signals_len = Arc::new(AtomicUsize::new(usize::max_value()));
+        let signals_len_inner = Arc::clone(&signals_len);
+
+        let mut send_task = sender.clone();
+        let mut handles = Vec::new();
+        let mut tasks = Vec::new();
+        for _ in 0..10 {
+            let (handle, task) = TaskHandle::new(async {
+                let mut stream = pending::<()>();
+                loop {
+                    stream.next().await;
+                }
+            });
+            handles.push(handle);
+            tasks.push(task);
+        }
+
+        let handle = thread::spawn(|| {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.spawn(async move {
+                for task in tasks {
+                    let _res = send_task.send(task).await;
+                }
+            });
+            rt.block_on(async move {
+                loop {
+                    if manager.next().await.is_none() {
+                        signals_len_inner.store(manager.signals.len(), Ordering::SeqCst);
+                        break;
+                    }
+                }
+            });
+        });
+
+        thread::sleep(time::Duration::from_millis(100));
+        assert!(handles.iter().all(|handle| !handle.is_finished()));
+        // half canceled, half dropped
+        for (index, handle) in handles.into_iter().enumerate() {
+            if index % 2 == 0 {
+                handle.cancel();
+            }
+        }
+        // Wait for manager receive all signals
+        thread::sleep(time::Duration::from_millis(100));
+        drop(sender);
+        thread::sleep(time::Duration::from_millis(100));
+        assert_eq!(signals_len.load(Ordering::SeqCst), 0);
+
+        handle.join().unwrap()
+    }
 }
@@ -1,23 +1,317 @@
-use futures::{channel::mpsc, prelude::*};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::{
+    channel::{mpsc, oneshot},
+    prelude::*,
+};
 use log::{debug, error, trace};
 use multiaddr::Multiaddr;
-use secio::handshake::Config;
+use nohash_hasher::IntSet;
+use secio::{
+    crypto::cipher::CipherType, handshake::Config, KeyExporter, PeerId, PublicKey, SecurityParams,
+};
 use std::{
+    cmp::Ordering as CmpOrdering,
+    collections::HashMap,
     io,
+    net::SocketAddr,
     pin::Pin,
-    task::{Context, Poll},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Waker},
     time::Duration,
 };
 use tokio::io::{AsyncRead, AsyncWrite};
-use yamux::session::SessionType as YamuxType;
+use yamux::{
+    session::SessionType as YamuxType, Config as YamuxConfig, Control as YamuxControl,
+    Session as YamuxSession, Stats as YamuxStats,
+};
 
 use crate::{
     error::{HandshakeErrorKind, TransportErrorKind},
-    service::future_task::BoxedFutureTask,
-    session::SessionEvent,
-    transports::MultiIncoming,
+    lock::Mutex,
+    protocol_select::ProtocolInfo,
+    runtime::CompatStream,
+    service::{
+        config::TargetProtocol,
+        event::ServiceError,
+        future_task::{BoxedFutureTask, TaskHandle},
+    },
+    session::{AsyncRw, SessionEvent},
+    traits::{
+        AsyncStream, ConnectionGater, MuxerControl, MuxerIncoming, OpenStreamFuture,
+        SecurityUpgrade, StreamMuxer, UpgradeFuture,
+    },
+    transports::{MultiIncoming, MultiStream},
+    ProtocolId, SessionId,
 };
 
+/// Shared by all listeners, pause accept new inbound connections when set
+pub(crate) struct AcceptSwitch {
+    paused: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl Default for AcceptSwitch {
+    fn default() -> Self {
+        AcceptSwitch {
+            paused: AtomicBool::new(false),
+            wakers: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl AcceptSwitch {
+    pub(crate) fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        for waker in self.wakers.lock().drain(..) {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Return true if paused, and the listener will be woken up on resume
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll_paused(&self, cx: &mut Context<'_>) -> bool {
+        if !self.is_paused() {
+            return false;
+        }
+        {
+            // the listener polls again on every wake, keep one waker of it only
+            let mut wakers = self.wakers.lock();
+            if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+        }
+        // double check here, resume may happen before the waker registered
+        self.is_paused()
+    }
+}
+
+/// Shared by all controls, wake up the `wait_shutdown` calls when the service stream ends
+pub(crate) struct ShutdownSignal {
+    /// None after the service stream ended
+    waiters: Mutex<Option<Vec<oneshot::Sender<()>>>>,
+}
+
+impl Default for ShutdownSignal {
+    fn default() -> Self {
+        ShutdownSignal {
+            waiters: Mutex::new(Some(Vec::new())),
+        }
+    }
+}
+
+impl ShutdownSignal {
+    /// Resolved when the service stream ends or the service is dropped
+    pub(crate) fn wait(&self) -> impl Future<Output = ()> {
+        let (sender, receiver) = oneshot::channel();
+        if let Some(waiters) = self.waiters.lock().as_mut() {
+            waiters.push(sender);
+        }
+        // the sender is dropped if the stream already ended
+        receiver.map(|_| ())
+    }
+
+    pub(crate) fn finish(&self) {
+        if let Some(waiters) = self.waiters.lock().take() {
+            for sender in waiters {
+                let _ignore = sender.send(());
+            }
+        }
+    }
+}
+
+/// Shared by all listeners, limit of the inbound handshakes in progress
+///
+/// The listeners stop accepting while it's reached, the new connections wait in the
+/// backlog of the listen sockets
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct HandshakeLimit {
+    limit: usize,
+    in_flight: AtomicUsize,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl HandshakeLimit {
+    pub(crate) fn new(limit: usize) -> Self {
+        HandshakeLimit {
+            limit,
+            in_flight: AtomicUsize::new(0),
+            wakers: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn try_acquire(self: &Arc<Self>) -> Option<HandshakePermit> {
+        let mut in_flight = self.in_flight.load(Ordering::SeqCst);
+        while in_flight < self.limit {
+            match self.in_flight.compare_exchange(
+                in_flight,
+                in_flight + 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return Some(HandshakePermit(self.clone())),
+                Err(current) => in_flight = current,
+            }
+        }
+        None
+    }
+
+    /// Return none if the limit is reached, and the listener will be woken up on a release
+    fn poll_acquire(self: &Arc<Self>, cx: &mut Context<'_>) -> Option<HandshakePermit> {
+        if let Some(permit) = self.try_acquire() {
+            return Some(permit);
+        }
+        {
+            // the listener polls again on every wake, keep one waker of it only
+            let mut wakers = self.wakers.lock();
+            if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+        }
+        // double check here, a release may happen before the waker registered
+        self.try_acquire()
+    }
+}
+
+/// A handshake in progress, released on drop
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct HandshakePermit(Arc<HandshakeLimit>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for HandshakePermit {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+        for waker in self.0.wakers.lock().drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// What became of a message pushed to a session
+pub(crate) enum Pushed {
+    Queued,
+    /// Held to coalesce, it starts a new batch to flush after the delay
+    Batch(Duration),
+    /// Dropped, the session just reached the high watermark of its unsent data
+    Blocked,
+    /// Dropped, the session is still blocked
+    Dropped,
+}
+
+/// A dial queued by the dial concurrency limit, the higher weight the earlier,
+/// and the smaller sequence the earlier for the same weight
+///
+/// The target protocol of it is kept in the dial protocols of the service
+pub(crate) struct PendingDial {
+    pub weight: u8,
+    pub seq: u64,
+    pub address: Multiaddr,
+    pub peer_id: Option<PeerId>,
+}
+
+impl PartialEq for PendingDial {
+    fn eq(&self, other: &Self) -> bool {
+        self.weight == other.weight && self.seq == other.seq
+    }
+}
+
+impl Eq for PendingDial {}
+
+impl PartialOrd for PendingDial {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PendingDial {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.weight
+            .cmp(&other.weight)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// An address kept connected by the service, re-dialed with backoff when its session
+/// closes or the dial fails
+pub(crate) struct PersistentPeer {
+    pub target: PersistentTarget,
+    /// Failed attempts since the last opened session
+    pub attempts: u32,
+    /// The opened session of the address
+    pub session: Option<SessionId>,
+    /// The scheduled re-dial, aborted on drop
+    pub retry: Option<TaskHandle>,
+}
+
+impl PersistentPeer {
+    pub fn new(target: PersistentTarget) -> Self {
+        PersistentPeer {
+            target,
+            attempts: 0,
+            session: None,
+            retry: None,
+        }
+    }
+}
+
+/// The addresses of a peer dialed concurrently, the first one to finish the handshake
+/// is kept and the dials of the others are cancelled
+pub(crate) struct AddrRace {
+    pub target: TargetProtocol,
+    /// The signals to cancel the dials in progress
+    pub dials: HashMap<Multiaddr, oneshot::Sender<()>>,
+    /// The failures of the finished dials
+    pub errors: Vec<ServiceError>,
+}
+
+/// The target protocol of a persistent peer, which can be dialed repeatedly
+pub(crate) enum PersistentTarget {
+    All,
+    Single(ProtocolId),
+    /// The filter evaluated on the registered protocols
+    Some(IntSet<ProtocolId>),
+    Fallback(Vec<ProtocolId>),
+    Version(ProtocolId, String),
+}
+
+impl PersistentTarget {
+    pub fn new(target: TargetProtocol, protocols: impl Iterator<Item = ProtocolId>) -> Self {
+        match target {
+            TargetProtocol::All => PersistentTarget::All,
+            TargetProtocol::Single(id) => PersistentTarget::Single(id),
+            TargetProtocol::Filter(filter) => {
+                PersistentTarget::Some(protocols.filter(|id| filter(id)).collect())
+            }
+            TargetProtocol::Fallback(ids) => PersistentTarget::Fallback(ids),
+            TargetProtocol::Version(id, version) => PersistentTarget::Version(id, version),
+        }
+    }
+
+    pub fn target(&self) -> TargetProtocol {
+        match self {
+            PersistentTarget::All => TargetProtocol::All,
+            PersistentTarget::Single(id) => TargetProtocol::Single(*id),
+            PersistentTarget::Some(ids) => {
+                let ids = ids.clone();
+                TargetProtocol::Filter(Box::new(move |id| ids.contains(id)))
+            }
+            PersistentTarget::Fallback(ids) => TargetProtocol::Fallback(ids.clone()),
+            PersistentTarget::Version(id, version) => TargetProtocol::Version(*id, version.clone()),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub(crate) enum Source {
     /// Event from user
@@ -72,14 +366,116 @@ impl From<SessionType> for YamuxType {
     }
 }
 
+/// Secio security upgrade, the default security upgrade of service
+#[derive(Clone)]
+pub struct SecioUpgrade {
+    key_pair: secio::SecioKeyPair,
+    max_frame_length: usize,
+    ciphers: Option<Vec<CipherType>>,
+}
+
+impl SecioUpgrade {
+    /// New a secio upgrade
+    pub fn new(key_pair: secio::SecioKeyPair, max_frame_length: usize) -> Self {
+        SecioUpgrade {
+            key_pair,
+            max_frame_length,
+            ciphers: None,
+        }
+    }
+
+    /// Ciphers in the order of preference, default is all of them
+    pub fn ciphers(mut self, ciphers: Vec<CipherType>) -> Self {
+        self.ciphers = Some(ciphers);
+        self
+    }
+}
+
+impl SecurityUpgrade for SecioUpgrade {
+    fn upgrade(&self, socket: Box<dyn AsyncStream>) -> UpgradeFuture {
+        let mut config = Config::new(self.key_pair.clone()).max_frame_length(self.max_frame_length);
+        if let Some(ref ciphers) = self.ciphers {
+            config = config.ciphers(ciphers);
+        }
+        Box::pin(async move {
+            config
+                .handshake(socket)
+                .await
+                .map(|(handle, public_key, _)| {
+                    let exporter = handle.exporter().clone();
+                    let params = handle.security_params();
+                    (
+                        Box::new(handle) as Box<dyn AsyncStream>,
+                        public_key,
+                        Some(exporter),
+                        Some(params),
+                    )
+                })
+                .map_err(HandshakeErrorKind::SecioError)
+        })
+    }
+}
+
+/// Yamux stream multiplexer, the default multiplexer of service
+#[derive(Clone, Copy)]
+pub struct YamuxMuxer {
+    config: YamuxConfig,
+}
+
+impl YamuxMuxer {
+    /// New a yamux multiplexer
+    pub fn new(config: YamuxConfig) -> Self {
+        YamuxMuxer { config }
+    }
+}
+
+impl StreamMuxer for YamuxMuxer {
+    fn multiplex(
+        &self,
+        socket: Box<dyn AsyncStream>,
+        ty: SessionType,
+    ) -> (MuxerIncoming, Arc<dyn MuxerControl>) {
+        let session = YamuxSession::new(socket, self.config, ty.into());
+        let control = session.control();
+        let incoming = session.map_ok(|stream| Box::new(stream) as Box<dyn AsyncStream>);
+        (Box::pin(incoming), Arc::new(control))
+    }
+}
+
+impl MuxerControl for YamuxControl {
+    fn open_stream(&self) -> OpenStreamFuture {
+        let mut control = self.clone();
+        Box::pin(async move {
+            YamuxControl::open_stream(&mut control)
+                .await
+                .map(|stream| Box::new(stream) as Box<dyn AsyncStream>)
+                .map_err(|err| io::Error::new(io::ErrorKind::BrokenPipe, err))
+        })
+    }
+
+    fn close(&self) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let mut control = self.clone();
+        Box::pin(async move { YamuxControl::close(&mut control).await })
+    }
+
+    fn yamux_stats(&self) -> Option<YamuxStats> {
+        Some(self.stats())
+    }
+}
+
 pub(crate) struct HandshakeContext {
-    pub(crate) key_pair: Option<secio::SecioKeyPair>,
+    pub(crate) security: Option<Arc<dyn SecurityUpgrade>>,
+    /// Muxer brought by the connection, such as quic, its channel binding is checked after
+    /// the security upgrade
+    pub(crate) muxer: Option<Arc<dyn StreamMuxer>>,
+    /// Local protocols to exchange with the remote after the security upgrade, if enabled
+    pub(crate) protocols: Option<Arc<HashMap<ProtocolId, ProtocolInfo>>>,
     pub(crate) event_sender: mpsc::Sender<SessionEvent>,
-    pub(crate) max_frame_length: usize,
     pub(crate) timeout: Duration,
     pub(crate) ty: SessionType,
     pub(crate) remote_address: Multiaddr,
     pub(crate) listen_address: Option<Multiaddr>,
+    pub(crate) local_address: Option<SocketAddr>,
 }
 
 impl HandshakeContext {
@@ -87,17 +483,13 @@ impl HandshakeContext {
     where
         H: AsyncRead + AsyncWrite + Send + 'static + Unpin,
     {
-        match self.key_pair {
-            Some(key_pair) => {
-                let result = crate::runtime::timeout(
-                    self.timeout,
-                    Config::new(key_pair)
-                        .max_frame_length(self.max_frame_length)
-                        .handshake(socket),
-                )
-                .await;
-
-                let event = match result {
+        let mut event_sender = self.event_sender.clone();
+        let event = match self.security.take() {
+            Some(security) => {
+                let result =
+                    crate::runtime::timeout(self.timeout, security.upgrade(Box::new(socket))).await;
+
+                match result {
                     Err(error) => {
                         debug!(
                             "Handshake with {} failed, error: {:?}",
@@ -111,13 +503,30 @@ impl HandshakeContext {
                         }
                     }
                     Ok(res) => match res {
-                        Ok((handle, public_key, _)) => SessionEvent::HandshakeSuccess {
-                            handle: Box::new(handle),
-                            public_key: Some(public_key),
-                            address: self.remote_address,
-                            ty: self.ty,
-                            listen_address: self.listen_address,
-                        },
+                        Ok((mut handle, public_key, exporter, security_params)) => {
+                            match self.check_channel_binding(&mut handle).await {
+                                Ok(()) => {
+                                    self.success(
+                                        Box::new(handle),
+                                        Some(public_key),
+                                        exporter,
+                                        security_params,
+                                    )
+                                    .await
+                                }
+                                Err(error) => {
+                                    debug!(
+                                        "Channel binding with {} failed, error: {:?}",
+                                        self.remote_address, error
+                                    );
+                                    SessionEvent::HandshakeError {
+                                        ty: self.ty,
+                                        error: HandshakeErrorKind::Upgrade(error),
+                                        address: self.remote_address,
+                                    }
+                                }
+                            }
+                        }
                         Err(error) => {
                             debug!(
                                 "Handshake with {} failed, error: {:?}",
@@ -125,41 +534,188 @@ impl HandshakeContext {
                             );
                             SessionEvent::HandshakeError {
                                 ty: self.ty,
-                                error: HandshakeErrorKind::SecioError(error),
+                                error,
                                 address: self.remote_address,
                             }
                         }
                     },
-                };
-                if let Err(err) = self.event_sender.send(event).await {
-                    error!("handshake result send back error: {:?}", err);
                 }
             }
-            None => {
-                let event = SessionEvent::HandshakeSuccess {
-                    handle: Box::new(socket),
-                    public_key: None,
-                    address: self.remote_address,
-                    ty: self.ty,
-                    listen_address: self.listen_address,
-                };
-                if let Err(err) = self.event_sender.send(event).await {
-                    error!("handshake result send back error: {:?}", err);
+            None => self.success(Box::new(socket), None, None, None).await,
+        };
+        if let Err(err) = event_sender.send(event).await {
+            error!("handshake result send back error: {:?}", err);
+        }
+    }
+
+    /// Check the channel binding of the muxer brought by the connection over the secure handle
+    async fn check_channel_binding<H>(&self, handle: &mut H) -> io::Result<()>
+    where
+        H: AsyncRead + AsyncWrite + Unpin,
+    {
+        let binding = match self
+            .muxer
+            .as_ref()
+            .and_then(|muxer| muxer.channel_binding())
+        {
+            Some(binding) => binding,
+            None => return Ok(()),
+        };
+        crate::runtime::timeout(self.timeout, exchange_channel_binding(handle, &binding))
+            .await
+            .unwrap_or_else(|error| Err(io::Error::new(io::ErrorKind::TimedOut, error.to_string())))
+    }
+
+    /// Exchange the protocols with the remote if enabled, then the handshake succeeds
+    async fn success(
+        self,
+        mut handle: Box<dyn AsyncRw + Send + Unpin + 'static>,
+        public_key: Option<PublicKey>,
+        exporter: Option<KeyExporter>,
+        security_params: Option<SecurityParams>,
+    ) -> SessionEvent {
+        let remote_protocols = match self.protocols {
+            Some(ref protocols) => {
+                let exchange = exchange_protocols(&mut handle, protocols);
+                match crate::runtime::timeout(self.timeout, exchange).await {
+                    Ok(Ok(remote_protocols)) => Some(remote_protocols),
+                    Ok(Err(error)) => {
+                        debug!(
+                            "Protocol exchange with {} failed, error: {:?}",
+                            self.remote_address, error
+                        );
+                        return SessionEvent::HandshakeError {
+                            ty: self.ty,
+                            error: HandshakeErrorKind::ProtocolExchange(error),
+                            address: self.remote_address,
+                        };
+                    }
+                    Err(error) => {
+                        return SessionEvent::HandshakeError {
+                            ty: self.ty,
+                            error: HandshakeErrorKind::Timeout(error.to_string()),
+                            address: self.remote_address,
+                        }
+                    }
                 }
             }
+            None => None,
+        };
+        SessionEvent::HandshakeSuccess {
+            handle,
+            muxer: self.muxer,
+            public_key,
+            exporter,
+            security_params,
+            address: self.remote_address,
+            ty: self.ty,
+            listen_address: self.listen_address,
+            local_address: self.local_address,
+            remote_protocols,
         }
     }
 }
 
+/// Upper bound of the channel binding of the remote
+const MAX_CHANNEL_BINDING_SIZE: usize = 16 * 1024;
+
+/// Send the local channel binding and check the remote one is the same
+///
+/// Both are prefixed by their length, a mismatch means the connection is relayed
+async fn exchange_channel_binding<H>(socket: &mut H, binding: &[u8]) -> io::Result<()>
+where
+    H: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = CompatStream::new(socket);
+    stream
+        .write_all(&(binding.len() as u32).to_be_bytes())
+        .await?;
+    stream.write_all(binding).await?;
+    stream.flush().await?;
+
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).await?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_CHANNEL_BINDING_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "channel binding too large",
+        ));
+    }
+    let mut remote = vec![0; len];
+    stream.read_exact(&mut remote).await?;
+    if remote != binding {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "channel binding mismatch",
+        ));
+    }
+    Ok(())
+}
+
+/// Upper bound of the encoded protocol list of the remote
+const MAX_PROTOCOLS_SIZE: usize = 64 * 1024;
+
+/// Send the local protocols and read the ones of the remote
+///
+/// The list is prefixed by its length, and so is each encoded `ProtocolInfo` in it. Exactly
+/// the bytes of the list are read, nothing of the muxer data following it is consumed.
+async fn exchange_protocols<H>(
+    socket: &mut H,
+    protocols: &HashMap<ProtocolId, ProtocolInfo>,
+) -> io::Result<Vec<ProtocolInfo>>
+where
+    H: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut list = BytesMut::new();
+    for info in protocols.values() {
+        let data = info.clone().encode();
+        list.put_u32(data.len() as u32);
+        list.put_slice(&data);
+    }
+    let mut stream = CompatStream::new(socket);
+    stream.write_all(&(list.len() as u32).to_be_bytes()).await?;
+    stream.write_all(&list).await?;
+    stream.flush().await?;
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid protocol list");
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).await?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_PROTOCOLS_SIZE {
+        return Err(invalid());
+    }
+    let mut list = vec![0; len];
+    stream.read_exact(&mut list).await?;
+    let mut list = Bytes::from(list);
+
+    let mut remote_protocols = Vec::new();
+    while list.has_remaining() {
+        if list.remaining() < 4 {
+            return Err(invalid());
+        }
+        let len = list.get_u32() as usize;
+        if list.remaining() < len {
+            return Err(invalid());
+        }
+        let data = list.split_to(len);
+        remote_protocols.push(ProtocolInfo::decode(&data).ok_or_else(invalid)?);
+    }
+    Ok(remote_protocols)
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 pub struct Listener {
     pub(crate) inner: MultiIncoming,
-    pub(crate) key_pair: Option<secio::SecioKeyPair>,
+    pub(crate) security: Option<Arc<dyn SecurityUpgrade>>,
+    pub(crate) protocols: Option<Arc<HashMap<ProtocolId, ProtocolInfo>>>,
+    pub(crate) gater: Option<Arc<dyn ConnectionGater>>,
     pub(crate) event_sender: mpsc::Sender<SessionEvent>,
-    pub(crate) max_frame_length: usize,
     pub(crate) timeout: Duration,
     pub(crate) listen_addr: Multiaddr,
     pub(crate) future_task_sender: mpsc::Sender<BoxedFutureTask>,
+    pub(crate) accept_switch: Arc<AcceptSwitch>,
+    pub(crate) handshake_limit: Option<Arc<HandshakeLimit>>,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -186,20 +742,29 @@ impl Listener {
         });
     }
 
-    fn handshake<H>(&self, socket: H, remote_address: Multiaddr)
-    where
-        H: AsyncRead + AsyncWrite + Send + 'static + Unpin,
-    {
+    fn handshake(
+        &self,
+        mut socket: MultiStream,
+        remote_address: Multiaddr,
+        permit: Option<HandshakePermit>,
+    ) {
         let handshake_task = HandshakeContext {
             ty: SessionType::Inbound,
             remote_address,
             listen_address: Some(self.listen_addr.clone()),
-            key_pair: self.key_pair.clone(),
+            local_address: None,
+            security: self.security.clone(),
+            muxer: socket.muxer(),
+            protocols: self.protocols.clone(),
             event_sender: self.event_sender.clone(),
-            max_frame_length: self.max_frame_length,
             timeout: self.timeout,
         }
         .handshake(socket);
+        // hold the permit until the handshake finishes
+        let handshake_task = async move {
+            handshake_task.await;
+            drop(permit);
+        };
 
         let mut future_task_sender = self.future_task_sender.clone();
 
@@ -220,9 +785,26 @@ impl Stream for Listener {
     type Item = ();
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // keep the listener bound, but don't accept new connection
+        if self.accept_switch.poll_paused(cx) {
+            return Poll::Pending;
+        }
+        // take the permit before accepting, release it if nothing is accepted
+        let permit = match self.handshake_limit {
+            Some(ref limit) => match limit.poll_acquire(cx) {
+                Some(permit) => Some(permit),
+                None => return Poll::Pending,
+            },
+            None => None,
+        };
         match Pin::new(&mut self.inner).as_mut().poll_next(cx) {
             Poll::Ready(Some(Ok((remote_address, socket)))) => {
-                self.handshake(socket, remote_address);
+                match self.gater {
+                    Some(ref gater) if !gater.intercept_accept(&remote_address) => {
+                        debug!("connection from {} is vetoed by the gater", remote_address);
+                    }
+                    _ => self.handshake(socket, remote_address, permit),
+                }
                 Poll::Ready(Some(()))
             }
             Poll::Ready(None) => {
@@ -237,3 +819,28 @@ impl Stream for Listener {
         }
     }
 }
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test {
+    use super::HandshakeLimit;
+    use futures::task::noop_waker;
+    use std::{sync::Arc, task::Context};
+
+    #[test]
+    fn test_handshake_limit_keeps_one_waker_per_listener() {
+        let limit = Arc::new(HandshakeLimit::new(1));
+        let permit = limit.try_acquire().unwrap();
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        for _ in 0..10 {
+            assert!(limit.poll_acquire(&mut cx).is_none());
+        }
+        assert_eq!(limit.wakers.lock().len(), 1);
+
+        // the release wakes and clears the wakers
+        drop(permit);
+        assert!(limit.wakers.lock().is_empty());
+        assert!(limit.poll_acquire(&mut cx).is_some());
+    }
+}
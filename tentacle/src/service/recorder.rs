@@ -0,0 +1,340 @@
+use log::{debug, warn};
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::mpsc::{channel, sync_channel, Receiver, SyncSender, TrySendError},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    error::SendErrorKind, multiaddr::Multiaddr, service::ServiceControl, service::TargetProtocol,
+    ProtocolId, SessionId,
+};
+
+/// Lines waiting for the writer, the lines recorded over it are dropped
+const PENDING_LINES: usize = 4096;
+/// How long the drop of the recorder waits for the writer to write the queued lines
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Record every session event and service task handled by the service with a timestamp,
+/// one per line, for reproducing ordering bugs.
+///
+/// The service only formats the lines, a background thread writes them, so a slow disk
+/// doesn't stall the service. `read_records` reads the trace back in order, and
+/// `replay_records` feeds the service tasks in it to a service.
+pub(crate) struct EventRecorder {
+    sender: Option<SyncSender<String>>,
+    /// Disconnected once the writer exits
+    writer_exited: Option<Receiver<()>>,
+    /// Lines dropped since the last accepted one
+    dropped: usize,
+}
+
+impl EventRecorder {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (sender, receiver) = sync_channel::<String>(PENDING_LINES);
+        let (exited, writer_exited) = channel::<()>();
+        thread::Builder::new()
+            .name("event-recorder".to_owned())
+            .spawn(move || {
+                let _exited = exited;
+                let mut writer = BufWriter::new(file);
+                while let Ok(line) = receiver.recv() {
+                    // write what is queued, then flush once
+                    let result = writeln!(writer, "{}", line)
+                        .and_then(|_| {
+                            receiver
+                                .try_iter()
+                                .try_for_each(|line| writeln!(writer, "{}", line))
+                        })
+                        .and_then(|_| writer.flush());
+                    if let Err(err) = result {
+                        warn!("event recorder write error: {:?}", err)
+                    }
+                }
+            })?;
+        Ok(EventRecorder {
+            sender: Some(sender),
+            writer_exited: Some(writer_exited),
+            dropped: 0,
+        })
+    }
+
+    /// Write a line as `<unix timestamp in micros> <kind> <event>`
+    pub fn record(&mut self, kind: &str, event: &dyn fmt::Debug) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_micros())
+            .unwrap_or_default();
+        let line = format!("{} {} {:?}", timestamp, kind, event);
+        let sender = match self.sender {
+            Some(ref sender) => sender,
+            None => return,
+        };
+        match sender.try_send(line) {
+            Ok(()) => {
+                if self.dropped > 0 {
+                    warn!("event recorder fell behind, {} lines dropped", self.dropped);
+                    self.dropped = 0;
+                }
+            }
+            Err(TrySendError::Full(_)) => self.dropped += 1,
+            Err(TrySendError::Disconnected(_)) => {
+                warn!("event recorder writer exited");
+                self.sender = None;
+            }
+        }
+    }
+}
+
+impl Drop for EventRecorder {
+    fn drop(&mut self) {
+        // the writer exits after writing the queued lines, it's detached if that takes
+        // longer than the timeout, so a slow disk doesn't block the runtime dropping the service
+        self.sender.take();
+        if let Some(writer_exited) = self.writer_exited.take() {
+            let _ignore = writer_exited.recv_timeout(FLUSH_TIMEOUT);
+        }
+    }
+}
+
+/// A line of the trace written by `ServiceBuilder::event_recorder`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedEvent {
+    /// Unix timestamp in microseconds
+    pub timestamp: u128,
+    /// `session` for a session event, `task` for a service task
+    pub kind: String,
+    /// Debug output of the event
+    pub event: String,
+}
+
+/// Read the trace written by `ServiceBuilder::event_recorder`, in the order the events
+/// were handled
+pub fn read_records<P: AsRef<Path>>(path: P) -> io::Result<Vec<RecordedEvent>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid record at line {}", index + 1),
+            )
+        };
+        let mut parts = line.splitn(3, ' ');
+        let timestamp = parts
+            .next()
+            .and_then(|timestamp| timestamp.parse().ok())
+            .ok_or_else(invalid)?;
+        let kind = parts.next().ok_or_else(invalid)?.to_owned();
+        let event = parts.next().ok_or_else(invalid)?.to_owned();
+        records.push(RecordedEvent {
+            timestamp,
+            kind,
+            event,
+        });
+    }
+    Ok(records)
+}
+
+/// A service task rebuilt from its recorded text
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ReplayTask {
+    Listen(Multiaddr),
+    Dial(Multiaddr),
+    Disconnect(SessionId),
+    ProtocolClose(SessionId, ProtocolId),
+    ProtocolReset(SessionId, ProtocolId),
+}
+
+impl ReplayTask {
+    fn parse(event: &str) -> Option<Self> {
+        if let Some(address) = event.strip_prefix("Listen address: ") {
+            return address.parse().ok().map(ReplayTask::Listen);
+        }
+        if let Some(address) = event
+            .strip_prefix("Dial address: ")
+            .or_else(|| event.strip_prefix("Dial address with result: "))
+        {
+            return address.parse().ok().map(ReplayTask::Dial);
+        }
+        if let Some(id) = event
+            .strip_prefix("Disconnect session [SessionId(")
+            .and_then(|rest| rest.strip_suffix(")]"))
+        {
+            return id
+                .parse()
+                .ok()
+                .map(|id| ReplayTask::Disconnect(SessionId::new(id)));
+        }
+        if let Some(rest) = event.strip_prefix("Close session ") {
+            return parse_session_proto(rest)
+                .map(|(session_id, proto_id)| ReplayTask::ProtocolClose(session_id, proto_id));
+        }
+        if let Some(rest) = event.strip_prefix("Reset session ") {
+            return parse_session_proto(rest)
+                .map(|(session_id, proto_id)| ReplayTask::ProtocolReset(session_id, proto_id));
+        }
+        None
+    }
+
+    fn send(self, control: &ServiceControl) -> Result<(), SendErrorKind> {
+        match self {
+            ReplayTask::Listen(address) => control.listen(address),
+            ReplayTask::Dial(address) => control.dial(address, TargetProtocol::All),
+            ReplayTask::Disconnect(session_id) => control.disconnect(session_id),
+            ReplayTask::ProtocolClose(session_id, proto_id) => {
+                control.close_protocol(session_id, proto_id)
+            }
+            ReplayTask::ProtocolReset(session_id, proto_id) => {
+                control.reset_protocol(session_id, proto_id)
+            }
+        }
+    }
+}
+
+/// Parse `[SessionId(x)] proto [ProtocolId(y)]`
+fn parse_session_proto(text: &str) -> Option<(SessionId, ProtocolId)> {
+    const SEPARATOR: &str = ")] proto [ProtocolId(";
+    let text = text.strip_prefix("[SessionId(")?.strip_suffix(")]")?;
+    let index = text.find(SEPARATOR)?;
+    let session_id = text[..index].parse().ok()?;
+    let proto_id = text[index + SEPARATOR.len()..].parse().ok()?;
+    Some((SessionId::new(session_id), ProtocolId::new(proto_id)))
+}
+
+/// Feed the service tasks of a trace read by `read_records` to the service of the control,
+/// keeping the recorded intervals between them, return the count of the tasks replayed
+///
+/// Only the tasks which can be rebuilt from their text are replayed: listen, dial, disconnect,
+/// protocol close and reset. The others carry messages, futures or closures which aren't
+/// recorded, they are skipped, and so are the session events, which the service emits again.
+/// Dials open all protocols, since the target isn't recorded.
+///
+/// The addresses and session ids are replayed as recorded, so replay a trace recorded on the
+/// memory transport to a fresh service, which then assigns the same session ids.
+/// This blocks the calling thread for the length of the trace, don't call it on a runtime.
+pub fn replay_records(
+    control: &ServiceControl,
+    records: &[RecordedEvent],
+) -> Result<usize, SendErrorKind> {
+    let mut last = None;
+    let mut replayed = 0;
+    for record in records.iter().filter(|record| record.kind == "task") {
+        let task = match ReplayTask::parse(&record.event) {
+            Some(task) => task,
+            None => {
+                debug!("skip the task which can't be replayed: {}", record.event);
+                continue;
+            }
+        };
+        if let Some(last) = last {
+            let interval = record.timestamp.saturating_sub(last);
+            thread::sleep(Duration::from_micros(interval as u64));
+        }
+        last = Some(record.timestamp);
+        task.send(control)?;
+        replayed += 1;
+    }
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_records, EventRecorder, RecordedEvent, ReplayTask};
+    use crate::{ProtocolId, SessionId};
+
+    #[test]
+    fn test_record() {
+        let path = std::env::temp_dir().join(format!("tentacle-recorder-{}", std::process::id()));
+        let _ignore = std::fs::remove_file(&path);
+
+        let mut recorder = EventRecorder::new(&path).unwrap();
+        recorder.record("session", &"first");
+        recorder.record("task", &1);
+        drop(recorder);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ignore = std::fs::remove_file(&path);
+
+        let lines: Vec<Vec<&str>> = content
+            .lines()
+            .map(|line| line.splitn(3, ' ').collect())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0][0].parse::<u128>().unwrap() <= lines[1][0].parse::<u128>().unwrap());
+        assert_eq!(&lines[0][1..], &["session", "\"first\""]);
+        assert_eq!(&lines[1][1..], &["task", "1"]);
+    }
+
+    #[test]
+    fn test_read_records() {
+        let path =
+            std::env::temp_dir().join(format!("tentacle-recorder-replay-{}", std::process::id()));
+        let _ignore = std::fs::remove_file(&path);
+
+        let mut recorder = EventRecorder::new(&path).unwrap();
+        for index in 0..100 {
+            recorder.record("task", &format!("event {}", index));
+        }
+        recorder.record("session", &Some(1));
+        drop(recorder);
+
+        let records = read_records(&path).unwrap();
+        let _ignore = std::fs::remove_file(&path);
+
+        assert_eq!(records.len(), 101);
+        assert!(records
+            .windows(2)
+            .all(|pair| pair[0].timestamp <= pair[1].timestamp));
+        for (index, record) in records[..100].iter().enumerate() {
+            assert_eq!(record.kind, "task");
+            assert_eq!(record.event, format!("\"event {}\"", index));
+        }
+        assert_eq!(
+            records[100],
+            RecordedEvent {
+                timestamp: records[100].timestamp,
+                kind: "session".to_owned(),
+                event: "Some(1)".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_replay_task() {
+        assert_eq!(
+            ReplayTask::parse("Listen address: /memory/1"),
+            Some(ReplayTask::Listen("/memory/1".parse().unwrap()))
+        );
+        assert_eq!(
+            ReplayTask::parse("Dial address with result: /memory/2"),
+            Some(ReplayTask::Dial("/memory/2".parse().unwrap()))
+        );
+        assert_eq!(
+            ReplayTask::parse("Disconnect session [SessionId(3)]"),
+            Some(ReplayTask::Disconnect(SessionId::new(3)))
+        );
+        assert_eq!(
+            ReplayTask::parse("Close session [SessionId(4)] proto [ProtocolId(5)]"),
+            Some(ReplayTask::ProtocolClose(
+                SessionId::new(4),
+                ProtocolId::new(5)
+            ))
+        );
+        assert_eq!(
+            ReplayTask::parse("Reset session [SessionId(6)] proto [ProtocolId(7)]"),
+            Some(ReplayTask::ProtocolReset(
+                SessionId::new(6),
+                ProtocolId::new(7)
+            ))
+        );
+        assert_eq!(ReplayTask::parse("Future task"), None);
+        assert_eq!(ReplayTask::parse("Disconnect session [SessionId(x)]"), None);
+    }
+}
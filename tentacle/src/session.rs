@@ -1,9 +1,16 @@
-use futures::{channel::mpsc, prelude::*, stream::iter, SinkExt};
+use futures::{
+    channel::{mpsc, oneshot},
+    prelude::*,
+    stream::iter,
+    SinkExt,
+};
 use log::{debug, error, log_enabled, trace, warn};
-use nohash_hasher::IntMap;
+use nohash_hasher::{IntMap, IntSet};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
     io::{self, ErrorKind},
+    net::SocketAddr,
     pin::Pin,
     sync::{atomic::Ordering, Arc},
     task::{Context, Poll},
@@ -11,27 +18,35 @@ use std::{
 };
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_util::codec::{Framed, FramedParts, FramedRead, FramedWrite, LengthDelimitedCodec};
-use yamux::{Control, Session as YamuxSession, StreamHandle};
 
 use crate::{
     buffer::{Buffer, PriorityBuffer, SendResult},
+    builder::BeforeReceive,
     channel::{mpsc as priority_mpsc, mpsc::Priority, QuickSinkExt},
     context::SessionContext,
     error::{HandshakeErrorKind, ProtocolHandleErrorKind, TransportErrorKind},
+    flow_control::{MemoryBudget, RecvRateLimit},
+    metrics::MessageLatency,
     multiaddr::Multiaddr,
     protocol_handle_stream::{ServiceProtocolEvent, SessionProtocolEvent},
-    protocol_select::{client_select, server_select, ProtocolInfo},
-    secio::PublicKey,
+    protocol_select::{client_select, multistream, server_select, NegotiationMode, ProtocolInfo},
+    secio::{KeyExporter, PublicKey, SecurityParams},
     service::{
         config::{Meta, SessionConfig},
         future_task::BoxedFutureTask,
-        ServiceControl, SessionType, RECEIVED_SIZE, SEND_SIZE,
+        DropReason, ProtocolClosePolicy, SelectErrorCause, ServiceControl, SessionType, YamuxMuxer,
+        RECEIVED_SIZE, SEND_SIZE,
     },
-    substream::{ProtocolEvent, SubstreamBuilder, SubstreamWritePartBuilder},
+    substream::{ProtocolEvent, RawSubstream, SubstreamBuilder, SubstreamWritePartBuilder},
+    traits::{AsyncStream, MuxerControl, MuxerIncoming, StreamMuxer},
     transports::MultiIncoming,
+    utils::compress::CompressionAlgo,
     ProtocolId, SessionId, StreamId, SubstreamReadPart,
 };
 
+/// Checks of the traffic in the idle timeout
+const IDLE_CHECKS: u32 = 4;
+
 pub trait AsyncRw: AsyncWrite + AsyncRead {}
 
 impl<T: AsyncRead + AsyncWrite> AsyncRw for T {}
@@ -46,19 +61,36 @@ pub(crate) enum SessionEvent {
     ListenStart {
         listen_address: Multiaddr,
         incoming: MultiIncoming,
+        /// The dns address it's resolved from
+        original: Option<Multiaddr>,
+    },
+    /// A dns listen address resolved, each address will be listened separately
+    ListenResolved {
+        original: Multiaddr,
+        addresses: Vec<Multiaddr>,
     },
     HandshakeSuccess {
         /// In order to be compatible with multiple underlying connection abstractions,
         /// the dyn trait needs to be used here
         handle: Box<dyn AsyncRw + Send + Unpin + 'static>,
+        /// Muxer brought by the connection instead of the one of service, such as quic
+        muxer: Option<Arc<dyn StreamMuxer>>,
         /// Remote Public key
         public_key: Option<PublicKey>,
+        /// Keying material exporter of the secure session
+        exporter: Option<KeyExporter>,
+        /// Algorithms negotiated by the secure session
+        security_params: Option<SecurityParams>,
         /// Remote address
         address: Multiaddr,
         /// Session type
         ty: SessionType,
         /// listen addr
         listen_address: Option<Multiaddr>,
+        /// Local socket address of outbound connection
+        local_address: Option<SocketAddr>,
+        /// Protocols of the remote, if exchanged
+        remote_protocols: Option<Vec<ProtocolInfo>>,
     },
     HandshakeError {
         /// remote address
@@ -91,14 +123,41 @@ pub(crate) enum SessionEvent {
     ProtocolOpen {
         /// Protocol id
         proto_id: ProtocolId,
+        /// The version asked for, the highest common one if None
+        version: Option<String>,
+    },
+    /// Open the first protocol that can be negotiated
+    ProtocolOpenFallback {
+        /// Protocol ids in order
+        proto_ids: Vec<ProtocolId>,
     },
     /// Protocol close event
     ProtocolClose {
         /// Protocol id
         proto_id: ProtocolId,
     },
+    /// Protocol reset event
+    ProtocolReset {
+        /// Protocol id
+        proto_id: ProtocolId,
+    },
+    /// Cancel the negotiation of a protocol opened by local
+    ProtocolCancelOpen {
+        /// Protocol id
+        proto_id: ProtocolId,
+    },
     StreamStart {
-        stream: StreamHandle,
+        stream: Box<dyn AsyncStream>,
+    },
+    /// A protocol registered on the running service
+    ProtocolRegistered {
+        /// Protocol meta
+        meta: Arc<Meta>,
+        /// Session level handle of the protocol on this session
+        handle: Option<(
+            Option<futures::channel::oneshot::Sender<()>>,
+            crate::runtime::JoinHandle<()>,
+        )>,
     },
     ChangeState {
         state: SessionState,
@@ -109,11 +168,20 @@ pub(crate) enum SessionEvent {
         id: SessionId,
         /// proto_name
         proto_name: Option<String>,
+        /// Why the negotiation failed
+        cause: SelectErrorCause,
     },
     SessionTimeout {
         /// Session id
         id: SessionId,
     },
+    /// The remote opened a protocol which is not allowed
+    ProtocolNotAllowed {
+        /// Session id
+        id: SessionId,
+        /// Protocol id
+        proto_id: ProtocolId,
+    },
     /// Codec error
     ProtocolError {
         /// Session id
@@ -136,9 +204,86 @@ pub(crate) enum SessionEvent {
     },
 }
 
+impl fmt::Debug for SessionEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::SessionEvent::*;
+
+        match self {
+            SessionClose { id } => write!(f, "Close session [{}]", id),
+            ListenStart { listen_address, .. } => write!(f, "Listen start: {}", listen_address),
+            ListenResolved {
+                original,
+                addresses,
+            } => write!(f, "Listen resolved: {} -> {:?}", original, addresses),
+            HandshakeSuccess {
+                address,
+                ty,
+                listen_address,
+                ..
+            } => write!(
+                f,
+                "Handshake success, address: {}, ty: {:?}, listen address: {:?}",
+                address, ty, listen_address
+            ),
+            HandshakeError { address, ty, error } => write!(
+                f,
+                "Handshake error, address: {}, ty: {:?}, error: {:?}",
+                address, ty, error
+            ),
+            DialError { address, error } => {
+                write!(f, "Dial error, address: {}, error: {:?}", address, error)
+            }
+            ListenError { address, error } => {
+                write!(f, "Listen error, address: {}, error: {:?}", address, error)
+            }
+            ProtocolMessage { proto_id, data } => {
+                write!(f, "proto_id: {}, message: {:?}", proto_id, data)
+            }
+            ProtocolOpen { proto_id, .. } => write!(f, "Open proto [{}]", proto_id),
+            ProtocolOpenFallback { proto_ids } => {
+                write!(f, "Open fallback protos {:?}", proto_ids)
+            }
+            ProtocolClose { proto_id } => write!(f, "Close proto [{}]", proto_id),
+            ProtocolReset { proto_id } => write!(f, "Reset proto [{}]", proto_id),
+            ProtocolCancelOpen { proto_id } => write!(f, "Cancel open proto [{}]", proto_id),
+            StreamStart { .. } => write!(f, "Stream start"),
+            ProtocolRegistered { meta, .. } => write!(f, "Register proto [{}]", meta.id),
+            ChangeState { state, error } => {
+                write!(f, "Change state to {:?}, error: {:?}", state, error)
+            }
+            ProtocolSelectError {
+                id,
+                proto_name,
+                cause,
+            } => write!(
+                f,
+                "Session [{}] select proto {:?} error: {:?}",
+                id, proto_name, cause
+            ),
+            SessionTimeout { id } => write!(f, "Session [{}] timeout", id),
+            ProtocolNotAllowed { id, proto_id } => {
+                write!(f, "Session [{}] proto [{}] not allowed", id, proto_id)
+            }
+            ProtocolError {
+                id,
+                proto_id,
+                error,
+            } => write!(
+                f,
+                "Session [{}] proto [{}] error: {:?}",
+                id, proto_id, error
+            ),
+            MuxerError { id, error } => write!(f, "Session [{}] muxer error: {:?}", id, error),
+            ProtocolHandleError { error, proto_id } => {
+                write!(f, "Proto [{}] handle error: {:?}", proto_id, error)
+            }
+        }
+    }
+}
+
 /// Wrapper for real data streams, such as TCP stream
 pub(crate) struct Session {
-    control: Control,
+    control: Arc<dyn MuxerControl>,
 
     protocol_configs_by_name: HashMap<String, Arc<Meta>>,
     protocol_configs_by_id: IntMap<ProtocolId, Arc<Meta>>,
@@ -159,6 +304,28 @@ pub(crate) struct Session {
     /// Sub streams maps a stream id to a sender of sub stream
     substreams: IntMap<StreamId, PriorityBuffer<ProtocolEvent>>,
     proto_streams: IntMap<ProtocolId, StreamId>,
+    /// Protocol name in negotiation -> the protocols to try if it fails
+    fallback_protocols: HashMap<String, VecDeque<String>>,
+    /// Protocol name opened by local -> the version asked for, only it is offered on the first try
+    requested_versions: HashMap<String, String>,
+    /// Protocol negotiations in progress
+    negotiating: usize,
+    /// Protocols to open by local once the negotiations are under the limit
+    pending_opens: VecDeque<String>,
+    /// Protocol name opened by local -> the signal to abort its negotiation
+    opening: HashMap<String, oneshot::Sender<()>>,
+    /// Protocols the remote can open, None means no limit
+    allowed_protocols: Option<IntSet<ProtocolId>>,
+    /// Protocols whose newest version is not offered to the peer
+    downgraded_protocols: IntSet<ProtocolId>,
+    /// Shared with substreams to record message latency
+    message_latency: Option<Arc<MessageLatency>>,
+    /// Shared with substreams to limit the memory of their buffers
+    memory_budget: Option<Arc<MemoryBudget>>,
+    /// Shared with substreams to limit the messages received per second
+    recv_rate_limit: Option<Arc<RecvRateLimit>>,
+    /// Traffic total on the last idle check, and the number of the checks it hasn't changed
+    idle_checks: (u64, u32),
 
     /// Clone to new sub stream
     proto_event_sender: mpsc::Sender<ProtocolEvent>,
@@ -189,8 +356,12 @@ impl Session {
         meta: SessionMeta,
         future_task_sender: mpsc::Sender<BoxedFutureTask>,
     ) -> Self {
-        let socket = YamuxSession::new(socket, meta.config.yamux_config, meta.context.ty.into());
-        let control = socket.control();
+        let socket: Box<dyn AsyncStream> = Box::new(socket);
+        let (incoming, control) = match meta.muxer {
+            Some(ref muxer) => muxer.multiplex(socket, meta.context.ty),
+            None => YamuxMuxer::new(meta.config.yamux_config).multiplex(socket, meta.context.ty),
+        };
+        meta.context.set_muxer(control.clone());
         let (proto_event_sender, proto_event_receiver) = mpsc::channel(RECEIVED_SIZE);
         let mut interval = proto_event_sender.clone();
 
@@ -211,10 +382,10 @@ impl Session {
         });
         // background inner socket
         crate::runtime::spawn(
-            InnerSocket::new(socket, meta.event_sender).for_each(|_| future::ready(())),
+            InnerSocket::new(incoming, meta.event_sender).for_each(|_| future::ready(())),
         );
 
-        Session {
+        let session = Session {
             control,
             protocol_configs_by_name: meta.protocol_configs_by_name,
             protocol_configs_by_id: meta.protocol_configs_by_id,
@@ -226,6 +397,20 @@ impl Session {
             next_stream: 0,
             substreams: HashMap::default(),
             proto_streams: HashMap::default(),
+            fallback_protocols: HashMap::default(),
+            requested_versions: HashMap::default(),
+            negotiating: 0,
+            pending_opens: VecDeque::new(),
+            opening: HashMap::default(),
+            allowed_protocols: meta.allowed_protocols,
+            downgraded_protocols: meta.downgraded_protocols,
+            message_latency: meta.message_latency,
+            memory_budget: meta.memory_budget,
+            recv_rate_limit: meta
+                .config
+                .max_recv_rate
+                .map(|rate| Arc::new(RecvRateLimit::new(rate))),
+            idle_checks: (0, 0),
             proto_event_sender,
             proto_event_receiver,
             service_sender: Buffer::new(service_sender),
@@ -235,7 +420,32 @@ impl Session {
             state: SessionState::Normal,
             future_task_sender,
             wait_handle: meta.session_proto_handles,
-        }
+        };
+        session.idle_check_later();
+        session
+    }
+
+    /// Check the traffic every quarter of the idle timeout
+    fn idle_check_later(&self) {
+        let delay = match self.config.idle_timeout {
+            Some(timeout) => timeout / IDLE_CHECKS,
+            None => return,
+        };
+        let mut event_sender = self.proto_event_sender.clone();
+        // NOTE: A Interval/Delay will block tokio runtime from gracefully shutdown.
+        //       So we spawn it in FutureTaskManager
+        let task = Box::pin(async move {
+            crate::runtime::delay_for(delay).await;
+            if event_sender.send(ProtocolEvent::IdleCheck).await.is_err() {
+                trace!("idle check send err")
+            }
+        }) as BoxedFutureTask;
+        let mut future_task_sender = self.future_task_sender.clone();
+        crate::runtime::spawn(async move {
+            if future_task_sender.send(task).await.is_err() {
+                trace!("idle check task send err")
+            }
+        });
     }
 
     /// select procedure
@@ -245,7 +455,7 @@ impl Session {
         procedure: impl Future<
                 Output = Result<
                     (
-                        Framed<StreamHandle, LengthDelimitedCodec>,
+                        Framed<Box<dyn AsyncStream>, LengthDelimitedCodec>,
                         String,
                         Option<String>,
                     ),
@@ -253,9 +463,12 @@ impl Session {
                 >,
             > + Send
             + 'static,
+        not_allowed: HashSet<String>,
+        cancel: Option<oneshot::Receiver<()>>,
     ) {
         let mut event_sender = self.proto_event_sender.clone();
-        let timeout = self.timeout;
+        let timeout = self.config.protocol_select_timeout.unwrap_or(self.timeout);
+        self.negotiating += 1;
 
         // NOTE: A Interval/Delay will block tokio runtime from gracefully shutdown.
         //       So we spawn it in FutureTaskManager
@@ -268,6 +481,10 @@ impl Session {
                             proto_name: name,
                             version,
                         },
+                        None if not_allowed.contains(&name) => {
+                            debug!("Remote is not allowed to open the protocol {}", name);
+                            ProtocolEvent::NotAllowed { proto_name: name }
+                        }
                         None => {
                             debug!("Negotiation to open the protocol {} failed", name);
                             ProtocolEvent::SelectError {
@@ -289,6 +506,13 @@ impl Session {
                 debug!("select result send back error: {:?}", err);
             }
         }) as BoxedFutureTask;
+        // The cancelled negotiation is aborted without sending back the result
+        let task = match cancel {
+            Some(cancel) => Box::pin(async move {
+                future::select(task, cancel).await;
+            }) as BoxedFutureTask,
+            None => task,
+        };
 
         let mut future_task_sender = self.future_task_sender.clone();
         crate::runtime::spawn(async move {
@@ -298,15 +522,47 @@ impl Session {
         });
     }
 
+    /// Whether the protocol negotiations in progress reach the limit
+    fn negotiation_full(&self) -> bool {
+        self.config
+            .max_negotiating_protocols
+            .map(|max| self.negotiating >= max)
+            .unwrap_or(false)
+    }
+
+    /// A negotiation is finished, open the waiting protocols
+    fn negotiation_finished(&mut self) {
+        self.negotiating = self.negotiating.saturating_sub(1);
+        while !self.negotiation_full() {
+            match self.pending_opens.pop_front() {
+                Some(name) => self.open_proto_stream(&name),
+                None => break,
+            }
+        }
+    }
+
     /// After the session is established, the client is requested to open some custom protocol sub stream.
     pub fn open_proto_stream(&mut self, proto_name: &str) {
+        if self.negotiation_full() {
+            debug!(
+                "too many protocols in negotiation, wait to open {}",
+                proto_name
+            );
+            self.pending_opens.push_back(proto_name.to_owned());
+            return;
+        }
         debug!("try open proto, {}", proto_name);
-        let versions = self.protocol_configs_by_name[proto_name]
-            .support_versions
-            .clone();
+        let meta = &self.protocol_configs_by_name[proto_name];
+        let versions = match self.requested_versions.get(proto_name) {
+            // offer the requested version only, all versions are offered again if the remote
+            // doesn't support it
+            Some(requested) => vec![requested.clone()],
+            None => self.offered_versions(meta),
+        };
         let proto_info = ProtocolInfo::new(&proto_name, versions);
-        let mut control = self.control.clone();
+        let control = self.control.clone();
         let id = self.context.id;
+        let mode = self.config.negotiation_mode;
 
         let task = async move {
             let handle = match control.open_stream().await {
@@ -316,9 +572,90 @@ impl Session {
                     return Err(io::ErrorKind::BrokenPipe.into());
                 }
             };
-            client_select(handle, proto_info).await
+            match mode {
+                NegotiationMode::Tentacle => client_select(handle, proto_info).await,
+                NegotiationMode::Multistream => {
+                    multistream::client_select(handle, proto_info).await
+                }
+            }
         };
-        self.select_procedure(task);
+        let (signal, cancel) = oneshot::channel();
+        self.opening.insert(proto_name.to_owned(), signal);
+        self.select_procedure(task, HashSet::new(), Some(cancel));
+    }
+
+    /// Versions of the protocol offered in negotiation, the newest one is not offered
+    /// if the protocol is downgraded with the peer
+    fn offered_versions(&self, meta: &Meta) -> Vec<String> {
+        let mut versions = meta.support_versions.clone();
+        if versions.len() > 1 && self.downgraded_protocols.contains(&meta.id) {
+            versions.sort();
+            versions.pop();
+        }
+        versions
+    }
+
+    /// Abort the negotiation of the protocol opened by local, or stop waiting to open it
+    fn cancel_open(&mut self, cx: &mut Context, proto_id: ProtocolId) {
+        let name = match self.protocol_configs_by_id.get(&proto_id) {
+            Some(meta) => (meta.name)(proto_id),
+            None => return,
+        };
+        let queued = self.pending_opens.len();
+        self.pending_opens.retain(|pending| pending != &name);
+        let queued = queued != self.pending_opens.len();
+
+        match self.opening.remove(&name) {
+            // The receiver is dropped once the negotiation is finished
+            Some(signal) if !signal.is_canceled() => {
+                drop(signal);
+                self.negotiation_finished();
+            }
+            _ if !queued => {
+                debug!("proto [{}] isn't in negotiation", proto_id);
+                return;
+            }
+            _ => (),
+        }
+        self.fallback_protocols.remove(&name);
+        self.requested_versions.remove(&name);
+        self.event_output(
+            cx,
+            SessionEvent::ProtocolSelectError {
+                id: self.context.id,
+                proto_name: Some(name),
+                cause: SelectErrorCause::Cancelled,
+            },
+        )
+    }
+
+    /// Try open the protocol at the version, if the remote doesn't support it, the highest common
+    /// version is opened
+    pub fn open_proto_stream_with_version(&mut self, proto_name: &str, version: String) {
+        if self.protocol_configs_by_name[proto_name]
+            .support_versions
+            .contains(&version)
+        {
+            self.requested_versions
+                .insert(proto_name.to_owned(), version);
+        } else {
+            debug!(
+                "proto [{}] doesn't support version {}, open the highest common one",
+                proto_name, version
+            );
+        }
+        self.open_proto_stream(proto_name);
+    }
+
+    /// Try open the protocols in order, open the next one only if the former fails to negotiate
+    pub fn open_proto_stream_with_fallback(&mut self, proto_names: Vec<String>) {
+        let mut proto_names = VecDeque::from(proto_names);
+        if let Some(name) = proto_names.pop_front() {
+            if !proto_names.is_empty() {
+                self.fallback_protocols.insert(name.clone(), proto_names);
+            }
+            self.open_proto_stream(&name);
+        }
     }
 
     /// Push the generated event to the Service
@@ -363,20 +700,40 @@ impl Session {
     }
 
     /// Handling client-initiated open protocol sub stream requests
-    fn handle_substream(&mut self, substream: StreamHandle) {
-        let proto_metas = self
-            .protocol_configs_by_name
-            .values()
-            .map(|proto_meta| {
-                let name = (proto_meta.name)(proto_meta.id);
-                let proto_info = ProtocolInfo::new(&name, proto_meta.support_versions.clone());
-                let select_fn = (proto_meta.select_version)();
-                (name, (proto_info, select_fn))
-            })
-            .collect();
-
-        let task = server_select(substream, proto_metas);
-        self.select_procedure(task);
+    fn handle_substream(&mut self, substream: Box<dyn AsyncStream>) {
+        if self.negotiation_full() {
+            debug!(
+                "session [{}] too many protocols in negotiation, drop the sub stream",
+                self.context.id
+            );
+            return;
+        }
+        let mut proto_metas = HashMap::with_capacity(self.protocol_configs_by_name.len());
+        let mut not_allowed = HashSet::new();
+        for proto_meta in self.protocol_configs_by_name.values() {
+            let name = (proto_meta.name)(proto_meta.id);
+            // don't offer the protocols out of allowlist, remote fails to negotiate them
+            if let Some(ref allowed) = self.allowed_protocols {
+                if !allowed.contains(&proto_meta.id) {
+                    not_allowed.insert(name);
+                    continue;
+                }
+            }
+            let proto_info = ProtocolInfo::new(&name, self.offered_versions(proto_meta));
+            let select_fn = (proto_meta.select_version)();
+            proto_metas.insert(name, (proto_info, select_fn));
+        }
+
+        let mode = self.config.negotiation_mode;
+        let task = async move {
+            match mode {
+                NegotiationMode::Tentacle => server_select(substream, proto_metas).await,
+                NegotiationMode::Multistream => {
+                    multistream::server_select(substream, proto_metas).await
+                }
+            }
+        };
+        self.select_procedure(task, not_allowed, None);
     }
 
     fn open_protocol(
@@ -384,7 +741,7 @@ impl Session {
         cx: &mut Context,
         name: String,
         version: String,
-        substream: Box<Framed<StreamHandle, LengthDelimitedCodec>>,
+        substream: Box<Framed<Box<dyn AsyncStream>, LengthDelimitedCodec>>,
     ) {
         let proto = match self.protocol_configs_by_name.get(&name) {
             Some(proto) => proto,
@@ -397,6 +754,7 @@ impl Session {
                     SessionEvent::ProtocolSelectError {
                         id: self.context.id,
                         proto_name: None,
+                        cause: SelectErrorCause::Failed,
                     },
                 );
                 return;
@@ -409,7 +767,30 @@ impl Session {
             return;
         }
 
-        let before_receive_fn = (proto.before_receive)();
+        if let Some(ref raw) = proto.raw {
+            let raw_part = substream.into_parts();
+            let stream = RawSubstream::new(raw_part.read_buf, raw_part.io);
+            debug!(
+                "session [{}] raw proto [{}] open",
+                self.context.id, proto_id
+            );
+            raw.connected(
+                self.context.clone(),
+                &self.service_control,
+                &version,
+                Box::new(stream),
+            );
+            return;
+        }
+
+        let before_receive_fn: Option<BeforeReceive> = if proto.decompress {
+            let max_len = self.config.max_frame_length;
+            Some(Box::new(move |data| {
+                CompressionAlgo::decompress(data, max_len)
+            }))
+        } else {
+            (proto.before_receive)()
+        };
         let (session_to_proto_sender, session_to_proto_receiver) =
             priority_mpsc::channel(SEND_SIZE);
 
@@ -418,6 +799,8 @@ impl Session {
             PriorityBuffer::new(session_to_proto_sender.clone()),
         );
         self.proto_streams.insert(proto_id, self.next_stream);
+        self.context
+            .set_protocol_open(proto_id, Some(version.clone()));
         let raw_part = substream.into_parts();
 
         match proto.spawn {
@@ -430,10 +813,12 @@ impl Session {
                     SubstreamReadPart {
                         substream: frame,
                         before_receive: before_receive_fn,
+                        max_message_size: proto.max_message_size,
                         proto_id,
                         stream_id: self.next_stream,
                         version,
                         close_sender: session_to_proto_sender,
+                        context: self.context.clone(),
                     }
                 };
 
@@ -445,6 +830,10 @@ impl Session {
                 .proto_id(proto_id)
                 .stream_id(self.next_stream)
                 .config(self.config)
+                .close_policy(proto.close_policy)
+                .coalesce(proto.coalesce.is_some())
+                .message_latency(self.message_latency.clone())
+                .memory_budget(self.memory_budget.clone())
                 .build(FramedWrite::new(write, (proto.codec)()));
 
                 crate::runtime::spawn(write_part.for_each(|_| future::ready(())));
@@ -468,7 +857,13 @@ impl Session {
                 .service_proto_sender(self.service_proto_senders.get(&proto_id).cloned())
                 .session_proto_sender(self.session_proto_senders.get(&proto_id).cloned())
                 .keep_buffer(self.keep_buffer)
+                .close_policy(proto.close_policy)
+                .coalesce(proto.coalesce.is_some())
                 .before_receive(before_receive_fn)
+                .max_message_size(proto.max_message_size)
+                .message_latency(self.message_latency.clone())
+                .memory_budget(self.memory_budget.clone())
+                .recv_rate_limit(self.recv_rate_limit.clone())
                 .build(frame);
 
                 proto_stream.proto_open(version);
@@ -489,22 +884,69 @@ impl Session {
                 substream,
                 version,
             } => {
+                self.negotiation_finished();
+                self.fallback_protocols.remove(&proto_name);
+                self.requested_versions.remove(&proto_name);
                 self.open_protocol(cx, proto_name, version, substream);
             }
             ProtocolEvent::Close { id, proto_id } => {
                 debug!("session [{}] proto [{}] closed", self.context.id, proto_id);
                 if self.substreams.remove(&id).is_some() {
                     self.proto_streams.remove(&proto_id);
+                    self.context.set_protocol_open(proto_id, None);
+                }
+            }
+            ProtocolEvent::Message { .. } | ProtocolEvent::Reset { .. } => unreachable!(),
+            ProtocolEvent::SelectError { proto_name } => {
+                self.negotiation_finished();
+                if let Some(name) = proto_name
+                    .as_ref()
+                    .filter(|name| self.requested_versions.remove(*name).is_some())
+                {
+                    debug!(
+                        "session [{}] proto [{}] remote doesn't support the requested version, \
+                         try all versions",
+                        self.context.id, name
+                    );
+                    self.open_proto_stream(name);
+                    return;
+                }
+                if let Some(fallback) = proto_name
+                    .as_ref()
+                    .and_then(|name| self.fallback_protocols.remove(name))
+                {
+                    debug!(
+                        "session [{}] proto [{:?}] negotiation failed, try fallback",
+                        self.context.id, proto_name
+                    );
+                    self.open_proto_stream_with_fallback(fallback.into());
+                    return;
+                }
+                self.event_output(
+                    cx,
+                    SessionEvent::ProtocolSelectError {
+                        id: self.context.id,
+                        proto_name,
+                        cause: SelectErrorCause::Failed,
+                    },
+                )
+            }
+            ProtocolEvent::NotAllowed { proto_name } => {
+                self.negotiation_finished();
+                if let Some(proto_id) = self
+                    .protocol_configs_by_name
+                    .get(&proto_name)
+                    .map(|meta| meta.id)
+                {
+                    self.event_output(
+                        cx,
+                        SessionEvent::ProtocolNotAllowed {
+                            id: self.context.id,
+                            proto_id,
+                        },
+                    )
                 }
             }
-            ProtocolEvent::Message { .. } => unreachable!(),
-            ProtocolEvent::SelectError { proto_name } => self.event_output(
-                cx,
-                SessionEvent::ProtocolSelectError {
-                    id: self.context.id,
-                    proto_name,
-                },
-            ),
             ProtocolEvent::Error {
                 proto_id, error, ..
             } => {
@@ -529,6 +971,34 @@ impl Session {
                     self.state = SessionState::LocalClose;
                 }
             }
+            ProtocolEvent::IdleCheck => {
+                if !self.state.is_normal() {
+                    return;
+                }
+                let total = self.context.traffic.total();
+                self.idle_checks = if total == self.idle_checks.0 {
+                    (total, self.idle_checks.1 + 1)
+                } else {
+                    (total, 0)
+                };
+                if self.idle_checks.1 < IDLE_CHECKS {
+                    self.idle_check_later();
+                    return;
+                }
+                debug!("session [{}] is idle, close it", self.context.id);
+                self.event_output(
+                    cx,
+                    SessionEvent::SessionTimeout {
+                        id: self.context.id,
+                    },
+                );
+                if self.substreams.is_empty() {
+                    self.close_session();
+                } else {
+                    self.state = SessionState::LocalClose;
+                    self.close_all_proto(cx);
+                }
+            }
         }
     }
 
@@ -536,10 +1006,15 @@ impl Session {
     fn handle_session_event(&mut self, cx: &mut Context, event: SessionEvent, priority: Priority) {
         match event {
             SessionEvent::ProtocolMessage { proto_id, data, .. } => {
-                if let Some(stream_id) = self.proto_streams.get(&proto_id) {
-                    if let Some(buffer) = self.substreams.get_mut(stream_id) {
+                let substreams = &mut self.substreams;
+                match self.proto_streams.get(&proto_id).and_then(|stream_id| {
+                    substreams
+                        .get_mut(stream_id)
+                        .map(|buffer| (*stream_id, buffer))
+                }) {
+                    Some((stream_id, buffer)) => {
                         let event = ProtocolEvent::Message {
-                            id: *stream_id,
+                            id: stream_id,
                             proto_id,
                             data,
                         };
@@ -550,8 +1025,14 @@ impl Session {
                         }
                         buffer.try_send(cx);
                     }
-                } else {
-                    trace!("protocol {} not ready", proto_id);
+                    None => {
+                        trace!("protocol {} not ready", proto_id);
+                        self.service_control.message_dropped(
+                            self.context.id,
+                            proto_id,
+                            DropReason::ProtocolNotOpen,
+                        );
+                    }
                 }
             }
             SessionEvent::SessionClose { .. } => {
@@ -563,7 +1044,7 @@ impl Session {
                     self.close_all_proto(cx);
                 }
             }
-            SessionEvent::ProtocolOpen { proto_id, .. } => {
+            SessionEvent::ProtocolOpen { proto_id, version } => {
                 if self.proto_streams.contains_key(&proto_id) {
                     debug!("proto [{}] has been open", proto_id);
                 } else if let Some(name) = self
@@ -571,15 +1052,66 @@ impl Session {
                     .get(&proto_id)
                     .map(|meta| (meta.name)(meta.id))
                 {
-                    self.open_proto_stream(&name)
+                    match version {
+                        Some(version) => self.open_proto_stream_with_version(&name, version),
+                        None => self.open_proto_stream(&name),
+                    }
                 } else {
                     debug!("This protocol [{}] is not supported", proto_id)
                 }
             }
+            SessionEvent::ProtocolOpenFallback { proto_ids } => {
+                if let Some(proto_id) = proto_ids
+                    .iter()
+                    .find(|proto_id| self.proto_streams.contains_key(proto_id))
+                {
+                    debug!("proto [{}] has been open", proto_id);
+                } else {
+                    let names = proto_ids
+                        .iter()
+                        .filter_map(|proto_id| self.protocol_configs_by_id.get(proto_id))
+                        .map(|meta| (meta.name)(meta.id))
+                        .collect();
+                    self.open_proto_stream_with_fallback(names)
+                }
+            }
             SessionEvent::ProtocolClose { proto_id, .. } => {
+                let policy = self
+                    .protocol_configs_by_id
+                    .get(&proto_id)
+                    .map(|meta| meta.close_policy)
+                    .unwrap_or_default();
                 if let Some(stream_id) = self.proto_streams.get(&proto_id) {
                     if let Some(buffer) = self.substreams.get_mut(stream_id) {
-                        buffer.push_high(ProtocolEvent::Close {
+                        let event = ProtocolEvent::Close {
+                            id: *stream_id,
+                            proto_id,
+                        };
+                        match policy {
+                            ProtocolClosePolicy::DropQueued => {
+                                let context = &self.context;
+                                buffer.retain(|event| match event {
+                                    ProtocolEvent::Message { data, .. } => {
+                                        context.decr_pending_data_size(data.len());
+                                        false
+                                    }
+                                    _ => true,
+                                });
+                                buffer.push_high(event)
+                            }
+                            // Queued behind the messages sent before it
+                            ProtocolClosePolicy::Flush => buffer.push_normal(event),
+                        }
+                        buffer.try_send(cx);
+                    }
+                } else {
+                    debug!("proto [{}] has been closed", proto_id);
+                }
+            }
+            SessionEvent::ProtocolReset { proto_id } => {
+                if let Some(stream_id) = self.proto_streams.get(&proto_id) {
+                    if let Some(buffer) = self.substreams.get_mut(stream_id) {
+                        buffer.push_high(ProtocolEvent::Reset {
                             id: *stream_id,
                             proto_id,
                         });
@@ -589,7 +1121,16 @@ impl Session {
                     debug!("proto [{}] has been closed", proto_id);
                 }
             }
+            SessionEvent::ProtocolCancelOpen { proto_id } => self.cancel_open(cx, proto_id),
             SessionEvent::StreamStart { stream } => self.handle_substream(stream),
+            SessionEvent::ProtocolRegistered { meta, handle } => {
+                self.protocol_configs_by_name
+                    .insert((meta.name)(meta.id), meta.clone());
+                self.protocol_configs_by_id.insert(meta.id, meta);
+                if let Some(handle) = handle {
+                    self.wait_handle.push(handle);
+                }
+            }
             SessionEvent::ChangeState { state, error } => {
                 if self.state == SessionState::Normal {
                     self.state = state;
@@ -714,13 +1255,12 @@ impl Session {
     /// Clean env
     fn clean(&mut self) {
         self.substreams.clear();
+        self.context.clear_opened_protocols();
         self.service_receiver.close();
         self.proto_event_receiver.close();
 
-        let mut control = self.control.clone();
-        crate::runtime::spawn(async move {
-            control.close().await;
-        });
+        let close = self.control.close();
+        crate::runtime::spawn(close);
     }
 
     #[inline]
@@ -809,6 +1349,11 @@ pub(crate) struct SessionMeta {
     session_proto_senders: IntMap<ProtocolId, Buffer<SessionProtocolEvent>>,
     event_sender: priority_mpsc::Sender<SessionEvent>,
     service_control: ServiceControl,
+    muxer: Option<Arc<dyn StreamMuxer>>,
+    allowed_protocols: Option<IntSet<ProtocolId>>,
+    downgraded_protocols: IntSet<ProtocolId>,
+    message_latency: Option<Arc<MessageLatency>>,
+    memory_budget: Option<Arc<MemoryBudget>>,
     session_proto_handles: Vec<(
         Option<futures::channel::oneshot::Sender<()>>,
         crate::runtime::JoinHandle<()>,
@@ -833,6 +1378,11 @@ impl SessionMeta {
             session_proto_senders: HashMap::default(),
             session_proto_handles: Vec::new(),
             service_control: control,
+            muxer: None,
+            allowed_protocols: None,
+            downgraded_protocols: IntSet::default(),
+            message_latency: None,
+            memory_budget: None,
             event_sender,
         }
     }
@@ -852,6 +1402,31 @@ impl SessionMeta {
         self
     }
 
+    pub fn muxer(mut self, muxer: Option<Arc<dyn StreamMuxer>>) -> Self {
+        self.muxer = muxer;
+        self
+    }
+
+    pub fn allowed_protocols(mut self, allowed: Option<IntSet<ProtocolId>>) -> Self {
+        self.allowed_protocols = allowed;
+        self
+    }
+
+    pub fn downgraded_protocols(mut self, downgraded: IntSet<ProtocolId>) -> Self {
+        self.downgraded_protocols = downgraded;
+        self
+    }
+
+    pub fn message_latency(mut self, latency: Option<Arc<MessageLatency>>) -> Self {
+        self.message_latency = latency;
+        self
+    }
+
+    pub fn memory_budget(mut self, budget: Option<Arc<MemoryBudget>>) -> Self {
+        self.memory_budget = budget;
+        self
+    }
+
     pub fn keep_buffer(mut self, keep: bool) -> Self {
         self.keep_buffer = keep;
         self
@@ -910,28 +1485,22 @@ impl SessionState {
     }
 }
 
-struct InnerSocket<T> {
-    socket: YamuxSession<T>,
+struct InnerSocket {
+    socket: MuxerIncoming,
     sender: priority_mpsc::Sender<SessionEvent>,
 }
 
-impl<T> InnerSocket<T>
-where
-    T: AsyncRead + AsyncWrite + Unpin + Send,
-{
-    fn new(socket: YamuxSession<T>, sender: priority_mpsc::Sender<SessionEvent>) -> Self {
+impl InnerSocket {
+    fn new(socket: MuxerIncoming, sender: priority_mpsc::Sender<SessionEvent>) -> Self {
         InnerSocket { socket, sender }
     }
 }
 
-impl<T> Stream for InnerSocket<T>
-where
-    T: AsyncRead + AsyncWrite + Unpin,
-{
+impl Stream for InnerSocket {
     type Item = ();
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
-        match Pin::new(&mut self.socket).as_mut().poll_next(cx) {
+        match self.socket.as_mut().poll_next(cx) {
             Poll::Ready(Some(Ok(stream))) => {
                 let mut sender = self.sender.clone();
 
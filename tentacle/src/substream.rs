@@ -1,13 +1,15 @@
 use futures::{channel::mpsc, prelude::*, stream::iter, SinkExt, StreamExt};
 use log::debug;
 use std::{
+    cmp,
     collections::VecDeque,
     io::{self, ErrorKind},
     pin::Pin,
     sync::{atomic::Ordering, Arc},
     task::{Context, Poll},
+    time::Instant,
 };
-use tokio::io::AsyncWrite;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio_util::codec::{length_delimited::LengthDelimitedCodec, Framed, FramedRead, FramedWrite};
 
 use crate::{
@@ -15,13 +17,85 @@ use crate::{
     builder::BeforeReceive,
     channel::{mpsc as priority_mpsc, mpsc::Priority},
     context::SessionContext,
+    flow_control::{ByteRateLimit, MemoryBudget, RecvRateLimit},
+    metrics::MessageLatency,
     protocol_handle_stream::{ServiceProtocolEvent, SessionProtocolEvent},
-    service::config::SessionConfig,
-    traits::Codec,
-    yamux::StreamHandle,
+    service::{config::SessionConfig, ProtocolClosePolicy},
+    traits::{AsyncStream, Codec},
     ProtocolId, StreamId,
 };
 
+/// Frame in the write buffer, with its enqueue time if the message latency is recorded
+type Frame = (bytes::Bytes, Option<Instant>);
+
+/// Whether the bandwidth limit is reached, the delay wakes up the task once it allows again
+fn bandwidth_limited(
+    limit: &ByteRateLimit,
+    delay: &mut Option<Pin<Box<crate::runtime::Delay>>>,
+    cx: &mut Context,
+) -> bool {
+    match limit.check() {
+        Ok(()) => {
+            *delay = None;
+            false
+        }
+        Err(wait) => {
+            let timer = delay.get_or_insert_with(|| Box::pin(crate::runtime::delay_for(wait)));
+            if timer.as_mut().poll(cx).is_ready() {
+                *delay = None;
+                cx.waker().wake_by_ref();
+            }
+            true
+        }
+    }
+}
+
+/// Raw sub stream handed over to the user, the data buffered during
+/// protocol negotiation will be read first
+pub(crate) struct RawSubstream {
+    buffered: bytes::BytesMut,
+    inner: Box<dyn AsyncStream>,
+}
+
+impl RawSubstream {
+    pub(crate) fn new(buffered: bytes::BytesMut, inner: Box<dyn AsyncStream>) -> Self {
+        RawSubstream { buffered, inner }
+    }
+}
+
+impl AsyncRead for RawSubstream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        if self.buffered.is_empty() {
+            return Pin::new(&mut self.inner).poll_read(cx, buf);
+        }
+        let len = std::cmp::min(buf.remaining(), self.buffered.len());
+        buf.put_slice(&self.buffered.split_to(len));
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for RawSubstream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
 /// Event generated/received by the protocol stream
 #[derive(Debug)]
 pub(crate) enum ProtocolEvent {
@@ -29,8 +103,8 @@ pub(crate) enum ProtocolEvent {
     Open {
         /// Protocol name
         proto_name: String,
-        /// Yamux sub stream handle handshake framed
-        substream: Box<Framed<StreamHandle, LengthDelimitedCodec>>,
+        /// Sub stream handle handshake framed
+        substream: Box<Framed<Box<dyn AsyncStream>, LengthDelimitedCodec>>,
         /// Protocol version
         version: String,
     },
@@ -41,6 +115,14 @@ pub(crate) enum ProtocolEvent {
         /// Protocol id
         proto_id: ProtocolId,
     },
+    /// Abort the protocol, the sub stream is dropped without shutdown,
+    /// which makes yamux send a RST instead of a FIN
+    Reset {
+        /// Stream id
+        id: StreamId,
+        /// Protocol id
+        proto_id: ProtocolId,
+    },
     /// Protocol data outbound and inbound
     Message {
         /// Stream id
@@ -53,6 +135,10 @@ pub(crate) enum ProtocolEvent {
     SelectError {
         proto_name: Option<String>,
     },
+    /// The remote opened a protocol it is not allowed to open
+    NotAllowed {
+        proto_name: String,
+    },
     /// Codec error
     Error {
         /// Stream id
@@ -63,12 +149,14 @@ pub(crate) enum ProtocolEvent {
         error: std::io::Error,
     },
     TimeoutCheck,
+    /// Check whether the session has traffic in the idle timeout
+    IdleCheck,
 }
 
 /// Each custom protocol in a session corresponds to a sub stream
 /// Can be seen as the route of each protocol
 pub(crate) struct Substream<U> {
-    substream: Framed<StreamHandle, U>,
+    substream: Framed<Box<dyn AsyncStream>, U>,
     id: StreamId,
     proto_id: ProtocolId,
 
@@ -76,11 +164,33 @@ pub(crate) struct Substream<U> {
 
     config: SessionConfig,
     /// The buffer will be prioritized for send to underlying network
-    high_write_buf: VecDeque<bytes::Bytes>,
+    high_write_buf: VecDeque<Frame>,
     // The buffer which will send to underlying network
-    write_buf: VecDeque<bytes::Bytes>,
+    write_buf: VecDeque<Frame>,
+    /// Bytes of the messages in the write buffers
+    write_bytes: usize,
+    message_latency: Option<Arc<MessageLatency>>,
+    memory_budget: Option<Arc<MemoryBudget>>,
+    /// Bytes held on the memory budget
+    held_bytes: usize,
+    recv_rate_limit: Option<Arc<RecvRateLimit>>,
+    /// Wake up reading when the rate limit allows it again
+    rate_delay: Option<Pin<Box<crate::runtime::Delay>>>,
+    /// Wake up writing when the session upload limit allows it again
+    upload_delay: Option<Pin<Box<crate::runtime::Delay>>>,
+    /// Wake up reading when the session download limit allows it again
+    download_delay: Option<Pin<Box<crate::runtime::Delay>>>,
     dead: bool,
+    /// Reset by local, skip the graceful shutdown
+    reset: bool,
+    /// Reset by remote, report it as an error on close
+    remote_reset: bool,
     keep_buffer: bool,
+    close_policy: ProtocolClosePolicy,
+    /// Closed by local with the flush policy, finish the close once the write buffers are sent
+    closing: bool,
+    /// Write the messages ready at the same time with one flush
+    coalesce: bool,
 
     /// Send event to session
     event_sender: Buffer<ProtocolEvent>,
@@ -90,6 +200,7 @@ pub(crate) struct Substream<U> {
     service_proto_sender: Option<Buffer<ServiceProtocolEvent>>,
     session_proto_sender: Option<Buffer<SessionProtocolEvent>>,
     before_receive: Option<BeforeReceive>,
+    max_message_size: Option<usize>,
 }
 
 impl<U> Substream<U>
@@ -109,7 +220,8 @@ where
         }
     }
 
-    fn push_front(&mut self, priority: Priority, frame: bytes::Bytes) {
+    fn push_front(&mut self, priority: Priority, frame: Frame) {
+        self.write_bytes += frame.0.len();
         if priority.is_high() {
             self.high_write_buf.push_front(frame);
         } else {
@@ -117,7 +229,9 @@ where
         }
     }
 
-    fn push_back(&mut self, priority: Priority, frame: bytes::Bytes) {
+    fn push_back(&mut self, priority: Priority, data: bytes::Bytes) {
+        self.write_bytes += data.len();
+        let frame = (data, self.message_latency.as_ref().map(|_| Instant::now()));
         if priority.is_high() {
             self.high_write_buf.push_back(frame);
         } else {
@@ -131,16 +245,28 @@ where
     fn send_inner(
         &mut self,
         cx: &mut Context,
-        frame: bytes::Bytes,
+        frame: Frame,
         priority: Priority,
     ) -> Result<bool, io::Error> {
-        let data_size = frame.len();
+        // Kept in the write buffer, in the order of its priority
+        if bandwidth_limited(&self.context.upload_limit, &mut self.upload_delay, cx) {
+            self.push_front(priority, frame);
+            self.poll_complete(cx)?;
+            return Ok(true);
+        }
+        let data_size = frame.0.len();
         let mut sink = Pin::new(&mut self.substream);
 
         match sink.as_mut().poll_ready(cx)? {
             Poll::Ready(()) => {
-                sink.as_mut().start_send(frame)?;
+                let (data, enqueue) = frame;
+                sink.as_mut().start_send(data)?;
                 self.context.decr_pending_data_size(data_size);
+                self.context.upload_limit.record(data_size);
+                self.context.traffic.record_sent(self.proto_id, data_size);
+                if let (Some(latency), Some(enqueue)) = (self.message_latency.as_ref(), enqueue) {
+                    latency.record(self.proto_id, enqueue.elapsed());
+                }
                 Ok(false)
             }
             Poll::Pending => {
@@ -154,12 +280,14 @@ where
     /// Send data to the lower `yamux` sub stream
     fn send_data(&mut self, cx: &mut Context) -> Result<(), io::Error> {
         while let Some(frame) = self.high_write_buf.pop_front() {
+            self.write_bytes -= frame.0.len();
             if self.send_inner(cx, frame, Priority::High)? {
                 return Ok(());
             }
         }
 
         while let Some(frame) = self.write_buf.pop_front() {
+            self.write_bytes -= frame.0.len();
             if self.send_inner(cx, frame, Priority::Normal)? {
                 return Ok(());
             }
@@ -182,11 +310,35 @@ where
         }
     }
 
+    /// Drop the messages in the write buffers
+    fn drop_write_buf(&mut self) {
+        self.write_bytes = 0;
+        for (data, _) in self
+            .high_write_buf
+            .drain(..)
+            .chain(self.write_buf.drain(..))
+        {
+            self.context.decr_pending_data_size(data.len());
+        }
+    }
+
+    /// Whether the close with the flush policy can be finished,
+    /// the write buffers are sent and flushed
+    fn flushed_close(&mut self, cx: &mut Context) -> Result<bool, io::Error> {
+        if !self.closing || !self.high_write_buf.is_empty() || !self.write_buf.is_empty() {
+            return Ok(false);
+        }
+        self.poll_complete(cx).map(|pending| !pending)
+    }
+
     /// Close protocol sub stream
     fn close_proto_stream(&mut self, cx: &mut Context) {
         self.event_receiver.close();
-        if let Poll::Ready(Err(e)) = Pin::new(self.substream.get_mut()).poll_shutdown(cx) {
-            log::trace!("sub stream poll shutdown err {}", e)
+        // On reset, the sub stream is dropped without shutdown when this task ends
+        if !self.reset {
+            if let Poll::Ready(Err(e)) = Pin::new(self.substream.get_mut()).poll_shutdown(cx) {
+                log::trace!("sub stream poll shutdown err {}", e)
+            }
         }
 
         if !self.keep_buffer {
@@ -222,6 +374,13 @@ where
 
         if !self.context.closed.load(Ordering::SeqCst) {
             let (mut sender, mut events) = self.event_sender.take();
+            if self.remote_reset {
+                events.push_back(ProtocolEvent::Error {
+                    id: self.id,
+                    proto_id: self.proto_id,
+                    error: ErrorKind::ConnectionReset.into(),
+                });
+            }
             events.push_back(ProtocolEvent::Close {
                 id: self.id,
                 proto_id: self.proto_id,
@@ -237,13 +396,29 @@ where
         }
     }
 
+    /// Handle the events already in the channel, the end of it is seen on the next `recv_event`
+    fn recv_ready_events(&mut self, cx: &mut Context) {
+        while !self.dead && self.write_buf.len() <= self.config.send_event_size() {
+            match Pin::new(&mut self.event_receiver).as_mut().poll_next(cx) {
+                Poll::Ready(Some((priority, event))) => {
+                    self.handle_proto_event(cx, event, priority)
+                }
+                _ => break,
+            }
+        }
+    }
+
     /// When send or receive message error, output error and close stream
     fn error_close(&mut self, cx: &mut Context, error: io::Error) {
         self.dead = true;
         match error.kind() {
+            // The remote aborted the protocol, reported on close
+            ErrorKind::ConnectionReset => {
+                self.remote_reset = true;
+                return;
+            }
             ErrorKind::BrokenPipe
             | ErrorKind::ConnectionAborted
-            | ErrorKind::ConnectionReset
             | ErrorKind::NotConnected
             | ErrorKind::UnexpectedEof => return,
             _ => (),
@@ -260,7 +435,15 @@ where
     fn handle_proto_event(&mut self, cx: &mut Context, event: ProtocolEvent, priority: Priority) {
         match event {
             ProtocolEvent::Message { data, .. } => {
+                if self.closing {
+                    self.context.decr_pending_data_size(data.len());
+                    return;
+                }
                 self.push_back(priority, data);
+                // Sent on the next flush, together with the messages received before it
+                if self.coalesce {
+                    return;
+                }
 
                 if let Err(err) = self.send_data(cx) {
                     // Whether it is a read send error or a flush error,
@@ -281,9 +464,19 @@ where
                     self.dead = true;
                 }
             }
-            ProtocolEvent::Close { .. } => {
+            ProtocolEvent::Close { .. } => match self.close_policy {
+                ProtocolClosePolicy::DropQueued => {
+                    self.drop_write_buf();
+                    self.dead = true;
+                }
+                ProtocolClosePolicy::Flush => self.closing = true,
+            },
+            ProtocolEvent::Reset { .. } => {
+                self.high_write_buf.clear();
                 self.write_buf.clear();
+                self.write_bytes = 0;
                 self.dead = true;
+                self.reset = true;
             }
             _ => (),
         }
@@ -310,6 +503,35 @@ where
         }
     }
 
+    /// Bytes of the messages in the write buffers and the buffers to the protocol handles
+    fn buffered_bytes(&self) -> usize {
+        let service = self
+            .service_proto_sender
+            .as_ref()
+            .map(Buffer::bytes)
+            .unwrap_or_default();
+        let session = self
+            .session_proto_sender
+            .as_ref()
+            .map(Buffer::bytes)
+            .unwrap_or_default();
+        // Received data is shared by both handles
+        self.write_bytes + cmp::max(service, session)
+    }
+
+    /// Update the bytes held on the memory budget
+    fn sync_memory(&mut self) {
+        if let Some(budget) = self.memory_budget.clone() {
+            let held = self.buffered_bytes();
+            if held > self.held_bytes {
+                budget.acquire(held - self.held_bytes)
+            } else if held < self.held_bytes {
+                budget.release(self.held_bytes - held)
+            }
+            self.held_bytes = held;
+        }
+    }
+
     /// Send event to user
     #[inline]
     fn output_event(&mut self, cx: &mut Context, event: ProtocolEvent) {
@@ -337,6 +559,9 @@ where
         match Pin::new(&mut self.event_receiver).as_mut().poll_next(cx) {
             Poll::Ready(Some((priority, event))) => {
                 self.handle_proto_event(cx, event, priority);
+                if self.coalesce {
+                    self.recv_ready_events(cx);
+                }
                 Poll::Ready(Some(()))
             }
             Poll::Ready(None) => {
@@ -372,8 +597,62 @@ where
             return Poll::Pending;
         }
 
+        if self.context.poll_read_paused(self.proto_id, cx) {
+            debug!("protocol [{}] reading paused", self.proto_id);
+            return Poll::Pending;
+        }
+
+        if let Some(ref budget) = self.memory_budget {
+            if budget.poll_available(cx).is_pending() {
+                debug!(
+                    "protocol [{}] pause reading on memory pressure",
+                    self.proto_id
+                );
+                return Poll::Pending;
+            }
+        }
+
+        if let Some(ref limit) = self.recv_rate_limit {
+            if let Err(wait) = limit.check() {
+                debug!(
+                    "protocol [{}] pause reading on session rate limit",
+                    self.proto_id
+                );
+                // wake up on the next second
+                let delay = self
+                    .rate_delay
+                    .get_or_insert_with(|| Box::pin(crate::runtime::delay_for(wait)));
+                if delay.as_mut().poll(cx).is_ready() {
+                    self.rate_delay = None;
+                    cx.waker().wake_by_ref();
+                }
+                return Poll::Pending;
+            }
+            self.rate_delay = None;
+        }
+
+        if bandwidth_limited(&self.context.download_limit, &mut self.download_delay, cx) {
+            debug!(
+                "protocol [{}] pause reading on session download limit",
+                self.proto_id
+            );
+            return Poll::Pending;
+        }
+
         match Pin::new(&mut self.substream).as_mut().poll_next(cx) {
             Poll::Ready(Some(Ok(data))) => {
+                if let Some(ref limit) = self.recv_rate_limit {
+                    limit.record();
+                }
+                self.context.download_limit.record(data.len());
+                self.context
+                    .traffic
+                    .record_received(self.proto_id, data.len());
+                if let Err(err) = check_message_size(self.max_message_size, data.len()) {
+                    debug!("protocol [{}] {}", self.proto_id, err);
+                    self.error_close(cx, err);
+                    return Poll::Ready(None);
+                }
                 let data = match self.before_receive {
                     Some(ref function) => match function(data) {
                         Ok(data) => data,
@@ -473,6 +752,19 @@ where
             return Poll::Ready(None);
         }
 
+        match self.flushed_close(cx) {
+            Ok(true) => {
+                self.dead = true;
+                self.close_proto_stream(cx);
+                return Poll::Ready(None);
+            }
+            Ok(false) => (),
+            Err(err) => {
+                self.error_close(cx, err);
+                return Poll::Ready(None);
+            }
+        }
+
         debug!(
             "Substream({}) write buf: {}, read buf: {}",
             self.id,
@@ -484,6 +776,8 @@ where
 
         is_pending &= self.recv_event(cx).is_pending();
 
+        self.sync_memory();
+
         if is_pending {
             Poll::Pending
         } else {
@@ -492,10 +786,20 @@ where
     }
 }
 
+impl<U> Drop for Substream<U> {
+    fn drop(&mut self) {
+        if let Some(ref budget) = self.memory_budget {
+            budget.release(self.held_bytes)
+        }
+    }
+}
+
 pub(crate) struct SubstreamBuilder {
     id: StreamId,
     proto_id: ProtocolId,
     keep_buffer: bool,
+    close_policy: ProtocolClosePolicy,
+    coalesce: bool,
     config: SessionConfig,
 
     context: Arc<SessionContext>,
@@ -503,6 +807,10 @@ pub(crate) struct SubstreamBuilder {
     service_proto_sender: Option<Buffer<ServiceProtocolEvent>>,
     session_proto_sender: Option<Buffer<SessionProtocolEvent>>,
     before_receive: Option<BeforeReceive>,
+    max_message_size: Option<usize>,
+    message_latency: Option<Arc<MessageLatency>>,
+    memory_budget: Option<Arc<MemoryBudget>>,
+    recv_rate_limit: Option<Arc<RecvRateLimit>>,
 
     /// Send event to session
     event_sender: mpsc::Sender<ProtocolEvent>,
@@ -520,12 +828,18 @@ impl SubstreamBuilder {
             service_proto_sender: None,
             session_proto_sender: None,
             before_receive: None,
+            max_message_size: None,
+            message_latency: None,
+            memory_budget: None,
+            recv_rate_limit: None,
             event_receiver,
             event_sender,
             context,
             id: 0,
             proto_id: 0.into(),
             keep_buffer: false,
+            close_policy: ProtocolClosePolicy::default(),
+            coalesce: false,
             config: SessionConfig::default(),
         }
     }
@@ -550,6 +864,16 @@ impl SubstreamBuilder {
         self
     }
 
+    pub fn close_policy(mut self, policy: ProtocolClosePolicy) -> Self {
+        self.close_policy = policy;
+        self
+    }
+
+    pub fn coalesce(mut self, coalesce: bool) -> Self {
+        self.coalesce = coalesce;
+        self
+    }
+
     pub fn service_proto_sender(mut self, sender: Option<Buffer<ServiceProtocolEvent>>) -> Self {
         self.service_proto_sender = sender;
         self
@@ -565,7 +889,27 @@ impl SubstreamBuilder {
         self
     }
 
-    pub fn build<U>(self, substream: Framed<StreamHandle, U>) -> Substream<U>
+    pub fn max_message_size(mut self, size: Option<usize>) -> Self {
+        self.max_message_size = size;
+        self
+    }
+
+    pub fn message_latency(mut self, latency: Option<Arc<MessageLatency>>) -> Self {
+        self.message_latency = latency;
+        self
+    }
+
+    pub fn memory_budget(mut self, budget: Option<Arc<MemoryBudget>>) -> Self {
+        self.memory_budget = budget;
+        self
+    }
+
+    pub fn recv_rate_limit(mut self, limit: Option<Arc<RecvRateLimit>>) -> Self {
+        self.recv_rate_limit = limit;
+        self
+    }
+
+    pub fn build<U>(self, substream: Framed<Box<dyn AsyncStream>, U>) -> Substream<U>
     where
         U: Codec,
     {
@@ -579,33 +923,84 @@ impl SubstreamBuilder {
             high_write_buf: VecDeque::new(),
 
             write_buf: VecDeque::new(),
+            write_bytes: 0,
+            message_latency: self.message_latency,
+            memory_budget: self.memory_budget,
+            held_bytes: 0,
+            recv_rate_limit: self.recv_rate_limit,
+            rate_delay: None,
+            upload_delay: None,
+            download_delay: None,
             dead: false,
+            reset: false,
+            remote_reset: false,
             keep_buffer: self.keep_buffer,
+            close_policy: self.close_policy,
+            closing: false,
+            coalesce: self.coalesce,
 
             event_sender: Buffer::new(self.event_sender),
             event_receiver: self.event_receiver,
 
-            service_proto_sender: self.service_proto_sender,
-            session_proto_sender: self.session_proto_sender,
+            // Counted for the memory budget
+            service_proto_sender: self.service_proto_sender.map(|buffer| {
+                buffer.count_bytes(|event| match event {
+                    ServiceProtocolEvent::Received { data, .. } => data.len(),
+                    _ => 0,
+                })
+            }),
+            session_proto_sender: self.session_proto_sender.map(|buffer| {
+                buffer.count_bytes(|event| match event {
+                    SessionProtocolEvent::Received { data } => data.len(),
+                    _ => 0,
+                })
+            }),
             before_receive: self.before_receive,
+            max_message_size: self.max_message_size,
         }
     }
 }
 
+/// The inbound message over the max message size of the protocol is an error
+fn check_message_size(limit: Option<usize>, size: usize) -> Result<(), io::Error> {
+    match limit {
+        Some(limit) if size > limit => Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("message size {} over the limit {}", size, limit),
+        )),
+        _ => Ok(()),
+    }
+}
+
 /* Code organization under read-write separation */
 
 pub(crate) struct SubstreamWritePart<U> {
-    substream: FramedWrite<crate::runtime::WriteHalf<StreamHandle>, U>,
+    substream: FramedWrite<crate::runtime::WriteHalf<Box<dyn AsyncStream>>, U>,
     id: StreamId,
     proto_id: ProtocolId,
 
     dead: bool,
+    /// Reset by local, skip the graceful shutdown
+    reset: bool,
+    close_policy: ProtocolClosePolicy,
+    /// Closed by local with the flush policy, finish the close once the write buffers are sent
+    closing: bool,
+    /// Write the messages ready at the same time with one flush
+    coalesce: bool,
     config: SessionConfig,
 
     /// The buffer will be prioritized for send to underlying network
-    high_write_buf: VecDeque<bytes::Bytes>,
+    high_write_buf: VecDeque<Frame>,
     // The buffer which will send to underlying network
-    write_buf: VecDeque<bytes::Bytes>,
+    write_buf: VecDeque<Frame>,
+    /// Bytes of the messages in the write buffers
+    write_bytes: usize,
+    message_latency: Option<Arc<MessageLatency>>,
+    memory_budget: Option<Arc<MemoryBudget>>,
+    /// Bytes held on the memory budget
+    held_bytes: usize,
+    /// Wake up writing when the session upload limit allows it again
+    upload_delay: Option<Pin<Box<crate::runtime::Delay>>>,
 
     /// Send event to session
     event_sender: Buffer<ProtocolEvent>,
@@ -619,7 +1014,8 @@ impl<U> SubstreamWritePart<U>
 where
     U: Codec + Unpin,
 {
-    fn push_front(&mut self, priority: Priority, frame: bytes::Bytes) {
+    fn push_front(&mut self, priority: Priority, frame: Frame) {
+        self.write_bytes += frame.0.len();
         if priority.is_high() {
             self.high_write_buf.push_front(frame);
         } else {
@@ -627,7 +1023,9 @@ where
         }
     }
 
-    fn push_back(&mut self, priority: Priority, frame: bytes::Bytes) {
+    fn push_back(&mut self, priority: Priority, data: bytes::Bytes) {
+        self.write_bytes += data.len();
+        let frame = (data, self.message_latency.as_ref().map(|_| Instant::now()));
         if priority.is_high() {
             self.high_write_buf.push_back(frame);
         } else {
@@ -641,16 +1039,28 @@ where
     fn send_inner(
         &mut self,
         cx: &mut Context,
-        frame: bytes::Bytes,
+        frame: Frame,
         priority: Priority,
     ) -> Result<bool, io::Error> {
-        let data_size = frame.len();
+        // Kept in the write buffer, in the order of its priority
+        if bandwidth_limited(&self.context.upload_limit, &mut self.upload_delay, cx) {
+            self.push_front(priority, frame);
+            self.poll_complete(cx)?;
+            return Ok(true);
+        }
+        let data_size = frame.0.len();
         let mut sink = Pin::new(&mut self.substream);
 
         match sink.as_mut().poll_ready(cx)? {
             Poll::Ready(()) => {
-                sink.as_mut().start_send(frame)?;
+                let (data, enqueue) = frame;
+                sink.as_mut().start_send(data)?;
                 self.context.decr_pending_data_size(data_size);
+                self.context.upload_limit.record(data_size);
+                self.context.traffic.record_sent(self.proto_id, data_size);
+                if let (Some(latency), Some(enqueue)) = (self.message_latency.as_ref(), enqueue) {
+                    latency.record(self.proto_id, enqueue.elapsed());
+                }
                 Ok(false)
             }
             Poll::Pending => {
@@ -668,15 +1078,38 @@ where
         }
     }
 
+    /// Drop the messages in the write buffers
+    fn drop_write_buf(&mut self) {
+        self.write_bytes = 0;
+        for (data, _) in self
+            .high_write_buf
+            .drain(..)
+            .chain(self.write_buf.drain(..))
+        {
+            self.context.decr_pending_data_size(data.len());
+        }
+    }
+
+    /// Whether the close with the flush policy can be finished,
+    /// the write buffers are sent and flushed
+    fn flushed_close(&mut self, cx: &mut Context) -> Result<bool, io::Error> {
+        if !self.closing || !self.high_write_buf.is_empty() || !self.write_buf.is_empty() {
+            return Ok(false);
+        }
+        self.poll_complete(cx).map(|pending| !pending)
+    }
+
     /// Send data to the lower `yamux` sub stream
     fn send_data(&mut self, cx: &mut Context) -> Result<(), io::Error> {
         while let Some(frame) = self.high_write_buf.pop_front() {
+            self.write_bytes -= frame.0.len();
             if self.send_inner(cx, frame, Priority::High)? {
                 return Ok(());
             }
         }
 
         while let Some(frame) = self.write_buf.pop_front() {
+            self.write_bytes -= frame.0.len();
             if self.send_inner(cx, frame, Priority::Normal)? {
                 return Ok(());
             }
@@ -709,7 +1142,15 @@ where
     fn handle_proto_event(&mut self, cx: &mut Context, event: ProtocolEvent, priority: Priority) {
         match event {
             ProtocolEvent::Message { data, .. } => {
+                if self.closing {
+                    self.context.decr_pending_data_size(data.len());
+                    return;
+                }
                 self.push_back(priority, data);
+                // Sent on the next flush, together with the messages received before it
+                if self.coalesce {
+                    return;
+                }
 
                 if let Err(err) = self.send_data(cx) {
                     // Whether it is a read send error or a flush error,
@@ -730,9 +1171,19 @@ where
                     self.dead = true;
                 }
             }
-            ProtocolEvent::Close { .. } => {
+            ProtocolEvent::Close { .. } => match self.close_policy {
+                ProtocolClosePolicy::DropQueued => {
+                    self.drop_write_buf();
+                    self.dead = true;
+                }
+                ProtocolClosePolicy::Flush => self.closing = true,
+            },
+            ProtocolEvent::Reset { .. } => {
+                self.high_write_buf.clear();
                 self.write_buf.clear();
+                self.write_bytes = 0;
                 self.dead = true;
+                self.reset = true;
             }
             _ => (),
         }
@@ -750,6 +1201,9 @@ where
         match Pin::new(&mut self.event_receiver).as_mut().poll_next(cx) {
             Poll::Ready(Some((priority, event))) => {
                 self.handle_proto_event(cx, event, priority);
+                if self.coalesce {
+                    self.recv_ready_events(cx);
+                }
                 Poll::Ready(Some(()))
             }
             Poll::Ready(None) => {
@@ -764,6 +1218,18 @@ where
         }
     }
 
+    /// Handle the events already in the channel, the end of it is seen on the next `recv_event`
+    fn recv_ready_events(&mut self, cx: &mut Context) {
+        while !self.dead && self.write_buf.len() <= self.config.send_event_size() {
+            match Pin::new(&mut self.event_receiver).as_mut().poll_next(cx) {
+                Poll::Ready(Some((priority, event))) => {
+                    self.handle_proto_event(cx, event, priority)
+                }
+                _ => break,
+            }
+        }
+    }
+
     /// When send or receive message error, output error and close stream
     fn error_close(&mut self, cx: &mut Context, error: io::Error) {
         self.dead = true;
@@ -785,8 +1251,11 @@ where
 
     fn close_proto_stream(&mut self, cx: &mut Context) {
         self.event_receiver.close();
-        if let Poll::Ready(Err(e)) = Pin::new(self.substream.get_mut()).poll_shutdown(cx) {
-            log::trace!("sub stream poll shutdown err {}", e)
+        // On reset, the sub stream is dropped without shutdown when this task ends
+        if !self.reset {
+            if let Poll::Ready(Err(e)) = Pin::new(self.substream.get_mut()).poll_shutdown(cx) {
+                log::trace!("sub stream poll shutdown err {}", e)
+            }
         }
         if !self.context.closed.load(Ordering::SeqCst) {
             let (mut sender, mut events) = self.event_sender.take();
@@ -805,6 +1274,19 @@ where
         }
     }
 
+    /// Update the bytes held on the memory budget
+    fn sync_memory(&mut self) {
+        if let Some(budget) = self.memory_budget.clone() {
+            let held = self.write_bytes;
+            if held > self.held_bytes {
+                budget.acquire(held - self.held_bytes)
+            } else if held < self.held_bytes {
+                budget.release(self.held_bytes - held)
+            }
+            self.held_bytes = held;
+        }
+    }
+
     /// Send event to user
     #[inline]
     fn output_event(&mut self, cx: &mut Context, event: ProtocolEvent) {
@@ -847,6 +1329,19 @@ where
             return Poll::Ready(None);
         }
 
+        match self.flushed_close(cx) {
+            Ok(true) => {
+                self.dead = true;
+                self.close_proto_stream(cx);
+                return Poll::Ready(None);
+            }
+            Ok(false) => (),
+            Err(err) => {
+                self.error_close(cx, err);
+                return Poll::Ready(None);
+            }
+        }
+
         debug!(
             "Substream({}) write buf: {}, read buf: {}",
             self.id,
@@ -856,6 +1351,8 @@ where
 
         let is_pending = self.recv_event(cx).is_pending();
 
+        self.sync_memory();
+
         if is_pending {
             Poll::Pending
         } else {
@@ -864,15 +1361,25 @@ where
     }
 }
 
+impl<U> Drop for SubstreamWritePart<U> {
+    fn drop(&mut self) {
+        if let Some(ref budget) = self.memory_budget {
+            budget.release(self.held_bytes)
+        }
+    }
+}
+
 /// Protocol Stream read part
 pub struct SubstreamReadPart {
     pub(crate) substream:
-        FramedRead<crate::runtime::ReadHalf<StreamHandle>, Box<dyn Codec + Send + 'static>>,
+        FramedRead<crate::runtime::ReadHalf<Box<dyn AsyncStream>>, Box<dyn Codec + Send + 'static>>,
     pub(crate) before_receive: Option<BeforeReceive>,
+    pub(crate) max_message_size: Option<usize>,
     pub(crate) proto_id: ProtocolId,
     pub(crate) stream_id: StreamId,
     pub(crate) version: String,
     pub(crate) close_sender: priority_mpsc::Sender<ProtocolEvent>,
+    pub(crate) context: Arc<SessionContext>,
 }
 
 impl SubstreamReadPart {
@@ -903,8 +1410,17 @@ impl Stream for SubstreamReadPart {
     type Item = Result<bytes::Bytes, io::Error>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if self.context.poll_read_paused(self.proto_id, cx) {
+            return Poll::Pending;
+        }
         match self.substream.poll_next_unpin(cx) {
             Poll::Ready(Some(Ok(data))) => {
+                self.context
+                    .traffic
+                    .record_received(self.proto_id, data.len());
+                if let Err(err) = check_message_size(self.max_message_size, data.len()) {
+                    return Poll::Ready(Some(Err(err)));
+                }
                 let data = match self.before_receive {
                     Some(ref function) => match function(data) {
                         Ok(data) => data,
@@ -926,7 +1442,11 @@ impl Stream for SubstreamReadPart {
 pub(crate) struct SubstreamWritePartBuilder {
     id: StreamId,
     proto_id: ProtocolId,
+    close_policy: ProtocolClosePolicy,
+    coalesce: bool,
     config: SessionConfig,
+    message_latency: Option<Arc<MessageLatency>>,
+    memory_budget: Option<Arc<MemoryBudget>>,
 
     context: Arc<SessionContext>,
 
@@ -948,7 +1468,11 @@ impl SubstreamWritePartBuilder {
             context,
             id: 0,
             proto_id: 0.into(),
+            close_policy: ProtocolClosePolicy::default(),
+            coalesce: false,
             config: SessionConfig::default(),
+            message_latency: None,
+            memory_budget: None,
         }
     }
 
@@ -967,9 +1491,29 @@ impl SubstreamWritePartBuilder {
         self
     }
 
+    pub fn close_policy(mut self, policy: ProtocolClosePolicy) -> Self {
+        self.close_policy = policy;
+        self
+    }
+
+    pub fn coalesce(mut self, coalesce: bool) -> Self {
+        self.coalesce = coalesce;
+        self
+    }
+
+    pub fn message_latency(mut self, latency: Option<Arc<MessageLatency>>) -> Self {
+        self.message_latency = latency;
+        self
+    }
+
+    pub fn memory_budget(mut self, budget: Option<Arc<MemoryBudget>>) -> Self {
+        self.memory_budget = budget;
+        self
+    }
+
     pub fn build<U>(
         self,
-        substream: FramedWrite<crate::runtime::WriteHalf<StreamHandle>, U>,
+        substream: FramedWrite<crate::runtime::WriteHalf<Box<dyn AsyncStream>>, U>,
     ) -> SubstreamWritePart<U>
     where
         U: Codec,
@@ -984,7 +1528,16 @@ impl SubstreamWritePartBuilder {
             high_write_buf: VecDeque::new(),
 
             write_buf: VecDeque::new(),
+            write_bytes: 0,
+            message_latency: self.message_latency,
+            memory_budget: self.memory_budget,
+            held_bytes: 0,
+            upload_delay: None,
             dead: false,
+            reset: false,
+            close_policy: self.close_policy,
+            closing: false,
+            coalesce: self.coalesce,
 
             event_sender: Buffer::new(self.event_sender),
             event_receiver: self.event_receiver,
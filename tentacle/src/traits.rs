@@ -1,15 +1,21 @@
+use futures::{Future, Stream};
 use std::{
-    io,
+    fmt, io,
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
 };
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_util::codec::{Decoder, Encoder};
 
 use crate::{
     context::{ProtocolContext, ProtocolContextMutRef, ServiceContext, SessionContext},
-    service::{ServiceControl, ServiceError, ServiceEvent},
+    error::HandshakeErrorKind,
+    multiaddr::Multiaddr,
+    secio::{KeyExporter, PeerId, PublicKey, SecurityParams},
+    service::{ServiceControl, ServiceError, ServiceEvent, SessionType},
     substream::SubstreamReadPart,
+    yamux::Stats as YamuxStats,
 };
 
 /// Service handle
@@ -65,6 +71,14 @@ pub trait ServiceProtocol {
     fn disconnected(&mut self, _context: ProtocolContextMutRef) {}
     /// Called when the corresponding protocol message is received
     fn received(&mut self, _context: ProtocolContextMutRef, _data: bytes::Bytes) {}
+    /// Called with the received messages, the queued messages of the same session are coalesced in one call
+    ///
+    /// Default calls `received` on each message in order, override it to reduce per-message overhead
+    fn received_batch(&mut self, mut context: ProtocolContextMutRef, data: Vec<bytes::Bytes>) {
+        for data in data {
+            self.received(context.reborrow(), data)
+        }
+    }
     /// Called when the Service receives the notify task
     fn notify(&mut self, _context: &mut ProtocolContext, _token: u64) {}
     /// Behave like `Stream::poll_next`, but nothing output
@@ -123,6 +137,23 @@ pub trait ProtocolSpawn {
     );
 }
 
+/// Handle of the protocol which takes over the raw sub stream, see `ProtocolHandle::Raw`
+///
+/// The sub stream is handed over as is when the protocol opened, the codec, `before_send`/`before_receive`,
+/// the read/write buffer and its backpressure/accounting of service are all bypassed,
+/// the protocol is also not tracked by session, such as `close_protocol`/`is_protocol_open`
+/// can't be used on it, it's closed when the user drops the stream or the session closed
+pub trait RawProtocol {
+    /// Call on protocol opened, the stream needs to be driven by the user
+    fn connected(
+        &self,
+        context: Arc<SessionContext>,
+        control: &ServiceControl,
+        version: &str,
+        stream: Box<dyn AsyncStream>,
+    );
+}
+
 /// A trait can define codec, just wrapper `Decoder` and `Encoder`
 pub trait Codec:
     Decoder<Item = bytes::BytesMut, Error = io::Error> + Encoder<bytes::Bytes, Error = io::Error>
@@ -191,6 +222,10 @@ impl ServiceProtocol for Box<dyn ServiceProtocol + Send + 'static + Unpin> {
         (&mut **self).received(context, data)
     }
 
+    fn received_batch(&mut self, context: ProtocolContextMutRef, data: Vec<bytes::Bytes>) {
+        (&mut **self).received_batch(context, data)
+    }
+
     fn notify(&mut self, context: &mut ProtocolContext, token: u64) {
         (&mut **self).notify(context, token)
     }
@@ -222,6 +257,10 @@ impl ServiceProtocol for Box<dyn ServiceProtocol + Send + Sync + 'static + Unpin
         (&mut **self).received(context, data)
     }
 
+    fn received_batch(&mut self, context: ProtocolContextMutRef, data: Vec<bytes::Bytes>) {
+        (&mut **self).received_batch(context, data)
+    }
+
     fn notify(&mut self, context: &mut ProtocolContext, token: u64) {
         (&mut **self).notify(context, token)
     }
@@ -289,3 +328,157 @@ impl SessionProtocol for Box<dyn SessionProtocol + Send + Sync + 'static + Unpin
         Pin::new(&mut **self).as_mut().poll(cx, context)
     }
 }
+
+/// A stream can be read and written, the connection before/after security upgrade
+pub trait AsyncStream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> AsyncStream for T {}
+
+impl std::fmt::Debug for dyn AsyncStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("AsyncStream")
+    }
+}
+
+/// The future returned by security upgrade
+pub type UpgradeFuture = Pin<
+    Box<
+        dyn Future<
+                Output = Result<
+                    (
+                        Box<dyn AsyncStream>,
+                        PublicKey,
+                        Option<KeyExporter>,
+                        Option<SecurityParams>,
+                    ),
+                    HandshakeErrorKind,
+                >,
+            > + Send,
+    >,
+>;
+
+/// Security upgrade of the raw connection
+///
+/// When the service has a key pair and no custom upgrade is set, secio is used by default.
+/// The upgrade is bounded by the service timeout.
+pub trait SecurityUpgrade: Send + Sync {
+    /// Upgrade the raw connection, return the secure stream, the remote public key, and the
+    /// keying material exporter and the negotiated algorithms of the session if the security
+    /// protocol supports them
+    fn upgrade(&self, socket: Box<dyn AsyncStream>) -> UpgradeFuture;
+}
+
+/// Derivation of the peer ids, used wherever the service derives or compares them
+///
+/// Default is the sha256 multihash of the public key, see `PublicKey::peer_id`
+pub trait PeerIdCodec: Send + Sync {
+    /// Derive the peer id of a public key
+    fn peer_id(&self, key: &PublicKey) -> PeerId;
+    /// Decode the peer id in the `/p2p/` part of an address, return None if it's invalid
+    fn decode(&self, bytes: &[u8]) -> Option<PeerId>;
+}
+
+/// Connection gating policy, consulted at each stage of the connection lifecycle,
+/// return false to close the connection there
+///
+/// The vetoed dials and sessions are reported as `DialerErrorKind::Gated` or
+/// `ListenErrorKind::Gated`, the vetoed inbound connections are closed silently.
+/// All functions are called on the service runtime except `intercept_accept`,
+/// which is called on the listener, do not block in them.
+pub trait ConnectionGater: Send + Sync {
+    /// An inbound connection is accepted, before its security handshake
+    fn intercept_accept(&self, _remote_address: &Multiaddr) -> bool {
+        true
+    }
+
+    /// Before dialing the address
+    fn intercept_dial(&self, _address: &Multiaddr) -> bool {
+        true
+    }
+
+    /// The security handshake finished, the peer id is None if the service has no security
+    fn intercept_secured(
+        &self,
+        _ty: SessionType,
+        _address: &Multiaddr,
+        _peer_id: Option<&PeerId>,
+    ) -> bool {
+        true
+    }
+
+    /// The connection passed all the checks of the service, before the session is opened
+    fn intercept_upgraded(&self, _context: &SessionContext) -> bool {
+        true
+    }
+}
+
+/// Address book of the peers, so higher layers can persist the known addresses
+///
+/// The service records the address of each outbound session on open, and reads the
+/// addresses of a peer on `ServiceControl::dial_peer`. All functions are called on the
+/// service runtime, do not block in them.
+pub trait PeerStore: Send + Sync {
+    /// Record an address of the peer
+    fn add_addr(&self, peer_id: PeerId, address: Multiaddr);
+    /// Known addresses of the peer, dialed in order
+    fn addrs(&self, peer_id: &PeerId) -> Vec<Multiaddr>;
+    /// Forget an address of the peer, it's called when the address turns out to be another peer
+    fn remove(&self, peer_id: &PeerId, address: &Multiaddr);
+}
+
+/// The future returned by lookup the TXT records
+pub type TxtFuture = Pin<Box<dyn Future<Output = Result<Vec<String>, io::Error>> + Send>>;
+
+/// Lookup of the TXT records, the `/dnsaddr` addresses are resolved by it
+pub trait TxtResolver: Send + Sync {
+    /// The content of each TXT record of the domain
+    fn resolve_txt(&self, domain: &str) -> TxtFuture;
+}
+
+/// Inbound sub streams of a multiplexed connection
+pub type MuxerIncoming =
+    Pin<Box<dyn Stream<Item = Result<Box<dyn AsyncStream>, io::Error>> + Send>>;
+
+/// The future returned by open a sub stream
+pub type OpenStreamFuture =
+    Pin<Box<dyn Future<Output = Result<Box<dyn AsyncStream>, io::Error>> + Send>>;
+
+/// Control of a multiplexed connection
+pub trait MuxerControl: Send + Sync {
+    /// Open a new outbound sub stream
+    fn open_stream(&self) -> OpenStreamFuture;
+    /// Close the connection
+    fn close(&self) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+    /// Flow control state of the connection, only the yamux multiplexer reports it
+    fn yamux_stats(&self) -> Option<YamuxStats> {
+        None
+    }
+}
+
+impl fmt::Debug for dyn MuxerControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("MuxerControl")
+    }
+}
+
+/// Stream multiplexer of the secure connection
+///
+/// Default is yamux
+pub trait StreamMuxer: Send + Sync {
+    /// Multiplex the connection, return the inbound sub streams and the control
+    ///
+    /// The inbound stream must be polled to drive the connection
+    fn multiplex(
+        &self,
+        socket: Box<dyn AsyncStream>,
+        ty: SessionType,
+    ) -> (MuxerIncoming, Arc<dyn MuxerControl>);
+
+    /// Bytes the two sides of the connection must agree on after the security upgrade,
+    /// the handshake fails if the remote sends other bytes
+    ///
+    /// Default is none, nothing is checked
+    fn channel_binding(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
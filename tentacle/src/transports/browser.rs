@@ -101,13 +101,26 @@ pub struct BrowserTransport {
 }
 
 impl BrowserTransport {
-    pub fn new(timeout: Duration) -> Self {
-        BrowserTransport { timeout }
+    pub fn with_timeouts(dial_timeout: Duration, _listen_timeout: Duration) -> Self {
+        BrowserTransport {
+            timeout: dial_timeout,
+        }
     }
 
     pub fn tcp_bind(self, _bind_addr: Option<SocketAddr>) -> Self {
         self
     }
+
+    pub fn outbound_bind(self, _bind_addr: Option<SocketAddr>) -> Self {
+        self
+    }
+
+    pub fn txt_resolver(
+        self,
+        _txt_resolver: Option<std::sync::Arc<dyn crate::traits::TxtResolver>>,
+    ) -> Self {
+        self
+    }
 }
 
 pub type BrowserDialFuture =
@@ -150,6 +163,11 @@ impl BrowserStream {
         }
     }
 
+    /// Browser stream is multiplexed by yamux
+    pub fn muxer(&mut self) -> Option<std::sync::Arc<dyn crate::traits::StreamMuxer>> {
+        None
+    }
+
     #[inline]
     fn drain(&mut self, buf: &mut ReadBuf) -> usize {
         // Return zero if there is no data remaining in the internal buffer.
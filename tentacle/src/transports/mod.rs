@@ -13,6 +13,8 @@ use std::{
 mod browser;
 #[cfg(not(target_arch = "wasm32"))]
 mod memory;
+#[cfg(all(feature = "quic", not(target_arch = "wasm32")))]
+mod quic;
 #[cfg(not(target_arch = "wasm32"))]
 mod tcp;
 #[cfg(all(feature = "tls", not(target_arch = "wasm32")))]
@@ -71,6 +73,7 @@ pub enum TransportType {
     Tcp,
     Tls,
     Memory,
+    Quic,
 }
 
 pub fn find_type(addr: &Multiaddr) -> TransportType {
@@ -85,6 +88,8 @@ pub fn find_type(addr: &Multiaddr) -> TransportType {
             Some(TransportType::Tls)
         } else if let Protocol::Memory(_) = proto {
             Some(TransportType::Memory)
+        } else if let Protocol::Quic = proto {
+            Some(TransportType::Quic)
         } else {
             None
         }
@@ -97,7 +102,8 @@ mod os {
     use super::*;
 
     use crate::{
-        runtime::{TcpListener, TcpStream},
+        runtime::{set_socket_options, TcpListener, TcpStream},
+        service::TcpSocketConfig,
         utils::socketaddr_to_multiaddr,
     };
 
@@ -117,6 +123,10 @@ mod os {
     use self::memory::{
         MemoryDialFuture, MemoryListenFuture, MemoryListener, MemorySocket, MemoryTransport,
     };
+    #[cfg(feature = "quic")]
+    use self::quic::{
+        QuicConnection, QuicDialFuture, QuicListenFuture, QuicListener, QuicTransport,
+    };
     use self::tcp::{TcpDialFuture, TcpListenFuture, TcpTransport};
     #[cfg(feature = "tls")]
     use self::tls::{TlsDialFuture, TlsListenFuture, TlsListener, TlsStream, TlsTransport};
@@ -124,26 +134,43 @@ mod os {
     use self::ws::{WebsocketListener, WsDialFuture, WsListenFuture, WsStream, WsTransport};
     #[cfg(feature = "tls")]
     use crate::service::config::TlsConfig;
+    use crate::{
+        traits::{StreamMuxer, TxtResolver},
+        utils::dns::{dnsaddr_domain, resolve_dnsaddr},
+    };
+    use std::sync::Arc;
 
     #[derive(Clone)]
     pub struct MultiTransport {
-        timeout: Duration,
+        dial_timeout: Duration,
+        listen_timeout: Duration,
         tcp_bind: Option<SocketAddr>,
         #[cfg(feature = "ws")]
         ws_bind: Option<SocketAddr>,
+        outbound_bind: Option<SocketAddr>,
+        tcp_socket_config: TcpSocketConfig,
         #[cfg(feature = "tls")]
         tls_config: Option<TlsConfig>,
+        txt_resolver: Option<Arc<dyn TxtResolver>>,
     }
 
     impl MultiTransport {
-        pub fn new(timeout: Duration) -> Self {
+        /// Dial timeout is used for connection establishment, listen timeout is used for accept handling
+        pub fn with_timeouts(dial_timeout: Duration, listen_timeout: Duration) -> Self {
             MultiTransport {
-                timeout,
+                dial_timeout,
+                listen_timeout,
                 tcp_bind: None,
                 #[cfg(feature = "ws")]
                 ws_bind: None,
+                outbound_bind: None,
+                tcp_socket_config: TcpSocketConfig::default(),
                 #[cfg(feature = "tls")]
                 tls_config: None,
+                #[cfg(feature = "dnsaddr")]
+                txt_resolver: Some(Arc::new(crate::utils::dns::SystemTxtResolver)),
+                #[cfg(not(feature = "dnsaddr"))]
+                txt_resolver: None,
             }
         }
 
@@ -158,11 +185,65 @@ mod os {
             self
         }
 
+        /// The source address of the outbound tcp/ws connections, it takes precedence over
+        /// the tcp/ws bind on dial and isn't used by listen
+        pub fn outbound_bind(mut self, bind_addr: Option<SocketAddr>) -> Self {
+            self.outbound_bind = bind_addr;
+            self
+        }
+
+        /// Socket options of the tcp connections, both the dialed and the accepted ones
+        pub fn tcp_socket_config(mut self, config: TcpSocketConfig) -> Self {
+            self.tcp_socket_config = config;
+            self
+        }
+
         #[cfg(feature = "tls")]
         pub fn tls_config(mut self, tls_config: Option<TlsConfig>) -> Self {
             self.tls_config = tls_config;
             self
         }
+
+        /// Replace the default TXT resolver of the `/dnsaddr` addresses if set
+        pub fn txt_resolver(mut self, txt_resolver: Option<Arc<dyn TxtResolver>>) -> Self {
+            if txt_resolver.is_some() {
+                self.txt_resolver = txt_resolver;
+            }
+            self
+        }
+    }
+
+    /// Resolve the `/dnsaddr` address and dial the candidates in order until one of them
+    /// succeeds, the source address is returned as the index of the dial
+    async fn dial_dnsaddr(
+        transport: MultiTransport,
+        resolver: Arc<dyn TxtResolver>,
+        address: Multiaddr,
+    ) -> Result<(Multiaddr, MultiStream)> {
+        let candidates = resolve_dnsaddr(resolver.as_ref(), address.clone())
+            .await
+            .map_err(|(multiaddr, io_error)| {
+                TransportErrorKind::DnsResolverError(multiaddr, io_error)
+            })?;
+
+        let mut last_error = None;
+        for candidate in candidates {
+            let res = match transport.clone().dial(candidate.clone()) {
+                Ok(future) => future.await,
+                Err(err) => Err(err),
+            };
+            match res {
+                Ok((_, stream)) => return Ok((address, stream)),
+                Err(err) => {
+                    debug!(
+                        "dial {} resolved from {} error: {}",
+                        candidate, address, err
+                    );
+                    last_error = Some(err)
+                }
+            }
+        }
+        Err(last_error.expect("resolved dnsaddr is never empty"))
     }
 
     impl Transport for MultiTransport {
@@ -172,14 +253,17 @@ mod os {
         fn listen(self, address: Multiaddr) -> Result<Self::ListenFuture> {
             match find_type(&address) {
                 TransportType::Tcp => {
-                    match TcpTransport::new(self.timeout, self.tcp_bind).listen(address) {
-                        Ok(future) => Ok(MultiListenFuture::Tcp(future)),
+                    match TcpTransport::new(self.listen_timeout, self.tcp_bind).listen(address) {
+                        Ok(future) => Ok(MultiListenFuture::Tcp(future, self.tcp_socket_config)),
                         Err(e) => Err(e),
                     }
                 }
                 #[cfg(feature = "ws")]
                 TransportType::Ws => {
-                    match WsTransport::new(self.timeout, self.ws_bind).listen(address) {
+                    match WsTransport::new(self.listen_timeout, self.ws_bind)
+                        .socket_config(self.tcp_socket_config)
+                        .listen(address)
+                    {
                         Ok(future) => Ok(MultiListenFuture::Ws(future)),
                         Err(e) => Err(e),
                     }
@@ -196,26 +280,49 @@ mod os {
                     let tls_config = self.tls_config.ok_or_else(|| {
                         TransportErrorKind::TlsError("tls config is not set".to_string())
                     })?;
-                    TlsTransport::new(self.timeout, tls_config)
+                    TlsTransport::new(self.listen_timeout, tls_config)
+                        .socket_config(self.tcp_socket_config)
                         .listen(address)
                         .map(MultiListenFuture::Tls)
                 }
                 #[cfg(not(feature = "tls"))]
                 TransportType::Tls => Err(TransportErrorKind::NotSupported(address)),
+                #[cfg(feature = "quic")]
+                TransportType::Quic => QuicTransport::new(self.listen_timeout)
+                    .listen(address)
+                    .map(MultiListenFuture::Quic),
+                #[cfg(not(feature = "quic"))]
+                TransportType::Quic => Err(TransportErrorKind::NotSupported(address)),
             }
         }
 
         fn dial(self, address: Multiaddr) -> Result<Self::DialFuture> {
+            if dnsaddr_domain(&address).is_some() {
+                return match self.txt_resolver.clone() {
+                    Some(resolver) => Ok(MultiDialFuture::Dnsaddr(Box::pin(dial_dnsaddr(
+                        self, resolver, address,
+                    )))),
+                    None => Err(TransportErrorKind::NotSupported(address)),
+                };
+            }
             match find_type(&address) {
                 TransportType::Tcp => {
-                    match TcpTransport::new(self.timeout, self.tcp_bind).dial(address) {
+                    let bind_addr = self.outbound_bind.or(self.tcp_bind);
+                    match TcpTransport::new(self.dial_timeout, bind_addr)
+                        .socket_config(self.tcp_socket_config)
+                        .dial(address)
+                    {
                         Ok(res) => Ok(MultiDialFuture::Tcp(res)),
                         Err(e) => Err(e),
                     }
                 }
                 #[cfg(feature = "ws")]
                 TransportType::Ws => {
-                    match WsTransport::new(self.timeout, self.ws_bind).dial(address) {
+                    let bind_addr = self.outbound_bind.or(self.ws_bind);
+                    match WsTransport::new(self.dial_timeout, bind_addr)
+                        .socket_config(self.tcp_socket_config)
+                        .dial(address)
+                    {
                         Ok(future) => Ok(MultiDialFuture::Ws(future)),
                         Err(e) => Err(e),
                     }
@@ -232,23 +339,32 @@ mod os {
                     let tls_config = self.tls_config.ok_or_else(|| {
                         TransportErrorKind::TlsError("tls config is not set".to_string())
                     })?;
-                    TlsTransport::new(self.timeout, tls_config)
+                    TlsTransport::new(self.dial_timeout, tls_config)
+                        .socket_config(self.tcp_socket_config)
                         .dial(address)
                         .map(MultiDialFuture::Tls)
                 }
                 #[cfg(not(feature = "tls"))]
                 TransportType::Tls => Err(TransportErrorKind::NotSupported(address)),
+                #[cfg(feature = "quic")]
+                TransportType::Quic => QuicTransport::new(self.dial_timeout)
+                    .dial(address)
+                    .map(MultiDialFuture::Quic),
+                #[cfg(not(feature = "quic"))]
+                TransportType::Quic => Err(TransportErrorKind::NotSupported(address)),
             }
         }
     }
 
     pub enum MultiListenFuture {
-        Tcp(TcpListenFuture),
+        Tcp(TcpListenFuture, TcpSocketConfig),
         Memory(MemoryListenFuture),
         #[cfg(feature = "ws")]
         Ws(WsListenFuture),
         #[cfg(feature = "tls")]
         Tls(TlsListenFuture),
+        #[cfg(feature = "quic")]
+        Quic(QuicListenFuture),
     }
 
     impl Future for MultiListenFuture {
@@ -256,10 +372,14 @@ mod os {
 
         fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
             match self.get_mut() {
-                MultiListenFuture::Tcp(inner) => Pin::new(
-                    &mut inner.map(|res| res.map(|res| (res.0, MultiIncoming::Tcp(res.1)))),
-                )
-                .poll(cx),
+                MultiListenFuture::Tcp(inner, config) => {
+                    let config = *config;
+                    Pin::new(
+                        &mut inner
+                            .map(|res| res.map(|res| (res.0, MultiIncoming::Tcp(res.1, config)))),
+                    )
+                    .poll(cx)
+                }
                 MultiListenFuture::Memory(inner) => Pin::new(
                     &mut inner.map(|res| res.map(|res| (res.0, MultiIncoming::Memory(res.1)))),
                 )
@@ -274,6 +394,11 @@ mod os {
                     &mut inner.map(|res| res.map(|res| (res.0, MultiIncoming::Tls(res.1)))),
                 )
                 .poll(cx),
+                #[cfg(feature = "quic")]
+                MultiListenFuture::Quic(inner) => Pin::new(
+                    &mut inner.map(|res| res.map(|res| (res.0, MultiIncoming::Quic(res.1)))),
+                )
+                .poll(cx),
             }
         }
     }
@@ -285,6 +410,9 @@ mod os {
         Ws(WsDialFuture),
         #[cfg(feature = "tls")]
         Tls(TlsDialFuture),
+        #[cfg(feature = "quic")]
+        Quic(QuicDialFuture),
+        Dnsaddr(Pin<Box<dyn Future<Output = Result<(Multiaddr, MultiStream)>> + Send>>),
     }
 
     impl Future for MultiDialFuture {
@@ -310,6 +438,12 @@ mod os {
                     Pin::new(&mut inner.map(|res| res.map(|res| (res.0, MultiStream::Tls(res.1)))))
                         .poll(cx)
                 }
+                #[cfg(feature = "quic")]
+                MultiDialFuture::Quic(inner) => {
+                    Pin::new(&mut inner.map(|res| res.map(|res| (res.0, MultiStream::Quic(res.1)))))
+                        .poll(cx)
+                }
+                MultiDialFuture::Dnsaddr(inner) => inner.as_mut().poll(cx),
             }
         }
     }
@@ -321,6 +455,42 @@ mod os {
         Ws(Box<WsStream>),
         #[cfg(feature = "tls")]
         Tls(TlsStream),
+        #[cfg(feature = "quic")]
+        Quic(QuicConnection),
+    }
+
+    impl MultiStream {
+        /// Local socket address of the underlying tcp connection
+        ///
+        /// Tls stream is boxed after the handshake and memory stream has no socket, return None
+        pub fn local_addr(&self) -> Option<SocketAddr> {
+            match self {
+                MultiStream::Tcp(inner) => inner.local_addr().ok(),
+                MultiStream::Memory(_) => None,
+                #[cfg(feature = "ws")]
+                MultiStream::Ws(inner) => inner.local_addr().ok(),
+                #[cfg(feature = "tls")]
+                MultiStream::Tls(_) => None,
+                #[cfg(feature = "quic")]
+                MultiStream::Quic(_) => None,
+            }
+        }
+
+        /// Take the muxer of the connection which brings its own encryption and streams,
+        /// such as quic, the session of it skips yamux
+        #[cfg(feature = "quic")]
+        pub fn muxer(&mut self) -> Option<Arc<dyn StreamMuxer>> {
+            match self {
+                MultiStream::Quic(inner) => inner.muxer(),
+                _ => None,
+            }
+        }
+
+        /// Only quic connection brings its own muxer
+        #[cfg(not(feature = "quic"))]
+        pub fn muxer(&mut self) -> Option<Arc<dyn StreamMuxer>> {
+            None
+        }
     }
 
     impl fmt::Debug for MultiStream {
@@ -332,6 +502,8 @@ mod os {
                 MultiStream::Ws(_) => write!(f, "Websocket stream"),
                 #[cfg(feature = "tls")]
                 MultiStream::Tls(_) => write!(f, "Tls stream"),
+                #[cfg(feature = "quic")]
+                MultiStream::Quic(_) => write!(f, "Quic connection"),
             }
         }
     }
@@ -349,6 +521,8 @@ mod os {
                 MultiStream::Ws(inner) => Pin::new(inner).poll_read(cx, buf),
                 #[cfg(feature = "tls")]
                 MultiStream::Tls(inner) => Pin::new(inner).poll_read(cx, buf),
+                #[cfg(feature = "quic")]
+                MultiStream::Quic(inner) => Pin::new(inner).poll_read(cx, buf),
             }
         }
     }
@@ -366,6 +540,8 @@ mod os {
                 MultiStream::Ws(inner) => Pin::new(inner).poll_write(cx, buf),
                 #[cfg(feature = "tls")]
                 MultiStream::Tls(inner) => Pin::new(inner).poll_write(cx, buf),
+                #[cfg(feature = "quic")]
+                MultiStream::Quic(inner) => Pin::new(inner).poll_write(cx, buf),
             }
         }
 
@@ -377,6 +553,8 @@ mod os {
                 MultiStream::Ws(inner) => Pin::new(inner).poll_flush(cx),
                 #[cfg(feature = "tls")]
                 MultiStream::Tls(inner) => Pin::new(inner).poll_flush(cx),
+                #[cfg(feature = "quic")]
+                MultiStream::Quic(inner) => Pin::new(inner).poll_flush(cx),
             }
         }
 
@@ -389,17 +567,21 @@ mod os {
                 MultiStream::Ws(inner) => Pin::new(inner).poll_shutdown(cx),
                 #[cfg(feature = "tls")]
                 MultiStream::Tls(inner) => Pin::new(inner).poll_shutdown(cx),
+                #[cfg(feature = "quic")]
+                MultiStream::Quic(inner) => Pin::new(inner).poll_shutdown(cx),
             }
         }
     }
 
     pub enum MultiIncoming {
-        Tcp(TcpListener),
+        Tcp(TcpListener, TcpSocketConfig),
         Memory(MemoryListener),
         #[cfg(feature = "ws")]
         Ws(WebsocketListener),
         #[cfg(feature = "tls")]
         Tls(TlsListener),
+        #[cfg(feature = "quic")]
+        Quic(QuicListener),
     }
 
     impl Stream for MultiIncoming {
@@ -407,15 +589,20 @@ mod os {
 
         fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
             match self.get_mut() {
-                MultiIncoming::Tcp(inner) => match inner.poll_accept(cx)? {
+                MultiIncoming::Tcp(inner, config) => match inner.poll_accept(cx)? {
                     // Why can't get the peer address of the connected stream ?
                     // Error will be "Transport endpoint is not connected",
                     // so why incoming will appear unconnected stream ?
                     Poll::Ready((stream, _)) => match stream.peer_addr() {
-                        Ok(remote_address) => Poll::Ready(Some(Ok((
-                            socketaddr_to_multiaddr(remote_address),
-                            MultiStream::Tcp(stream),
-                        )))),
+                        Ok(remote_address) => {
+                            if let Err(err) = set_socket_options(&stream, config) {
+                                debug!("stream set socket options error: {:?}", err);
+                            }
+                            Poll::Ready(Some(Ok((
+                                socketaddr_to_multiaddr(remote_address),
+                                MultiStream::Tcp(stream),
+                            ))))
+                        }
                         Err(err) => {
                             debug!("stream get peer address error: {:?}", err);
                             Poll::Pending
@@ -446,6 +633,14 @@ mod os {
                     Poll::Ready(None) => Poll::Ready(None),
                     Poll::Pending => Poll::Pending,
                 },
+                #[cfg(feature = "quic")]
+                MultiIncoming::Quic(inner) => match inner.poll_next_unpin(cx)? {
+                    Poll::Ready(Some((addr, connection))) => {
+                        Poll::Ready(Some(Ok((addr, MultiStream::Quic(connection)))))
+                    }
+                    Poll::Ready(None) => Poll::Ready(None),
+                    Poll::Pending => Poll::Pending,
+                },
             }
         }
     }
@@ -471,9 +666,14 @@ mod os {
         bind_addr: Option<SocketAddr>,
         timeout: Duration,
     ) -> Result<TcpStream> {
+        if let Some(bind) = bind_addr {
+            if bind.is_ipv4() != addr.is_ipv4() {
+                return Err(TransportErrorKind::BindFamilyMismatch(bind, addr));
+            }
+        }
         match crate::runtime::timeout(timeout, crate::runtime::connect(addr, bind_addr)).await {
-            Err(_) => Err(TransportErrorKind::Io(io::ErrorKind::TimedOut.into())),
-            Ok(res) => Ok(res?),
+            Err(_) => Err(TransportErrorKind::Connect(io::ErrorKind::TimedOut.into())),
+            Ok(res) => res.map_err(TransportErrorKind::Connect),
         }
     }
 }
@@ -514,4 +714,40 @@ mod test {
 
         assert_eq!(find_type(&a), TransportType::Tls);
     }
+
+    #[cfg(feature = "tokio-runtime")]
+    #[test]
+    fn test_tcp_socket_config() {
+        use super::{MultiStream, MultiTransport, Transport};
+        use crate::service::TcpSocketConfig;
+        use futures::StreamExt;
+        use std::time::Duration;
+
+        let config = TcpSocketConfig {
+            nodelay: true,
+            ..Default::default()
+        };
+        let transport =
+            MultiTransport::with_timeouts(Duration::from_secs(5), Duration::from_secs(5))
+                .tcp_socket_config(config);
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (addr, mut incoming) = transport
+                .clone()
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .unwrap()
+                .await
+                .unwrap();
+
+            match transport.dial(addr).unwrap().await {
+                Ok((_, MultiStream::Tcp(stream))) => assert!(stream.nodelay().unwrap()),
+                _ => panic!("the tcp stream should be dialed"),
+            }
+            match incoming.next().await {
+                Some(Ok((_, MultiStream::Tcp(stream)))) => assert!(stream.nodelay().unwrap()),
+                _ => panic!("the tcp stream should be accepted"),
+            }
+        });
+    }
 }
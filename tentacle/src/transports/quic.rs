@@ -0,0 +1,497 @@
+//! Quic transport
+//!
+//! Quic brings its own encryption and streams, so a quic connection bypasses yamux,
+//! its bidirectional streams are the sub streams of the session.
+//!
+//! The certificate of the listener is self-signed, so the dialer accepts it as is. The peer
+//! is authenticated by the security upgrade of the service, run over the first stream opened
+//! by the dialer, then the certificate the dialer sees is compared with the one the listener
+//! presents over the secure stream, which fails the handshake of a relayed connection.
+
+use futures::{
+    channel::mpsc::{channel, Receiver, Sender},
+    future, SinkExt, Stream, StreamExt,
+};
+use log::debug;
+use quinn::{
+    Certificate, CertificateChain, ClientConfig, ClientConfigBuilder, Connection, Endpoint,
+    Incoming, IncomingBiStreams, NewConnection, PrivateKey, RecvStream, SendStream, ServerConfig,
+    ServerConfigBuilder,
+};
+use std::{
+    future::Future,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{
+    error::TransportErrorKind,
+    lock::Mutex,
+    multiaddr::{Multiaddr, Protocol},
+    service::SessionType,
+    traits::{AsyncStream, MuxerControl, MuxerIncoming, OpenStreamFuture, StreamMuxer},
+    transports::{Result, Transport, TransportFuture},
+};
+
+/// Server name of the self-signed certificate
+const SERVER_NAME: &str = "tentacle";
+/// Application protocol negotiated by quic handshake
+const ALPN: &[u8] = b"tentacle";
+
+fn quic_error<E: ToString>(err: E) -> TransportErrorKind {
+    TransportErrorKind::QuicError(err.to_string())
+}
+
+/// Get the socket address of `/ip4/.../udp/<port>/quic` or `/ip6/.../udp/<port>/quic`
+pub fn quic_socketaddr(addr: &Multiaddr) -> Option<SocketAddr> {
+    let mut iter = addr.iter();
+    let ip: IpAddr = match iter.next()? {
+        Protocol::Ip4(ip) => ip.into(),
+        Protocol::Ip6(ip) => ip.into(),
+        _ => return None,
+    };
+    let port = match iter.next()? {
+        Protocol::Udp(port) => port,
+        _ => return None,
+    };
+    match iter.next()? {
+        Protocol::Quic => Some(SocketAddr::new(ip, port)),
+        _ => None,
+    }
+}
+
+/// Convert the socket address to `/ip4/.../udp/<port>/quic` or `/ip6/.../udp/<port>/quic`
+pub fn socketaddr_to_quic_multiaddr(address: SocketAddr) -> Multiaddr {
+    let mut addr: Multiaddr = Protocol::from(address.ip()).into();
+    addr.push(Protocol::Udp(address.port()));
+    addr.push(Protocol::Quic);
+    addr
+}
+
+/// The server config and the der of its self-signed certificate
+fn server_config() -> Result<(ServerConfig, Vec<u8>)> {
+    let cert =
+        rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_owned()]).map_err(quic_error)?;
+    let key = PrivateKey::from_der(&cert.serialize_private_key_der()).map_err(quic_error)?;
+    let cert_der = cert.serialize_der().map_err(quic_error)?;
+    let cert = Certificate::from_der(&cert_der).map_err(quic_error)?;
+
+    let mut builder = ServerConfigBuilder::default();
+    builder.protocols(&[ALPN]);
+    builder
+        .certificate(CertificateChain::from_certs(vec![cert]), key)
+        .map_err(quic_error)?;
+    Ok((builder.build(), cert_der))
+}
+
+/// The certificate of the listener is self-signed, accept it as is and record it,
+/// it's verified after the security upgrade
+struct RecordServerCert {
+    presented: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl rustls::ServerCertVerifier for RecordServerCert {
+    fn verify_server_cert(
+        &self,
+        _roots: &rustls::RootCertStore,
+        presented_certs: &[rustls::Certificate],
+        _dns_name: webpki::DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> std::result::Result<rustls::ServerCertVerified, rustls::TLSError> {
+        *self.presented.lock() = presented_certs.first().map(|cert| cert.0.clone());
+        Ok(rustls::ServerCertVerified::assertion())
+    }
+}
+
+fn client_config(presented: Arc<Mutex<Option<Vec<u8>>>>) -> ClientConfig {
+    let mut builder = ClientConfigBuilder::default();
+    builder.protocols(&[ALPN]);
+    let mut config = builder.build();
+    if let Some(crypto) = Arc::get_mut(&mut config.crypto) {
+        crypto
+            .dangerous()
+            .set_certificate_verifier(Arc::new(RecordServerCert { presented }));
+    }
+    config
+}
+
+/// Quic listen bind
+async fn bind(address: Multiaddr, timeout: Duration) -> Result<(Multiaddr, QuicListener)> {
+    let socket_address =
+        quic_socketaddr(&address).ok_or_else(|| TransportErrorKind::NotSupported(address))?;
+    let (server_config, server_cert) = server_config()?;
+    let mut builder = Endpoint::builder();
+    builder.listen(server_config);
+    let (endpoint, incoming) = builder.bind(&socket_address).map_err(quic_error)?;
+    let local_address = endpoint.local_addr()?;
+
+    Ok((
+        socketaddr_to_quic_multiaddr(local_address),
+        QuicListener::new(timeout, endpoint, incoming, server_cert),
+    ))
+}
+
+/// Quic connect
+async fn connect(address: Multiaddr, timeout: Duration) -> Result<(Multiaddr, QuicConnection)> {
+    let socket_address = quic_socketaddr(&address)
+        .ok_or_else(|| TransportErrorKind::NotSupported(address.clone()))?;
+    let bind_address = match socket_address {
+        SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+        SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+    };
+    let presented = Arc::new(Mutex::new(None));
+    let mut builder = Endpoint::builder();
+    builder.default_client_config(client_config(presented.clone()));
+    let (endpoint, _) = builder.bind(&bind_address).map_err(quic_error)?;
+    let connecting = endpoint
+        .connect(&socket_address, SERVER_NAME)
+        .map_err(quic_error)?;
+
+    let connection = match crate::runtime::timeout(timeout, connecting).await {
+        Err(_) => return Err(TransportErrorKind::Connect(io::ErrorKind::TimedOut.into())),
+        Ok(Err(err)) => {
+            return Err(TransportErrorKind::Connect(io::Error::new(
+                io::ErrorKind::Other,
+                err,
+            )))
+        }
+        Ok(Ok(connection)) => connection,
+    };
+    // the first stream carries the security upgrade
+    let (send, recv) = connection.connection.open_bi().await.map_err(|err| {
+        TransportErrorKind::Connect(io::Error::new(io::ErrorKind::BrokenPipe, err))
+    })?;
+    let server_cert = presented.lock().take().unwrap_or_default();
+    Ok((
+        address,
+        QuicConnection::new(
+            Some(endpoint),
+            connection,
+            QuicStream { send, recv },
+            server_cert,
+        ),
+    ))
+}
+
+pub struct QuicTransport {
+    timeout: Duration,
+}
+
+impl QuicTransport {
+    pub fn new(timeout: Duration) -> Self {
+        QuicTransport { timeout }
+    }
+}
+
+pub type QuicListenFuture =
+    TransportFuture<Pin<Box<dyn Future<Output = Result<(Multiaddr, QuicListener)>> + Send>>>;
+pub type QuicDialFuture =
+    TransportFuture<Pin<Box<dyn Future<Output = Result<(Multiaddr, QuicConnection)>> + Send>>>;
+
+impl Transport for QuicTransport {
+    type ListenFuture = QuicListenFuture;
+    type DialFuture = QuicDialFuture;
+
+    fn listen(self, address: Multiaddr) -> Result<Self::ListenFuture> {
+        Ok(TransportFuture::new(Box::pin(bind(address, self.timeout))))
+    }
+
+    fn dial(self, address: Multiaddr) -> Result<Self::DialFuture> {
+        Ok(TransportFuture::new(Box::pin(connect(
+            address,
+            self.timeout,
+        ))))
+    }
+}
+
+/// Quic listener, the handshakes of the incoming connections run in background
+pub struct QuicListener {
+    // keep the endpoint alive as long as the listener
+    _endpoint: Endpoint,
+    incoming: Incoming,
+    timeout: Duration,
+    /// Der of the certificate presented to the dialers
+    server_cert: Vec<u8>,
+    sender: Sender<(Multiaddr, QuicConnection)>,
+    pending_connection: Receiver<(Multiaddr, QuicConnection)>,
+}
+
+impl QuicListener {
+    fn new(
+        timeout: Duration,
+        endpoint: Endpoint,
+        incoming: Incoming,
+        server_cert: Vec<u8>,
+    ) -> Self {
+        let (sender, rx) = channel(24);
+        QuicListener {
+            _endpoint: endpoint,
+            incoming,
+            timeout,
+            server_cert,
+            sender,
+            pending_connection: rx,
+        }
+    }
+
+    fn poll_pending(
+        &mut self,
+        cx: &mut Context,
+    ) -> Poll<Option<std::result::Result<(Multiaddr, QuicConnection), io::Error>>> {
+        match Pin::new(&mut self.pending_connection)
+            .as_mut()
+            .poll_next(cx)
+        {
+            Poll::Ready(Some(res)) => Poll::Ready(Some(Ok(res))),
+            Poll::Ready(None) | Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Stream for QuicListener {
+    type Item = std::result::Result<(Multiaddr, QuicConnection), io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if let Poll::Ready(res) = self.poll_pending(cx) {
+            return Poll::Ready(res);
+        }
+
+        match self.incoming.poll_next_unpin(cx) {
+            Poll::Ready(Some(connecting)) => {
+                let timeout = self.timeout;
+                let server_cert = self.server_cert.clone();
+                let mut sender = self.sender.clone();
+                let accept = async move {
+                    let mut connection = connecting
+                        .await
+                        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+                    // the dialer opens the first stream for the security upgrade
+                    let (send, recv) = match connection.bi_streams.next().await {
+                        Some(stream) => {
+                            stream.map_err(|err| io::Error::new(io::ErrorKind::BrokenPipe, err))?
+                        }
+                        None => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                    };
+                    Ok::<_, io::Error>((connection, QuicStream { send, recv }))
+                };
+                crate::runtime::spawn(async move {
+                    match crate::runtime::timeout(timeout, accept).await {
+                        Err(_) => debug!("accept quic connection timeout"),
+                        Ok(Ok((connection, stream))) => {
+                            let addr = socketaddr_to_quic_multiaddr(
+                                connection.connection.remote_address(),
+                            );
+                            let connection =
+                                QuicConnection::new(None, connection, stream, server_cert);
+                            if sender.send((addr, connection)).await.is_err() {
+                                debug!("receiver closed unexpectedly")
+                            }
+                        }
+                        Ok(Err(err)) => debug!("accept quic connection err: {:?}", err),
+                    }
+                });
+                self.poll_pending(cx)
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+struct QuicParts {
+    endpoint: Option<Endpoint>,
+    connection: Connection,
+    bi_streams: IncomingBiStreams,
+    /// Der of the certificate of the listener
+    server_cert: Vec<u8>,
+}
+
+/// An established quic connection
+///
+/// As a byte stream, it reads and writes the first stream of the connection, which carries
+/// the security upgrade, the other streams are opened by the muxer taken by `muxer`
+pub struct QuicConnection {
+    parts: Option<QuicParts>,
+    stream: QuicStream,
+}
+
+impl QuicConnection {
+    fn new(
+        endpoint: Option<Endpoint>,
+        connection: NewConnection,
+        stream: QuicStream,
+        server_cert: Vec<u8>,
+    ) -> Self {
+        QuicConnection {
+            parts: Some(QuicParts {
+                endpoint,
+                connection: connection.connection,
+                bi_streams: connection.bi_streams,
+                server_cert,
+            }),
+            stream,
+        }
+    }
+
+    /// Take the muxer of the connection, the session opens the streams with it
+    pub fn muxer(&mut self) -> Option<Arc<dyn StreamMuxer>> {
+        self.parts.take().map(|parts| {
+            Arc::new(QuicMuxer {
+                parts: Mutex::new(Some(parts)),
+            }) as Arc<dyn StreamMuxer>
+        })
+    }
+}
+
+impl AsyncRead for QuicConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for QuicConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        // the connection not taken by the session is closed
+        if let Some(parts) = self.parts.take() {
+            parts.connection.close(0u32.into(), b"shutdown");
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Multiplexer of a quic connection, it can only multiplex the connection it's taken from
+struct QuicMuxer {
+    parts: Mutex<Option<QuicParts>>,
+}
+
+impl StreamMuxer for QuicMuxer {
+    fn multiplex(
+        &self,
+        _socket: Box<dyn AsyncStream>,
+        _ty: SessionType,
+    ) -> (MuxerIncoming, Arc<dyn MuxerControl>) {
+        let parts = match self.parts.lock().take() {
+            Some(parts) => parts,
+            None => {
+                let error = || {
+                    io::Error::new(
+                        io::ErrorKind::NotConnected,
+                        "quic muxer multiplexes only one connection",
+                    )
+                };
+                let incoming = futures::stream::once(future::ready(Err(error())));
+                return (Box::pin(incoming), Arc::new(ClosedControl));
+            }
+        };
+        let incoming = parts.bi_streams.map(|res| {
+            res.map(|(send, recv)| Box::new(QuicStream { send, recv }) as Box<dyn AsyncStream>)
+                .map_err(|err| io::Error::new(io::ErrorKind::BrokenPipe, err))
+        });
+        let control = QuicControl {
+            _endpoint: parts.endpoint,
+            connection: parts.connection,
+        };
+        (Box::pin(incoming), Arc::new(control))
+    }
+
+    fn channel_binding(&self) -> Option<Vec<u8>> {
+        self.parts
+            .lock()
+            .as_ref()
+            .map(|parts| parts.server_cert.clone())
+    }
+}
+
+/// Control of a muxer without connection, opening streams fails
+struct ClosedControl;
+
+impl MuxerControl for ClosedControl {
+    fn open_stream(&self) -> OpenStreamFuture {
+        Box::pin(future::ready(Err(io::Error::new(
+            io::ErrorKind::NotConnected,
+            "quic connection is already multiplexed",
+        ))))
+    }
+
+    fn close(&self) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(future::ready(()))
+    }
+}
+
+struct QuicControl {
+    // the endpoint of an outbound connection lives as long as the connection
+    _endpoint: Option<Endpoint>,
+    connection: Connection,
+}
+
+impl MuxerControl for QuicControl {
+    fn open_stream(&self) -> OpenStreamFuture {
+        let open = self.connection.open_bi();
+        Box::pin(async move {
+            open.await
+                .map(|(send, recv)| Box::new(QuicStream { send, recv }) as Box<dyn AsyncStream>)
+                .map_err(|err| io::Error::new(io::ErrorKind::BrokenPipe, err))
+        })
+    }
+
+    fn close(&self) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        self.connection.close(0u32.into(), b"close");
+        Box::pin(future::ready(()))
+    }
+}
+
+/// A bidirectional quic stream
+struct QuicStream {
+    send: SendStream,
+    recv: RecvStream,
+}
+
+impl AsyncRead for QuicStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.recv).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for QuicStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.send).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.send).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.send).poll_shutdown(cx)
+    }
+}
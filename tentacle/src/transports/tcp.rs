@@ -5,7 +5,8 @@ use std::{future::Future, net::SocketAddr, pin::Pin, time::Duration};
 use crate::{
     error::TransportErrorKind,
     multiaddr::Multiaddr,
-    runtime::{TcpListener, TcpStream},
+    runtime::{set_socket_options, TcpListener, TcpStream},
+    service::TcpSocketConfig,
     transports::{tcp_dial, tcp_listen, Transport, TransportFuture},
     utils::{dns::DnsResolver, multiaddr_to_socketaddr, socketaddr_to_multiaddr},
 };
@@ -34,11 +35,13 @@ async fn connect(
     timeout: Duration,
     original: Option<Multiaddr>,
     bind_addr: Option<SocketAddr>,
+    socket_config: TcpSocketConfig,
 ) -> Result<(Multiaddr, TcpStream)> {
     let addr = address.await?;
     match multiaddr_to_socketaddr(&addr) {
         Some(socket_address) => {
             let stream = tcp_dial(socket_address, bind_addr, timeout).await?;
+            set_socket_options(&stream, &socket_config)?;
             Ok((original.unwrap_or(addr), stream))
         }
         None => Err(TransportErrorKind::NotSupported(original.unwrap_or(addr))),
@@ -50,11 +53,22 @@ async fn connect(
 pub struct TcpTransport {
     timeout: Duration,
     bind_addr: Option<SocketAddr>,
+    socket_config: TcpSocketConfig,
 }
 
 impl TcpTransport {
     pub fn new(timeout: Duration, bind_addr: Option<SocketAddr>) -> Self {
-        TcpTransport { timeout, bind_addr }
+        TcpTransport {
+            timeout,
+            bind_addr,
+            socket_config: TcpSocketConfig::default(),
+        }
+    }
+
+    /// Socket options of the dialed connections
+    pub fn socket_config(mut self, socket_config: TcpSocketConfig) -> Self {
+        self.socket_config = socket_config;
+        self
     }
 }
 
@@ -98,11 +112,18 @@ impl Transport for TcpTransport {
                     self.timeout,
                     Some(address),
                     self.bind_addr,
+                    self.socket_config,
                 );
                 Ok(TransportFuture::new(Box::pin(task)))
             }
             None => {
-                let dial = connect(ok(address), self.timeout, None, self.bind_addr);
+                let dial = connect(
+                    ok(address),
+                    self.timeout,
+                    None,
+                    self.bind_addr,
+                    self.socket_config,
+                );
                 Ok(TransportFuture::new(Box::pin(dial)))
             }
         }
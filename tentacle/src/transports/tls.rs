@@ -9,8 +9,8 @@ use std::{
     time::Duration,
 };
 
-use crate::runtime::TcpListener;
-use crate::service::TlsConfig;
+use crate::runtime::{set_socket_options, TcpListener};
+use crate::service::{TcpSocketConfig, TlsConfig};
 use crate::{
     error::TransportErrorKind,
     multiaddr::{Multiaddr, Protocol},
@@ -33,6 +33,7 @@ async fn bind(
     timeout: Duration,
     config: TlsConfig,
     domain_name: String,
+    socket_config: TcpSocketConfig,
 ) -> Result<(Multiaddr, TlsListener)> {
     let addr = address.await?;
     match multiaddr_to_socketaddr(&addr) {
@@ -45,7 +46,7 @@ async fn bind(
             listen_addr.push(Protocol::Tls(Cow::Owned(domain_name)));
             Ok((
                 listen_addr,
-                TlsListener::new(timeout, tcp, tls_server_config),
+                TlsListener::new(timeout, tcp, tls_server_config, socket_config),
             ))
         }
         None => Err(TransportErrorKind::NotSupported(addr)),
@@ -59,6 +60,7 @@ async fn connect(
     original: Option<Multiaddr>,
     config: TlsConfig,
     domain_name: String,
+    socket_config: TcpSocketConfig,
 ) -> Result<(Multiaddr, TlsStream)> {
     let tls_client_config = config
         .tls_client_config
@@ -68,6 +70,7 @@ async fn connect(
     match multiaddr_to_socketaddr(&addr) {
         Some(socket_address) => {
             let stream = tcp_dial(socket_address, config.tls_bind, timeout).await?;
+            set_socket_options(&stream, &socket_config)?;
 
             let domain_name = DNSNameRef::try_from_ascii_str(&domain_name)
                 .map_err(|_| TransportErrorKind::TlsError("invalid dnsname".to_string()))?;
@@ -92,10 +95,16 @@ pub struct TlsListener {
     sender: Sender<(Multiaddr, TlsStream)>,
     pending_stream: Receiver<(Multiaddr, TlsStream)>,
     tls_config: Arc<ServerConfig>,
+    socket_config: TcpSocketConfig,
 }
 
 impl TlsListener {
-    fn new(timeout: Duration, listen: TcpListener, tls_config: Arc<ServerConfig>) -> Self {
+    fn new(
+        timeout: Duration,
+        listen: TcpListener,
+        tls_config: Arc<ServerConfig>,
+        socket_config: TcpSocketConfig,
+    ) -> Self {
         let (sender, rx) = channel(24);
         TlsListener {
             inner: listen,
@@ -103,6 +112,7 @@ impl TlsListener {
             sender,
             pending_stream: rx,
             tls_config,
+            socket_config,
         }
     }
 
@@ -128,6 +138,9 @@ impl Stream for TlsListener {
         match self.inner.poll_accept(cx)? {
             Poll::Ready((stream, _)) => match stream.peer_addr() {
                 Ok(remote_address) => {
+                    if let Err(err) = set_socket_options(&stream, &self.socket_config) {
+                        warn!("stream set socket options error: {:?}", err);
+                    }
                     let timeout = self.timeout;
                     let mut sender = self.sender.clone();
                     let acceptor = TlsAcceptor::from(Arc::clone(&self.tls_config));
@@ -177,11 +190,22 @@ fn parse_tls_domain_name(addr: &Multiaddr) -> Option<String> {
 pub struct TlsTransport {
     timeout: Duration,
     config: TlsConfig,
+    socket_config: TcpSocketConfig,
 }
 
 impl TlsTransport {
     pub fn new(timeout: Duration, config: TlsConfig) -> Self {
-        TlsTransport { timeout, config }
+        TlsTransport {
+            timeout,
+            config,
+            socket_config: TcpSocketConfig::default(),
+        }
+    }
+
+    /// Socket options of the dialed and the accepted connections
+    pub fn socket_config(mut self, socket_config: TcpSocketConfig) -> Self {
+        self.socket_config = socket_config;
+        self
     }
 }
 
@@ -205,11 +229,18 @@ impl Transport for TlsTransport {
                         self.timeout,
                         self.config,
                         domain_name,
+                        self.socket_config,
                     );
                     Ok(TransportFuture::new(Box::pin(task)))
                 }
                 None => {
-                    let task = bind(ok(address), self.timeout, self.config, domain_name);
+                    let task = bind(
+                        ok(address),
+                        self.timeout,
+                        self.config,
+                        domain_name,
+                        self.socket_config,
+                    );
                     Ok(TransportFuture::new(Box::pin(task)))
                 }
             }
@@ -232,11 +263,19 @@ impl Transport for TlsTransport {
                         Some(address),
                         self.config,
                         domain_name,
+                        self.socket_config,
                     );
                     Ok(TransportFuture::new(Box::pin(task)))
                 }
                 None => {
-                    let dial = connect(ok(address), self.timeout, None, self.config, domain_name);
+                    let dial = connect(
+                        ok(address),
+                        self.timeout,
+                        None,
+                        self.config,
+                        domain_name,
+                        self.socket_config,
+                    );
                     Ok(TransportFuture::new(Box::pin(dial)))
                 }
             }
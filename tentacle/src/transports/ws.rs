@@ -22,7 +22,8 @@ use tokio_tungstenite::{
 use crate::{
     error::TransportErrorKind,
     multiaddr::{Multiaddr, Protocol},
-    runtime::{TcpListener, TcpStream},
+    runtime::{set_socket_options, TcpListener, TcpStream},
+    service::TcpSocketConfig,
     transports::{tcp_dial, tcp_listen, Result, Transport, TransportFuture},
     utils::{dns::DnsResolver, multiaddr_to_socketaddr, socketaddr_to_multiaddr},
 };
@@ -32,6 +33,7 @@ async fn bind(
     address: impl Future<Output = Result<Multiaddr>>,
     timeout: Duration,
     reuse: bool,
+    socket_config: TcpSocketConfig,
 ) -> Result<(Multiaddr, WebsocketListener)> {
     let addr = address.await?;
     match multiaddr_to_socketaddr(&addr) {
@@ -40,7 +42,10 @@ async fn bind(
             let mut listen_addr = socketaddr_to_multiaddr(addr);
             listen_addr.push(Protocol::Ws);
 
-            Ok((listen_addr, WebsocketListener::new(timeout, tcp)))
+            Ok((
+                listen_addr,
+                WebsocketListener::new(timeout, tcp, socket_config),
+            ))
         }
         None => Err(TransportErrorKind::NotSupported(addr)),
     }
@@ -52,12 +57,14 @@ async fn connect(
     timeout: Duration,
     original: Option<Multiaddr>,
     bind_addr: Option<SocketAddr>,
+    socket_config: TcpSocketConfig,
 ) -> Result<(Multiaddr, WsStream)> {
     let addr = address.await?;
     match multiaddr_to_socketaddr(&addr) {
         Some(socket_address) => {
             let url = format!("ws://{}:{}", socket_address.ip(), socket_address.port());
             let tcp = tcp_dial(socket_address, bind_addr, timeout).await?;
+            set_socket_options(&tcp, &socket_config)?;
 
             match crate::runtime::timeout(timeout, client_async_with_config(url, tcp, None)).await {
                 Err(_) => Err(TransportErrorKind::Io(io::ErrorKind::TimedOut.into())),
@@ -66,7 +73,7 @@ async fn connect(
                         if let Error::Io(e) = err {
                             TransportErrorKind::Io(e)
                         } else {
-                            TransportErrorKind::Io(io::ErrorKind::ConnectionAborted.into())
+                            TransportErrorKind::WsHandshake(err.to_string())
                         }
                     })?;
                     WsStream::new(stream)
@@ -80,11 +87,22 @@ async fn connect(
 pub struct WsTransport {
     timeout: Duration,
     bind_addr: Option<SocketAddr>,
+    socket_config: TcpSocketConfig,
 }
 
 impl WsTransport {
     pub fn new(timeout: Duration, bind_addr: Option<SocketAddr>) -> Self {
-        WsTransport { timeout, bind_addr }
+        WsTransport {
+            timeout,
+            bind_addr,
+            socket_config: TcpSocketConfig::default(),
+        }
+    }
+
+    /// Socket options of the dialed and the accepted connections
+    pub fn socket_config(mut self, socket_config: TcpSocketConfig) -> Self {
+        self.socket_config = socket_config;
+        self
     }
 }
 
@@ -106,11 +124,17 @@ impl Transport for WsTransport {
                     }),
                     self.timeout,
                     self.bind_addr.is_some(),
+                    self.socket_config,
                 );
                 Ok(TransportFuture::new(Box::pin(task)))
             }
             None => {
-                let task = bind(ok(address), self.timeout, self.bind_addr.is_some());
+                let task = bind(
+                    ok(address),
+                    self.timeout,
+                    self.bind_addr.is_some(),
+                    self.socket_config,
+                );
                 Ok(TransportFuture::new(Box::pin(task)))
             }
         }
@@ -128,11 +152,18 @@ impl Transport for WsTransport {
                     self.timeout,
                     Some(address),
                     self.bind_addr,
+                    self.socket_config,
                 );
                 Ok(TransportFuture::new(Box::pin(task)))
             }
             None => {
-                let dial = connect(ok(address), self.timeout, None, self.bind_addr);
+                let dial = connect(
+                    ok(address),
+                    self.timeout,
+                    None,
+                    self.bind_addr,
+                    self.socket_config,
+                );
                 Ok(TransportFuture::new(Box::pin(dial)))
             }
         }
@@ -143,16 +174,18 @@ impl Transport for WsTransport {
 pub struct WebsocketListener {
     inner: TcpListener,
     timeout: Duration,
+    socket_config: TcpSocketConfig,
     sender: Sender<(Multiaddr, WsStream)>,
     pending_stream: Receiver<(Multiaddr, WsStream)>,
 }
 
 impl WebsocketListener {
-    fn new(timeout: Duration, listen: TcpListener) -> Self {
+    fn new(timeout: Duration, listen: TcpListener, socket_config: TcpSocketConfig) -> Self {
         let (sender, rx) = channel(24);
         WebsocketListener {
             inner: listen,
             timeout,
+            socket_config,
             sender,
             pending_stream: rx,
         }
@@ -180,6 +213,9 @@ impl Stream for WebsocketListener {
         match self.inner.poll_accept(cx)? {
             Poll::Ready((stream, _)) => match stream.peer_addr() {
                 Ok(remote_address) => {
+                    if let Err(err) = set_socket_options(&stream, &self.socket_config) {
+                        debug!("stream set socket options error: {:?}", err);
+                    }
                     let timeout = self.timeout;
                     let mut sender = self.sender.clone();
                     crate::runtime::spawn(async move {
@@ -220,6 +256,10 @@ pub struct WsStream {
 }
 
 impl WsStream {
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.get_ref().local_addr()
+    }
+
     fn new(inner: WebSocketStream<TcpStream>) -> Self {
         WsStream {
             inner,
@@ -398,3 +438,36 @@ impl AsyncWrite for WsStream {
             .map_err(|_| io::ErrorKind::BrokenPipe.into())
     }
 }
+
+#[cfg(all(test, feature = "tokio-runtime"))]
+mod test {
+    use super::WsTransport;
+    use crate::{service::TcpSocketConfig, transports::Transport};
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    #[test]
+    fn test_ws_socket_config() {
+        let config = TcpSocketConfig {
+            nodelay: true,
+            ..Default::default()
+        };
+        let transport = || WsTransport::new(Duration::from_secs(5), None).socket_config(config);
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (addr, mut incoming) = transport()
+                .listen("/ip4/127.0.0.1/tcp/0/ws".parse().unwrap())
+                .unwrap()
+                .await
+                .unwrap();
+
+            let (_, stream) = transport().dial(addr).unwrap().await.unwrap();
+            assert!(stream.inner.get_ref().nodelay().unwrap());
+            match incoming.next().await {
+                Some(Ok((_, stream))) => assert!(stream.inner.get_ref().nodelay().unwrap()),
+                _ => panic!("the ws stream should be accepted"),
+            }
+        });
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     collections::HashSet,
-    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
     time::{Duration, Instant},
 };
 
@@ -8,20 +9,10 @@ use log::debug;
 
 use crate::{
     multiaddr::Multiaddr,
-    utils::{is_reachable, multiaddr_to_socketaddr},
+    utils::{interfaces::interfaces, is_reachable, multiaddr_to_socketaddr},
 };
-
-#[cfg(not(windows))]
-use self::unix::get_local_net_state;
-#[cfg(windows)]
-use self::windows::get_local_net_state;
 use std::collections::HashMap;
 
-#[cfg(not(windows))]
-mod unix;
-#[cfg(windows)]
-mod windows;
-
 #[derive(Copy, Clone, Debug)]
 pub struct Network {
     /// local address
@@ -86,6 +77,11 @@ impl IgdClient {
         })
     }
 
+    /// Gateway address
+    pub fn gateway(&self) -> SocketAddr {
+        SocketAddr::V4(self.gateway.addr)
+    }
+
     /// Register ip
     pub fn register(&mut self, address: &Multiaddr) {
         if let Some(addr) = multiaddr_to_socketaddr(address) {
@@ -181,6 +177,25 @@ impl Drop for IgdClient {
 }
 
 /// Return `true` if two addresses are in the same subnet
+/// Get machine local network status
+fn get_local_net_state() -> io::Result<Vec<Network>> {
+    Ok(interfaces()?
+        .into_iter()
+        .filter_map(|interface| match (interface.address, interface.net_mask) {
+            // Filter docker virtual NIC and lo
+            (IpAddr::V4(address), Some(IpAddr::V4(net_mask)))
+                if !address.is_loopback()
+                    && !interface.name.to_lowercase().starts_with("docker") =>
+            {
+                Some(Network { address, net_mask })
+            }
+            // Why ignore ipv6?
+            // Because igd does not support ipv6
+            _ => None,
+        })
+        .collect())
+}
+
 fn in_same_subnet(addr1: Ipv4Addr, addr2: Ipv4Addr, subnet_mask: Ipv4Addr) -> bool {
     addr1
         .octets()
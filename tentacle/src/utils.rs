@@ -7,9 +7,13 @@ use std::{
     net::{IpAddr, SocketAddr},
 };
 
+/// Compression of the protocol messages
+pub mod compress;
 /// This module create a `DnsResolver` future task to DNS resolver
 #[cfg(not(target_arch = "wasm32"))]
 pub mod dns;
+/// This module enumerates the ip addresses of the local network interfaces
+pub(crate) mod interfaces;
 
 /// Check if the ip address is reachable.
 /// Copy from std::net::IpAddr::is_global
@@ -101,6 +105,32 @@ pub fn socketaddr_to_multiaddr(address: SocketAddr) -> Multiaddr {
         .collect()
 }
 
+/// Replace the unspecified ip(`0.0.0.0`/`::`) of the address with each ip of the same family,
+/// the address is returned as is if it isn't unspecified or there is no such ip
+pub(crate) fn expand_unspecified(addr: &Multiaddr, ips: &[IpAddr]) -> Vec<Multiaddr> {
+    let unspecified = match addr.iter().next() {
+        Some(Protocol::Ip4(ip)) if ip.is_unspecified() => IpAddr::V4(ip),
+        Some(Protocol::Ip6(ip)) if ip.is_unspecified() => IpAddr::V6(ip),
+        _ => return vec![addr.clone()],
+    };
+    let expanded = ips
+        .iter()
+        .filter(|ip| ip.is_ipv4() == unspecified.is_ipv4() && !ip.is_unspecified())
+        .map(|ip| {
+            let proto = match ip {
+                IpAddr::V4(ip) => Protocol::Ip4(*ip),
+                IpAddr::V6(ip) => Protocol::Ip6(*ip),
+            };
+            iter::once(proto).chain(addr.iter().skip(1)).collect()
+        })
+        .collect::<Vec<Multiaddr>>();
+    if expanded.is_empty() {
+        vec![addr.clone()]
+    } else {
+        expanded
+    }
+}
+
 /// Get peer id from multiaddr
 pub fn extract_peer_id(addr: &Multiaddr) -> Option<PeerId> {
     let mut iter = addr.iter();
@@ -119,7 +149,7 @@ mod test {
     use crate::{
         multiaddr::Multiaddr,
         secio::SecioKeyPair,
-        utils::{extract_peer_id, multiaddr_to_socketaddr},
+        utils::{expand_unspecified, extract_peer_id, multiaddr_to_socketaddr},
     };
 
     #[test]
@@ -164,4 +194,32 @@ mod test {
             .unwrap();
         multiaddr_to_socketaddr(&addr).unwrap();
     }
+
+    #[test]
+    fn expand_unspecified_addr() {
+        let ips = vec![
+            "127.0.0.1".parse().unwrap(),
+            "192.168.1.2".parse().unwrap(),
+            "::1".parse().unwrap(),
+        ];
+        let addr: Multiaddr = "/ip4/0.0.0.0/tcp/1337".parse().unwrap();
+        assert_eq!(
+            expand_unspecified(&addr, &ips),
+            vec![
+                "/ip4/127.0.0.1/tcp/1337".parse::<Multiaddr>().unwrap(),
+                "/ip4/192.168.1.2/tcp/1337".parse().unwrap(),
+            ]
+        );
+
+        let addr: Multiaddr = "/ip6/::/tcp/1337/ws".parse().unwrap();
+        assert_eq!(
+            expand_unspecified(&addr, &ips),
+            vec!["/ip6/::1/tcp/1337/ws".parse::<Multiaddr>().unwrap()]
+        );
+
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/1337".parse().unwrap();
+        assert_eq!(expand_unspecified(&addr, &ips), vec![addr.clone()]);
+        let addr: Multiaddr = "/ip4/0.0.0.0/tcp/1337".parse().unwrap();
+        assert_eq!(expand_unspecified(&addr, &[]), vec![addr.clone()]);
+    }
 }
@@ -0,0 +1,125 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use std::io;
+
+/// The message is stored as is
+const FLAG_RAW: u8 = 0;
+/// The message is compressed by snappy
+const FLAG_SNAPPY: u8 = 1;
+
+/// Compression algorithm of the messages of a protocol, see `MetaBuilder::compress`
+///
+/// Every message is prefixed with a flag byte of how it's stored,
+/// so the peers of different algorithms can still read the messages of each other
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CompressionAlgo {
+    /// Send the messages uncompressed
+    None,
+    /// Compress the messages by snappy, the ones not getting smaller are sent uncompressed
+    Snappy,
+}
+
+impl CompressionAlgo {
+    /// Compress the message and prefix it with the flag
+    pub fn compress(self, data: Bytes) -> Bytes {
+        if let CompressionAlgo::Snappy = self {
+            if let Ok(compressed) = snap::raw::Encoder::new().compress_vec(&data) {
+                if compressed.len() < data.len() {
+                    return with_flag(FLAG_SNAPPY, &compressed);
+                }
+            }
+        }
+        with_flag(FLAG_RAW, &data)
+    }
+
+    /// Decompress the message by its flag, whatever the local algorithm is
+    ///
+    /// The message whose decompressed length claimed by the header is larger than `max_len`
+    /// is rejected before the buffer of that length is allocated
+    pub fn decompress(data: BytesMut, max_len: usize) -> Result<Bytes, io::Error> {
+        let mut data = data.freeze();
+        if data.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "compression flag is missing",
+            ));
+        }
+        let flag = data.split_to(1)[0];
+        match flag {
+            FLAG_RAW => Ok(data),
+            FLAG_SNAPPY => {
+                let len = snap::raw::decompress_len(&data)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                if len > max_len {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("decompressed length {} exceeds the limit {}", len, max_len),
+                    ));
+                }
+                snap::raw::Decoder::new()
+                    .decompress_vec(&data)
+                    .map(Bytes::from)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+            }
+            flag => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown compression flag {}", flag),
+            )),
+        }
+    }
+}
+
+fn with_flag(flag: u8, data: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(data.len() + 1);
+    buf.put_u8(flag);
+    buf.put_slice(data);
+    buf.freeze()
+}
+
+#[cfg(test)]
+mod test {
+    use super::CompressionAlgo;
+    use bytes::{Bytes, BytesMut};
+
+    const MAX_LEN: usize = 8 * 1024 * 1024;
+
+    #[test]
+    fn test_compress_round_trip() {
+        let large = Bytes::from(vec![7u8; 1024]);
+        let small = Bytes::from_static(b"hi");
+
+        let compressed = CompressionAlgo::Snappy.compress(large.clone());
+        assert!(compressed.len() < large.len());
+        assert_eq!(
+            CompressionAlgo::decompress(BytesMut::from(&compressed[..]), MAX_LEN).unwrap(),
+            large
+        );
+
+        // would expand, stored uncompressed
+        let stored = CompressionAlgo::Snappy.compress(small.clone());
+        assert_eq!(stored.len(), small.len() + 1);
+        assert_eq!(
+            CompressionAlgo::decompress(BytesMut::from(&stored[..]), MAX_LEN).unwrap(),
+            small
+        );
+
+        let plain = CompressionAlgo::None.compress(large.clone());
+        assert_eq!(
+            CompressionAlgo::decompress(BytesMut::from(&plain[..]), MAX_LEN).unwrap(),
+            large
+        );
+
+        assert!(CompressionAlgo::decompress(BytesMut::new(), MAX_LEN).is_err());
+        assert!(CompressionAlgo::decompress(BytesMut::from(&[9u8, 1][..]), MAX_LEN).is_err());
+    }
+
+    #[test]
+    fn test_decompress_forged_length() {
+        // snappy header claiming 1GB followed by no data
+        let forged = [1u8, 0x80, 0x80, 0x80, 0x80, 0x04];
+        let err = CompressionAlgo::decompress(BytesMut::from(&forged[..]), MAX_LEN).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        let large = CompressionAlgo::Snappy.compress(Bytes::from(vec![7u8; 1024]));
+        assert!(CompressionAlgo::decompress(BytesMut::from(&large[..]), 512).is_err());
+    }
+}
@@ -3,7 +3,7 @@ use std::{
     borrow::Cow,
     future::Future,
     io,
-    net::{SocketAddr, ToSocketAddrs},
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
     pin::Pin,
     task::{Context, Poll},
     vec::IntoIter,
@@ -11,16 +11,20 @@ use std::{
 
 use crate::{
     multiaddr::{Multiaddr, Protocol},
-    secio::PeerId,
+    traits::TxtResolver,
     transports::{find_type, TransportType},
-    utils::{extract_peer_id, socketaddr_to_multiaddr},
+    utils::socketaddr_to_multiaddr,
 };
 
+/// Max TXT lookups to resolve a `/dnsaddr` address, the nested ones included
+const MAX_DNSADDR_LOOKUPS: usize = 32;
+
 /// DNS resolver, use on multi-thread tokio runtime
 pub struct DnsResolver {
     source_address: Multiaddr,
     ty: TransportType,
-    peer_id: Option<PeerId>,
+    /// Raw bytes of the `/p2p/` part, kept as is whatever the peer id scheme is
+    peer_id: Option<Vec<u8>>,
     port: u16,
     domain: String,
     join_handle: Option<crate::runtime::JoinHandle<::std::io::Result<IntoIter<SocketAddr>>>>,
@@ -58,7 +62,10 @@ impl DnsResolver {
         match (domain, port) {
             (Some(domain), Some(port)) => Some(DnsResolver {
                 ty: find_type(&source_address),
-                peer_id: extract_peer_id(&source_address),
+                peer_id: source_address.iter().find_map(|proto| match proto {
+                    Protocol::P2P(raw_bytes) => Some(raw_bytes.into_owned()),
+                    _ => None,
+                }),
                 domain: domain.to_string(),
                 source_address,
                 port,
@@ -79,10 +86,12 @@ impl DnsResolver {
                     TransportType::Tcp | TransportType::Memory | TransportType::Tls => (),
                     TransportType::Ws => address.push(Protocol::Ws),
                     TransportType::Wss => address.push(Protocol::Wss),
+                    // quic dials the ip addresses only, never resolved here
+                    TransportType::Quic => (),
                 }
 
                 if let Some(peer_id) = self.peer_id.take() {
-                    address.push(Protocol::P2P(Cow::Owned(peer_id.into_bytes())))
+                    address.push(Protocol::P2P(Cow::Owned(peer_id)))
                 }
                 Poll::Ready(Ok(address))
             }
@@ -92,6 +101,153 @@ impl DnsResolver {
             ))),
         }
     }
+
+    /// Resolve all the addresses of the domain, the dns part of source address is replaced
+    /// by each ip, other parts such as ws/tls/p2p are kept as is, the duplicates are removed
+    pub async fn resolve_all(self) -> Result<Vec<Multiaddr>, (Multiaddr, io::Error)> {
+        let domain = self.domain.clone();
+        let port = self.port;
+        let handle = crate::runtime::spawn_blocking(move || (&domain[..], port).to_socket_addrs());
+
+        #[cfg(feature = "tokio-runtime")]
+        let res = match handle.await {
+            Ok(res) => res,
+            Err(e) => Err(e.into()),
+        };
+        #[cfg(feature = "async-runtime")]
+        let res = handle.await;
+
+        let mut addresses: Vec<Multiaddr> = Vec::new();
+        for socket_address in res.map_err(|e| (self.source_address.clone(), e))? {
+            let address = self.replace_domain(socket_address);
+            if !addresses.contains(&address) {
+                addresses.push(address)
+            }
+        }
+
+        if addresses.is_empty() {
+            Err((self.source_address, io::ErrorKind::InvalidData.into()))
+        } else {
+            Ok(addresses)
+        }
+    }
+
+    fn replace_domain(&self, socket_address: SocketAddr) -> Multiaddr {
+        self.source_address
+            .iter()
+            .map(|proto| match proto {
+                Protocol::Dns4(_) | Protocol::Dns6(_) => match socket_address.ip() {
+                    IpAddr::V4(ip) => Protocol::Ip4(ip),
+                    IpAddr::V6(ip) => Protocol::Ip6(ip),
+                },
+                Protocol::Tcp(_) => Protocol::Tcp(socket_address.port()),
+                proto => proto,
+            })
+            .collect()
+    }
+}
+
+/// Domain of the address if it starts with `/dnsaddr`
+pub(crate) fn dnsaddr_domain(address: &Multiaddr) -> Option<String> {
+    match address.iter().next() {
+        Some(Protocol::Dnsaddr(domain)) => Some(domain.into_owned()),
+        _ => None,
+    }
+}
+
+/// Resolve the `/dnsaddr/<domain>` address by the `dnsaddr=<multiaddr>` TXT records of
+/// `_dnsaddr.<domain>`, the nested `/dnsaddr` ones are resolved the same way.
+///
+/// If the address has a `/p2p/` part, only the addresses of the same peer are kept.
+/// The error is reported with the source address.
+pub async fn resolve_dnsaddr(
+    resolver: &dyn TxtResolver,
+    source_address: Multiaddr,
+) -> Result<Vec<Multiaddr>, (Multiaddr, io::Error)> {
+    let peer_id = source_address.iter().find_map(|proto| match proto {
+        Protocol::P2P(raw_bytes) => Some(raw_bytes.into_owned()),
+        _ => None,
+    });
+
+    let mut pending = vec![source_address.clone()];
+    let mut addresses: Vec<Multiaddr> = Vec::new();
+    let mut lookups = 0;
+    while let Some(address) = pending.pop() {
+        let domain = match dnsaddr_domain(&address) {
+            Some(domain) => domain,
+            None => {
+                if !addresses.contains(&address) {
+                    addresses.push(address)
+                }
+                continue;
+            }
+        };
+        if lookups == MAX_DNSADDR_LOOKUPS {
+            return Err((
+                source_address,
+                io::Error::new(io::ErrorKind::Other, "too many nested dnsaddr lookups"),
+            ));
+        }
+        lookups += 1;
+
+        let records = resolver
+            .resolve_txt(&format!("_dnsaddr.{}", domain))
+            .await
+            .map_err(|e| (source_address.clone(), e))?;
+        // the records are popped from the back, reverse them to dial in order
+        for record in records.iter().rev() {
+            let address = match record
+                .strip_prefix("dnsaddr=")
+                .and_then(|address| address.parse::<Multiaddr>().ok())
+            {
+                Some(address) => address,
+                None => continue,
+            };
+            let same_peer = peer_id.as_ref().map_or(true, |peer_id| {
+                address.iter().any(|proto| match proto {
+                    Protocol::P2P(raw_bytes) => raw_bytes.as_ref() == peer_id.as_slice(),
+                    _ => false,
+                })
+            });
+            if same_peer {
+                pending.push(address)
+            }
+        }
+    }
+
+    if addresses.is_empty() {
+        Err((source_address, io::ErrorKind::InvalidData.into()))
+    } else {
+        Ok(addresses)
+    }
+}
+
+/// TXT lookup by the system dns config
+#[cfg(feature = "dnsaddr")]
+pub struct SystemTxtResolver;
+
+#[cfg(feature = "dnsaddr")]
+impl TxtResolver for SystemTxtResolver {
+    fn resolve_txt(&self, domain: &str) -> crate::traits::TxtFuture {
+        let domain = domain.to_owned();
+        Box::pin(async move {
+            let resolver = trust_dns_resolver::TokioAsyncResolver::tokio_from_system_conf()
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            let lookup = resolver
+                .txt_lookup(domain)
+                .await
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            Ok(lookup
+                .iter()
+                .map(|txt| {
+                    txt.txt_data()
+                        .iter()
+                        .map(|data| String::from_utf8_lossy(data))
+                        .collect::<String>()
+                })
+                .collect())
+        })
+    }
 }
 
 impl Future for DnsResolver {
@@ -140,8 +296,20 @@ impl Future for DnsResolver {
 mod test {
     use crate::{
         multiaddr::{Multiaddr, Protocol},
-        utils::dns::DnsResolver,
+        secio::SecioKeyPair,
+        traits::{TxtFuture, TxtResolver},
+        utils::dns::{resolve_dnsaddr, DnsResolver},
     };
+    use std::{borrow::Cow, collections::HashMap};
+
+    struct StubResolver(HashMap<String, Vec<String>>);
+
+    impl TxtResolver for StubResolver {
+        fn resolve_txt(&self, domain: &str) -> TxtFuture {
+            let records = self.0.get(domain).cloned().unwrap_or_default();
+            Box::pin(async move { Ok(records) })
+        }
+    }
 
     #[test]
     fn dns_parser() {
@@ -157,4 +325,75 @@ mod test {
             _ => panic!("Dns resolver fail"),
         }
     }
+
+    #[test]
+    fn dns_resolve_all() {
+        let mut address: Multiaddr = "/dns4/localhost/tcp/80/ws".parse().unwrap();
+        address.push(Protocol::P2P(Cow::Owned(
+            SecioKeyPair::secp256k1_generated().peer_id().into_bytes(),
+        )));
+        let resolver = DnsResolver::new(address).unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let addresses = rt.block_on(resolver.resolve_all()).unwrap();
+        assert!(!addresses.is_empty());
+        for addr in addresses {
+            let mut iter = addr.iter();
+            assert!(matches!(
+                iter.next(),
+                Some(Protocol::Ip4(_)) | Some(Protocol::Ip6(_))
+            ));
+            assert_eq!(iter.next(), Some(Protocol::Tcp(80)));
+            assert_eq!(iter.next(), Some(Protocol::Ws));
+            assert!(matches!(iter.next(), Some(Protocol::P2P(_))));
+        }
+    }
+
+    #[test]
+    fn dnsaddr_resolve_nested() {
+        let peer_id = SecioKeyPair::secp256k1_generated().peer_id().to_base58();
+        let other = SecioKeyPair::secp256k1_generated().peer_id().to_base58();
+        let mut records = HashMap::new();
+        records.insert(
+            "_dnsaddr.bootstrap.example.com".to_owned(),
+            vec![
+                format!("dnsaddr=/dnsaddr/sjc.bootstrap.example.com/p2p/{}", peer_id),
+                format!("dnsaddr=/ip4/1.2.3.4/tcp/1234/p2p/{}", other),
+                "unrelated=record".to_owned(),
+            ],
+        );
+        records.insert(
+            "_dnsaddr.sjc.bootstrap.example.com".to_owned(),
+            vec![
+                format!("dnsaddr=/ip4/1.2.3.4/tcp/4001/p2p/{}", peer_id),
+                format!("dnsaddr=/ip6/::1/tcp/4001/p2p/{}", peer_id),
+            ],
+        );
+        let resolver = StubResolver(records);
+        let address: Multiaddr = format!("/dnsaddr/bootstrap.example.com/p2p/{}", peer_id)
+            .parse()
+            .unwrap();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let addresses = rt
+            .block_on(resolve_dnsaddr(&resolver, address.clone()))
+            .unwrap();
+        // the address of the other peer is filtered out
+        assert_eq!(
+            addresses,
+            vec![
+                format!("/ip4/1.2.3.4/tcp/4001/p2p/{}", peer_id)
+                    .parse::<Multiaddr>()
+                    .unwrap(),
+                format!("/ip6/::1/tcp/4001/p2p/{}", peer_id)
+                    .parse::<Multiaddr>()
+                    .unwrap(),
+            ]
+        );
+
+        let unknown: Multiaddr = "/dnsaddr/unknown.example.com".parse().unwrap();
+        let (source, _) = rt
+            .block_on(resolve_dnsaddr(&resolver, unknown.clone()))
+            .unwrap_err();
+        assert_eq!(source, unknown);
+    }
 }
@@ -0,0 +1,49 @@
+use std::{io, net::IpAddr};
+
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(unix)]
+pub use self::unix::interfaces;
+#[cfg(windows)]
+pub use self::windows::interfaces;
+
+/// An address of a local network interface
+#[derive(Clone, Debug)]
+pub struct Interface {
+    /// Interface name, the friendly name on windows
+    pub name: String,
+    /// Whether the interface is up
+    pub up: bool,
+    /// Address of the interface
+    pub address: IpAddr,
+    /// Subnet mask of the address
+    pub net_mask: Option<IpAddr>,
+}
+
+/// Interfaces enumeration is only supported on unix and windows
+#[cfg(not(any(unix, windows)))]
+pub fn interfaces() -> io::Result<Vec<Interface>> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "interfaces enumeration is not supported on this platform",
+    ))
+}
+
+/// Get the ip addresses of the local network interfaces which are up,
+/// ipv6 link local addresses are ignored, because they can't be dialed without a scope
+pub fn local_ip_addrs() -> io::Result<Vec<IpAddr>> {
+    let mut result = Vec::new();
+    for interface in interfaces()? {
+        let link_local = match interface.address {
+            IpAddr::V6(ip) => ip.segments()[0] & 0xffc0 == 0xfe80,
+            IpAddr::V4(_) => false,
+        };
+        if interface.up && !link_local && !result.contains(&interface.address) {
+            result.push(interface.address);
+        }
+    }
+    Ok(result)
+}
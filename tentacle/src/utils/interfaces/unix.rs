@@ -0,0 +1,75 @@
+use std::{
+    ffi, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    ptr,
+};
+
+use libc::{
+    freeifaddrs, getifaddrs, ifaddrs, sockaddr, sockaddr_in, sockaddr_in6, AF_INET, AF_INET6,
+    IFF_UP,
+};
+
+use super::Interface;
+
+/// Get the addresses of the local network interfaces
+pub fn interfaces() -> io::Result<Vec<Interface>> {
+    let mut p_ifa: *mut ifaddrs = ptr::null_mut();
+    if unsafe { getifaddrs(&mut p_ifa) } != 0 {
+        return Err(io::Error::new(io::ErrorKind::Other, "getifaddrs() failed"));
+    }
+
+    // free it when leave this function
+    let top_ptr = p_ifa;
+
+    let mut result = Vec::new();
+
+    while !p_ifa.is_null() {
+        let ifa = unsafe { *p_ifa };
+        if let Some(address) = parse_addr(ifa.ifa_addr) {
+            let name = unsafe { ffi::CStr::from_ptr(ifa.ifa_name).to_string_lossy() };
+            result.push(Interface {
+                name: name.into_owned(),
+                up: ifa.ifa_flags & IFF_UP as libc::c_uint != 0,
+                address,
+                net_mask: parse_net_mask(ifa.ifa_netmask, address),
+            });
+        }
+        p_ifa = unsafe { (*p_ifa).ifa_next };
+    }
+
+    unsafe { freeifaddrs(top_ptr) };
+    Ok(result)
+}
+
+/// parse ptr to std struct
+fn parse_addr(p_sock: *const sockaddr) -> Option<IpAddr> {
+    if p_sock.is_null() {
+        return None;
+    }
+    match i32::from(unsafe { (*p_sock).sa_family }) {
+        AF_INET => Some(IpAddr::V4(parse_v4(p_sock))),
+        AF_INET6 => Some(IpAddr::V6(parse_v6(p_sock))),
+        _ => None,
+    }
+}
+
+/// The family of an ipv4 mask isn't always set on bsd, it follows the address
+fn parse_net_mask(p_sock: *const sockaddr, address: IpAddr) -> Option<IpAddr> {
+    if p_sock.is_null() {
+        return None;
+    }
+    match address {
+        IpAddr::V4(_) => Some(IpAddr::V4(parse_v4(p_sock))),
+        IpAddr::V6(_) => parse_addr(p_sock),
+    }
+}
+
+fn parse_v4(p_sock: *const sockaddr) -> Ipv4Addr {
+    let addr = unsafe { *(p_sock as *const sockaddr_in) };
+    Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr))
+}
+
+fn parse_v6(p_sock: *const sockaddr) -> Ipv6Addr {
+    let addr = unsafe { *(p_sock as *const sockaddr_in6) };
+    Ipv6Addr::from(addr.sin6_addr.s6_addr)
+}
@@ -3,7 +3,12 @@
 /// To write this code, I looked at the code that includes the crates like
 /// `ipconfig`/`systemstat`/`get_if_addr` and flipped through the documentation for mdns.
 ///
-use std::{io, net::Ipv4Addr, ptr, slice::from_raw_parts};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr},
+    ptr,
+    slice::from_raw_parts,
+};
 
 use winapi::ctypes::*;
 use winapi::{
@@ -16,10 +21,12 @@ use winapi::{
     um::heapapi::{GetProcessHeap, HeapAlloc, HeapFree},
 };
 
-use crate::upnp::Network;
+use super::Interface;
 
 const MAX_ADAPTER_ADDRESS_LENGTH: usize = 8;
 const WORKING_BUFFER_SIZEL: SIZE_T = 15000;
+/// `IfOperStatusUp` of `IF_OPER_STATUS`
+const OPER_STATUS_UP: c_int = 1;
 
 #[repr(C)]
 struct LengthIfIndex {
@@ -99,7 +106,8 @@ extern "system" {
     ) -> ULONG;
 }
 
-pub fn get_local_net_state() -> io::Result<Vec<Network>> {
+/// Get the ipv4 addresses of the local network interfaces
+pub fn interfaces() -> io::Result<Vec<Interface>> {
     let mut new_size: ULONG = WORKING_BUFFER_SIZEL as ULONG;
     // free it when leave this function
     let mut p_adapter: *mut IpAdapterAddresses;
@@ -142,21 +150,18 @@ pub fn get_local_net_state() -> io::Result<Vec<Network>> {
     unsafe {
         let mut cur_p_adapter = p_adapter;
         while !cur_p_adapter.is_null() {
-            let friendly_name = u16_array_to_string((*cur_p_adapter).friendly_name).to_lowercase();
-            // Filter docker virtual NIC and lo
-            if friendly_name.starts_with("loopback") || friendly_name.starts_with("docker") {
-                cur_p_adapter = (*cur_p_adapter).next;
-                continue;
-            }
+            let friendly_name = u16_array_to_string((*cur_p_adapter).friendly_name);
+            let up = (*cur_p_adapter).oper_status == OPER_STATUS_UP;
 
             // ip
             let mut cur_p_addr = (*cur_p_adapter).first_unicass_address;
             while !cur_p_addr.is_null() {
                 if let Some(address) = parse_addr((*cur_p_addr).address.lp_sockaddr) {
-                    result.push(Network {
-                        address,
-                        net_mask: netmask_v4((*cur_p_addr).on_link_prefix_length)
-                            .expect("Invalid subnet mask"),
+                    result.push(Interface {
+                        name: friendly_name.clone(),
+                        up,
+                        address: IpAddr::V4(address),
+                        net_mask: netmask_v4((*cur_p_addr).on_link_prefix_length).map(IpAddr::V4),
                     });
                 }
 
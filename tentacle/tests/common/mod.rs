@@ -0,0 +1,32 @@
+use futures::StreamExt;
+use std::{sync::mpsc::channel, thread};
+use tentacle::{multiaddr::Multiaddr, service::Service, traits::ServiceHandle};
+
+/// Run the service on its own runtime thread until it shuts down,
+/// listen on the address first and return the address listened
+pub fn start_service<F>(mut service: Service<F>, listen: Option<Multiaddr>) -> Option<Multiaddr>
+where
+    F: ServiceHandle + Unpin + Send + 'static,
+{
+    let (addr_sender, addr_receiver) = channel::<Multiaddr>();
+    let wait_listen = listen.is_some();
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            if let Some(addr) = listen {
+                let listen_addr = service.listen(addr).await.unwrap();
+                addr_sender.send(listen_addr).unwrap();
+            }
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+    if wait_listen {
+        addr_receiver.recv().ok()
+    } else {
+        None
+    }
+}
@@ -141,3 +141,98 @@ fn test_before_with_secio() {
 fn test_before_with_no_secio() {
     test_before_handle(false)
 }
+
+/// Mask every byte on send and unmask it on receive
+fn mask(data: &[u8]) -> Bytes {
+    data.iter()
+        .map(|byte| byte ^ 0x5a)
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// The dialer sends a message once connected, the listener reports what it received
+struct RoundTrip {
+    sender: crossbeam_channel::Sender<Bytes>,
+}
+
+impl ServiceProtocol for RoundTrip {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.session.ty.is_outbound() {
+            let _res = context.send_message(Bytes::from("round trip"));
+        }
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        let _res = self.sender.send(data);
+    }
+}
+
+fn create_round_trip_meta(
+    before_send: bool,
+    before_receive: bool,
+    sender: crossbeam_channel::Sender<Bytes>,
+) -> ProtocolMeta {
+    let mut meta = MetaBuilder::new().id(1.into());
+    if before_send {
+        meta = meta.before_send(|data| mask(&data));
+    }
+    if before_receive {
+        meta = meta.before_receive(|| Some(Box::new(|data: bytes::BytesMut| Ok(mask(&data)))));
+    }
+    meta.service_handle(move || ProtocolHandle::Callback(Box::new(RoundTrip { sender })))
+        .build()
+}
+
+/// Return the message received by the listener
+fn round_trip(dialer_send: bool, listener_receive: bool) -> Bytes {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    let mut listener = create(
+        true,
+        create_round_trip_meta(false, listener_receive, sender.clone()),
+        (),
+    );
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let listen_addr = listener
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if listener.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let mut dialer = create(true, create_round_trip_meta(dialer_send, false, sender), ());
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            dialer.dial(listen_addr, TargetProtocol::All).await.unwrap();
+            loop {
+                if dialer.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    receiver
+        .recv_timeout(std::time::Duration::from_secs(5))
+        .unwrap()
+}
+
+#[test]
+fn test_before_receive_reverses_before_send() {
+    assert_eq!(round_trip(true, true), Bytes::from("round trip"));
+    // the data on the wire is the transformed one
+    assert_eq!(round_trip(true, false), mask(b"round trip"));
+}
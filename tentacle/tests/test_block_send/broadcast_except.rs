@@ -0,0 +1,98 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    bytes::Bytes,
+    context::{ProtocolContext, ProtocolContextMutRef},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, TargetProtocol, TargetSession},
+    traits::ServiceProtocol,
+    SessionId,
+};
+
+#[derive(Debug, PartialEq)]
+enum Report {
+    Connected(SessionId),
+    Received(Bytes),
+}
+
+struct PHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        let _res = self.sender.send(Report::Connected(context.session.id));
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        let _res = self.sender.send(Report::Received(data));
+    }
+}
+
+fn create(sender: crossbeam_channel::Sender<Report>) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { sender })))
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+fn recv(receiver: &crossbeam_channel::Receiver<Report>) -> Option<Report> {
+    receiver.recv_timeout(Duration::from_secs(3)).ok()
+}
+
+/// Connect a new service to the listener, return its receiver and the id of its session on the listener
+fn connect(
+    listen_addr: &Multiaddr,
+    receiver: &crossbeam_channel::Receiver<Report>,
+) -> (crossbeam_channel::Receiver<Report>, SessionId) {
+    let (sender, peer_receiver) = crossbeam_channel::unbounded();
+    let service = create(sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control
+        .dial(listen_addr.clone(), TargetProtocol::All)
+        .unwrap();
+
+    let id = match recv(receiver) {
+        Some(Report::Connected(id)) => id,
+        report => panic!("unexpected report {:?}", report),
+    };
+    assert!(matches!(recv(&peer_receiver), Some(Report::Connected(_))));
+    (peer_receiver, id)
+}
+
+#[test]
+fn test_broadcast_except() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(sender);
+    let control = service.control().clone();
+    let listen_addr =
+        start_service(service, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let (excluded_receiver, excluded_id) = connect(&listen_addr, &receiver);
+    let (other_receiver, _) = connect(&listen_addr, &receiver);
+
+    control
+        .filter_broadcast(
+            TargetSession::Except(vec![excluded_id]),
+            1.into(),
+            Bytes::from("gossip"),
+        )
+        .unwrap();
+
+    assert_eq!(
+        recv(&other_receiver),
+        Some(Report::Received(Bytes::from("gossip")))
+    );
+    assert_eq!(recv(&excluded_receiver), None);
+}
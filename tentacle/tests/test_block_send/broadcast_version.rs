@@ -0,0 +1,130 @@
+use crate::common::start_service;
+use bytes::Bytes;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, TargetProtocol, TargetSession},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+pub fn create<F>(meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    ServiceBuilder::default()
+        .insert_protocol(meta)
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(shandle)
+}
+
+/// Send the upgrade frame to v2 sessions only, then an end frame to all sessions
+struct Broadcaster {
+    connected: usize,
+}
+
+impl ServiceProtocol for Broadcaster {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        self.connected += 1;
+        if self.connected == 2 {
+            let proto_id = context.proto_id;
+            let _res = context.broadcast_version(proto_id, "2.0.0", Bytes::from("v2"));
+            let _res = context.filter_broadcast(TargetSession::All, proto_id, Bytes::from("end"));
+        }
+    }
+}
+
+/// Collect received messages until the end frame
+struct Receiver {
+    received: Vec<Bytes>,
+    sender: crossbeam_channel::Sender<(String, Vec<Bytes>)>,
+    version: String,
+}
+
+impl ServiceProtocol for Receiver {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, version: &str) {
+        assert_eq!(
+            context
+                .session
+                .protocol_version(context.proto_id)
+                .as_deref(),
+            Some(version)
+        );
+        self.version = version.to_owned();
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        let end = data == Bytes::from("end");
+        self.received.push(data);
+        if end {
+            let _res = self
+                .sender
+                .send((self.version.clone(), ::std::mem::take(&mut self.received)));
+        }
+    }
+}
+
+fn create_meta<F>(versions: &[&str], handle: F) -> ProtocolMeta
+where
+    F: Fn() -> Box<dyn ServiceProtocol + Send + Unpin> + Send + Sync + 'static,
+{
+    MetaBuilder::new()
+        .id(ProtocolId::new(1))
+        .support_versions(versions.iter().map(|v| (*v).to_owned()).collect())
+        .service_handle(move || ProtocolHandle::Callback(handle()))
+        .build()
+}
+
+#[test]
+fn test_broadcast_version() {
+    let meta = create_meta(&["1.0.0", "2.0.0"], || {
+        Box::new(Broadcaster { connected: 0 })
+    });
+    let listen_addr = start_service(
+        create(meta, ()),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (sender, receiver) = crossbeam_channel::bounded(2);
+    for versions in [&["1.0.0"][..], &["1.0.0", "2.0.0"][..]].iter() {
+        let sender = sender.clone();
+        let meta = create_meta(versions, move || {
+            Box::new(Receiver {
+                received: Vec::new(),
+                sender: sender.clone(),
+                version: String::new(),
+            })
+        });
+        let service = create(meta, ());
+        let control = service.control().clone();
+        start_service(service, None);
+        control
+            .dial(listen_addr.clone(), TargetProtocol::All)
+            .unwrap();
+    }
+
+    let mut results = vec![
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+    ];
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+
+    assert_eq!(
+        results,
+        vec![
+            ("1.0.0".to_owned(), vec![Bytes::from("end")]),
+            (
+                "2.0.0".to_owned(),
+                vec![Bytes::from("v2"), Bytes::from("end")]
+            ),
+        ]
+    );
+}
@@ -0,0 +1,90 @@
+use crate::common::start_service;
+use bytes::Bytes;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, TargetProtocol},
+    traits::ServiceProtocol,
+};
+
+const SMALL: usize = 64;
+
+/// The listener sends numbered messages, every 50th of them larger than the coalesced size,
+/// the dialer reports what it receives
+struct PHandle {
+    listener: bool,
+    sender: crossbeam_channel::Sender<Bytes>,
+}
+
+fn message(index: usize) -> Bytes {
+    let len = if index % 50 == 0 { SMALL * 4 } else { 8 };
+    let mut data = vec![(index % 256) as u8; len];
+    data[..8].copy_from_slice(&(index as u64).to_be_bytes());
+    Bytes::from(data)
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if self.listener {
+            for index in 0..500 {
+                context.send_message(message(index)).unwrap();
+            }
+        }
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        let _res = self.sender.send(data);
+    }
+}
+
+fn create(listener: bool, sender: crossbeam_channel::Sender<Bytes>) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .coalesce(Duration::from_millis(5), SMALL)
+                .service_handle(move || {
+                    ProtocolHandle::Callback(Box::new(PHandle {
+                        listener,
+                        sender: sender.clone(),
+                    }))
+                })
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+fn test_coalesce(listen: Multiaddr) {
+    let listen_addr =
+        start_service(create(true, crossbeam_channel::unbounded().0), Some(listen)).unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(false, sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    for index in 0..500 {
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok(message(index))
+        );
+    }
+}
+
+#[test]
+fn test_coalesce_keeps_message_boundaries_and_order_with_memory() {
+    test_coalesce("/memory/0".parse().unwrap())
+}
+
+#[test]
+fn test_coalesce_keeps_message_boundaries_and_order_with_tcp() {
+    test_coalesce("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+}
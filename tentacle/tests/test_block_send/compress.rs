@@ -0,0 +1,124 @@
+use crate::common::start_service;
+use bytes::Bytes;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, TargetProtocol},
+    traits::ServiceProtocol,
+    utils::compress::CompressionAlgo,
+};
+
+fn messages() -> Vec<Bytes> {
+    vec![
+        Bytes::from(vec![1u8; 64 * 1024]),
+        Bytes::from("small"),
+        Bytes::new(),
+    ]
+}
+
+/// Snappy flag and a header claiming 1GB decompressed, followed by no data
+const FORGED: &[u8] = &[1, 0x80, 0x80, 0x80, 0x80, 0x04];
+
+/// The listener sends the messages on connected, the dialer reports what it receives,
+/// and None when the protocol is closed
+struct PHandle {
+    listener: bool,
+    forged: bool,
+    sender: crossbeam_channel::Sender<Option<Bytes>>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if self.listener && self.forged {
+            context.send_message(Bytes::from_static(FORGED)).unwrap();
+        } else if self.listener {
+            for message in messages() {
+                context.send_message(message).unwrap();
+            }
+        }
+    }
+
+    fn disconnected(&mut self, _context: ProtocolContextMutRef) {
+        let _res = self.sender.send(None);
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        let _res = self.sender.send(Some(data));
+    }
+}
+
+/// The forging listener sends the messages as is
+fn create(
+    listener: bool,
+    algo: Option<CompressionAlgo>,
+    sender: crossbeam_channel::Sender<Option<Bytes>>,
+) -> Service<()> {
+    let meta = MetaBuilder::new().id(1.into());
+    let meta = match algo {
+        Some(algo) => meta.compress(algo),
+        None => meta,
+    };
+    ServiceBuilder::default()
+        .insert_protocol(
+            meta.service_handle(move || {
+                ProtocolHandle::Callback(Box::new(PHandle {
+                    listener,
+                    forged: algo.is_none(),
+                    sender: sender.clone(),
+                }))
+            })
+            .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+fn dial(
+    listener_algo: Option<CompressionAlgo>,
+    dialer_algo: CompressionAlgo,
+) -> crossbeam_channel::Receiver<Option<Bytes>> {
+    let listen_addr = start_service(
+        create(true, listener_algo, crossbeam_channel::unbounded().0),
+        Some("/memory/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(false, Some(dialer_algo), sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+    receiver
+}
+
+fn test_compress(listener_algo: CompressionAlgo, dialer_algo: CompressionAlgo) {
+    let receiver = dial(Some(listener_algo), dialer_algo);
+    for message in messages() {
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok(Some(message))
+        );
+    }
+}
+
+#[test]
+fn test_snappy_round_trip() {
+    test_compress(CompressionAlgo::Snappy, CompressionAlgo::Snappy)
+}
+
+#[test]
+fn test_snappy_to_uncompressed_peer() {
+    test_compress(CompressionAlgo::Snappy, CompressionAlgo::None)
+}
+
+#[test]
+fn test_forged_decompressed_length() {
+    let receiver = dial(None, CompressionAlgo::Snappy);
+    // rejected without reaching the handle, the sub stream is closed
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(None));
+}
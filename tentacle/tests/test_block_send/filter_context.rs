@@ -0,0 +1,95 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    bytes::Bytes,
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, TargetProtocol, TargetSession},
+    traits::ServiceProtocol,
+};
+
+#[derive(Debug, PartialEq)]
+enum Report {
+    Connected,
+    Received(Bytes),
+}
+
+struct PHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, _context: ProtocolContextMutRef, _version: &str) {
+        let _res = self.sender.send(Report::Connected);
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        let _res = self.sender.send(Report::Received(data));
+    }
+}
+
+fn create(sender: crossbeam_channel::Sender<Report>) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { sender })))
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+fn recv(receiver: &crossbeam_channel::Receiver<Report>) -> Option<Report> {
+    receiver.recv_timeout(Duration::from_secs(3)).ok()
+}
+
+#[test]
+fn test_broadcast_to_outbound_sessions() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(sender);
+    let control = service.control().clone();
+    let listen_addr = start_service(service, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()));
+
+    // connects to the service, its session is inbound on the service
+    let (inbound_sender, inbound_receiver) = crossbeam_channel::unbounded();
+    let inbound = create(inbound_sender);
+    let inbound_control = inbound.control().clone();
+    start_service(inbound, None);
+    inbound_control
+        .dial(listen_addr.unwrap(), TargetProtocol::All)
+        .unwrap();
+
+    // connected by the service, its session is outbound on the service
+    let (outbound_sender, outbound_receiver) = crossbeam_channel::unbounded();
+    let outbound_addr = start_service(
+        create(outbound_sender),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    );
+    control
+        .dial(outbound_addr.unwrap(), TargetProtocol::All)
+        .unwrap();
+
+    assert_eq!(recv(&receiver), Some(Report::Connected));
+    assert_eq!(recv(&receiver), Some(Report::Connected));
+    assert_eq!(recv(&inbound_receiver), Some(Report::Connected));
+    assert_eq!(recv(&outbound_receiver), Some(Report::Connected));
+
+    control
+        .filter_broadcast(
+            TargetSession::FilterContext(Box::new(|context| context.ty.is_outbound())),
+            1.into(),
+            Bytes::from("outbound only"),
+        )
+        .unwrap();
+
+    assert_eq!(
+        recv(&outbound_receiver),
+        Some(Report::Received(Bytes::from("outbound only")))
+    );
+    assert_eq!(recv(&inbound_receiver), None);
+}
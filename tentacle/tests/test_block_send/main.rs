@@ -1,3 +1,18 @@
+mod broadcast_except;
+mod broadcast_version;
+mod coalesce;
+#[path = "../common/mod.rs"]
+mod common;
+mod compress;
+mod filter_context;
+mod max_message_size;
+mod message_drop_log;
+mod message_latency;
+mod notify_now;
+mod pause_protocol_read;
+mod received_batch;
+mod reply;
+
 use bytes::Bytes;
 use futures::{channel, StreamExt};
 use std::{
@@ -0,0 +1,99 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    bytes::Bytes,
+    context::{ProtocolContext, ProtocolContextMutRef, ServiceContext},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceError, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+const MAX_MESSAGE_SIZE: usize = 1024;
+
+#[derive(Debug, PartialEq)]
+enum Report {
+    Received(usize),
+    ProtocolError(ProtocolId),
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::ProtocolError { proto_id, .. } = error {
+            let _res = self.sender.send(Report::ProtocolError(proto_id));
+        }
+    }
+}
+
+/// The dialer sends a message under the limit and one over it on connected,
+/// the listener reports the received sizes
+struct PHandle {
+    sender: Option<crossbeam_channel::Sender<Report>>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if self.sender.is_none() {
+            let _res = context.send_message(Bytes::from(vec![1u8; MAX_MESSAGE_SIZE]));
+            let _res = context.send_message(Bytes::from(vec![2u8; MAX_MESSAGE_SIZE + 1]));
+        }
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        if let Some(ref sender) = self.sender {
+            let _res = sender.send(Report::Received(data.len()));
+        }
+    }
+}
+
+fn create<F>(shandle: F, sender: Option<crossbeam_channel::Sender<Report>>) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let meta = MetaBuilder::new()
+        .id(1.into())
+        .max_message_size(MAX_MESSAGE_SIZE)
+        .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { sender })))
+        .build();
+    ServiceBuilder::default()
+        .insert_protocol(meta)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .forever(true)
+        .build(shandle)
+}
+
+#[test]
+fn test_max_message_size() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(
+            SHandle {
+                sender: sender.clone(),
+            },
+            Some(sender),
+        ),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let dialer = create((), None);
+    let control = dialer.control().clone();
+    start_service(dialer, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    // the reports come from different channels, their order isn't fixed
+    let reports = (0..2)
+        .map(|_| receiver.recv_timeout(Duration::from_secs(5)).unwrap())
+        .collect::<Vec<_>>();
+    assert!(reports.contains(&Report::Received(MAX_MESSAGE_SIZE)));
+    assert!(reports.contains(&Report::ProtocolError(1.into())));
+    // the oversized message never reaches the handle
+    assert!(receiver.recv_timeout(Duration::from_millis(500)).is_err());
+}
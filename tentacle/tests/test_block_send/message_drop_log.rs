@@ -0,0 +1,114 @@
+use crate::common::start_service;
+use bytes::Bytes;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef, ServiceContext},
+    secio::SecioKeyPair,
+    service::{DropReason, ProtocolHandle, ProtocolMeta, Service, ServiceError, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId, SessionId,
+};
+
+/// Report the dropped messages
+struct SHandle {
+    sender: crossbeam_channel::Sender<(SessionId, ProtocolId, DropReason)>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _env: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::MessageDropped {
+            id,
+            proto_id,
+            reason,
+        } = error
+        {
+            let _res = self.sender.send((id, proto_id, reason));
+        }
+    }
+}
+
+/// Send a message on protocol 2 when protocol 1 connected
+struct PHandle;
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        let _res = context.send_message_to(context.session.id, 2.into(), Bytes::from("drop"));
+    }
+}
+
+fn create_metas() -> Vec<ProtocolMeta> {
+    vec![
+        MetaBuilder::new()
+            .id(1.into())
+            .service_handle(|| ProtocolHandle::Callback(Box::new(PHandle)))
+            .build(),
+        MetaBuilder::new()
+            .id(2.into())
+            .service_handle(|| ProtocolHandle::None)
+            .build(),
+    ]
+}
+
+fn create<F>(shandle: F, sample: u64) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    create_metas()
+        .into_iter()
+        .fold(ServiceBuilder::default(), |builder, meta| {
+            builder.insert_protocol(meta)
+        })
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .message_drop_log(sample)
+        .build(shandle)
+}
+
+#[test]
+fn test_drop_log_sampled() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(SHandle { sender }, 3);
+    let control = service.control().clone();
+    start_service(service, None);
+
+    for _ in 0..7 {
+        control
+            .send_message_to(100.into(), 1.into(), Bytes::from("drop"))
+            .unwrap();
+    }
+
+    for _ in 0..3 {
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok((100.into(), 1.into(), DropReason::SessionNotFound))
+        );
+    }
+    assert!(receiver.recv_timeout(Duration::from_millis(500)).is_err());
+    assert_eq!(control.dropped_messages(), 7);
+}
+
+#[test]
+fn test_drop_on_protocol_not_open() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(SHandle { sender }, 1);
+    let control = service.control().clone();
+    let listen_addr =
+        start_service(service, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let service = create((), 0);
+    let dial_control = service.control().clone();
+    start_service(service, None);
+    dial_control
+        .dial(listen_addr, TargetProtocol::Single(1.into()))
+        .unwrap();
+
+    // the listener sends on protocol 2 which the dialer never opened
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok((1.into(), 2.into(), DropReason::ProtocolNotOpen))
+    );
+    assert_eq!(control.dropped_messages(), 1);
+}
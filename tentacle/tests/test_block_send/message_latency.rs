@@ -0,0 +1,94 @@
+use crate::common::start_service;
+use bytes::Bytes;
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceControl, TargetProtocol},
+    traits::ServiceProtocol,
+};
+
+const MESSAGE_COUNT: u64 = 10;
+
+/// Send some messages when connected
+struct PHandle;
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        for _ in 0..MESSAGE_COUNT {
+            let _res = context.send_message(Bytes::from("latency"));
+        }
+    }
+}
+
+fn create(message_latency: bool) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::Callback(Box::new(PHandle)))
+                .build(),
+        )
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(2.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .message_latency(message_latency)
+        .build(())
+}
+
+fn connect(message_latency: bool) -> ServiceControl {
+    let service = create(message_latency);
+    let control = service.control().clone();
+    let listen_addr =
+        start_service(service, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let service = create(false);
+    let dial_control = service.control().clone();
+    start_service(service, None);
+    dial_control.dial(listen_addr, TargetProtocol::All).unwrap();
+    control
+}
+
+#[test]
+fn test_message_latency() {
+    let control = connect(true);
+
+    let mut latency = Default::default();
+    for _ in 0..50 {
+        latency = futures::executor::block_on(control.message_latency()).unwrap();
+        if latency
+            .get(&1.into())
+            .map(|snapshot| snapshot.count == MESSAGE_COUNT)
+            .unwrap_or(false)
+        {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    assert_eq!(latency.len(), 2);
+    let snapshot = &latency[&1.into()];
+    assert_eq!(snapshot.count, MESSAGE_COUNT);
+    assert_eq!(
+        snapshot.buckets.iter().map(|(_, count)| count).sum::<u64>(),
+        MESSAGE_COUNT
+    );
+    assert_eq!(latency[&2.into()].count, 0);
+}
+
+#[test]
+fn test_message_latency_disabled() {
+    let control = connect(false);
+    thread::sleep(Duration::from_millis(500));
+
+    let latency = futures::executor::block_on(control.message_latency()).unwrap();
+    assert!(latency.is_empty());
+}
@@ -0,0 +1,51 @@
+use futures::StreamExt;
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ProtocolContext,
+    service::ProtocolHandle,
+    traits::ServiceProtocol,
+};
+
+struct PHandle {
+    sender: crossbeam_channel::Sender<u64>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, context: &mut ProtocolContext) {
+        context.notify_now(7).unwrap();
+    }
+
+    fn notify(&mut self, _context: &mut ProtocolContext, token: u64) {
+        let _res = self.sender.send(token);
+    }
+}
+
+#[test]
+fn test_notify_now_fires_once() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let mut service = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { sender })))
+                .build(),
+        )
+        .forever(true)
+        .build(());
+
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(7));
+    // no timer is set for the token
+    assert!(receiver.recv_timeout(Duration::from_millis(500)).is_err());
+}
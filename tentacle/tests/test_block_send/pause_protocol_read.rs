@@ -0,0 +1,126 @@
+use crate::common::start_service;
+use std::time::{Duration, Instant};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    bytes::Bytes,
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, TargetProtocol},
+    traits::ServiceProtocol,
+    SessionId,
+};
+
+const MESSAGE_SIZE: usize = 64 * 1024;
+/// Far over the stream window
+const MESSAGE_COUNT: usize = 64;
+
+enum Report {
+    Connected(SessionId),
+    Received(usize),
+}
+
+/// The listener pauses reading on connected, the dialer sends all the messages on connected
+struct PHandle {
+    listener: bool,
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if self.listener {
+            context
+                .pause_protocol_read(context.session.id, context.proto_id)
+                .unwrap();
+        } else {
+            for _ in 0..MESSAGE_COUNT {
+                let _res = context.send_message(Bytes::from(vec![0u8; MESSAGE_SIZE]));
+            }
+        }
+        let _res = self.sender.send(Report::Connected(context.session.id));
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        let _res = self.sender.send(Report::Received(data.len()));
+    }
+}
+
+fn create(listener: bool, sender: crossbeam_channel::Sender<Report>) -> Service<()> {
+    let meta = MetaBuilder::new()
+        .id(1.into())
+        .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { listener, sender })))
+        .build();
+    ServiceBuilder::default()
+        .insert_protocol(meta)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .forever(true)
+        .build(())
+}
+
+fn connected(receiver: &crossbeam_channel::Receiver<Report>) -> SessionId {
+    match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+        Report::Connected(id) => id,
+        Report::Received(_) => panic!("unexpected message"),
+    }
+}
+
+/// Bytes received until the timeout, or the expected bytes are received
+fn received(
+    receiver: &crossbeam_channel::Receiver<Report>,
+    expected: usize,
+    timeout: Duration,
+) -> usize {
+    let deadline = Instant::now() + timeout;
+    let mut total = 0;
+    while let Ok(report) = receiver.recv_deadline(deadline) {
+        if let Report::Received(size) = report {
+            total += size;
+            if total == expected {
+                break;
+            }
+        }
+    }
+    total
+}
+
+#[test]
+fn test_pause_protocol_read() {
+    let (listener_sender, listener_receiver) = crossbeam_channel::unbounded();
+    let listener = create(true, listener_sender);
+    let listener_control = listener.control().clone();
+    let listen_addr =
+        start_service(listener, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let (dialer_sender, dialer_receiver) = crossbeam_channel::unbounded();
+    let dialer = create(false, dialer_sender);
+    let dialer_control = dialer.control().clone();
+    start_service(dialer, None);
+    dialer_control
+        .dial(listen_addr, TargetProtocol::All)
+        .unwrap();
+
+    let listener_session = connected(&listener_receiver);
+    let dialer_session = connected(&dialer_receiver);
+
+    // the messages read before the pause took effect are at most a window
+    let total = MESSAGE_SIZE * MESSAGE_COUNT;
+    let before = received(&listener_receiver, total, Duration::from_secs(2));
+    assert!(before < total);
+    // the dialer used up the window and waits for the credits
+    assert_eq!(
+        dialer_control
+            .yamux_stats(dialer_session)
+            .unwrap()
+            .send_window,
+        0
+    );
+
+    listener_control
+        .resume_protocol_read(listener_session, 1.into())
+        .unwrap();
+    assert_eq!(
+        received(&listener_receiver, total - before, Duration::from_secs(10)),
+        total - before
+    );
+}
@@ -0,0 +1,119 @@
+use bytes::Bytes;
+use futures::{channel, StreamExt};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+const MESSAGE_COUNT: usize = 1024;
+
+pub fn create<F>(meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    ServiceBuilder::default()
+        .insert_protocol(meta)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(shandle)
+}
+
+struct PHandle {
+    count: Arc<AtomicUsize>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.session.ty.is_inbound() {
+            for i in 0..MESSAGE_COUNT {
+                let _res = context.send_message(Bytes::from(i.to_string()));
+            }
+        }
+    }
+
+    fn disconnected(&mut self, context: ProtocolContextMutRef) {
+        let _res = context.shutdown();
+    }
+
+    fn received_batch(&mut self, context: ProtocolContextMutRef, data: Vec<Bytes>) {
+        for data in data {
+            // messages keep the order of sending
+            let index = self.count.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(data, Bytes::from(index.to_string()));
+        }
+        if self.count.load(Ordering::SeqCst) == MESSAGE_COUNT {
+            let _res = context.shutdown();
+        }
+    }
+}
+
+fn create_meta(id: ProtocolId) -> (ProtocolMeta, Arc<AtomicUsize>) {
+    let count = Arc::new(AtomicUsize::new(0));
+    let count_clone = count.clone();
+    let meta = MetaBuilder::new()
+        .id(id)
+        .service_handle(move || {
+            let handle = Box::new(PHandle { count: count_clone });
+            ProtocolHandle::Callback(handle)
+        })
+        .build();
+    (meta, count)
+}
+
+#[test]
+fn test_received_batch() {
+    let (meta, _) = create_meta(1.into());
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(meta, ());
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let (meta, result) = create_meta(1.into());
+
+    let handle = thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(meta, ());
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            service
+                .dial(listen_addr, TargetProtocol::All)
+                .await
+                .unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+    handle.join().unwrap();
+
+    assert_eq!(result.load(Ordering::SeqCst), MESSAGE_COUNT);
+}
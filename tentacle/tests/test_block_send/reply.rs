@@ -0,0 +1,87 @@
+use crate::common::start_service;
+use bytes::Bytes;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{Priority, ProtocolHandle, Service, TargetProtocol},
+    traits::ServiceProtocol,
+};
+
+/// The listener echoes every message back twice, on normal and high priority,
+/// the dialer sends its name once connected and reports what it receives
+struct PHandle {
+    name: Option<&'static str>,
+    sender: crossbeam_channel::Sender<Bytes>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if let Some(name) = self.name {
+            context.send_message(Bytes::from(name)).unwrap();
+        }
+    }
+
+    fn received(&mut self, context: ProtocolContextMutRef, data: Bytes) {
+        if self.name.is_some() {
+            let _res = self.sender.send(data);
+        } else {
+            context.reply(data.clone()).unwrap();
+            context.reply_with_priority(data, Priority::High).unwrap();
+        }
+    }
+}
+
+fn create(name: Option<&'static str>, sender: crossbeam_channel::Sender<Bytes>) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || {
+                    ProtocolHandle::Callback(Box::new(PHandle {
+                        name,
+                        sender: sender.clone(),
+                    }))
+                })
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+#[test]
+fn test_reply_to_the_source_session() {
+    let listen_addr = start_service(
+        create(None, crossbeam_channel::unbounded().0),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let receivers = ["alice", "bob"]
+        .iter()
+        .map(|name| {
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            let service = create(Some(name), sender);
+            let control = service.control().clone();
+            start_service(service, None);
+            control
+                .dial(listen_addr.clone(), TargetProtocol::All)
+                .unwrap();
+            (name, receiver)
+        })
+        .collect::<Vec<_>>();
+
+    for (name, receiver) in receivers {
+        for _ in 0..2 {
+            assert_eq!(
+                receiver.recv_timeout(Duration::from_secs(5)),
+                Ok(Bytes::from(*name))
+            );
+        }
+        assert!(receiver.recv_timeout(Duration::from_millis(500)).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::common::start_service;
+use bytes::Bytes;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef, ServiceContext},
+    error::ProtocolHandleErrorKind,
+    secio::SecioKeyPair,
+    service::{
+        HandleClosedPolicy, ProtocolHandle, ProtocolMeta, Service, ServiceError, TargetProtocol,
+    },
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+pub fn create<F>(policy: HandleClosedPolicy, metas: Vec<ProtocolMeta>, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    metas
+        .into_iter()
+        .fold(ServiceBuilder::default(), |builder, meta| {
+            builder.insert_protocol(meta)
+        })
+        .forever(true)
+        .handle_closed_policy(policy)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(shandle)
+}
+
+struct PanicHandle;
+
+impl ServiceProtocol for PanicHandle {
+    fn init(&mut self, context: &mut ProtocolContext) {
+        let proto_id = context.proto_id;
+        let _res = context.set_service_notify(proto_id, Duration::from_millis(10), 0);
+    }
+
+    fn notify(&mut self, _context: &mut ProtocolContext, _token: u64) {
+        panic!("handle panicked on purpose");
+    }
+}
+
+/// Inbound side echoes the message, outbound side sends ping and reports the echo
+struct EchoHandle {
+    sender: Option<crossbeam_channel::Sender<Bytes>>,
+}
+
+impl ServiceProtocol for EchoHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.session.ty.is_outbound() {
+            let _res = context.send_message(Bytes::from("ping"));
+        }
+    }
+
+    fn received(&mut self, context: ProtocolContextMutRef, data: Bytes) {
+        match self.sender {
+            Some(ref sender) => {
+                let _res = sender.send(data);
+            }
+            None => {
+                let _res = context.send_message(data);
+            }
+        }
+    }
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<ProtocolId>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _env: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::ProtocolHandleError {
+            proto_id,
+            error: ProtocolHandleErrorKind::AbnormallyClosed(_),
+        } = error
+        {
+            let _res = self.sender.send(proto_id);
+        }
+    }
+}
+
+fn echo_meta(sender: Option<crossbeam_channel::Sender<Bytes>>) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(2.into())
+        .service_handle(move || {
+            ProtocolHandle::Callback(Box::new(EchoHandle {
+                sender: sender.clone(),
+            }))
+        })
+        .build()
+}
+
+fn test_handle_closed(policy: HandleClosedPolicy) -> bool {
+    let (closed_sender, closed_receiver) = crossbeam_channel::unbounded();
+    let panic_meta = MetaBuilder::new()
+        .id(1.into())
+        .service_handle(|| ProtocolHandle::Callback(Box::new(PanicHandle)))
+        .build();
+    let service = create(
+        policy,
+        vec![panic_meta, echo_meta(None)],
+        SHandle {
+            sender: closed_sender,
+        },
+    );
+    let listen_addr =
+        start_service(service, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    assert_eq!(
+        closed_receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(1.into())
+    );
+
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    let service = create(policy, vec![echo_meta(Some(sender))], ());
+    let control = service.control().clone();
+    start_service(service, None);
+    control
+        .dial(listen_addr, TargetProtocol::Single(2.into()))
+        .unwrap();
+
+    receiver.recv_timeout(Duration::from_secs(5)) == Ok(Bytes::from("ping"))
+}
+
+#[test]
+fn test_handle_closed_shutdown() {
+    assert!(!test_handle_closed(HandleClosedPolicy::Shutdown));
+}
+
+#[test]
+fn test_handle_closed_drop_protocol() {
+    assert!(test_handle_closed(HandleClosedPolicy::DropProtocol));
+}
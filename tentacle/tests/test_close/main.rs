@@ -1,3 +1,10 @@
+#[path = "../common/mod.rs"]
+mod common;
+mod handle_closed_policy;
+mod protocol_close_policy;
+mod run;
+mod wait_shutdown;
+
 use futures::StreamExt;
 use tentacle::{
     builder::{MetaBuilder, ServiceBuilder},
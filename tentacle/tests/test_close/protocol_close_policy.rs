@@ -0,0 +1,81 @@
+use crate::common::start_service;
+use bytes::Bytes;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolClosePolicy, ProtocolHandle, Service, TargetProtocol},
+    traits::ServiceProtocol,
+};
+
+/// The listener sends a batch of messages and closes the protocol right after,
+/// the dialer reports the messages it receives and the disconnect
+struct PHandle {
+    listener: bool,
+    sender: crossbeam_channel::Sender<Option<Bytes>>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if self.listener {
+            for _ in 0..100 {
+                context.send_message(Bytes::from("flush")).unwrap();
+            }
+            context
+                .close_protocol(context.session.id, context.proto_id)
+                .unwrap();
+        }
+    }
+
+    fn disconnected(&mut self, _context: ProtocolContextMutRef) {
+        if !self.listener {
+            let _res = self.sender.send(None);
+        }
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        let _res = self.sender.send(Some(data));
+    }
+}
+
+fn create(listener: bool, sender: crossbeam_channel::Sender<Option<Bytes>>) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .close_policy(ProtocolClosePolicy::Flush)
+                .service_handle(move || {
+                    ProtocolHandle::Callback(Box::new(PHandle { listener, sender }))
+                })
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+#[test]
+fn test_flush_queued_messages_on_protocol_close() {
+    let listen_addr = start_service(
+        create(true, crossbeam_channel::unbounded().0),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(false, sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    for _ in 0..100 {
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok(Some(Bytes::from("flush")))
+        );
+    }
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(None));
+}
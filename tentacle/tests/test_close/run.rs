@@ -0,0 +1,55 @@
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, TargetProtocol},
+};
+
+fn create(key_pair: SecioKeyPair) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .key_pair(key_pair)
+        .build(())
+}
+
+#[test]
+fn test_run_to_completion() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async move {
+        let mut listen_service = create(SecioKeyPair::secp256k1_generated());
+        let listen_addr = listen_service
+            .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .await
+            .unwrap();
+        let (listen_handle, listen_control) = listen_service.run();
+
+        let dial_key = SecioKeyPair::secp256k1_generated();
+        let dial_peer_id = dial_key.peer_id();
+        let (dial_handle, dial_control) = create(dial_key).run();
+        dial_control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+        let mut connected = false;
+        for _ in 0..50 {
+            if listen_control
+                .is_connected(dial_peer_id.clone())
+                .await
+                .unwrap_or(false)
+            {
+                connected = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(connected);
+
+        dial_control.shutdown().unwrap();
+        listen_control.shutdown().unwrap();
+        dial_handle.await.unwrap();
+        listen_handle.await.unwrap();
+    });
+}
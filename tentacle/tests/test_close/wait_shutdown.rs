@@ -0,0 +1,65 @@
+use futures::{executor::block_on, StreamExt};
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service},
+};
+
+fn create() -> Service<()> {
+    let meta = MetaBuilder::new()
+        .id(1.into())
+        .service_handle(|| ProtocolHandle::None)
+        .build();
+    ServiceBuilder::default()
+        .insert_protocol(meta)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+/// Run the service until its stream ends, the receiver is notified then
+fn start_service(mut service: Service<()>) -> crossbeam_channel::Receiver<()> {
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            while service.next().await.is_some() {}
+            let _res = sender.send(());
+        });
+    });
+    receiver
+}
+
+#[test]
+fn test_wait_shutdown() {
+    let service = create();
+    let control = service.control().clone();
+    let ended = start_service(service);
+
+    let (sender, waited) = crossbeam_channel::bounded(1);
+    let wait = control.wait_shutdown();
+    thread::spawn(move || {
+        block_on(wait);
+        let _res = sender.send(());
+    });
+    assert!(waited.recv_timeout(Duration::from_millis(500)).is_err());
+
+    control.shutdown().unwrap();
+    waited.recv_timeout(Duration::from_secs(5)).unwrap();
+    ended.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    // resolved at once after the service ended
+    block_on(control.wait_shutdown());
+}
+
+#[test]
+fn test_wait_shutdown_on_drop() {
+    let service = create();
+    let wait = service.control().wait_shutdown();
+    drop(service);
+    block_on(wait);
+}
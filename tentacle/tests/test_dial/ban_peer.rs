@@ -0,0 +1,162 @@
+use crate::common::start_service;
+use std::{borrow::Cow, thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    error::{DialerErrorKind, ListenErrorKind},
+    multiaddr::Protocol,
+    secio::{PeerId, SecioKeyPair},
+    service::{ProtocolHandle, Service, ServiceError, ServiceEvent, TargetProtocol},
+    traits::ServiceHandle,
+};
+
+#[derive(Debug, PartialEq)]
+enum Report {
+    Open(PeerId),
+    ListenBanned(PeerId),
+    DialBanned(PeerId),
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        match error {
+            ServiceError::ListenError {
+                error: ListenErrorKind::Banned(peer_id),
+                ..
+            } => {
+                let _res = self.sender.send(Report::ListenBanned(peer_id));
+            }
+            ServiceError::DialerError {
+                error: DialerErrorKind::Banned(peer_id),
+                ..
+            } => {
+                let _res = self.sender.send(Report::DialBanned(peer_id));
+            }
+            _ => (),
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { session_context } = event {
+            let peer_id = session_context.remote_pubkey.as_ref().unwrap().peer_id();
+            let _res = self.sender.send(Report::Open(peer_id));
+        }
+    }
+}
+
+fn create(key_pair: SecioKeyPair, sender: crossbeam_channel::Sender<Report>) -> Service<SHandle> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(key_pair)
+        .build(SHandle { sender })
+}
+
+fn recv(receiver: &crossbeam_channel::Receiver<Report>) -> Report {
+    receiver.recv_timeout(Duration::from_secs(5)).unwrap()
+}
+
+#[test]
+fn test_ban_and_unban_inbound_peer() {
+    let listener_key = SecioKeyPair::secp256k1_generated();
+    let dialer_key = SecioKeyPair::secp256k1_generated();
+    let dialer_id = dialer_key.peer_id();
+
+    let (listener_sender, listener_receiver) = crossbeam_channel::unbounded();
+    let listener = create(listener_key, listener_sender);
+    let listener_control = listener.control().clone();
+    let listen_addr =
+        start_service(listener, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let (sender, _receiver) = crossbeam_channel::unbounded();
+    let dialer = create(dialer_key, sender);
+    let dialer_control = dialer.control().clone();
+    start_service(dialer, None);
+
+    dialer_control
+        .dial(listen_addr.clone(), TargetProtocol::All)
+        .unwrap();
+    assert_eq!(recv(&listener_receiver), Report::Open(dialer_id.clone()));
+
+    // the ban disconnects the session, and the reconnect is refused
+    listener_control
+        .ban_peer(dialer_id.clone(), Duration::from_secs(60))
+        .unwrap();
+    thread::sleep(Duration::from_millis(200));
+    dialer_control
+        .dial(listen_addr.clone(), TargetProtocol::All)
+        .unwrap();
+    assert_eq!(
+        recv(&listener_receiver),
+        Report::ListenBanned(dialer_id.clone())
+    );
+
+    listener_control.unban_peer(dialer_id.clone()).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    dialer_control
+        .dial(listen_addr, TargetProtocol::All)
+        .unwrap();
+    assert_eq!(recv(&listener_receiver), Report::Open(dialer_id));
+}
+
+#[test]
+fn test_ban_expires() {
+    let dialer_key = SecioKeyPair::secp256k1_generated();
+    let dialer_id = dialer_key.peer_id();
+
+    let (listener_sender, listener_receiver) = crossbeam_channel::unbounded();
+    let listener = create(SecioKeyPair::secp256k1_generated(), listener_sender);
+    let listener_control = listener.control().clone();
+    let listen_addr =
+        start_service(listener, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let (sender, _receiver) = crossbeam_channel::unbounded();
+    let dialer = create(dialer_key, sender);
+    let dialer_control = dialer.control().clone();
+    start_service(dialer, None);
+
+    listener_control
+        .ban_peer(dialer_id.clone(), Duration::from_millis(500))
+        .unwrap();
+    thread::sleep(Duration::from_secs(1));
+    dialer_control
+        .dial(listen_addr, TargetProtocol::All)
+        .unwrap();
+    assert_eq!(recv(&listener_receiver), Report::Open(dialer_id));
+}
+
+#[test]
+fn test_banned_peer_is_not_dialed() {
+    let listener_key = SecioKeyPair::secp256k1_generated();
+    let listener_id = listener_key.peer_id();
+
+    let (listener_sender, _listener_receiver) = crossbeam_channel::unbounded();
+    let mut listen_addr = start_service(
+        create(listener_key, listener_sender),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+    listen_addr.push(Protocol::P2P(Cow::Owned(listener_id.clone().into_bytes())));
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let dialer = create(SecioKeyPair::secp256k1_generated(), sender);
+    let dialer_control = dialer.control().clone();
+    start_service(dialer, None);
+
+    dialer_control
+        .ban_peer(listener_id.clone(), Duration::from_secs(60))
+        .unwrap();
+    dialer_control
+        .dial(listen_addr, TargetProtocol::All)
+        .unwrap();
+    assert_eq!(recv(&receiver), Report::DialBanned(listener_id));
+}
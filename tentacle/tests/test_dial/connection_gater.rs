@@ -0,0 +1,191 @@
+use crate::common::start_service;
+use std::{net::IpAddr, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ServiceContext, SessionContext},
+    error::{DialerErrorKind, ListenErrorKind},
+    multiaddr::Multiaddr,
+    secio::{PeerId, SecioKeyPair},
+    service::{ProtocolHandle, Service, ServiceError, ServiceEvent, SessionType, TargetProtocol},
+    traits::{ConnectionGater, ServiceHandle},
+    utils::multiaddr_to_socketaddr,
+};
+
+#[derive(Default)]
+struct Gater {
+    deny_accept: Option<IpAddr>,
+    deny_dial: bool,
+    deny_peer: Option<PeerId>,
+    deny_upgraded: bool,
+}
+
+impl ConnectionGater for Gater {
+    fn intercept_accept(&self, remote_address: &Multiaddr) -> bool {
+        self.deny_accept.is_none()
+            || self.deny_accept != multiaddr_to_socketaddr(remote_address).map(|addr| addr.ip())
+    }
+
+    fn intercept_dial(&self, _address: &Multiaddr) -> bool {
+        !self.deny_dial
+    }
+
+    fn intercept_secured(
+        &self,
+        _ty: SessionType,
+        _address: &Multiaddr,
+        peer_id: Option<&PeerId>,
+    ) -> bool {
+        self.deny_peer.is_none() || self.deny_peer.as_ref() != peer_id
+    }
+
+    fn intercept_upgraded(&self, _context: &SessionContext) -> bool {
+        !self.deny_upgraded
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Report {
+    Open,
+    DialGated,
+    ListenGated,
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        match error {
+            ServiceError::DialerError {
+                error: DialerErrorKind::Gated,
+                ..
+            } => {
+                let _res = self.sender.send(Report::DialGated);
+            }
+            ServiceError::ListenError {
+                error: ListenErrorKind::Gated,
+                ..
+            } => {
+                let _res = self.sender.send(Report::ListenGated);
+            }
+            _ => (),
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { .. } = event {
+            let _res = self.sender.send(Report::Open);
+        }
+    }
+}
+
+fn create(
+    key_pair: SecioKeyPair,
+    gater: Gater,
+    sender: crossbeam_channel::Sender<Report>,
+) -> Service<SHandle> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(key_pair)
+        .connection_gater(gater)
+        .build(SHandle { sender })
+}
+
+/// Connect the dialer to the listener, return the first reports of both sides
+fn connect(
+    listener_gater: Gater,
+    dialer_key: SecioKeyPair,
+    dialer_gater: Gater,
+) -> (Option<Report>, Option<Report>) {
+    let (listener_sender, listener_receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(
+            SecioKeyPair::secp256k1_generated(),
+            listener_gater,
+            listener_sender,
+        ),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(dialer_key, dialer_gater, sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    (
+        listener_receiver.recv_timeout(Duration::from_secs(3)).ok(),
+        receiver.recv_timeout(Duration::from_secs(3)).ok(),
+    )
+}
+
+#[test]
+fn test_gater_allows_by_default() {
+    assert_eq!(
+        connect(
+            Gater::default(),
+            SecioKeyPair::secp256k1_generated(),
+            Gater::default()
+        ),
+        (Some(Report::Open), Some(Report::Open))
+    );
+}
+
+#[test]
+fn test_gater_vetoes_accept() {
+    let gater = Gater {
+        deny_accept: Some("127.0.0.1".parse().unwrap()),
+        ..Default::default()
+    };
+    let (listener, _) = connect(gater, SecioKeyPair::secp256k1_generated(), Gater::default());
+    assert_eq!(listener, None);
+
+    // The connections from the other addresses are accepted
+    let gater = Gater {
+        deny_accept: Some("127.0.0.2".parse().unwrap()),
+        ..Default::default()
+    };
+    let (listener, _) = connect(gater, SecioKeyPair::secp256k1_generated(), Gater::default());
+    assert_eq!(listener, Some(Report::Open));
+}
+
+#[test]
+fn test_gater_vetoes_dial() {
+    let gater = Gater {
+        deny_dial: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        connect(Gater::default(), SecioKeyPair::secp256k1_generated(), gater),
+        (None, Some(Report::DialGated))
+    );
+}
+
+#[test]
+fn test_gater_vetoes_secured_peer() {
+    let dialer_key = SecioKeyPair::secp256k1_generated();
+    let gater = Gater {
+        deny_peer: Some(dialer_key.peer_id()),
+        ..Default::default()
+    };
+    let (listener, _) = connect(gater, dialer_key, Gater::default());
+    assert_eq!(listener, Some(Report::ListenGated));
+}
+
+#[test]
+fn test_gater_vetoes_upgraded_session() {
+    let gater = Gater {
+        deny_upgraded: true,
+        ..Default::default()
+    };
+    let (_, dialer) = connect(Gater::default(), SecioKeyPair::secp256k1_generated(), gater);
+    assert_eq!(dialer, Some(Report::DialGated));
+}
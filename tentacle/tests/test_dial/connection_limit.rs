@@ -0,0 +1,122 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceError, ServiceEvent, SessionType, TargetProtocol},
+    traits::ServiceHandle,
+};
+
+#[derive(Debug, PartialEq)]
+enum Report {
+    Open,
+    Limit(SessionType, usize),
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::ConnectionLimit { ty, limit, .. } = error {
+            let _res = self.sender.send(Report::Limit(ty, limit));
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { .. } = event {
+            let _res = self.sender.send(Report::Open);
+        }
+    }
+}
+
+fn create<F>(sender: crossbeam_channel::Sender<Report>, config: F) -> Service<SHandle>
+where
+    F: FnOnce(ServiceBuilder) -> ServiceBuilder,
+{
+    config(
+        ServiceBuilder::default()
+            .insert_protocol(
+                MetaBuilder::new()
+                    .id(1.into())
+                    .service_handle(|| ProtocolHandle::None)
+                    .build(),
+            )
+            .forever(true)
+            .key_pair(SecioKeyPair::secp256k1_generated()),
+    )
+    .build(SHandle { sender })
+}
+
+fn recv(receiver: &crossbeam_channel::Receiver<Report>) -> Option<Report> {
+    receiver.recv_timeout(Duration::from_secs(5)).ok()
+}
+
+#[test]
+fn test_inbound_over_limit_is_refused() {
+    let max_inbound = 2;
+    let (listener_sender, listener_receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(listener_sender, |builder| builder.max_inbound(max_inbound)),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let mut dialers = Vec::new();
+    for _ in 0..=max_inbound {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let service = create(sender, |builder| builder);
+        let control = service.control().clone();
+        start_service(service, None);
+        control
+            .dial(listen_addr.clone(), TargetProtocol::All)
+            .unwrap();
+        dialers.push((control, receiver));
+    }
+
+    let mut reports = (0..=max_inbound)
+        .filter_map(|_| recv(&listener_receiver))
+        .collect::<Vec<_>>();
+    reports.sort_by_key(|report| format!("{:?}", report));
+    assert_eq!(
+        reports,
+        vec![
+            Report::Limit(SessionType::Inbound, max_inbound),
+            Report::Open,
+            Report::Open
+        ]
+    );
+}
+
+#[test]
+fn test_dial_over_outbound_limit_is_refused() {
+    let listen_addrs = (0..2)
+        .map(|_| {
+            start_service(
+                create(crossbeam_channel::unbounded().0, |builder| builder),
+                Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+            )
+            .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(sender, |builder| builder.max_outbound(1));
+    let control = service.control().clone();
+    start_service(service, None);
+
+    control
+        .dial(listen_addrs[0].clone(), TargetProtocol::All)
+        .unwrap();
+    assert_eq!(recv(&receiver), Some(Report::Open));
+
+    control
+        .dial(listen_addrs[1].clone(), TargetProtocol::All)
+        .unwrap();
+    assert_eq!(
+        recv(&receiver),
+        Some(Report::Limit(SessionType::Outbound, 1))
+    );
+}
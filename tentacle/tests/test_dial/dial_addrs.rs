@@ -0,0 +1,165 @@
+use crate::common::start_service;
+use std::{net::TcpListener, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceError, ServiceEvent, TargetProtocol},
+    traits::ServiceHandle,
+    utils::multiaddr_to_socketaddr,
+};
+
+enum Report {
+    Opened(Multiaddr),
+    AllFailed(usize),
+    Error,
+}
+
+/// Report the outbound sessions and the dial errors
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        let report = match error {
+            ServiceError::DialAddrsError { errors, .. } => Report::AllFailed(errors.len()),
+            _ => Report::Error,
+        };
+        let _res = self.sender.send(report);
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { session_context } = event {
+            if session_context.ty.is_outbound() {
+                let _res = self
+                    .sender
+                    .send(Report::Opened(session_context.address.clone()));
+            }
+        }
+    }
+}
+
+fn create<F>(shandle: F, key_pair: SecioKeyPair) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .timeout(Duration::from_secs(5))
+        .key_pair(key_pair)
+        .build(shandle)
+}
+
+/// An address nothing listens on
+fn refused_address() -> Multiaddr {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap()
+}
+
+#[test]
+fn test_dial_addrs_keep_the_live_one() {
+    let key_pair = SecioKeyPair::secp256k1_generated();
+    let peer_id = key_pair.peer_id();
+    let live_addr = start_service(
+        create((), key_pair),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+    // accepted by the kernel, but the handshake never finishes
+    let black_hole = TcpListener::bind("127.0.0.1:0").unwrap();
+    let dead_addr: Multiaddr = format!(
+        "/ip4/127.0.0.1/tcp/{}",
+        black_hole.local_addr().unwrap().port()
+    )
+    .parse()
+    .unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(SHandle { sender }, SecioKeyPair::secp256k1_generated());
+    let control = service.control().clone();
+    start_service(service, None);
+
+    control
+        .dial_addrs(
+            peer_id,
+            vec![dead_addr, live_addr.clone()],
+            TargetProtocol::All,
+        )
+        .unwrap();
+
+    match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+        Report::Opened(address) => assert_eq!(
+            multiaddr_to_socketaddr(&address),
+            multiaddr_to_socketaddr(&live_addr)
+        ),
+        _ => panic!("the live address isn't connected"),
+    }
+    // the dial of the dead one is cancelled silently
+    assert!(receiver.recv_timeout(Duration::from_secs(1)).is_err());
+}
+
+#[test]
+fn test_dial_addrs_all_failed() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(SHandle { sender }, SecioKeyPair::secp256k1_generated());
+    let control = service.control().clone();
+    start_service(service, None);
+
+    control
+        .dial_addrs(
+            SecioKeyPair::secp256k1_generated().peer_id(),
+            vec![refused_address(), refused_address()],
+            TargetProtocol::All,
+        )
+        .unwrap();
+
+    match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+        Report::AllFailed(count) => assert_eq!(count, 2),
+        _ => panic!("the failures aren't reported as one"),
+    }
+    assert!(receiver.recv_timeout(Duration::from_secs(1)).is_err());
+}
+
+#[test]
+fn test_dial_addrs_over_connection_limit() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .max_outbound(0)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(SHandle { sender });
+    let control = service.control().clone();
+    start_service(service, None);
+
+    control
+        .dial_addrs(
+            SecioKeyPair::secp256k1_generated().peer_id(),
+            vec![refused_address(), refused_address()],
+            TargetProtocol::All,
+        )
+        .unwrap();
+
+    // every address is refused by the limit, reported as one
+    match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+        Report::AllFailed(count) => assert_eq!(count, 2),
+        _ => panic!("the limit isn't reported as the failures of the race"),
+    }
+}
@@ -0,0 +1,82 @@
+use crate::common::start_service;
+use std::{
+    net::{SocketAddr, TcpListener},
+    time::Duration,
+};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceEvent, TargetProtocol},
+    traits::ServiceHandle,
+    utils::{multiaddr_to_socketaddr, socketaddr_to_multiaddr},
+};
+
+/// Reports the remote address of the opened sessions
+struct SHandle {
+    sender: crossbeam_channel::Sender<SocketAddr>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { session_context } = event {
+            let _res = self
+                .sender
+                .send(multiaddr_to_socketaddr(&session_context.address).unwrap());
+        }
+    }
+}
+
+fn create(sender: crossbeam_channel::Sender<SocketAddr>) -> Service<SHandle> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .timeout(Duration::from_secs(2))
+        .max_dial_concurrency(1)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(SHandle { sender })
+}
+
+#[test]
+fn test_higher_weight_dial_starts_first() {
+    // accepted by the os but never handshakes, occupies the only dial slot until timeout
+    let stuck = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stuck_addr = socketaddr_to_multiaddr(stuck.local_addr().unwrap());
+
+    let listen_addrs = (0..2)
+        .map(|_| {
+            start_service(
+                create(crossbeam_channel::unbounded().0),
+                Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+            )
+            .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(sender);
+    let control = service.control().clone();
+    start_service(service, None);
+
+    control.dial(stuck_addr, TargetProtocol::All).unwrap();
+    control
+        .dial_with_priority(listen_addrs[0].clone(), TargetProtocol::All, 0)
+        .unwrap();
+    control
+        .dial_with_priority(listen_addrs[1].clone(), TargetProtocol::All, 10)
+        .unwrap();
+
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(10)),
+        Ok(multiaddr_to_socketaddr(&listen_addrs[1]).unwrap())
+    );
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(10)),
+        Ok(multiaddr_to_socketaddr(&listen_addrs[0]).unwrap())
+    );
+}
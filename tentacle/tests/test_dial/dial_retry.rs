@@ -0,0 +1,138 @@
+use crate::common::start_service;
+use futures::StreamExt;
+use std::{
+    sync::mpsc::{channel, Sender},
+    thread,
+    time::Duration,
+};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    error::{DialError, DialFailure},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceEvent, TargetProtocol},
+    traits::ServiceHandle,
+};
+
+/// Report the opened sessions
+struct SessionOpened(Sender<()>);
+
+impl ServiceHandle for SessionOpened {
+    fn handle_event(&mut self, _control: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { .. } = event {
+            let _ignore = self.0.send(());
+        }
+    }
+}
+
+fn create<F>(shandle: F, retries: Option<(u32, Duration)>, forever: bool) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let builder = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(forever)
+        .key_pair(SecioKeyPair::secp256k1_generated());
+    match retries {
+        Some((retries, delay)) => builder.dial_retries(retries, delay).build(shandle),
+        None => builder.build(shandle),
+    }
+}
+
+/// Dial the address on the start, the service runs until the dial and its session end
+fn start_dialer<F>(mut service: Service<F>, address: Multiaddr)
+where
+    F: ServiceHandle + Unpin + Send + 'static,
+{
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            service.dial(address, TargetProtocol::All).await.unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+}
+
+/// An address nothing listens on yet
+fn free_address() -> Multiaddr {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap()
+}
+
+#[test]
+fn test_dial_retry_until_listened() {
+    let address = free_address();
+
+    let service = create((), Some((3, Duration::from_millis(500))), true);
+    let control = service.control().clone();
+    start_service(service, None);
+
+    let listen_addr = address.clone();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        start_service(create((), None, true), Some(listen_addr));
+    });
+
+    let res = futures::executor::block_on(control.dial_with_result(address, TargetProtocol::All));
+    assert!(res.is_ok(), "unexpected dial result: {:?}", res);
+}
+
+#[test]
+fn test_dial_without_retry_fails() {
+    let address = free_address();
+
+    let service = create((), None, true);
+    let control = service.control().clone();
+    start_service(service, None);
+
+    match futures::executor::block_on(control.dial_with_result(address, TargetProtocol::All)) {
+        Err(DialError::Failed(DialFailure::ConnectFailed(_))) => (),
+        res => panic!("unexpected dial result: {:?}", res),
+    }
+}
+
+#[test]
+fn test_dial_retries_exhausted() {
+    let address = free_address();
+
+    let service = create((), Some((2, Duration::from_millis(50))), true);
+    let control = service.control().clone();
+    start_service(service, None);
+
+    match futures::executor::block_on(control.dial_with_result(address, TargetProtocol::All)) {
+        Err(DialError::Failed(DialFailure::ConnectFailed(_))) => (),
+        res => panic!("unexpected dial result: {:?}", res),
+    }
+}
+
+#[test]
+fn test_dial_retry_keeps_service_running() {
+    let address = free_address();
+
+    let (sender, receiver) = channel();
+    let service = create(
+        SessionOpened(sender),
+        Some((3, Duration::from_millis(500))),
+        false,
+    );
+    start_dialer(service, address.clone());
+
+    thread::sleep(Duration::from_millis(200));
+    start_service(create((), None, true), Some(address));
+
+    assert!(receiver.recv_timeout(Duration::from_secs(10)).is_ok());
+}
@@ -0,0 +1,115 @@
+use crate::common::start_service;
+use std::{io::ErrorKind, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    error::{DialError, DialFailure},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceEvent, TargetProtocol},
+    traits::ServiceHandle,
+    SessionId,
+};
+
+/// Report the id of the opened outbound session
+struct SHandle {
+    sender: crossbeam_channel::Sender<SessionId>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { session_context } = event {
+            if session_context.ty.is_outbound() {
+                let _res = self.sender.send(session_context.id);
+            }
+        }
+    }
+}
+
+fn create<F>(shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(shandle)
+}
+
+#[test]
+fn test_dial_with_result_opened() {
+    let listen_addr =
+        start_service(create(()), Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(SHandle { sender });
+    let control = service.control().clone();
+    start_service(service, None);
+
+    let session_id =
+        futures::executor::block_on(control.dial_with_result(listen_addr, TargetProtocol::All))
+            .unwrap();
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)).ok(),
+        Some(session_id)
+    );
+}
+
+#[test]
+fn test_dial_with_result_failed() {
+    // nothing listens on the port once the listener is dropped
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(SHandle { sender });
+    let control = service.control().clone();
+    start_service(service, None);
+
+    let address = format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap();
+    match futures::executor::block_on(control.dial_with_result(address, TargetProtocol::All)) {
+        Err(DialError::Failed(DialFailure::ConnectFailed(ErrorKind::ConnectionRefused))) => (),
+        res => panic!("unexpected dial result: {:?}", res),
+    }
+    assert!(receiver.try_recv().is_err());
+}
+
+#[test]
+fn test_dial_with_result_over_max_connection() {
+    let first_addr =
+        start_service(create(()), Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+    let second_addr =
+        start_service(create(()), Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    // full once a session is opened
+    let service = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .max_connection_number(0)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(());
+    let control = service.control().clone();
+    start_service(service, None);
+
+    assert!(
+        futures::executor::block_on(control.dial_with_result(first_addr, TargetProtocol::All))
+            .is_ok()
+    );
+    match futures::executor::block_on(control.dial_with_result(second_addr, TargetProtocol::All)) {
+        Err(DialError::Failed(DialFailure::ConnectionLimit(_))) => (),
+        res => panic!("unexpected dial result: {:?}", res),
+    }
+}
@@ -0,0 +1,125 @@
+use crate::common::start_service;
+use std::{collections::HashMap, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    error::{DialerErrorKind, TransportErrorKind},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceError, ServiceEvent, TargetProtocol},
+    traits::{ServiceHandle, TxtFuture, TxtResolver},
+};
+
+/// Answer the TXT lookups from the fixed records
+#[derive(Clone, Default)]
+struct StubResolver {
+    records: HashMap<String, Vec<String>>,
+}
+
+impl TxtResolver for StubResolver {
+    fn resolve_txt(&self, domain: &str) -> TxtFuture {
+        let records = self.records.get(domain).cloned().unwrap_or_default();
+        Box::pin(async move { Ok(records) })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Report {
+    Open(Multiaddr),
+    DialError(Multiaddr),
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::DialerError {
+            address,
+            error: DialerErrorKind::TransportError(TransportErrorKind::DnsResolverError(..)),
+        } = error
+        {
+            let _res = self.sender.send(Report::DialError(address));
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { session_context } = event {
+            let _res = self
+                .sender
+                .send(Report::Open(session_context.address.clone()));
+        }
+    }
+}
+
+fn create(resolver: StubResolver, sender: crossbeam_channel::Sender<Report>) -> Service<SHandle> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .txt_resolver(resolver)
+        .build(SHandle { sender })
+}
+
+/// An address nobody listens on
+fn closed_address() -> Multiaddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap()
+}
+
+#[test]
+fn test_dnsaddr_dials_the_resolved_addresses() {
+    let (listener_sender, _listener_receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(StubResolver::default(), listener_sender),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    // the first candidate refuses the connection, the second one is the listener
+    let closed = closed_address();
+    let mut resolver = StubResolver::default();
+    resolver.records.insert(
+        "_dnsaddr.bootstrap.example.com".to_owned(),
+        vec![
+            format!("dnsaddr={}", closed),
+            format!("dnsaddr={}", listen_addr),
+        ],
+    );
+    let dnsaddr: Multiaddr = "/dnsaddr/bootstrap.example.com".parse().unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(resolver, sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(dnsaddr.clone(), TargetProtocol::All).unwrap();
+
+    // the listener is reached after the closed one fails, the session is indexed by the dialed address
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+        Report::Open(dnsaddr)
+    );
+}
+
+#[test]
+fn test_dnsaddr_resolve_failure_reports_the_source_address() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(StubResolver::default(), sender);
+    let control = service.control().clone();
+    start_service(service, None);
+
+    let dnsaddr: Multiaddr = "/dnsaddr/unknown.example.com".parse().unwrap();
+    control.dial(dnsaddr.clone(), TargetProtocol::All).unwrap();
+
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+        Report::DialError(dnsaddr)
+    );
+}
@@ -0,0 +1,98 @@
+#![cfg(unix)]
+use futures::StreamExt;
+use std::{sync::mpsc::channel, thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ProtocolContext,
+    multiaddr::{Multiaddr, Protocol},
+    service::ProtocolHandle,
+    traits::ServiceProtocol,
+};
+
+/// Reports the advertised listens periodically
+struct PHandle {
+    sender: crossbeam_channel::Sender<Vec<Multiaddr>>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, context: &mut ProtocolContext) {
+        context
+            .set_service_notify(1.into(), Duration::from_millis(100), 1)
+            .unwrap();
+    }
+
+    fn notify(&mut self, context: &mut ProtocolContext, _token: u64) {
+        let _res = self.sender.send(context.listens().to_vec());
+    }
+}
+
+fn start_service(expand: bool, sender: crossbeam_channel::Sender<Vec<Multiaddr>>) -> Multiaddr {
+    let mut service = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { sender })))
+                .build(),
+        )
+        .expand_unspecified_listens(expand)
+        .forever(true)
+        .build(());
+
+    let (addr_sender, addr_receiver) = channel::<Multiaddr>();
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/0.0.0.0/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            addr_sender.send(listen_addr).unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+    addr_receiver.recv().unwrap()
+}
+
+fn advertised(receiver: &crossbeam_channel::Receiver<Vec<Multiaddr>>) -> Vec<Multiaddr> {
+    loop {
+        let listens = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        if !listens.is_empty() {
+            return listens;
+        }
+    }
+}
+
+fn port(address: &Multiaddr) -> Option<u16> {
+    address.iter().find_map(|proto| match proto {
+        Protocol::Tcp(port) => Some(port),
+        _ => None,
+    })
+}
+
+#[test]
+fn test_expand_unspecified_listens() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(true, sender);
+
+    let listens = advertised(&receiver);
+    let loopback = Protocol::Ip4("127.0.0.1".parse().unwrap());
+    assert!(listens
+        .iter()
+        .all(|address| port(address) == port(&listen_addr)));
+    assert!(!listens.contains(&listen_addr));
+    assert!(listens
+        .iter()
+        .any(|address| address.iter().next() == Some(loopback.clone())));
+}
+
+#[test]
+fn test_keep_unspecified_listens_by_default() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(false, sender);
+
+    assert_eq!(advertised(&receiver), vec![listen_addr]);
+}
@@ -0,0 +1,98 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceEvent, TargetProtocol},
+    traits::ServiceHandle,
+    SessionId,
+};
+
+enum Report {
+    Open(SessionId),
+    External(Multiaddr),
+}
+
+/// Report the opened sessions and the external address changes
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        let report = match event {
+            ServiceEvent::SessionOpen { session_context } => Report::Open(session_context.id),
+            ServiceEvent::ExternalAddressChanged { address } => Report::External(address),
+            _ => return,
+        };
+        let _res = self.sender.send(report);
+    }
+}
+
+fn create<F>(shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let meta = MetaBuilder::new()
+        .id(1.into())
+        .service_handle(|| ProtocolHandle::None)
+        .build();
+    ServiceBuilder::default()
+        .insert_protocol(meta)
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(shandle)
+}
+
+fn next_external(receiver: &crossbeam_channel::Receiver<Report>) -> Option<Multiaddr> {
+    match receiver.recv_timeout(Duration::from_secs(2)) {
+        Ok(Report::External(address)) => Some(address),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_external_address_majority() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(SHandle { sender });
+    let control = service.control().clone();
+    let listen_addr =
+        start_service(service, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let mut sessions = Vec::new();
+    for _ in 0..3 {
+        let dialer = create(());
+        let dialer_control = dialer.control().clone();
+        start_service(dialer, None);
+        dialer_control
+            .dial(listen_addr.clone(), TargetProtocol::All)
+            .unwrap();
+        match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+            Report::Open(id) => sessions.push(id),
+            Report::External(_) => panic!("unexpected external address"),
+        }
+    }
+
+    let minority: Multiaddr = "/ip4/1.1.1.1/tcp/1337".parse().unwrap();
+    let majority: Multiaddr = "/ip4/2.2.2.2/tcp/1337".parse().unwrap();
+
+    // The first observation is the only one
+    control
+        .report_observed_address(sessions[0], minority.clone())
+        .unwrap();
+    assert_eq!(next_external(&receiver), Some(minority.clone()));
+
+    // A tie keeps the current address
+    control
+        .report_observed_address(sessions[1], majority.clone())
+        .unwrap();
+    assert_eq!(next_external(&receiver), None);
+
+    // The majority wins
+    control
+        .report_observed_address(sessions[2], majority.clone())
+        .unwrap();
+    assert_eq!(next_external(&receiver), Some(majority));
+}
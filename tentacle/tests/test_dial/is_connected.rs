@@ -0,0 +1,61 @@
+use crate::common::start_service;
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    secio::{PeerId, SecioKeyPair},
+    service::{ProtocolHandle, Service, ServiceControl, TargetProtocol},
+};
+
+fn create(key_pair: SecioKeyPair) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(key_pair)
+        .build(())
+}
+
+fn wait_connected(control: &ServiceControl, peer_id: &PeerId, expected: bool) -> bool {
+    for _ in 0..50 {
+        if futures::executor::block_on(control.is_connected(peer_id.clone())).ok() == Some(expected)
+        {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    false
+}
+
+#[test]
+fn test_is_connected() {
+    let listen_key = SecioKeyPair::secp256k1_generated();
+    let listen_peer_id = listen_key.peer_id();
+    let service = create(listen_key);
+    let listen_control = service.control().clone();
+    let listen_addr =
+        start_service(service, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let dial_key = SecioKeyPair::secp256k1_generated();
+    let dial_peer_id = dial_key.peer_id();
+    let service = create(dial_key);
+    let dial_control = service.control().clone();
+    start_service(service, None);
+
+    assert!(wait_connected(&listen_control, &dial_peer_id, false));
+
+    dial_control.dial(listen_addr, TargetProtocol::All).unwrap();
+    assert!(wait_connected(&listen_control, &dial_peer_id, true));
+    assert!(wait_connected(&dial_control, &listen_peer_id, true));
+    assert!(wait_connected(
+        &dial_control,
+        &SecioKeyPair::secp256k1_generated().peer_id(),
+        false
+    ));
+
+    dial_control.shutdown().unwrap();
+    assert!(wait_connected(&listen_control, &dial_peer_id, false));
+}
@@ -0,0 +1,161 @@
+use futures::StreamExt;
+use std::{
+    collections::HashSet,
+    net::{SocketAddr, TcpListener, ToSocketAddrs},
+    thread,
+    time::Duration,
+};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    multiaddr::{Multiaddr, Protocol},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ServiceEvent},
+    traits::ServiceHandle,
+};
+
+/// Report the started listen addresses, and the dns addresses they're resolved from
+struct SHandle {
+    sender: crossbeam_channel::Sender<Multiaddr>,
+    resolved: crossbeam_channel::Sender<(Multiaddr, Multiaddr)>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, _env: &mut ServiceContext, event: ServiceEvent) {
+        match event {
+            ServiceEvent::ListenStarted { address } => {
+                let _res = self.sender.send(address);
+            }
+            ServiceEvent::ListenResolved { original, address } => {
+                let _res = self.resolved.send((original, address));
+            }
+            _ => (),
+        }
+    }
+}
+
+fn started_ips(
+    receiver: &crossbeam_channel::Receiver<Multiaddr>,
+    resolved: &crossbeam_channel::Receiver<(Multiaddr, Multiaddr)>,
+    count: usize,
+) -> HashSet<String> {
+    let mut ips = HashSet::new();
+    for _ in 0..count {
+        let address = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        // each of them is reported as resolved from the dns address
+        assert_eq!(
+            resolved.recv_timeout(Duration::from_secs(5)).unwrap(),
+            ("/dns4/localhost/tcp/0".parse().unwrap(), address.clone())
+        );
+        match address.iter().next() {
+            Some(Protocol::Ip4(ip)) => ips.insert(ip.to_string()),
+            Some(Protocol::Ip6(ip)) => ips.insert(ip.to_string()),
+            _ => panic!("listen started on unresolved address: {}", address),
+        };
+    }
+    // no extra listen started
+    assert!(receiver.recv_timeout(Duration::from_millis(500)).is_err());
+    ips
+}
+
+fn test_listen_dns(use_control: bool) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let (resolved_sender, resolved) = crossbeam_channel::unbounded();
+    let mut service = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(SHandle {
+            sender,
+            resolved: resolved_sender,
+        });
+    let control = service.control().clone();
+
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            if !use_control {
+                let address = service
+                    .listen("/dns4/localhost/tcp/0".parse().unwrap())
+                    .await
+                    .unwrap();
+                assert!(!matches!(address.iter().next(), Some(Protocol::Dns4(_))));
+            }
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    if use_control {
+        control
+            .listen("/dns4/localhost/tcp/0".parse().unwrap())
+            .unwrap();
+    }
+
+    let expected: HashSet<String> = ("localhost", 0)
+        .to_socket_addrs()
+        .unwrap()
+        .map(|addr: SocketAddr| addr.ip().to_string())
+        .collect();
+    assert_eq!(started_ips(&receiver, &resolved, expected.len()), expected);
+}
+
+#[test]
+fn test_listen_dns_on_all_resolved_addresses() {
+    test_listen_dns(false)
+}
+
+#[test]
+fn test_listen_dns_on_all_resolved_addresses_with_control() {
+    test_listen_dns(true)
+}
+
+#[test]
+fn test_listen_dns_failure_closes_bound_listeners() {
+    let mut ips = Vec::new();
+    for address in ("localhost", 0).to_socket_addrs().unwrap() {
+        if !ips.contains(&address.ip()) {
+            ips.push(address.ip());
+        }
+    }
+    if ips.len() < 2 {
+        return;
+    }
+    // occupy the port on the last resolved address only
+    let occupied = TcpListener::bind(SocketAddr::new(*ips.last().unwrap(), 0)).unwrap();
+    let port = occupied.local_addr().unwrap().port();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let (resolved_sender, _resolved) = crossbeam_channel::unbounded();
+    let mut service = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(SHandle {
+            sender,
+            resolved: resolved_sender,
+        });
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let result =
+        rt.block_on(service.listen(format!("/dns4/localhost/tcp/{}", port).parse().unwrap()));
+    assert!(result.is_err());
+    assert!(receiver.try_recv().is_err());
+
+    // the addresses bound before the failure are closed
+    for ip in &ips[..ips.len() - 1] {
+        TcpListener::bind(SocketAddr::new(*ip, port)).unwrap();
+    }
+}
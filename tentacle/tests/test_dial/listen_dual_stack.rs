@@ -0,0 +1,101 @@
+use futures::StreamExt;
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    multiaddr::{Multiaddr, Protocol},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ServiceError, ServiceEvent},
+    traits::ServiceHandle,
+    utils::multiaddr_to_socketaddr,
+};
+
+#[derive(Debug)]
+enum Report {
+    Started(Multiaddr),
+    Error(Multiaddr),
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::ListenError { address, .. } = error {
+            let _res = self.sender.send(Report::Error(address));
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::ListenStarted { address } = event {
+            let _res = self.sender.send(Report::Started(address));
+        }
+    }
+}
+
+fn ipv6_available() -> bool {
+    std::net::TcpListener::bind("[::]:0").is_ok()
+}
+
+#[test]
+fn test_listen_dual_stack() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let (result_sender, result_receiver) = crossbeam_channel::bounded(1);
+    let mut service = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(SHandle { sender });
+
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let addresses = service.listen_dual_stack(0).await.unwrap();
+            result_sender.send(addresses).unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let addresses = result_receiver
+        .recv_timeout(Duration::from_secs(5))
+        .unwrap();
+    let ports = addresses
+        .iter()
+        .map(|address| multiaddr_to_socketaddr(address).unwrap().port())
+        .collect::<Vec<_>>();
+    assert!(ports.iter().all(|port| *port != 0 && *port == ports[0]));
+
+    if ipv6_available() {
+        assert_eq!(addresses.len(), 2);
+        assert!(matches!(addresses[0].iter().next(), Some(Protocol::Ip6(_))));
+        assert!(matches!(addresses[1].iter().next(), Some(Protocol::Ip4(_))));
+        for address in addresses {
+            match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+                Report::Started(started) => assert_eq!(started, address),
+                report => panic!("unexpected report: {:?}", report),
+            }
+        }
+    } else {
+        // ipv6 is reported as listen error, only ipv4 is listened
+        assert_eq!(addresses.len(), 1);
+        assert!(matches!(addresses[0].iter().next(), Some(Protocol::Ip4(_))));
+        assert!(matches!(
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+            Report::Error(_)
+        ));
+        assert!(matches!(
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+            Report::Started(_)
+        ));
+    }
+}
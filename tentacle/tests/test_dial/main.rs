@@ -1,3 +1,29 @@
+mod ban_peer;
+#[path = "../common/mod.rs"]
+mod common;
+mod connection_gater;
+mod connection_limit;
+mod dial_addrs;
+mod dial_priority;
+mod dial_retry;
+mod dial_with_result;
+mod dnsaddr;
+mod expand_listens;
+mod external_address;
+mod is_connected;
+mod listen_dns;
+mod listen_dual_stack;
+mod max_concurrent_handshakes;
+mod max_listeners;
+mod memory_transport;
+mod outbound_bind;
+mod pause_accept;
+mod peer_store;
+mod persistent_peer;
+mod quic;
+mod replay;
+mod ws_handshake_error;
+
 use futures::{channel, StreamExt};
 use std::{
     thread,
@@ -6,7 +32,7 @@ use std::{
 use tentacle::{
     builder::{MetaBuilder, ServiceBuilder},
     context::{ProtocolContext, ProtocolContextMutRef, ServiceContext},
-    error::{DialerErrorKind, ListenErrorKind, TransportErrorKind},
+    error::{DialerErrorKind, ListenErrorKind},
     multiaddr::Multiaddr,
     secio::SecioKeyPair,
     service::{
@@ -52,8 +78,13 @@ impl ServiceHandle for EmptySHandle {
 
         let error_type = if let ServiceError::DialerError { error, .. } = error {
             match error {
-                DialerErrorKind::TransportError(TransportErrorKind::Io(e)) => assert_eq!(io::ErrorKind::ConnectionRefused, e.kind()),
-                e => panic!("test fail, expected DialerErrorKind::TransportError(TransportErrorKind::Io), got {:?}", e),
+                DialerErrorKind::ConnectFailed(e) => {
+                    assert_eq!(io::ErrorKind::ConnectionRefused, e.kind())
+                }
+                e => panic!(
+                    "test fail, expected DialerErrorKind::ConnectFailed, got {:?}",
+                    e
+                ),
             }
             ServiceErrorType::Dialer
         } else {
@@ -105,6 +136,10 @@ impl ServiceHandle for SHandle {
         if let ServiceEvent::SessionOpen { session_context } = event {
             self.session_id = session_context.id;
             self.kind = session_context.ty;
+            assert_eq!(
+                session_context.local_address.is_some(),
+                session_context.ty.is_outbound()
+            );
         }
     }
 }
@@ -0,0 +1,82 @@
+use crate::common::start_service;
+use std::{
+    io,
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    error::HandshakeErrorKind,
+    service::{ProtocolHandle, Service},
+    traits::{AsyncStream, SecurityUpgrade, UpgradeFuture},
+    utils::multiaddr_to_socketaddr,
+};
+
+const LIMIT: usize = 4;
+const CONNECTIONS: usize = 20;
+
+/// Count the handshakes in progress and the peak of them, every handshake takes a while and fails
+#[derive(Clone, Default)]
+struct SlowUpgrade {
+    in_flight: Arc<AtomicUsize>,
+    peak: Arc<AtomicUsize>,
+    finished: Arc<AtomicUsize>,
+}
+
+impl SecurityUpgrade for SlowUpgrade {
+    fn upgrade(&self, _socket: Box<dyn AsyncStream>) -> UpgradeFuture {
+        let upgrade = self.clone();
+        Box::pin(async move {
+            let in_flight = upgrade.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            upgrade.peak.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            upgrade.in_flight.fetch_sub(1, Ordering::SeqCst);
+            upgrade.finished.fetch_add(1, Ordering::SeqCst);
+            Err(HandshakeErrorKind::Upgrade(io::ErrorKind::Other.into()))
+        })
+    }
+}
+
+fn create(upgrade: SlowUpgrade) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .security(upgrade)
+        .max_concurrent_handshakes(LIMIT)
+        .forever(true)
+        .build(())
+}
+
+#[test]
+fn test_max_concurrent_handshakes() {
+    let upgrade = SlowUpgrade::default();
+    let listen_addr = start_service(
+        create(upgrade.clone()),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+    let addr = multiaddr_to_socketaddr(&listen_addr).unwrap();
+
+    // connect all at once, the ones over the limit wait in the backlog
+    let _sockets = (0..CONNECTIONS)
+        .map(|_| TcpStream::connect(addr).unwrap())
+        .collect::<Vec<_>>();
+
+    for _ in 0..50 {
+        if upgrade.finished.load(Ordering::SeqCst) == CONNECTIONS {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert_eq!(upgrade.finished.load(Ordering::SeqCst), CONNECTIONS);
+    assert_eq!(upgrade.peak.load(Ordering::SeqCst), LIMIT);
+}
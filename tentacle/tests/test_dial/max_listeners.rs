@@ -0,0 +1,76 @@
+use futures::StreamExt;
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    error::ListenErrorKind,
+    multiaddr::Multiaddr,
+    service::{ProtocolHandle, ServiceError, ServiceEvent},
+    traits::ServiceHandle,
+};
+
+#[derive(Debug, PartialEq)]
+enum Report {
+    Started,
+    TooMany(usize),
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::ListenError {
+            error: ListenErrorKind::TooManyListeners(limit),
+            ..
+        } = error
+        {
+            let _res = self.sender.send(Report::TooMany(limit));
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::ListenStarted { .. } = event {
+            let _res = self.sender.send(Report::Started);
+        }
+    }
+}
+
+#[test]
+fn test_listens_over_the_limit_are_rejected() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let mut service = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .max_listeners(2)
+        .forever(true)
+        .build(SHandle { sender });
+    let control = service.control().clone();
+
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let listen = |index: u64| {
+        let address: Multiaddr = format!("/memory/{}", 7000 + index).parse().unwrap();
+        control.listen(address).unwrap();
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap()
+    };
+
+    assert_eq!(listen(1), Report::Started);
+    assert_eq!(listen(2), Report::Started);
+    assert_eq!(listen(3), Report::TooMany(2));
+    assert_eq!(listen(4), Report::TooMany(2));
+}
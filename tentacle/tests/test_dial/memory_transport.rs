@@ -0,0 +1,77 @@
+use crate::common::start_service;
+use bytes::Bytes;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, TargetProtocol},
+    traits::ServiceProtocol,
+};
+
+/// The listener says hello on connected, the other side replies with the same message
+struct PHandle {
+    listener: bool,
+    sender: crossbeam_channel::Sender<Bytes>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if self.listener {
+            context.send_message(Bytes::from("hello")).unwrap();
+        }
+    }
+
+    fn received(&mut self, context: ProtocolContextMutRef, data: Bytes) {
+        if !self.listener {
+            context.send_message(data.clone()).unwrap();
+        }
+        let _res = self.sender.send(data);
+    }
+}
+
+fn create(listener: bool, sender: crossbeam_channel::Sender<Bytes>) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || {
+                    ProtocolHandle::Callback(Box::new(PHandle {
+                        listener,
+                        sender: sender.clone(),
+                    }))
+                })
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+#[test]
+fn test_exchange_message_over_memory() {
+    let (listener_sender, listener_receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(true, listener_sender),
+        Some("/memory/1".parse().unwrap()),
+    )
+    .unwrap();
+    assert_eq!(listen_addr, "/memory/1".parse().unwrap());
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(false, sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Bytes::from("hello"))
+    );
+    assert_eq!(
+        listener_receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Bytes::from("hello"))
+    );
+}
@@ -0,0 +1,113 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    error::{DialerErrorKind, TransportErrorKind},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceError, ServiceEvent, TargetProtocol},
+    traits::ServiceHandle,
+    utils::multiaddr_to_socketaddr,
+};
+
+enum Report {
+    Inbound(Multiaddr),
+    BindFamilyMismatch,
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::DialerError {
+            error: DialerErrorKind::TransportError(TransportErrorKind::BindFamilyMismatch(..)),
+            ..
+        } = error
+        {
+            let _res = self.sender.send(Report::BindFamilyMismatch);
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { session_context } = event {
+            if session_context.ty.is_inbound() {
+                let _res = self
+                    .sender
+                    .send(Report::Inbound(session_context.address.clone()));
+            }
+        }
+    }
+}
+
+fn create<F>(shandle: F, outbound_bind: Option<Multiaddr>) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let builder = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated());
+    match outbound_bind {
+        Some(addr) => builder.outbound_bind(addr).build(shandle),
+        None => builder.build(shandle),
+    }
+}
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[test]
+fn test_outbound_bind_source_port() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(SHandle { sender }, None),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let bind: Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", free_port())
+        .parse()
+        .unwrap();
+    let service = create((), Some(bind.clone()));
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    // The remote address seen by the listener is the local address of the dialer
+    match receiver.recv_timeout(Duration::from_secs(5)) {
+        Ok(Report::Inbound(address)) => assert_eq!(
+            multiaddr_to_socketaddr(&address),
+            multiaddr_to_socketaddr(&bind)
+        ),
+        _ => panic!("the session should be opened from the bind address"),
+    }
+}
+
+#[test]
+fn test_outbound_bind_family_mismatch() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let bind = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+    let service = create(SHandle { sender }, Some(bind));
+    let control = service.control().clone();
+    start_service(service, None);
+
+    let target = format!("/ip6/::1/tcp/{}", free_port()).parse().unwrap();
+    control.dial(target, TargetProtocol::All).unwrap();
+    match receiver.recv_timeout(Duration::from_secs(5)) {
+        Ok(Report::BindFamilyMismatch) => (),
+        _ => panic!("the dial should fail with the family mismatch"),
+    }
+}
@@ -0,0 +1,106 @@
+use futures::StreamExt;
+use std::{sync::mpsc::channel, thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ServiceContext},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, ServiceError, ServiceEvent, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+pub fn create<F>(meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    ServiceBuilder::default()
+        .insert_protocol(meta)
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(shandle)
+}
+
+struct PHandle;
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _control: &mut ProtocolContext) {}
+}
+
+#[derive(Clone)]
+struct SHandle {
+    sender: crossbeam_channel::Sender<()>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _env: &mut ServiceContext, error: ServiceError) {
+        panic!("test fail {:?}", error);
+    }
+
+    fn handle_event(&mut self, _control: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { .. } = event {
+            let _res = self.sender.try_send(());
+        }
+    }
+}
+
+fn create_meta(id: ProtocolId) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .service_handle(move || {
+            let handle = Box::new(PHandle);
+            ProtocolHandle::Callback(handle)
+        })
+        .build()
+}
+
+#[test]
+fn test_pause_accept() {
+    let (addr_sender, addr_receiver) = channel::<Multiaddr>();
+    let (sender, receiver) = crossbeam_channel::bounded(2);
+    let mut service = create(create_meta(1.into()), SHandle { sender });
+    let listen_control = service.control().clone();
+    listen_control.pause_accept();
+    assert!(listen_control.state().accept_paused);
+
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+
+            addr_sender.send(listen_addr).unwrap();
+
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let listen_addr = addr_receiver.recv().unwrap();
+
+    let mut service = create(create_meta(1.into()), ());
+    let control = service.control().clone();
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+    assert!(receiver.recv_timeout(Duration::from_secs(1)).is_err());
+    assert_eq!(listen_control.state().session_count, 0);
+
+    listen_control.resume_accept();
+    assert!(!listen_control.state().accept_paused);
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(()));
+}
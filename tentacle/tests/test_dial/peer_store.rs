@@ -0,0 +1,144 @@
+use crate::common::start_service;
+use std::{
+    collections::HashMap,
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    multiaddr::Multiaddr,
+    secio::{PeerId, SecioKeyPair},
+    service::{ProtocolHandle, Service, ServiceEvent, TargetProtocol},
+    traits::{PeerStore, ServiceHandle},
+    utils::{multiaddr_to_socketaddr, socketaddr_to_multiaddr},
+};
+
+#[derive(Default)]
+struct MemoryStore {
+    addrs: Mutex<HashMap<PeerId, Vec<Multiaddr>>>,
+}
+
+impl PeerStore for MemoryStore {
+    fn add_addr(&self, peer_id: PeerId, address: Multiaddr) {
+        let mut addrs = self.addrs.lock().unwrap();
+        let addrs = addrs.entry(peer_id).or_default();
+        if !addrs.contains(&address) {
+            addrs.push(address)
+        }
+    }
+
+    fn addrs(&self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.addrs
+            .lock()
+            .unwrap()
+            .get(peer_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn remove(&self, peer_id: &PeerId, address: &Multiaddr) {
+        if let Some(addrs) = self.addrs.lock().unwrap().get_mut(peer_id) {
+            addrs.retain(|addr| addr != address)
+        }
+    }
+}
+
+/// Reports the remote address of the opened sessions
+struct SHandle {
+    sender: crossbeam_channel::Sender<Multiaddr>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { session_context } = event {
+            let _res = self.sender.send(session_context.address.clone());
+        }
+    }
+}
+
+fn create(
+    key_pair: SecioKeyPair,
+    store: Option<Arc<MemoryStore>>,
+    sender: crossbeam_channel::Sender<Multiaddr>,
+) -> Service<SHandle> {
+    let builder = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(key_pair);
+    match store {
+        Some(store) => builder.peer_store(store),
+        None => builder,
+    }
+    .build(SHandle { sender })
+}
+
+/// Start a listener, return its peer id and listen address
+fn listener() -> (PeerId, Multiaddr) {
+    let key_pair = SecioKeyPair::secp256k1_generated();
+    let peer_id = key_pair.peer_id();
+    let listen_addr = start_service(
+        create(key_pair, None, crossbeam_channel::unbounded().0),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+    (peer_id, listen_addr)
+}
+
+#[test]
+fn test_record_address_on_connect() {
+    let (peer_id, listen_addr) = listener();
+
+    let store = Arc::new(MemoryStore::default());
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(
+        SecioKeyPair::secp256k1_generated(),
+        Some(store.clone()),
+        sender,
+    );
+    let control = service.control().clone();
+    start_service(service, None);
+    control
+        .dial(listen_addr.clone(), TargetProtocol::All)
+        .unwrap();
+
+    let address = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    let addrs = store.addrs(&peer_id);
+    assert_eq!(addrs, vec![address]);
+    assert_eq!(
+        multiaddr_to_socketaddr(&addrs[0]),
+        multiaddr_to_socketaddr(&listen_addr)
+    );
+}
+
+#[test]
+fn test_dial_peer_tries_the_next_address() {
+    let (peer_id, listen_addr) = listener();
+    // nothing listens on it, the dial is refused
+    let dead_addr = {
+        let socket = TcpListener::bind("127.0.0.1:0").unwrap();
+        socketaddr_to_multiaddr(socket.local_addr().unwrap())
+    };
+
+    let store = Arc::new(MemoryStore::default());
+    store.add_addr(peer_id.clone(), dead_addr);
+    store.add_addr(peer_id.clone(), listen_addr.clone());
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(SecioKeyPair::secp256k1_generated(), Some(store), sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial_peer(peer_id, TargetProtocol::All).unwrap();
+
+    let address = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(
+        multiaddr_to_socketaddr(&address),
+        multiaddr_to_socketaddr(&listen_addr)
+    );
+}
@@ -0,0 +1,99 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ReconnectBackoff, Service, ServiceEvent, TargetProtocol},
+    traits::ServiceHandle,
+    SessionId,
+};
+
+#[derive(Debug, PartialEq)]
+enum Report {
+    Open(SessionId),
+    Close,
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        let report = match event {
+            ServiceEvent::SessionOpen { session_context } => Report::Open(session_context.id),
+            ServiceEvent::SessionClose { .. } => Report::Close,
+            _ => return,
+        };
+        let _res = self.sender.send(report);
+    }
+}
+
+fn create(sender: crossbeam_channel::Sender<Report>) -> Service<SHandle> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .reconnect_backoff(ReconnectBackoff {
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(1),
+            jitter: 0.0,
+        })
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(SHandle { sender })
+}
+
+/// Connect a persistent peer, then close the connection from the listener side,
+/// return the reports of the dialer after the close
+fn kill_persistent_connection(remove: bool) -> Vec<Report> {
+    let (listener_sender, listener_receiver) = crossbeam_channel::unbounded();
+    let listener = create(listener_sender);
+    let listener_control = listener.control().clone();
+    let listen_addr =
+        start_service(listener, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control
+        .add_persistent_peer(listen_addr.clone(), TargetProtocol::All)
+        .unwrap();
+
+    assert!(matches!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Report::Open(_))
+    ));
+    let id = match listener_receiver.recv_timeout(Duration::from_secs(5)) {
+        Ok(Report::Open(id)) => id,
+        report => panic!("unexpected report {:?}", report),
+    };
+    if remove {
+        control.remove_persistent_peer(listen_addr).unwrap();
+    }
+    listener_control.disconnect(id).unwrap();
+
+    let mut reports = Vec::new();
+    while let Ok(report) = receiver.recv_timeout(Duration::from_secs(2)) {
+        reports.push(report);
+    }
+    reports
+}
+
+#[test]
+fn test_reconnect_persistent_peer() {
+    let reports = kill_persistent_connection(false);
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0], Report::Close);
+    assert!(matches!(reports[1], Report::Open(_)));
+}
+
+#[test]
+fn test_removed_persistent_peer_is_not_reconnected() {
+    assert_eq!(kill_persistent_connection(true), vec![Report::Close]);
+}
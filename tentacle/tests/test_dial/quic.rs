@@ -0,0 +1,82 @@
+#![cfg(feature = "quic")]
+use crate::common::start_service;
+use bytes::Bytes;
+use std::{borrow::Cow, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    multiaddr::Protocol,
+    secio::{PeerId, SecioKeyPair},
+    service::{ProtocolHandle, Service, TargetProtocol},
+    traits::ServiceProtocol,
+};
+
+/// The listener sends a message on connected, the dialer reports it with the remote peer id
+struct PHandle {
+    listener: bool,
+    sender: crossbeam_channel::Sender<(Bytes, Option<PeerId>)>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if self.listener {
+            context.send_message(Bytes::from("over quic")).unwrap();
+        }
+    }
+
+    fn received(&mut self, context: ProtocolContextMutRef, data: Bytes) {
+        let peer_id = context
+            .session
+            .remote_pubkey
+            .as_ref()
+            .map(|key| key.peer_id());
+        let _res = self.sender.send((data, peer_id));
+    }
+}
+
+fn create(
+    listener: bool,
+    key_pair: SecioKeyPair,
+    sender: crossbeam_channel::Sender<(Bytes, Option<PeerId>)>,
+) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || {
+                    ProtocolHandle::Callback(Box::new(PHandle {
+                        listener,
+                        sender: sender.clone(),
+                    }))
+                })
+                .build(),
+        )
+        .forever(true)
+        .key_pair(key_pair)
+        .build(())
+}
+
+#[test]
+fn test_protocol_over_quic() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listener_key = SecioKeyPair::secp256k1_generated();
+    let listener_id = listener_key.peer_id();
+    let mut listen_addr = start_service(
+        create(true, listener_key, sender.clone()),
+        Some("/ip4/127.0.0.1/udp/0/quic".parse().unwrap()),
+    )
+    .unwrap();
+    listen_addr.push(Protocol::P2P(Cow::Owned(listener_id.clone().into_bytes())));
+
+    let service = create(false, SecioKeyPair::secp256k1_generated(), sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    let (data, peer_id) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(data, Bytes::from("over quic"));
+    // the listener is authenticated by secio over the first quic stream
+    assert_eq!(peer_id, Some(listener_id));
+}
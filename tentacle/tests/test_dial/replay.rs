@@ -0,0 +1,112 @@
+use crate::common::start_service;
+use std::{path::Path, thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    secio::SecioKeyPair,
+    service::{
+        read_records, replay_records, ProtocolHandle, Service, ServiceEvent, TargetProtocol,
+    },
+    traits::ServiceHandle,
+    SessionId,
+};
+
+#[derive(Debug, PartialEq)]
+enum Report {
+    Open,
+    Close,
+}
+
+/// Report the sessions opened and closed
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        match event {
+            ServiceEvent::SessionOpen { .. } => {
+                let _res = self.sender.send(Report::Open);
+            }
+            ServiceEvent::SessionClose { .. } => {
+                let _res = self.sender.send(Report::Close);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn create<F>(shandle: F, recorder: Option<&Path>) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let builder = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated());
+    match recorder {
+        Some(path) => builder.event_recorder(path).build(shandle),
+        None => builder.build(shandle),
+    }
+}
+
+#[test]
+fn test_replay_recorded_tasks() {
+    let path = std::env::temp_dir().join(format!("tentacle-replay-{}", std::process::id()));
+    let _ignore = std::fs::remove_file(&path);
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(SHandle { sender }, None),
+        Some("/memory/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    // record a dial and the disconnect of its session
+    let service = create((), Some(&path));
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Report::Open)
+    );
+    control.disconnect(SessionId::new(1)).unwrap();
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Report::Close)
+    );
+
+    // the writer flushes after each batch of lines
+    let mut records = Vec::new();
+    for _ in 0..50 {
+        records = read_records(&path).unwrap();
+        if records
+            .iter()
+            .any(|record| record.event.starts_with("Disconnect session"))
+        {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let _ignore = std::fs::remove_file(&path);
+
+    // a fresh service does the same again
+    let service = create((), None);
+    let control = service.control().clone();
+    start_service(service, None);
+    assert_eq!(replay_records(&control, &records).unwrap(), 2);
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Report::Open)
+    );
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Report::Close)
+    );
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "ws")]
+use futures::StreamExt;
+use std::{io::Write, net::TcpListener, thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    error::{DialerErrorKind, TransportErrorKind},
+    multiaddr::Protocol,
+    service::{ProtocolHandle, ServiceError, TargetProtocol},
+    traits::ServiceHandle,
+    utils::socketaddr_to_multiaddr,
+};
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<bool>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::DialerError { error, .. } = error {
+            let _res = self.sender.send(matches!(
+                error,
+                DialerErrorKind::TransportError(TransportErrorKind::WsHandshake(_))
+            ));
+        }
+    }
+}
+
+#[test]
+fn test_ws_dial_to_non_websocket_server() {
+    // answers the websocket upgrade request with a plain http response
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut address = socketaddr_to_multiaddr(listener.local_addr().unwrap());
+    address.push(Protocol::Ws);
+    thread::spawn(move || {
+        if let Ok((mut stream, _)) = listener.accept() {
+            let _res = stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n");
+            thread::sleep(Duration::from_secs(1));
+        }
+    });
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let mut service = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .build(SHandle { sender });
+    let control = service.control().clone();
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    control.dial(address, TargetProtocol::All).unwrap();
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(true));
+}
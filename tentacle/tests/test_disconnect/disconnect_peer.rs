@@ -0,0 +1,79 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceEvent, TargetProtocol},
+    traits::ServiceHandle,
+};
+
+#[derive(Debug, PartialEq)]
+enum Report {
+    Open,
+    Close,
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        match event {
+            ServiceEvent::SessionOpen { .. } => {
+                let _res = self.sender.send(Report::Open);
+            }
+            ServiceEvent::SessionClose { .. } => {
+                let _res = self.sender.send(Report::Close);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn create(key_pair: SecioKeyPair, sender: crossbeam_channel::Sender<Report>) -> Service<SHandle> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(key_pair)
+        .build(SHandle { sender })
+}
+
+#[test]
+fn test_disconnect_by_peer_id() {
+    let listener_key = SecioKeyPair::secp256k1_generated();
+    let listener_peer_id = listener_key.peer_id();
+    let listen_addr = start_service(
+        create(listener_key, crossbeam_channel::unbounded().0),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(SecioKeyPair::secp256k1_generated(), sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Report::Open)
+    );
+
+    // not connected, nothing happens
+    control
+        .disconnect_by_peer_id(SecioKeyPair::secp256k1_generated().peer_id())
+        .unwrap();
+    assert!(receiver.recv_timeout(Duration::from_millis(500)).is_err());
+
+    control.disconnect_by_peer_id(listener_peer_id).unwrap();
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Report::Close)
+    );
+}
@@ -1,3 +1,9 @@
+#[path = "../common/mod.rs"]
+mod common;
+mod disconnect_peer;
+mod session_idle_timeout;
+mod session_lifetime;
+
 use futures::{channel, StreamExt};
 use std::{thread, time::Duration};
 use tentacle::{
@@ -0,0 +1,107 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    bytes::Bytes,
+    context::{ProtocolContext, ServiceContext},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceError, ServiceEvent, TargetProtocol, TargetSession},
+    traits::{ServiceHandle, ServiceProtocol},
+};
+
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, PartialEq)]
+enum Report {
+    Timeout,
+    Close,
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::SessionTimeout { .. } = error {
+            let _res = self.sender.send(Report::Timeout);
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionClose { .. } = event {
+            let _res = self.sender.send(Report::Close);
+        }
+    }
+}
+
+/// Sends a message to all sessions periodically if chatty
+struct PHandle {
+    chatty: bool,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, context: &mut ProtocolContext) {
+        if self.chatty {
+            context.set_service_notify(context.proto_id, Duration::from_millis(200), 0);
+        }
+    }
+
+    fn notify(&mut self, context: &mut ProtocolContext, _token: u64) {
+        let _res =
+            context.filter_broadcast(TargetSession::All, context.proto_id, Bytes::from("ping"));
+    }
+}
+
+fn create<F>(shandle: F, chatty: bool, idle_timeout: Option<Duration>) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let builder = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { chatty })))
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated());
+    match idle_timeout {
+        Some(timeout) => builder.session_idle_timeout(timeout).build(shandle),
+        None => builder.build(shandle),
+    }
+}
+
+/// The listener closes the idle sessions, return what it reported in the time
+fn idle_reports(chatty: bool, wait: Duration) -> Vec<Report> {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(SHandle { sender }, false, Some(IDLE_TIMEOUT)),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let service = create((), chatty, None);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    let mut reports = Vec::new();
+    while let Ok(report) = receiver.recv_timeout(wait) {
+        reports.push(report);
+    }
+    reports
+}
+
+#[test]
+fn test_idle_session_is_closed() {
+    assert_eq!(
+        idle_reports(false, IDLE_TIMEOUT * 3),
+        vec![Report::Timeout, Report::Close]
+    );
+}
+
+#[test]
+fn test_session_with_traffic_is_kept() {
+    assert!(idle_reports(true, IDLE_TIMEOUT * 3).is_empty());
+}
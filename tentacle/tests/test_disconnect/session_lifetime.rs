@@ -0,0 +1,77 @@
+use crate::common::start_service;
+use std::time::{Duration, Instant};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceEvent, TargetProtocol},
+    traits::ServiceHandle,
+};
+
+const LIFETIME: Duration = Duration::from_secs(2);
+
+/// Report the time of the session open and close
+struct SHandle {
+    sender: crossbeam_channel::Sender<(bool, Instant)>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        match event {
+            ServiceEvent::SessionOpen { .. } => {
+                let _res = self.sender.send((true, Instant::now()));
+            }
+            ServiceEvent::SessionClose { .. } => {
+                let _res = self.sender.send((false, Instant::now()));
+            }
+            _ => (),
+        }
+    }
+}
+
+fn create<F>(shandle: F, lifetime: Option<Duration>) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let builder = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated());
+    match lifetime {
+        Some(lifetime) => builder.max_session_lifetime(lifetime).build(shandle),
+        None => builder.build(shandle),
+    }
+}
+
+#[test]
+fn test_session_closed_in_last_quarter_of_lifetime() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(SHandle { sender }, Some(LIFETIME)),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let service = create((), None);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    let (open, opened_at) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(open);
+    let (open, closed_at) = receiver.recv_timeout(LIFETIME * 2).unwrap();
+    assert!(!open);
+
+    let lived = closed_at - opened_at;
+    assert!(lived >= LIFETIME * 3 / 4, "closed too early: {:?}", lived);
+    assert!(
+        lived < LIFETIME + Duration::from_millis(500),
+        "closed too late: {:?}",
+        lived
+    );
+}
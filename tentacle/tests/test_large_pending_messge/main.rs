@@ -1,3 +1,13 @@
+#[path = "../common/mod.rs"]
+mod common;
+mod max_buffer_bytes;
+mod metrics;
+mod rate_limit;
+mod recv_rate;
+mod session_buffer_stats;
+mod session_buffer_watermarks;
+mod traffic;
+
 use bytes::Bytes;
 use futures::{channel, StreamExt};
 use std::thread;
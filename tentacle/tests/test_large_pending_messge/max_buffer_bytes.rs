@@ -0,0 +1,111 @@
+use crate::common::start_service;
+use bytes::Bytes;
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef, ServiceContext},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceError, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+};
+
+const MESSAGE_COUNT: usize = 2000;
+const LIMIT: usize = 16 * 1024;
+
+/// Report the memory pressure
+struct SHandle {
+    sender: crossbeam_channel::Sender<(usize, usize)>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _env: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::MemoryPressure { used, limit } = error {
+            let _res = self.sender.send((used, limit));
+        }
+    }
+}
+
+/// The dialer floods messages when connected, the listener is slow at first,
+/// and reports when all messages are received
+struct PHandle {
+    received: usize,
+    sender: crossbeam_channel::Sender<()>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.session.ty.is_outbound() {
+            for _ in 0..MESSAGE_COUNT {
+                let _res = context.send_message(Bytes::from(vec![0; 1024]));
+            }
+        }
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, _data: Bytes) {
+        if self.received == 0 {
+            thread::sleep(Duration::from_secs(1));
+        }
+        self.received += 1;
+        if self.received == MESSAGE_COUNT {
+            let _res = self.sender.send(());
+        }
+    }
+}
+
+fn create(
+    limit: Option<usize>,
+    pressure_sender: crossbeam_channel::Sender<(usize, usize)>,
+    received_sender: crossbeam_channel::Sender<()>,
+) -> Service<SHandle> {
+    let builder = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || {
+                    ProtocolHandle::Callback(Box::new(PHandle {
+                        received: 0,
+                        sender: received_sender.clone(),
+                    }))
+                })
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated());
+    match limit {
+        Some(limit) => builder.max_buffer_bytes(limit),
+        None => builder,
+    }
+    .build(SHandle {
+        sender: pressure_sender,
+    })
+}
+
+#[test]
+fn test_max_buffer_bytes() {
+    let (pressure_sender, pressure_receiver) = crossbeam_channel::unbounded();
+    let (received_sender, received_receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(Some(LIMIT), pressure_sender, received_sender),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (sender, _receiver) = crossbeam_channel::unbounded();
+    let service = create(None, sender, crossbeam_channel::unbounded().0);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    let (used, limit) = pressure_receiver
+        .recv_timeout(Duration::from_secs(10))
+        .unwrap();
+    assert_eq!(limit, LIMIT);
+    assert!(used > LIMIT);
+
+    // reading is paused, not dropped, all messages arrive once the handle catches up
+    assert!(received_receiver
+        .recv_timeout(Duration::from_secs(20))
+        .is_ok());
+}
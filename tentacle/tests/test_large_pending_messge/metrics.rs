@@ -0,0 +1,134 @@
+#![cfg(feature = "metrics")]
+use crate::common::start_service;
+use prometheus::Registry;
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    bytes::Bytes,
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, TargetProtocol},
+    traits::ServiceProtocol,
+};
+
+/// The dialer sends a message once connected, the listener reports what it received
+struct PHandle {
+    sender: crossbeam_channel::Sender<Bytes>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.session.ty.is_outbound() {
+            let _res = context.send_message(Bytes::from("hello metrics"));
+        }
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        let _res = self.sender.send(data);
+    }
+}
+
+fn create(registry: &Registry, sender: crossbeam_channel::Sender<Bytes>) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { sender })))
+                .build(),
+        )
+        .metrics(registry)
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+/// Value of the metric in the registry, with the label if given
+fn value(registry: &Registry, name: &str, label: Option<(&str, &str)>) -> u64 {
+    registry
+        .gather()
+        .iter()
+        .filter(|family| family.get_name() == name)
+        .flat_map(|family| family.get_metric())
+        .filter(|metric| {
+            label.map_or(true, |(name, value)| {
+                metric
+                    .get_label()
+                    .iter()
+                    .any(|pair| pair.get_name() == name && pair.get_value() == value)
+            })
+        })
+        .map(|metric| {
+            if metric.has_gauge() {
+                metric.get_gauge().get_value()
+            } else {
+                metric.get_counter().get_value()
+            }
+        })
+        .sum::<f64>() as u64
+}
+
+#[test]
+fn test_metrics_after_one_connection() {
+    let listener_registry = Registry::new();
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(&listener_registry, sender.clone()),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let dialer_registry = Registry::new();
+    let service = create(&dialer_registry, sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    let data = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(data, Bytes::from("hello metrics"));
+
+    assert_eq!(value(&listener_registry, "tentacle_sessions", None), 1);
+    assert_eq!(
+        value(
+            &listener_registry,
+            "tentacle_opened_sessions_total",
+            Some(("direction", "inbound"))
+        ),
+        1
+    );
+    assert_eq!(value(&dialer_registry, "tentacle_sessions", None), 1);
+    assert!(value(&listener_registry, "tentacle_received_bytes_total", None) >= data.len() as u64);
+    assert!(value(&dialer_registry, "tentacle_sent_bytes_total", None) >= data.len() as u64);
+
+    // nothing listens on the port of the closed listener
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let closed_addr = format!("/ip4/127.0.0.1/tcp/{}", closed.local_addr().unwrap().port());
+    drop(closed);
+    control
+        .dial(closed_addr.parse().unwrap(), TargetProtocol::All)
+        .unwrap();
+    let mut dial_errors = 0;
+    for _ in 0..50 {
+        dial_errors = value(
+            &dialer_registry,
+            "tentacle_errors_total",
+            Some(("kind", "dial")),
+        );
+        if dial_errors > 0 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert_eq!(dial_errors, 1);
+}
+
+#[test]
+fn test_metrics_registered_twice() {
+    let registry = Registry::new();
+    let (sender, _receiver) = crossbeam_channel::unbounded();
+    let _first = create(&registry, sender.clone());
+    // the second service can't register the same metrics, it's built without them
+    let _second = create(&registry, sender);
+    assert_eq!(value(&registry, "tentacle_sessions", None), 0);
+}
@@ -0,0 +1,107 @@
+use crate::common::start_service;
+use std::time::{Duration, Instant};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    bytes::Bytes,
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, RateLimit, Service, TargetProtocol},
+    traits::ServiceProtocol,
+};
+
+const MESSAGE_SIZE: usize = 16 * 1024;
+const MESSAGE_COUNT: usize = 16;
+const RATE: u64 = 64 * 1024;
+
+/// Sends all messages once connected, and reports how long the received ones were spread over
+struct PHandle {
+    sender: crossbeam_channel::Sender<Duration>,
+    first: Option<Instant>,
+    received: usize,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        for _ in 0..MESSAGE_COUNT {
+            let _res = context.send_message(Bytes::from(vec![0; MESSAGE_SIZE]));
+        }
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        let first = *self.first.get_or_insert_with(Instant::now);
+        self.received += data.len();
+        if self.received == MESSAGE_SIZE * MESSAGE_COUNT {
+            let _res = self.sender.send(first.elapsed());
+        }
+    }
+}
+
+fn create(limit: RateLimit, sender: crossbeam_channel::Sender<Duration>) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || {
+                    ProtocolHandle::Callback(Box::new(PHandle {
+                        sender,
+                        first: None,
+                        received: 0,
+                    }))
+                })
+                .build(),
+        )
+        .global_rate_limit(limit)
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+/// Both sides send the messages, return how long the listener took to receive them
+fn transfer(listener_limit: RateLimit, dialer_limit: RateLimit) -> Duration {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(listener_limit, sender),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let service = create(dialer_limit, crossbeam_channel::unbounded().0);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    receiver.recv_timeout(Duration::from_secs(20)).unwrap()
+}
+
+/// One second of burst, the rest is spread at the rate
+fn expected() -> Duration {
+    Duration::from_secs_f64(((MESSAGE_SIZE * MESSAGE_COUNT) as u64 - RATE) as f64 / RATE as f64)
+}
+
+#[test]
+fn test_upload_limit() {
+    let limit = RateLimit {
+        upload: Some(RATE),
+        download: None,
+    };
+    let elapsed = transfer(RateLimit::default(), limit);
+    assert!(elapsed >= expected().mul_f64(0.8), "{:?}", elapsed);
+}
+
+#[test]
+fn test_download_limit() {
+    let limit = RateLimit {
+        upload: None,
+        download: Some(RATE),
+    };
+    let elapsed = transfer(limit, RateLimit::default());
+    assert!(elapsed >= expected().mul_f64(0.8), "{:?}", elapsed);
+}
+
+#[test]
+fn test_no_limit() {
+    let elapsed = transfer(RateLimit::default(), RateLimit::default());
+    assert!(elapsed < expected().mul_f64(0.5), "{:?}", elapsed);
+}
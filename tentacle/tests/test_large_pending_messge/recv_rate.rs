@@ -0,0 +1,84 @@
+use crate::common::start_service;
+use bytes::Bytes;
+use std::time::{Duration, Instant};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, TargetProtocol},
+    traits::ServiceProtocol,
+};
+
+const RATE: u32 = 50;
+
+/// The dialer floods the listener, the listener reports what it receives
+struct PHandle {
+    listener: bool,
+    sender: crossbeam_channel::Sender<Bytes>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if !self.listener {
+            for index in 0..RATE * 3 {
+                context
+                    .send_message(Bytes::from(index.to_be_bytes().to_vec()))
+                    .unwrap();
+            }
+        }
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        let _res = self.sender.send(data);
+    }
+}
+
+fn create(listener: bool, sender: crossbeam_channel::Sender<Bytes>) -> Service<()> {
+    let builder = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || {
+                    ProtocolHandle::Callback(Box::new(PHandle {
+                        listener,
+                        sender: sender.clone(),
+                    }))
+                })
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated());
+    if listener {
+        builder.max_recv_rate(RATE).build(())
+    } else {
+        builder.build(())
+    }
+}
+
+#[test]
+fn test_recv_rate_limit_delays_but_keeps_messages() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(true, sender),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let service = create(false, crossbeam_channel::unbounded().0);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    let mut start = None;
+    for index in 0..RATE * 3 {
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok(Bytes::from(index.to_be_bytes().to_vec()))
+        );
+        start.get_or_insert_with(Instant::now);
+    }
+    // three seconds worth of messages, none of them is dropped
+    assert!(start.unwrap().elapsed() >= Duration::from_millis(1500));
+}
@@ -0,0 +1,139 @@
+use crate::common::start_service;
+use futures::executor::block_on;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ProtocolContextMutRef,
+    service::{ProtocolHandle, ProtocolMeta, Service, SessionBufferStats, TargetProtocol},
+    traits::{ServiceHandle, SessionProtocol},
+    SessionId,
+};
+
+const NOTIFY_COUNT: usize = 1024;
+
+/// Blocks the inbound session handle on connected until the gate opens
+struct PHandle {
+    opened: crossbeam_channel::Sender<SessionId>,
+    gate: crossbeam_channel::Receiver<()>,
+    notified: Arc<AtomicUsize>,
+}
+
+impl SessionProtocol for PHandle {
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.session.ty.is_inbound() {
+            let _res = self.opened.send(context.session.id);
+            let _res = self.gate.recv();
+        }
+    }
+
+    fn notify(&mut self, _context: ProtocolContextMutRef, _token: u64) {
+        self.notified.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn create_meta(
+    opened: crossbeam_channel::Sender<SessionId>,
+    gate: crossbeam_channel::Receiver<()>,
+    notified: Arc<AtomicUsize>,
+) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(1.into())
+        .session_handle(move || {
+            ProtocolHandle::Callback(Box::new(PHandle {
+                opened: opened.clone(),
+                gate: gate.clone(),
+                notified: notified.clone(),
+            }))
+        })
+        .build()
+}
+
+fn create<F>(meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    ServiceBuilder::default()
+        .insert_protocol(meta)
+        .forever(true)
+        .build(shandle)
+}
+
+fn wait_stats<F>(check: F) -> bool
+where
+    F: Fn() -> bool,
+{
+    let now = Instant::now();
+    while now.elapsed() < Duration::from_secs(5) {
+        if check() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+#[test]
+fn test_session_buffer_stats() {
+    let (opened_sender, opened_receiver) = crossbeam_channel::unbounded();
+    let (gate_sender, gate_receiver) = crossbeam_channel::unbounded();
+    let notified = Arc::new(AtomicUsize::new(0));
+    let service = create(
+        create_meta(
+            opened_sender.clone(),
+            gate_receiver.clone(),
+            notified.clone(),
+        ),
+        (),
+    );
+    let control = service.control().clone();
+    let listen_addr =
+        start_service(service, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let dialer = create(
+        create_meta(opened_sender, gate_receiver, Arc::new(AtomicUsize::new(0))),
+        (),
+    );
+    let dialer_control = dialer.control().clone();
+    start_service(dialer, None);
+    dialer_control
+        .dial(listen_addr, TargetProtocol::All)
+        .unwrap();
+
+    let session_id = opened_receiver
+        .recv_timeout(Duration::from_secs(5))
+        .expect("the inbound protocol should be opened");
+
+    // The handle is blocked, the notifications over its channel are kept on the service
+    for token in 0..NOTIFY_COUNT as u64 {
+        control
+            .session_notify_now(session_id, 1.into(), token)
+            .unwrap();
+    }
+    let stats = || block_on(control.session_buffer_stats(session_id)).unwrap();
+    assert!(wait_stats(
+        || matches!(stats(), Some(stats) if stats.read_session_buf > 0)
+    ));
+    let blocked = stats().unwrap();
+    assert!(blocked.read_session_buf < NOTIFY_COUNT);
+    assert_eq!(blocked.high_write_buf + blocked.write_buf, 0);
+
+    // No stats of a session not found
+    assert_eq!(
+        block_on(control.session_buffer_stats(100.into())).unwrap(),
+        None
+    );
+
+    // Drained once the handle is unblocked
+    gate_sender.send(()).unwrap();
+    assert!(wait_stats(
+        || notified.load(Ordering::SeqCst) == NOTIFY_COUNT
+    ));
+    assert_eq!(stats(), Some(SessionBufferStats::default()));
+}
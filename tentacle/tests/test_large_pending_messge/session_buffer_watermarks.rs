@@ -0,0 +1,113 @@
+use crate::common::start_service;
+use std::{sync::Arc, thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    bytes::Bytes,
+    context::{ProtocolContext, ProtocolContextMutRef, ServiceContext, SessionContext},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, RateLimit, Service, ServiceError, ServiceEvent, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+};
+
+const MESSAGE_SIZE: usize = 1024;
+const MESSAGE_COUNT: usize = 16;
+const HIGH: usize = 4 * MESSAGE_SIZE;
+const LOW: usize = 3 * MESSAGE_SIZE;
+
+enum Report {
+    Blocked(Arc<SessionContext>),
+    Closed,
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Report>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::SessionBlocked { session_context } = error {
+            let _res = self.sender.send(Report::Blocked(session_context));
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionClose { .. } = event {
+            let _res = self.sender.send(Report::Closed);
+        }
+    }
+}
+
+/// Sends all messages once connected
+struct PHandle;
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        for _ in 0..MESSAGE_COUNT {
+            let _res = context.send_message(Bytes::from(vec![0; MESSAGE_SIZE]));
+        }
+    }
+}
+
+fn create<F>(shandle: F, limit: RateLimit) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::Callback(Box::new(PHandle)))
+                .build(),
+        )
+        .global_rate_limit(limit)
+        .session_buffer_watermarks(HIGH, LOW)
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(shandle)
+}
+
+#[test]
+fn test_session_buffer_watermarks() {
+    let listen_addr = start_service(
+        create((), RateLimit::default()),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    // The messages over the rate limit are kept unsent
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let limit = RateLimit {
+        upload: Some(MESSAGE_SIZE as u64),
+        download: None,
+    };
+    let service = create(SHandle { sender }, limit);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    let session_context = match receiver.recv_timeout(Duration::from_secs(5)) {
+        Ok(Report::Blocked(session_context)) => session_context,
+        _ => panic!("the session should be blocked"),
+    };
+    // The messages over the high watermark are dropped, but the session is kept
+    assert!(receiver.recv_timeout(Duration::from_secs(2)).is_err());
+    let dropped = session_context.dropped_messages();
+    assert!(dropped > 0);
+
+    // Resumed once the unsent data falls to the low watermark
+    while session_context.pending_data_size() > LOW {
+        thread::sleep(Duration::from_millis(100));
+    }
+    control
+        .send_message_to(
+            session_context.id,
+            1.into(),
+            Bytes::from(vec![0; MESSAGE_SIZE]),
+        )
+        .unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(session_context.dropped_messages(), dropped);
+    assert!(receiver.try_recv().is_err());
+}
@@ -0,0 +1,116 @@
+use crate::common::start_service;
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    bytes::Bytes,
+    context::{ProtocolContext, ProtocolContextMutRef},
+    metrics::TrafficCount,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, ServiceControl, TargetProtocol},
+    traits::ServiceProtocol,
+    ProtocolId,
+};
+
+const MESSAGE_SIZE: usize = 100;
+const MESSAGE_COUNT: usize = 10;
+
+/// The dialer sends the messages once connected, the listener reports the received ones
+struct PHandle {
+    sender: crossbeam_channel::Sender<ProtocolId>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.session.ty.is_outbound() {
+            for _ in 0..MESSAGE_COUNT {
+                let _res = context.send_message(Bytes::from(vec![0; MESSAGE_SIZE]));
+            }
+        }
+    }
+
+    fn received(&mut self, context: ProtocolContextMutRef, _data: Bytes) {
+        let _res = self.sender.send(context.proto_id);
+    }
+}
+
+fn meta(id: ProtocolId, sender: crossbeam_channel::Sender<ProtocolId>) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { sender })))
+        .build()
+}
+
+fn create(sender: crossbeam_channel::Sender<ProtocolId>) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(meta(1.into(), sender.clone()))
+        .insert_protocol(meta(2.into(), sender))
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+fn traffic(control: &ServiceControl, proto_id: Option<ProtocolId>) -> TrafficCount {
+    let session = &control.sessions()[0];
+    let traffic = control.session_traffic(session.id).unwrap();
+    match proto_id {
+        Some(proto_id) => traffic.protocols[&proto_id],
+        None => traffic.total,
+    }
+}
+
+#[test]
+fn test_session_traffic() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listener = create(sender);
+    let listener_control = listener.control().clone();
+    let listen_addr =
+        start_service(listener, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let dialer = create(crossbeam_channel::unbounded().0);
+    let dialer_control = dialer.control().clone();
+    start_service(dialer, None);
+    dialer_control
+        .dial(listen_addr, TargetProtocol::All)
+        .unwrap();
+
+    for _ in 0..MESSAGE_COUNT * 2 {
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    let bytes = (MESSAGE_SIZE * MESSAGE_COUNT) as u64;
+    assert_eq!(
+        traffic(&dialer_control, None),
+        TrafficCount {
+            sent: bytes * 2,
+            received: 0
+        }
+    );
+    assert_eq!(
+        traffic(&listener_control, Some(1.into())),
+        TrafficCount {
+            sent: 0,
+            received: bytes
+        }
+    );
+
+    // kept after the protocol is closed
+    let session_id = dialer_control.sessions()[0].id;
+    dialer_control.close_protocol(session_id, 1.into()).unwrap();
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(
+        traffic(&listener_control, Some(1.into())),
+        TrafficCount {
+            sent: 0,
+            received: bytes
+        }
+    );
+    assert_eq!(
+        traffic(&listener_control, None),
+        TrafficCount {
+            sent: 0,
+            received: bytes * 2
+        }
+    );
+}
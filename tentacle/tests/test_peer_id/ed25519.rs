@@ -0,0 +1,85 @@
+use crate::common::start_service;
+use std::{borrow::Cow, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    multiaddr::{Multiaddr, Protocol},
+    secio::{PublicKey, SecioKeyPair},
+    service::{ProtocolHandle, Service, ServiceEvent, TargetProtocol},
+    traits::ServiceHandle,
+};
+
+/// Reports the remote public key of the opened sessions
+struct SHandle {
+    sender: crossbeam_channel::Sender<Option<PublicKey>>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { session_context } = event {
+            let _res = self.sender.send(session_context.remote_pubkey.clone());
+        }
+    }
+}
+
+fn create(
+    key_pair: SecioKeyPair,
+    sender: crossbeam_channel::Sender<Option<PublicKey>>,
+) -> Service<SHandle> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(key_pair)
+        .build(SHandle { sender })
+}
+
+/// The dialer connects to the listener by an address with its peer id
+fn connect(listener_key: SecioKeyPair, dialer_key: SecioKeyPair) {
+    let (listener_sender, listener_receiver) = crossbeam_channel::unbounded();
+    let mut listen_addr = start_service(
+        create(listener_key.clone(), listener_sender),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+    listen_addr.push(Protocol::P2P(Cow::Owned(
+        listener_key.peer_id().into_bytes(),
+    )));
+    // the peer id can be given as a string, such as a libp2p one
+    let listen_addr: Multiaddr = listen_addr.to_string().parse().unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(dialer_key.clone(), sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Some(listener_key.public_key()))
+    );
+    assert_eq!(
+        listener_receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Some(dialer_key.public_key()))
+    );
+}
+
+#[test]
+fn test_ed25519_peers() {
+    connect(
+        SecioKeyPair::ed25519_generated(),
+        SecioKeyPair::ed25519_generated(),
+    )
+}
+
+#[test]
+fn test_ed25519_with_secp256k1_peer() {
+    connect(
+        SecioKeyPair::ed25519_generated(),
+        SecioKeyPair::secp256k1_generated(),
+    )
+}
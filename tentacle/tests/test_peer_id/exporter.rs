@@ -0,0 +1,104 @@
+#![cfg(feature = "danger-exporter")]
+
+use futures::StreamExt;
+use std::{sync::mpsc::channel, thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+};
+
+pub fn create<F>(secio: bool, meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let builder = ServiceBuilder::default()
+        .insert_protocol(meta)
+        .forever(true);
+
+    if secio {
+        builder
+            .key_pair(SecioKeyPair::secp256k1_generated())
+            .build(shandle)
+    } else {
+        builder.build(shandle)
+    }
+}
+
+struct PHandle {
+    sender: crossbeam_channel::Sender<Option<Vec<u8>>>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        let exported = context.session.export_keying_material(b"test exporter", 48);
+        let _res = self.sender.send(exported);
+    }
+}
+
+fn create_meta(sender: crossbeam_channel::Sender<Option<Vec<u8>>>) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(1.into())
+        .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { sender })))
+        .build()
+}
+
+fn export_on_both_side(secio: bool) -> (Option<Vec<u8>>, Option<Vec<u8>>) {
+    let (addr_sender, addr_receiver) = channel::<Multiaddr>();
+    let (sender, receiver) = crossbeam_channel::bounded(2);
+
+    let mut service = create(secio, create_meta(sender.clone()), ());
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            addr_sender.send(listen_addr).unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let mut service = create(secio, create_meta(sender), ());
+    let control = service.control().clone();
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let listen_addr = addr_receiver.recv().unwrap();
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    (
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+    )
+}
+
+#[test]
+fn test_export_keying_material() {
+    let (a, b) = export_on_both_side(true);
+    assert_eq!(a.as_ref().map(Vec::len), Some(48));
+    assert_eq!(a, b);
+}
+
+#[test]
+fn test_export_without_secio() {
+    assert_eq!(export_on_both_side(false), (None, None));
+}
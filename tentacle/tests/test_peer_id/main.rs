@@ -1,3 +1,10 @@
+#[path = "../common/mod.rs"]
+mod common;
+mod ed25519;
+mod exporter;
+mod peer_id_codec;
+mod security_params;
+
 use futures::StreamExt;
 use std::{borrow::Cow, sync::mpsc::channel, thread};
 use tentacle::{
@@ -33,6 +40,7 @@ impl ServiceProtocol for PHandle {
 struct EmptySHandle {
     sender: crossbeam_channel::Sender<usize>,
     error_count: usize,
+    expected_errors: usize,
 }
 
 impl ServiceHandle for EmptySHandle {
@@ -51,7 +59,7 @@ impl ServiceHandle for EmptySHandle {
             panic!("test fail {:?}", error);
         }
 
-        if self.error_count > 8 {
+        if self.error_count >= self.expected_errors {
             let _res = self.sender.try_send(self.error_count);
         }
     }
@@ -63,12 +71,13 @@ impl ServiceHandle for EmptySHandle {
     }
 }
 
-fn create_shandle() -> (EmptySHandle, crossbeam_channel::Receiver<usize>) {
+fn create_shandle(expected_errors: usize) -> (EmptySHandle, crossbeam_channel::Receiver<usize>) {
     let (sender, receiver) = crossbeam_channel::bounded(2);
     (
         EmptySHandle {
             sender,
             error_count: 0,
+            expected_errors,
         },
         receiver,
     )
@@ -88,7 +97,7 @@ fn create_meta(id: ProtocolId) -> ProtocolMeta {
         .build()
 }
 
-fn test_peer_id(fail: bool) {
+fn test_peer_id(fail: bool, with_expected_peer_id: bool) {
     let meta = create_meta(1.into());
     let key = SecioKeyPair::secp256k1_generated();
     let (addr_sender, addr_receiver) = channel::<Multiaddr>();
@@ -114,7 +123,8 @@ fn test_peer_id(fail: bool) {
 
     let mut listen_addr = addr_receiver.recv().unwrap();
 
-    let (shandle, error_receiver) = create_shandle();
+    let expected_errors = if with_expected_peer_id { 1 } else { 9 };
+    let (shandle, error_receiver) = create_shandle(expected_errors);
     let meta = create_meta(1.into());
     let mut service = create(SecioKeyPair::secp256k1_generated(), meta, shandle);
     let control = service.control().clone();
@@ -129,7 +139,21 @@ fn test_peer_id(fail: bool) {
         });
     });
 
-    if fail {
+    if with_expected_peer_id {
+        let peer_id = if fail {
+            SecioKeyPair::secp256k1_generated().peer_id()
+        } else {
+            key.peer_id()
+        };
+        control
+            .dial_with_peer_id(listen_addr, peer_id, TargetProtocol::All)
+            .unwrap();
+        if fail {
+            assert_eq!(error_receiver.recv(), Ok(1));
+        } else {
+            assert_eq!(error_receiver.recv(), Ok(0));
+        }
+    } else if fail {
         (1..11).for_each(|_| {
             let mut addr = listen_addr.clone();
             addr.push(MultiProtocol::P2P(Cow::Owned(
@@ -147,10 +171,20 @@ fn test_peer_id(fail: bool) {
 
 #[test]
 fn test_fail() {
-    test_peer_id(true)
+    test_peer_id(true, false)
 }
 
 #[test]
 fn test_succeed() {
-    test_peer_id(false)
+    test_peer_id(false, false)
+}
+
+#[test]
+fn test_expected_peer_id_fail() {
+    test_peer_id(true, true)
+}
+
+#[test]
+fn test_expected_peer_id_succeed() {
+    test_peer_id(false, true)
 }
@@ -0,0 +1,110 @@
+use crate::common::start_service;
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    multiaddr::Protocol,
+    secio::{PeerId, PublicKey, SecioKeyPair},
+    service::{ProtocolHandle, Service, ServiceControl, ServiceEvent, TargetProtocol},
+    traits::{PeerIdCodec, ServiceHandle},
+};
+
+/// Truncate the default peer id to 12 bytes
+struct TruncateCodec;
+
+impl PeerIdCodec for TruncateCodec {
+    fn peer_id(&self, key: &PublicKey) -> PeerId {
+        PeerId::from_raw_bytes(key.peer_id().as_bytes()[..12].to_vec())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Option<PeerId> {
+        if bytes.len() == 12 {
+            Some(PeerId::from_raw_bytes(bytes.to_vec()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Report the peer id in the snapshot of the opened sessions
+struct SnapshotHandle(crossbeam_channel::Sender<Option<String>>);
+
+impl ServiceHandle for SnapshotHandle {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { session_context } = event {
+            let _res = self.0.send(session_context.to_snapshot().peer_id);
+        }
+    }
+}
+
+fn create<F>(key_pair: SecioKeyPair, handle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(key_pair)
+        .peer_id_codec(TruncateCodec)
+        .build(handle)
+}
+
+fn wait_connected(control: &ServiceControl, peer_id: &PeerId) -> bool {
+    for _ in 0..50 {
+        if futures::executor::block_on(control.is_connected(peer_id.clone())).unwrap_or(false) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    false
+}
+
+#[test]
+fn test_dial_with_custom_peer_id() {
+    let listen_key = SecioKeyPair::secp256k1_generated();
+    let listen_peer_id = TruncateCodec.peer_id(&listen_key.public_key());
+    let default_peer_id = listen_key.peer_id();
+    let mut listen_addr = start_service(
+        create(listen_key, ()),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+    listen_addr.push(Protocol::P2P(listen_peer_id.as_bytes().to_vec().into()));
+
+    let service = create(SecioKeyPair::secp256k1_generated(), ());
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    // the session is identified by the custom peer id
+    assert!(wait_connected(&control, &listen_peer_id));
+    assert!(!futures::executor::block_on(control.is_connected(default_peer_id)).unwrap());
+}
+
+#[test]
+fn test_snapshot_with_custom_peer_id() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(SecioKeyPair::secp256k1_generated(), SnapshotHandle(sender)),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let dial_key = SecioKeyPair::secp256k1_generated();
+    let dial_peer_id = TruncateCodec.peer_id(&dial_key.public_key());
+    let service = create(dial_key, ());
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    // the snapshot shows the peer id derived by the codec
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+        Some(dial_peer_id.to_base58())
+    );
+}
@@ -0,0 +1,95 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    secio::{crypto::cipher::CipherType, SecioKeyPair, SecurityParams},
+    service::{ProtocolHandle, Service, ServiceEvent, TargetProtocol},
+    traits::ServiceHandle,
+};
+
+/// Reports the negotiated algorithms of the opened sessions
+struct SHandle {
+    sender: crossbeam_channel::Sender<Option<SecurityParams>>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { session_context } = event {
+            let _res = self.sender.send(session_context.security_params());
+        }
+    }
+}
+
+fn create(
+    secio: bool,
+    ciphers: Option<Vec<CipherType>>,
+    sender: crossbeam_channel::Sender<Option<SecurityParams>>,
+) -> Service<SHandle> {
+    let builder = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true);
+    let builder = match ciphers {
+        Some(ciphers) => builder.secio_ciphers(ciphers),
+        None => builder,
+    };
+    if secio {
+        builder
+            .key_pair(SecioKeyPair::secp256k1_generated())
+            .build(SHandle { sender })
+    } else {
+        builder.build(SHandle { sender })
+    }
+}
+
+fn connect(
+    secio: bool,
+    ciphers: Option<Vec<CipherType>>,
+) -> (Option<SecurityParams>, Option<SecurityParams>) {
+    let (listener_sender, listener_receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(secio, ciphers.clone(), listener_sender),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(secio, ciphers, sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    (
+        listener_receiver
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap(),
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+    )
+}
+
+#[test]
+fn test_both_sides_report_the_negotiated_params() {
+    let (listener, dialer) = connect(true, None);
+    assert!(listener.is_some());
+    assert_eq!(listener, dialer);
+}
+
+#[test]
+fn test_no_params_without_secio() {
+    assert_eq!(connect(false, None), (None, None));
+}
+
+#[test]
+fn test_preferred_secio_cipher() {
+    let (listener, dialer) = connect(
+        true,
+        Some(vec![CipherType::Aes256Gcm, CipherType::ChaCha20Poly1305]),
+    );
+    assert_eq!(listener.unwrap().cipher, CipherType::Aes256Gcm);
+    assert_eq!(dialer.unwrap().cipher, CipherType::Aes256Gcm);
+}
@@ -0,0 +1,113 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    bytes::Bytes,
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, TargetProtocol},
+    traits::ServiceProtocol,
+    ProtocolId, SessionId,
+};
+
+const BULK: ProtocolId = ProtocolId::new(1);
+const MARKED: ProtocolId = ProtocolId::new(2);
+const UNMARKED: ProtocolId = ProtocolId::new(3);
+const BULK_SIZE: usize = 16 * 1024;
+const BULK_COUNT: usize = 1000;
+
+/// Report the session once the protocol is connected, and the protocol of the received messages
+struct PHandle {
+    connected: crossbeam_channel::Sender<SessionId>,
+    received: crossbeam_channel::Sender<ProtocolId>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        let _res = self.connected.send(context.session.id);
+    }
+
+    fn received(&mut self, context: ProtocolContextMutRef, _data: Bytes) {
+        let _res = self.received.send(context.proto_id);
+    }
+}
+
+fn create_meta(
+    id: ProtocolId,
+    connected: crossbeam_channel::Sender<SessionId>,
+    received: crossbeam_channel::Sender<ProtocolId>,
+) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .low_latency(id == MARKED)
+        .service_handle(move || {
+            ProtocolHandle::Callback(Box::new(PHandle {
+                connected: connected.clone(),
+                received: received.clone(),
+            }))
+        })
+        .build()
+}
+
+fn create(
+    connected: crossbeam_channel::Sender<SessionId>,
+    received: crossbeam_channel::Sender<ProtocolId>,
+) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(create_meta(BULK, connected.clone(), received.clone()))
+        .insert_protocol(create_meta(MARKED, connected.clone(), received.clone()))
+        .insert_protocol(create_meta(UNMARKED, connected, received))
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+#[test]
+fn test_low_latency_protocol_under_load() {
+    let (received_sender, received) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(crossbeam_channel::unbounded().0, received_sender),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (connected_sender, connected) = crossbeam_channel::unbounded();
+    let service = create(connected_sender, crossbeam_channel::unbounded().0);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    let mut session_id = None;
+    for _ in 0..3 {
+        session_id = connected.recv_timeout(Duration::from_secs(5)).ok();
+    }
+    let session_id = session_id.unwrap();
+
+    for _ in 0..BULK_COUNT {
+        control
+            .send_message_to(session_id, BULK, Bytes::from(vec![0; BULK_SIZE]))
+            .unwrap();
+    }
+    // The unmarked one is sent first, but waits behind the bulk data
+    control
+        .send_message_to(session_id, UNMARKED, Bytes::from("unmarked"))
+        .unwrap();
+    control
+        .send_message_to(session_id, MARKED, Bytes::from("marked"))
+        .unwrap();
+
+    // The number of the bulk messages received before each of them
+    let mut arrived = Vec::new();
+    let mut bulk = 0;
+    while arrived.len() < 2 {
+        let id = received.recv_timeout(Duration::from_secs(20)).unwrap();
+        if id == BULK {
+            bulk += 1;
+        } else {
+            arrived.push((id, bulk));
+        }
+    }
+    assert_eq!(arrived[0].0, MARKED, "{:?}", arrived);
+}
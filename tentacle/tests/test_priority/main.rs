@@ -1,3 +1,9 @@
+#[path = "../common/mod.rs"]
+mod common;
+mod low_latency_protocol;
+mod message_order;
+mod session_priority;
+
 use bytes::Bytes;
 use futures::{channel, StreamExt};
 use std::{
@@ -0,0 +1,152 @@
+use crate::common::start_service;
+use bytes::Bytes;
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolClosePolicy, ProtocolHandle, Service, TargetProtocol},
+    traits::ServiceProtocol,
+};
+
+const MESSAGE_COUNT: u32 = 1024;
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    Connected,
+    Received(u32),
+    Disconnected,
+}
+
+/// The listener sends numbered messages and closes the protocol right after,
+/// the dialer reports what it sees and slows down now and then to build backpressure
+struct PHandle {
+    listener: bool,
+    sender: crossbeam_channel::Sender<Event>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if self.listener {
+            for i in 0..MESSAGE_COUNT {
+                // 4kb per message
+                let mut data = i.to_be_bytes().to_vec();
+                data.resize(4096, 0);
+                context.send_message(Bytes::from(data)).unwrap();
+            }
+            context
+                .close_protocol(context.session.id, context.proto_id)
+                .unwrap();
+        } else {
+            let _res = self.sender.send(Event::Connected);
+        }
+    }
+
+    fn disconnected(&mut self, _context: ProtocolContextMutRef) {
+        if !self.listener {
+            let _res = self.sender.send(Event::Disconnected);
+        }
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        let mut seq = [0; 4];
+        seq.copy_from_slice(&data[..4]);
+        let seq = u32::from_be_bytes(seq);
+        if seq % 128 == 0 {
+            thread::sleep(Duration::from_millis(50));
+        }
+        let _res = self.sender.send(Event::Received(seq));
+    }
+}
+
+fn create(
+    secio: bool,
+    listener: bool,
+    policy: ProtocolClosePolicy,
+    sender: crossbeam_channel::Sender<Event>,
+) -> Service<()> {
+    let builder = ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .close_policy(policy)
+                .service_handle(move || {
+                    ProtocolHandle::Callback(Box::new(PHandle { listener, sender }))
+                })
+                .build(),
+        )
+        .forever(true);
+    if secio {
+        builder
+            .key_pair(SecioKeyPair::secp256k1_generated())
+            .build(())
+    } else {
+        builder.build(())
+    }
+}
+
+/// Open → messages in the sending order → close, return the count of received messages
+fn check_order(secio: bool, addr: &str, policy: ProtocolClosePolicy) -> u32 {
+    let listen_addr = start_service(
+        create(secio, true, policy, crossbeam_channel::unbounded().0),
+        Some(addr.parse().unwrap()),
+    )
+    .unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(secio, false, policy, sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(10)),
+        Ok(Event::Connected)
+    );
+    let mut next = 0;
+    loop {
+        match receiver.recv_timeout(Duration::from_secs(10)) {
+            Ok(Event::Received(seq)) => {
+                assert_eq!(seq, next, "message out of order");
+                next += 1;
+            }
+            Ok(Event::Disconnected) => break,
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+    assert!(receiver.recv_timeout(Duration::from_millis(500)).is_err());
+    next
+}
+
+#[test]
+fn test_flush_close_delivers_all_in_order_mem() {
+    assert_eq!(
+        check_order(false, "/memory/0", ProtocolClosePolicy::Flush),
+        MESSAGE_COUNT
+    );
+}
+
+#[test]
+fn test_flush_close_delivers_all_in_order_secio_mem() {
+    assert_eq!(
+        check_order(true, "/memory/0", ProtocolClosePolicy::Flush),
+        MESSAGE_COUNT
+    );
+}
+
+#[test]
+fn test_flush_close_delivers_all_in_order_tcp() {
+    assert_eq!(
+        check_order(true, "/ip4/127.0.0.1/tcp/0", ProtocolClosePolicy::Flush),
+        MESSAGE_COUNT
+    );
+}
+
+#[test]
+fn test_drop_close_delivers_a_prefix_in_order_mem() {
+    // the close skips ahead of the queued messages, which are dropped, so only a prefix
+    // is delivered, the order of which is checked by `check_order`
+    assert!(check_order(false, "/memory/0", ProtocolClosePolicy::DropQueued) < MESSAGE_COUNT);
+}
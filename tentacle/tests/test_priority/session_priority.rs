@@ -0,0 +1,86 @@
+use crate::common::start_service;
+use bytes::Bytes;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, TargetProtocol},
+    traits::ServiceProtocol,
+};
+
+/// The listener boosts every inbound session and then sends it a batch of messages,
+/// the dialer reports how many it receives
+struct PHandle {
+    listener: bool,
+    sender: crossbeam_channel::Sender<Bytes>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if self.listener {
+            context
+                .control()
+                .set_session_priority(context.session.id, 1)
+                .unwrap();
+            for _ in 0..100 {
+                context.send_message(Bytes::from("boosted")).unwrap();
+            }
+        }
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        let _res = self.sender.send(data);
+    }
+}
+
+fn create(listener: bool, sender: crossbeam_channel::Sender<Bytes>) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || {
+                    ProtocolHandle::Callback(Box::new(PHandle {
+                        listener,
+                        sender: sender.clone(),
+                    }))
+                })
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+#[test]
+fn test_boosted_session_receives_all_messages() {
+    let listen_addr = start_service(
+        create(true, crossbeam_channel::unbounded().0),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let receivers = (0..2)
+        .map(|_| {
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            let service = create(false, sender);
+            let control = service.control().clone();
+            start_service(service, None);
+            control
+                .dial(listen_addr.clone(), TargetProtocol::All)
+                .unwrap();
+            receiver
+        })
+        .collect::<Vec<_>>();
+
+    for receiver in receivers {
+        for _ in 0..100 {
+            assert_eq!(
+                receiver.recv_timeout(Duration::from_secs(5)),
+                Ok(Bytes::from("boosted"))
+            );
+        }
+    }
+}
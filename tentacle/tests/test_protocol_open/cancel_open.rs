@@ -0,0 +1,126 @@
+use futures::StreamExt;
+use std::{sync::mpsc::channel, thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef, ServiceContext},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{
+        ProtocolHandle, ProtocolMeta, SelectErrorCause, Service, ServiceError, TargetProtocol,
+    },
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+/// Reports the negotiation errors
+struct SHandle {
+    sender: crossbeam_channel::Sender<(Option<String>, SelectErrorCause)>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::ProtocolSelectError {
+            proto_name, cause, ..
+        } = error
+        {
+            let _res = self.sender.send((proto_name, cause));
+        }
+    }
+}
+
+/// Once protocol 1 is open, the listener stalls its runtime, so it never responds to the
+/// negotiation of protocol 2, and the dialer opens protocol 2 then cancels it
+struct PHandle {
+    listener: bool,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.proto_id() != 1.into() {
+            return;
+        }
+        if self.listener {
+            thread::sleep(Duration::from_secs(3));
+        } else {
+            context.open_protocol(context.session.id, 2.into()).unwrap();
+            let control = context.control().clone();
+            let session_id = context.session.id;
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(500));
+                control.cancel_protocol_open(session_id, 2.into()).unwrap();
+            });
+        }
+    }
+}
+
+fn create_meta(id: ProtocolId, listener: bool) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { listener })))
+        .build()
+}
+
+fn create(
+    listener: bool,
+    sender: crossbeam_channel::Sender<(Option<String>, SelectErrorCause)>,
+) -> Service<SHandle> {
+    ServiceBuilder::default()
+        .insert_protocol(create_meta(1.into(), listener))
+        .insert_protocol(create_meta(2.into(), listener))
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(SHandle { sender })
+}
+
+fn start_service(mut service: Service<SHandle>, listen: Option<Multiaddr>) -> Option<Multiaddr> {
+    let (addr_sender, addr_receiver) = channel::<Multiaddr>();
+    let wait_listen = listen.is_some();
+    thread::spawn(move || {
+        // A single thread runtime, which is stalled by the blocking callback
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async move {
+            if let Some(addr) = listen {
+                let listen_addr = service.listen(addr).await.unwrap();
+                addr_sender.send(listen_addr).unwrap();
+            }
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+    if wait_listen {
+        addr_receiver.recv().ok()
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_cancel_stuck_protocol_open() {
+    let listen_addr = start_service(
+        create(true, crossbeam_channel::unbounded().0),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(false, sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control
+        .dial(listen_addr, TargetProtocol::Single(1.into()))
+        .unwrap();
+
+    // reported before the listener wakes up
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(2)),
+        Ok((Some("/p2p/2".to_owned()), SelectErrorCause::Cancelled))
+    );
+}
@@ -0,0 +1,110 @@
+use futures::StreamExt;
+use std::{sync::mpsc::channel, thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef, ServiceContext},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, ServiceError, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+pub fn create<F>(metas: Vec<ProtocolMeta>, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    metas
+        .into_iter()
+        .fold(ServiceBuilder::default(), |builder, meta| {
+            builder.insert_protocol(meta)
+        })
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(shandle)
+}
+
+struct PHandle {
+    sender: crossbeam_channel::Sender<ProtocolId>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _control: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        let _res = self.sender.try_send(context.proto_id());
+    }
+}
+
+struct SHandle;
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _env: &mut ServiceContext, error: ServiceError) {
+        panic!("test fail {:?}", error);
+    }
+}
+
+fn create_meta(id: ProtocolId, sender: crossbeam_channel::Sender<ProtocolId>) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .service_handle(move || {
+            let handle = Box::new(PHandle { sender });
+            ProtocolHandle::Callback(handle)
+        })
+        .build()
+}
+
+#[test]
+fn test_fallback_protocol() {
+    let (addr_sender, addr_receiver) = channel::<Multiaddr>();
+    let (listen_sender, _listen_receiver) = crossbeam_channel::bounded(2);
+    let mut service = create(vec![create_meta(1.into(), listen_sender)], ());
+
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+
+            addr_sender.send(listen_addr).unwrap();
+
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let listen_addr = addr_receiver.recv().unwrap();
+
+    let (sender, receiver) = crossbeam_channel::bounded(2);
+    let mut service = create(
+        vec![
+            create_meta(1.into(), sender.clone()),
+            create_meta(2.into(), sender),
+        ],
+        SHandle,
+    );
+    let control = service.control().clone();
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    control
+        .dial(
+            listen_addr,
+            TargetProtocol::Fallback(vec![2.into(), 1.into()]),
+        )
+        .unwrap();
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(1.into()));
+}
@@ -1,3 +1,17 @@
+mod cancel_open;
+#[path = "../common/mod.rs"]
+mod common;
+mod fallback_protocol;
+mod max_negotiating_protocols;
+mod open_protocol_version;
+mod protocol_allowlist;
+mod protocol_reset;
+mod protocol_select_timeout;
+mod raw_protocol;
+mod register_protocol;
+mod remote_protocols;
+mod version_downgrade;
+
 use futures::{channel, StreamExt};
 use std::{
     sync::{
@@ -0,0 +1,53 @@
+use crate::common::start_service;
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, ServiceControl, TargetProtocol},
+    ProtocolId,
+};
+
+fn create(max_negotiating: Option<usize>) -> Service<()> {
+    let builder = (1..=3)
+        .map(|id| create_meta(id.into()))
+        .fold(ServiceBuilder::default(), |builder, meta| {
+            builder.insert_protocol(meta)
+        })
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated());
+    match max_negotiating {
+        Some(number) => builder.max_negotiating_protocols(number).build(()),
+        None => builder.build(()),
+    }
+}
+
+fn create_meta(id: ProtocolId) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .service_handle(|| ProtocolHandle::None)
+        .build()
+}
+
+fn wait_substream_count(control: &ServiceControl, expected: usize) -> bool {
+    for _ in 0..50 {
+        if futures::executor::block_on(control.total_substream_count()).ok() == Some(expected) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    false
+}
+
+#[test]
+fn test_local_opens_over_the_limit_wait() {
+    let listen_addr =
+        start_service(create(None), Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let service = create(Some(1));
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    // negotiated one by one, all of them are opened at last
+    assert!(wait_substream_count(&control, 3));
+}
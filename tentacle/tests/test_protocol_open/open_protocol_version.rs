@@ -0,0 +1,95 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+pub fn create<F>(meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    ServiceBuilder::default()
+        .insert_protocol(meta)
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(shandle)
+}
+
+/// Report the version of the opened protocol
+struct PHandle {
+    sender: Option<crossbeam_channel::Sender<String>>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, _context: ProtocolContextMutRef, version: &str) {
+        if let Some(sender) = self.sender.as_ref() {
+            let _res = sender.send(version.to_owned());
+        }
+    }
+}
+
+fn create_meta(
+    versions: &[&str],
+    sender: Option<crossbeam_channel::Sender<String>>,
+) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(ProtocolId::new(1))
+        .support_versions(versions.iter().map(|v| (*v).to_owned()).collect())
+        .service_handle(move || {
+            ProtocolHandle::Callback(Box::new(PHandle {
+                sender: sender.clone(),
+            }))
+        })
+        .build()
+}
+
+/// Open the protocol at the requested version, return the version reported by `connected`
+fn open_version(listener_versions: &[&str], dialer_versions: &[&str], requested: &str) -> String {
+    let listen_addr = start_service(
+        create(create_meta(listener_versions, None), ()),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(create_meta(dialer_versions, Some(sender)), ());
+    let control = service.control().clone();
+    start_service(service, None);
+    control
+        .dial(
+            listen_addr,
+            TargetProtocol::Version(1.into(), requested.to_owned()),
+        )
+        .unwrap();
+
+    receiver.recv_timeout(Duration::from_secs(5)).unwrap()
+}
+
+#[test]
+fn test_open_the_requested_version() {
+    let versions = ["1.0.0", "2.0.0", "3.0.0"];
+    assert_eq!(open_version(&versions, &versions, "2.0.0"), "2.0.0");
+}
+
+#[test]
+fn test_remote_without_the_requested_version() {
+    assert_eq!(
+        open_version(&["1.0.0", "3.0.0"], &["1.0.0", "2.0.0", "3.0.0"], "2.0.0"),
+        "3.0.0"
+    );
+}
+
+#[test]
+fn test_requested_version_unsupported_locally() {
+    assert_eq!(
+        open_version(&["1.0.0", "2.0.0"], &["1.0.0", "2.0.0"], "3.0.0"),
+        "2.0.0"
+    );
+}
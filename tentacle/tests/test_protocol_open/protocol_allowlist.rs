@@ -0,0 +1,98 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef, ServiceContext},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, ServiceError, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+/// Report the opened protocols
+struct PHandle {
+    sender: crossbeam_channel::Sender<ProtocolId>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        let _res = self.sender.send(context.proto_id);
+    }
+}
+
+/// Report the rejected protocols
+struct SHandle {
+    sender: crossbeam_channel::Sender<ProtocolId>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _env: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::ProtocolNotAllowed { proto_id, .. } = error {
+            let _res = self.sender.send(proto_id);
+        }
+    }
+}
+
+fn create_metas(sender: crossbeam_channel::Sender<ProtocolId>) -> Vec<ProtocolMeta> {
+    (1..=2)
+        .map(|id| {
+            let sender = sender.clone();
+            MetaBuilder::new()
+                .id(id.into())
+                .service_handle(move || {
+                    ProtocolHandle::Callback(Box::new(PHandle {
+                        sender: sender.clone(),
+                    }))
+                })
+                .build()
+        })
+        .collect()
+}
+
+#[test]
+fn test_protocol_allowlist() {
+    let dialer_key = SecioKeyPair::secp256k1_generated();
+
+    let (open_sender, open_receiver) = crossbeam_channel::unbounded();
+    let (reject_sender, reject_receiver) = crossbeam_channel::unbounded();
+    let service = create_metas(open_sender)
+        .into_iter()
+        .fold(ServiceBuilder::default(), |builder, meta| {
+            builder.insert_protocol(meta)
+        })
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .protocol_allowlist(dialer_key.peer_id(), vec![1.into()])
+        .build(SHandle {
+            sender: reject_sender,
+        });
+    let listen_addr =
+        start_service(service, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let (sender, _receiver) = crossbeam_channel::unbounded();
+    let service = create_metas(sender)
+        .into_iter()
+        .fold(ServiceBuilder::default(), |builder, meta| {
+            builder.insert_protocol(meta)
+        })
+        .forever(true)
+        .key_pair(dialer_key)
+        .build(());
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    assert_eq!(
+        reject_receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(2.into())
+    );
+    assert_eq!(
+        open_receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(1.into())
+    );
+    assert!(open_receiver
+        .recv_timeout(Duration::from_millis(500))
+        .is_err());
+}
@@ -0,0 +1,113 @@
+use crate::common::start_service;
+use std::{io, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef, ServiceContext},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceError, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+};
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    Error(io::ErrorKind),
+    Disconnected,
+}
+
+/// Report the protocol errors
+struct SHandle {
+    sender: crossbeam_channel::Sender<Event>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _env: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::ProtocolError { error, .. } = error {
+            let _res = self.sender.send(Event::Error(error.kind()));
+        }
+    }
+}
+
+/// Reset or close the protocol once connected if `abort` is set, report disconnected
+struct PHandle {
+    abort: Option<bool>,
+    sender: crossbeam_channel::Sender<Event>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        match self.abort {
+            Some(true) => context.reset(context.proto_id).unwrap(),
+            Some(false) => context
+                .close_protocol(context.session.id, context.proto_id)
+                .unwrap(),
+            None => (),
+        }
+    }
+
+    fn disconnected(&mut self, _context: ProtocolContextMutRef) {
+        let _res = self.sender.send(Event::Disconnected);
+    }
+}
+
+fn create(abort: Option<bool>, sender: crossbeam_channel::Sender<Event>) -> Service<SHandle> {
+    let proto_sender = sender.clone();
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || {
+                    ProtocolHandle::Callback(Box::new(PHandle {
+                        abort,
+                        sender: proto_sender.clone(),
+                    }))
+                })
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(SHandle { sender })
+}
+
+/// Return the events of the side which aborts and the remote side
+fn abort_protocol(reset: bool) -> (Vec<Event>, Vec<Event>) {
+    let (local_sender, local_receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(Some(reset), local_sender),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (remote_sender, remote_receiver) = crossbeam_channel::unbounded();
+    let service = create(None, remote_sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    let collect = |receiver: crossbeam_channel::Receiver<Event>| {
+        let mut events = vec![receiver.recv_timeout(Duration::from_secs(5)).unwrap()];
+        while let Ok(event) = receiver.recv_timeout(Duration::from_millis(500)) {
+            events.push(event)
+        }
+        events
+    };
+    (collect(local_receiver), collect(remote_receiver))
+}
+
+#[test]
+fn test_reset_protocol() {
+    let (local, remote) = abort_protocol(true);
+    assert_eq!(local, vec![Event::Disconnected]);
+    // service handle and protocol handle are not ordered with each other
+    assert_eq!(remote.len(), 2);
+    assert!(remote.contains(&Event::Error(io::ErrorKind::ConnectionReset)));
+    assert!(remote.contains(&Event::Disconnected));
+}
+
+#[test]
+fn test_close_protocol_is_not_reset() {
+    let (local, remote) = abort_protocol(false);
+    assert_eq!(local, vec![Event::Disconnected]);
+    assert_eq!(remote, vec![Event::Disconnected]);
+}
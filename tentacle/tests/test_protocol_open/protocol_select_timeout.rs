@@ -0,0 +1,62 @@
+use crate::common::start_service;
+use futures::StreamExt;
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    service::{ProtocolHandle, Service, ServiceError},
+    traits::ServiceHandle,
+    utils::multiaddr_to_socketaddr,
+    yamux::{Config, Session},
+};
+
+/// Reports the failed protocol negotiations
+struct SHandle {
+    sender: crossbeam_channel::Sender<Option<String>>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::ProtocolSelectError { proto_name, .. } = error {
+            let _res = self.sender.send(proto_name);
+        }
+    }
+}
+
+fn create(sender: crossbeam_channel::Sender<Option<String>>) -> Service<SHandle> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .timeout(Duration::from_secs(30))
+        .protocol_select_timeout(Duration::from_millis(500))
+        .build(SHandle { sender })
+}
+
+#[test]
+fn test_stalled_negotiation_times_out() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(sender),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+    let socket_addr = multiaddr_to_socketaddr(&listen_addr).unwrap();
+
+    // a plain yamux peer which opens a sub stream and never negotiates on it
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let socket = tokio::net::TcpStream::connect(socket_addr).await.unwrap();
+            let mut session = Session::new_client(socket, Config::default());
+            let _stream = session.open_stream().unwrap();
+            while let Some(Ok(_)) = session.next().await {}
+        });
+    });
+
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(None));
+}
@@ -0,0 +1,130 @@
+use futures::StreamExt;
+use std::{
+    sync::{mpsc::channel, Arc},
+    thread,
+    time::Duration,
+};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ServiceContext, SessionContext},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, ServiceControl, ServiceError},
+    traits::{AsyncStream, RawProtocol, ServiceHandle},
+    ProtocolId,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+pub fn create<F>(meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    ServiceBuilder::default()
+        .insert_protocol(meta)
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(shandle)
+}
+
+/// Write the message and close
+struct Writer;
+
+impl RawProtocol for Writer {
+    fn connected(
+        &self,
+        _context: Arc<SessionContext>,
+        _control: &ServiceControl,
+        _version: &str,
+        mut stream: Box<dyn AsyncStream>,
+    ) {
+        tokio::spawn(async move {
+            stream.write_all(b"hello raw").await.unwrap();
+            stream.shutdown().await.unwrap();
+        });
+    }
+}
+
+/// Read all of the message
+struct Reader {
+    sender: crossbeam_channel::Sender<Vec<u8>>,
+}
+
+impl RawProtocol for Reader {
+    fn connected(
+        &self,
+        _context: Arc<SessionContext>,
+        _control: &ServiceControl,
+        _version: &str,
+        mut stream: Box<dyn AsyncStream>,
+    ) {
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            let mut buf = Vec::new();
+            let _ignore = stream.read_to_end(&mut buf).await;
+            let _res = sender.send(buf);
+        });
+    }
+}
+
+struct SHandle;
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _env: &mut ServiceContext, error: ServiceError) {
+        panic!("test fail {:?}", error);
+    }
+}
+
+fn create_meta<T: RawProtocol + Send + Sync + 'static>(id: ProtocolId, raw: T) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .service_handle(move || ProtocolHandle::Raw(Box::new(raw)))
+        .build()
+}
+
+#[test]
+fn test_raw_protocol() {
+    let (addr_sender, addr_receiver) = channel::<Multiaddr>();
+    let mut service = create(create_meta(1.into(), Writer), SHandle);
+
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+
+            addr_sender.send(listen_addr).unwrap();
+
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let listen_addr = addr_receiver.recv().unwrap();
+
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    let mut service = create(create_meta(1.into(), Reader { sender }), SHandle);
+    let control = service.control().clone();
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    control
+        .dial(listen_addr, tentacle::service::TargetProtocol::All)
+        .unwrap();
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(b"hello raw".to_vec())
+    );
+}
@@ -0,0 +1,133 @@
+use crate::common::start_service;
+use futures::executor::block_on;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    error::RegisterError,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, TargetProtocol},
+    traits::ServiceProtocol,
+    ProtocolId, SessionId,
+};
+
+/// Report the protocol opened on the sessions
+struct PHandle {
+    sender: crossbeam_channel::Sender<(SessionId, ProtocolId)>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        let _res = self.sender.send((context.session.id, context.proto_id));
+    }
+}
+
+fn create_meta(
+    id: usize,
+    name: &'static str,
+    sender: Option<crossbeam_channel::Sender<(SessionId, ProtocolId)>>,
+) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id.into())
+        .name(move |_| name.to_owned())
+        .service_handle(move || match sender {
+            Some(sender) => ProtocolHandle::Callback(Box::new(PHandle { sender })),
+            None => ProtocolHandle::None,
+        })
+        .build()
+}
+
+fn create(metas: Vec<ProtocolMeta>) -> Service<()> {
+    metas
+        .into_iter()
+        .fold(ServiceBuilder::default(), |builder, meta| {
+            builder.insert_protocol(meta)
+        })
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+#[test]
+fn test_register_protocol_after_start() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(vec![create_meta(1, "/test/ping", None)]);
+    let control = service.control().clone();
+    let listen_addr =
+        start_service(service, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    block_on(control.register_protocol(create_meta(2, "/test/sync", Some(sender)))).unwrap();
+
+    let dialer = create(vec![
+        create_meta(1, "/test/ping", None),
+        create_meta(2, "/test/sync", None),
+    ]);
+    let dialer_control = dialer.control().clone();
+    start_service(dialer, None);
+    dialer_control
+        .dial(listen_addr, TargetProtocol::All)
+        .unwrap();
+
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap().1,
+        2.into()
+    );
+    assert!(control.protocols().contains_key(&2.into()));
+    assert_eq!(
+        control
+            .registered_protocols()
+            .into_iter()
+            .map(|(id, name, _)| (id, name))
+            .collect::<Vec<_>>(),
+        vec![
+            (1.into(), "/test/ping".to_owned()),
+            (2.into(), "/test/sync".to_owned())
+        ]
+    );
+}
+
+#[test]
+fn test_register_protocol_on_open_session() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(vec![create_meta(1, "/test/ping", Some(sender.clone()))]);
+    let control = service.control().clone();
+    let listen_addr =
+        start_service(service, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+
+    let dialer = create(vec![create_meta(1, "/test/ping", None)]);
+    let dialer_control = dialer.control().clone();
+    start_service(dialer, None);
+    dialer_control
+        .dial(listen_addr, TargetProtocol::All)
+        .unwrap();
+    let (session_id, proto_id) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(proto_id, 1.into());
+
+    block_on(control.register_protocol(create_meta(2, "/test/sync", Some(sender)))).unwrap();
+    block_on(dialer_control.register_protocol(create_meta(2, "/test/sync", None))).unwrap();
+    control.open_protocol(session_id, 2.into()).unwrap();
+
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+        (session_id, 2.into())
+    );
+}
+
+#[test]
+fn test_register_duplicate_protocol() {
+    let service = create(vec![create_meta(1, "/test/ping", None)]);
+    let control = service.control().clone();
+    start_service(service, None);
+
+    match block_on(control.register_protocol(create_meta(1, "/test/sync", None))) {
+        Err(RegisterError::DuplicateId(id)) => assert_eq!(id, 1.into()),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match block_on(control.register_protocol(create_meta(2, "/test/ping", None))) {
+        Err(RegisterError::DuplicateName(name)) => assert_eq!(name, "/test/ping"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    block_on(control.register_protocol(create_meta(2, "/test/sync", None))).unwrap();
+}
@@ -0,0 +1,91 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    protocol_select::ProtocolInfo,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, ServiceEvent, TargetProtocol},
+    traits::ServiceHandle,
+};
+
+/// Report the remote protocols of the opened inbound session
+struct SHandle {
+    sender: crossbeam_channel::Sender<Option<Vec<ProtocolInfo>>>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { session_context } = event {
+            if session_context.ty.is_inbound() {
+                let _res = self
+                    .sender
+                    .send(session_context.remote_protocols().map(<[_]>::to_vec));
+            }
+        }
+    }
+}
+
+fn create_meta(id: usize, name: &'static str, versions: &[&str]) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id.into())
+        .name(move |_| name.to_owned())
+        .support_versions(versions.iter().map(|v| v.to_string()).collect())
+        .service_handle(|| ProtocolHandle::None)
+        .build()
+}
+
+fn create<F>(shandle: F, exchange: bool, advertised: bool) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let builder = ServiceBuilder::default()
+        .insert_protocol(create_meta(1, "/test/ping", &["1.0.0"]))
+        .exchange_protocols(exchange)
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated());
+    if advertised {
+        builder
+            .insert_protocol(create_meta(2, "/test/sync", &["1.0.0", "2.0.0"]))
+            .insert_protocol(create_meta(3, "/test/relay", &["0.1.0"]))
+            .build(shandle)
+    } else {
+        builder.build(shandle)
+    }
+}
+
+/// The remote protocols the listener knows when the session of the dialer is opened
+fn remote_protocols(exchange: bool) -> Option<Vec<ProtocolInfo>> {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(SHandle { sender }, exchange, false),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let service = create((), exchange, true);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    receiver.recv_timeout(Duration::from_secs(5)).unwrap()
+}
+
+#[test]
+fn test_remote_protocols_exchanged() {
+    let mut protocols = remote_protocols(true).unwrap();
+    protocols.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(
+        protocols,
+        vec![
+            ProtocolInfo::new("/test/ping", vec!["1.0.0".to_owned()]),
+            ProtocolInfo::new("/test/relay", vec!["0.1.0".to_owned()]),
+            ProtocolInfo::new("/test/sync", vec!["1.0.0".to_owned(), "2.0.0".to_owned()]),
+        ]
+    );
+}
+
+#[test]
+fn test_remote_protocols_not_exchanged() {
+    assert_eq!(remote_protocols(false), None);
+}
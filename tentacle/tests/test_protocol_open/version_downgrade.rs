@@ -0,0 +1,132 @@
+use crate::common::start_service;
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef, ServiceContext},
+    protocol_select::select_version,
+    secio::SecioKeyPair,
+    service::{
+        ProtocolHandle, ProtocolMeta, SelectErrorCause, ServiceError, ServiceEvent, TargetProtocol,
+    },
+    traits::{ServiceHandle, ServiceProtocol},
+    SessionId,
+};
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    Open(SessionId),
+    SelectError,
+    Connected(String),
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Event>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::ProtocolSelectError {
+            cause: SelectErrorCause::Failed,
+            ..
+        } = error
+        {
+            let _res = self.sender.send(Event::SelectError);
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { session_context } = event {
+            let _res = self.sender.send(Event::Open(session_context.id));
+        }
+    }
+}
+
+struct PHandle {
+    sender: crossbeam_channel::Sender<Event>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, _context: ProtocolContextMutRef, version: &str) {
+        let _res = self.sender.send(Event::Connected(version.to_owned()));
+    }
+}
+
+/// The old peer only supports 1.0.0, and rejects the proposals with the versions it doesn't know
+fn old_meta() -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(1.into())
+        .support_versions(vec!["1.0.0".to_owned()])
+        .select_version(|| {
+            Some(Box::new(|local: &[String], remote: &[String]| {
+                if remote.iter().any(|version| !local.contains(version)) {
+                    None
+                } else {
+                    select_version(local, remote)
+                }
+            }))
+        })
+        .service_handle(|| ProtocolHandle::None)
+        .build()
+}
+
+fn new_meta(sender: crossbeam_channel::Sender<Event>) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(1.into())
+        .support_versions(vec!["1.0.0".to_owned(), "2.0.0".to_owned()])
+        .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { sender })))
+        .build()
+}
+
+#[test]
+fn test_downgrade_after_select_failure() {
+    let listen_service = ServiceBuilder::default()
+        .insert_protocol(old_meta())
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(SHandle {
+            sender: crossbeam_channel::unbounded().0,
+        });
+    let listen_addr = start_service(
+        listen_service,
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = ServiceBuilder::default()
+        .insert_protocol(new_meta(sender.clone()))
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .version_downgrade(1, Duration::from_secs(60))
+        .build(SHandle { sender });
+    let control = service.control().clone();
+    start_service(service, None);
+
+    // the newest version is offered, and rejected by the old peer
+    control
+        .dial(listen_addr.clone(), TargetProtocol::All)
+        .unwrap();
+    let id = match receiver.recv_timeout(Duration::from_secs(5)) {
+        Ok(Event::Open(id)) => id,
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Event::SelectError)
+    );
+    control.disconnect(id).unwrap();
+    thread::sleep(Duration::from_millis(500));
+
+    // the older version is negotiated on the next connection
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+    assert!(matches!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Event::Open(_))
+    ));
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Event::Connected("1.0.0".to_owned()))
+    );
+}
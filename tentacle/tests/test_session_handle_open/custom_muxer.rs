@@ -0,0 +1,105 @@
+use crate::common::start_service;
+use futures::TryStreamExt;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    bytes::Bytes,
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, SessionType, TargetProtocol, YamuxMuxer},
+    traits::{AsyncStream, MuxerControl, MuxerIncoming, ServiceProtocol, StreamMuxer},
+    yamux::Config,
+};
+
+/// Yamux under a wrapper, counts the connections multiplexed and the inbound sub streams
+#[derive(Clone, Default)]
+struct CountingMuxer {
+    connections: Arc<AtomicUsize>,
+    inbound_streams: Arc<AtomicUsize>,
+}
+
+impl StreamMuxer for CountingMuxer {
+    fn multiplex(
+        &self,
+        socket: Box<dyn AsyncStream>,
+        ty: SessionType,
+    ) -> (MuxerIncoming, Arc<dyn MuxerControl>) {
+        self.connections.fetch_add(1, Ordering::SeqCst);
+        let (incoming, control) = YamuxMuxer::new(Config::default()).multiplex(socket, ty);
+        let inbound_streams = self.inbound_streams.clone();
+        let incoming = incoming.inspect_ok(move |_| {
+            inbound_streams.fetch_add(1, Ordering::SeqCst);
+        });
+        (Box::pin(incoming), control)
+    }
+}
+
+/// The dialer says hello on connected, the listener reports what it received
+struct PHandle {
+    sender: crossbeam_channel::Sender<Bytes>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.session.ty.is_outbound() {
+            context.send_message(Bytes::from("hello")).unwrap();
+        }
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        let _res = self.sender.send(data);
+    }
+}
+
+fn create(muxer: CountingMuxer, sender: crossbeam_channel::Sender<Bytes>) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || {
+                    ProtocolHandle::Callback(Box::new(PHandle {
+                        sender: sender.clone(),
+                    }))
+                })
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .muxer(muxer)
+        .build(())
+}
+
+#[test]
+fn test_session_over_custom_muxer() {
+    let listener_muxer = CountingMuxer::default();
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(listener_muxer.clone(), sender),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let dialer_muxer = CountingMuxer::default();
+    let service = create(dialer_muxer.clone(), crossbeam_channel::unbounded().0);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(Bytes::from("hello"))
+    );
+    // each side multiplexed its connection by the custom muxer,
+    // and the listener accepted the sub stream opened by the dialer through it
+    assert_eq!(listener_muxer.connections.load(Ordering::SeqCst), 1);
+    assert_eq!(dialer_muxer.connections.load(Ordering::SeqCst), 1);
+    assert_eq!(listener_muxer.inbound_streams.load(Ordering::SeqCst), 1);
+}
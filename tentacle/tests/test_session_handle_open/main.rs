@@ -1,3 +1,12 @@
+#[path = "../common/mod.rs"]
+mod common;
+mod custom_muxer;
+mod session_count;
+mod session_extensions;
+mod substream_count;
+mod yamux_presets;
+mod yamux_stats;
+
 use futures::StreamExt;
 use std::{sync::mpsc::channel, thread};
 use tentacle::{
@@ -0,0 +1,78 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::ServiceContext,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, ServiceEvent, TargetProtocol},
+    traits::ServiceHandle,
+};
+
+/// Reports the session count seen by the control when a session opens or closes
+struct SHandle {
+    sender: crossbeam_channel::Sender<usize>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, context: &mut ServiceContext, event: ServiceEvent) {
+        match event {
+            ServiceEvent::SessionOpen { .. } | ServiceEvent::SessionClose { .. } => {
+                let _res = self.sender.send(context.control().session_count());
+            }
+            _ => (),
+        }
+    }
+}
+
+fn create(sender: crossbeam_channel::Sender<usize>) -> Service<SHandle> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(|| ProtocolHandle::None)
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(SHandle { sender })
+}
+
+#[test]
+fn test_session_count_and_snapshot() {
+    let (listener_sender, listener_receiver) = crossbeam_channel::unbounded();
+    let listener = create(listener_sender);
+    let listener_control = listener.control().clone();
+    let listen_addr =
+        start_service(listener, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+    assert_eq!(listener_control.session_count(), 0);
+    assert!(listener_control.sessions().is_empty());
+
+    let mut dialers = Vec::new();
+    for expected in 1..=2 {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let service = create(sender);
+        let control = service.control().clone();
+        start_service(service, None);
+        control
+            .dial(listen_addr.clone(), TargetProtocol::All)
+            .unwrap();
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(1));
+        assert_eq!(
+            listener_receiver.recv_timeout(Duration::from_secs(5)),
+            Ok(expected)
+        );
+        dialers.push(control);
+    }
+
+    let sessions = listener_control.sessions();
+    assert_eq!(sessions.len(), 2);
+    assert!(sessions[0].id < sessions[1].id);
+
+    dialers[0].disconnect(dialers[0].sessions()[0].id).unwrap();
+    assert_eq!(
+        listener_receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(1)
+    );
+    assert_eq!(listener_control.session_count(), 1);
+}
@@ -0,0 +1,76 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    bytes::Bytes,
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, TargetProtocol},
+    traits::ServiceProtocol,
+    SessionId,
+};
+
+/// Application data attached to the session
+#[derive(Clone, Debug, PartialEq)]
+struct Score(u32);
+
+/// Attach a score on connected, read it back and bump it on received
+struct PHandle {
+    sender: crossbeam_channel::Sender<(SessionId, Option<Score>)>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        assert_eq!(context.session.insert_extension(Score(1)), None);
+        let _res = context.send_message(Bytes::from("hello"));
+    }
+
+    fn received(&mut self, context: ProtocolContextMutRef, _data: Bytes) {
+        let score = context.session.extension::<Score>();
+        context.session.with_extensions(|extensions| {
+            if let Some(score) = extensions.get_mut::<Score>() {
+                score.0 += 1;
+            }
+        });
+        let _res = self.sender.send((context.session.id, score));
+    }
+}
+
+fn create(sender: crossbeam_channel::Sender<(SessionId, Option<Score>)>) -> Service<()> {
+    ServiceBuilder::default()
+        .insert_protocol(
+            MetaBuilder::new()
+                .id(1.into())
+                .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { sender })))
+                .build(),
+        )
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+#[test]
+fn test_session_extensions() {
+    let (listener_sender, _listener_receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(listener_sender),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    let (session_id, score) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(score, Some(Score(1)));
+    assert_eq!(
+        control.session_extension::<Score>(session_id),
+        Some(Score(2))
+    );
+    assert_eq!(control.session_extension::<String>(session_id), None);
+}
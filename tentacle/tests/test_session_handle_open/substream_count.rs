@@ -0,0 +1,71 @@
+use crate::common::start_service;
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, ServiceControl, TargetProtocol},
+    traits::ServiceHandle,
+    ProtocolId,
+};
+
+pub fn create<F>(metas: Vec<ProtocolMeta>, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    metas
+        .into_iter()
+        .fold(ServiceBuilder::default(), |builder, meta| {
+            builder.insert_protocol(meta)
+        })
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(shandle)
+}
+
+fn create_meta(id: ProtocolId) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .service_handle(|| ProtocolHandle::None)
+        .build()
+}
+
+fn wait_substream_count(control: &ServiceControl, expected: usize) -> bool {
+    for _ in 0..50 {
+        if futures::executor::block_on(control.total_substream_count()).ok() == Some(expected) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    false
+}
+
+#[test]
+fn test_total_substream_count() {
+    let metas = || vec![create_meta(1.into()), create_meta(2.into())];
+
+    let service = create(metas(), ());
+    let listen_control = service.control().clone();
+    let listen_addr =
+        start_service(service, Some("/ip4/127.0.0.1/tcp/0".parse().unwrap())).unwrap();
+    assert!(wait_substream_count(&listen_control, 0));
+
+    let mut dial_controls = Vec::new();
+    for _ in 0..2 {
+        let service = create(metas(), ());
+        let control = service.control().clone();
+        start_service(service, None);
+        control
+            .dial(listen_addr.clone(), TargetProtocol::All)
+            .unwrap();
+        dial_controls.push(control);
+    }
+
+    // 2 sessions with 2 protocols on the listen side, 1 session with 2 protocols on each dialer
+    assert!(wait_substream_count(&listen_control, 4));
+    for control in dial_controls.iter() {
+        assert!(wait_substream_count(control, 2));
+    }
+
+    dial_controls[0].close_protocol(1.into(), 1.into()).unwrap();
+    assert!(wait_substream_count(&listen_control, 3));
+}
@@ -0,0 +1,85 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    bytes::Bytes,
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, TargetProtocol},
+    traits::ServiceProtocol,
+    yamux::Config as YamuxConfig,
+};
+
+/// Over the initial window, the transfer needs window updates
+const DATA_SIZE: usize = 1024 * 1024;
+
+/// The dialer sends the data on connected, the listener reports the received size
+struct PHandle {
+    sender: Option<crossbeam_channel::Sender<usize>>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if self.sender.is_none() {
+            let _res = context.send_message(Bytes::from(vec![7u8; DATA_SIZE]));
+        }
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, data: Bytes) {
+        if let Some(ref sender) = self.sender {
+            let _res = sender.send(data.len());
+        }
+    }
+}
+
+fn create(
+    yamux_config: YamuxConfig,
+    sender: Option<crossbeam_channel::Sender<usize>>,
+) -> Service<()> {
+    let meta = MetaBuilder::new()
+        .id(1.into())
+        .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { sender })))
+        .build();
+    ServiceBuilder::default()
+        .insert_protocol(meta)
+        .yamux_config(yamux_config)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .forever(true)
+        .build(())
+}
+
+fn round_trip(yamux_config: YamuxConfig) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let listen_addr = start_service(
+        create(yamux_config, Some(sender)),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let dialer = create(yamux_config, None);
+    let control = dialer.control().clone();
+    start_service(dialer, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap(),
+        DATA_SIZE
+    );
+}
+
+#[test]
+fn test_yamux_low_latency() {
+    round_trip(YamuxConfig::low_latency())
+}
+
+#[test]
+fn test_yamux_high_throughput() {
+    round_trip(YamuxConfig::high_throughput())
+}
+
+#[test]
+fn test_yamux_memory_constrained() {
+    round_trip(YamuxConfig::memory_constrained())
+}
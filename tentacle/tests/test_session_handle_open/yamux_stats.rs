@@ -0,0 +1,70 @@
+use crate::common::start_service;
+use std::time::Duration;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, Service, TargetProtocol},
+    traits::ServiceProtocol,
+    SessionId,
+};
+
+/// Reports the session of the opened protocols
+struct PHandle {
+    sender: crossbeam_channel::Sender<SessionId>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        let _res = self.sender.send(context.session.id);
+    }
+}
+
+fn create(sender: crossbeam_channel::Sender<SessionId>) -> Service<()> {
+    let mut builder = ServiceBuilder::default();
+    for id in 1..=3 {
+        let sender = sender.clone();
+        builder = builder.insert_protocol(
+            MetaBuilder::new()
+                .id(id.into())
+                .service_handle(move || {
+                    ProtocolHandle::Callback(Box::new(PHandle {
+                        sender: sender.clone(),
+                    }))
+                })
+                .build(),
+        );
+    }
+    builder
+        .forever(true)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .build(())
+}
+
+#[test]
+fn test_yamux_stats_count_open_substreams() {
+    let listen_addr = start_service(
+        create(crossbeam_channel::unbounded().0),
+        Some("/ip4/127.0.0.1/tcp/0".parse().unwrap()),
+    )
+    .unwrap();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let service = create(sender);
+    let control = service.control().clone();
+    start_service(service, None);
+    control.dial(listen_addr, TargetProtocol::All).unwrap();
+
+    let mut session_id = None;
+    for _ in 1..=3 {
+        session_id = receiver.recv_timeout(Duration::from_secs(5)).ok();
+    }
+    let stats = control.yamux_stats(session_id.unwrap()).unwrap();
+    assert_eq!(stats.streams, 3);
+    assert!(stats.send_window > 0);
+    assert!(stats.recv_window > 0);
+
+    assert_eq!(control.yamux_stats(100.into()), None);
+}
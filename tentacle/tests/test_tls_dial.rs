@@ -9,6 +9,7 @@ use tentacle::{
     context::{ProtocolContext, ProtocolContextMutRef, ServiceContext},
     error::{DialerErrorKind, ListenErrorKind},
     multiaddr::Multiaddr,
+    secio::SecioKeyPair,
     service::{
         ProtocolHandle, ProtocolMeta, Service, ServiceError, ServiceEvent, SessionType,
         TargetProtocol, TlsConfig,
@@ -22,13 +23,16 @@ use tokio_rustls::rustls::{
     ProtocolVersion, RootCertStore, ServerConfig, SupportedCipherSuite, ALL_CIPHERSUITES,
 };
 
-pub fn create<F>(meta: ProtocolMeta, shandle: F, cert_path: String) -> Service<F>
+pub fn create<F>(meta: ProtocolMeta, shandle: F, cert_path: String, secio: bool) -> Service<F>
 where
     F: ServiceHandle + Unpin,
 {
     let mut builder = ServiceBuilder::default()
         .insert_protocol(meta)
         .forever(true);
+    if secio {
+        builder = builder.key_pair(SecioKeyPair::secp256k1_generated());
+    }
 
     let tls_config = TlsConfig::new(
         Some(make_server_config(&NetConfig::example(cert_path.clone()))),
@@ -375,7 +379,7 @@ pub fn make_client_config(config: &NetConfig) -> ClientConfig {
     client_config
 }
 
-fn test_tls_dial() {
+fn test_tls_dial(secio: bool) {
     let (meta_1, receiver_1) = create_meta(1.into());
     let (meta_2, receiver_2) = create_meta(1.into());
     let (shandle, _error_receiver_1) = create_shandle(true);
@@ -387,7 +391,12 @@ fn test_tls_dial() {
         )
         .unwrap();
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let mut service = create(meta_1, shandle, "tests/certificates/node0/".to_string());
+        let mut service = create(
+            meta_1,
+            shandle,
+            "tests/certificates/node0/".to_string(),
+            secio,
+        );
         rt.block_on(async move {
             let listen_addr = service.listen(multi_addr_1).await.unwrap();
             let _res = addr_sender.send(listen_addr);
@@ -407,7 +416,12 @@ fn test_tls_dial() {
         )
         .unwrap();
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let mut service = create(meta_2, shandle, "tests/certificates/node1/".to_string());
+        let mut service = create(
+            meta_2,
+            shandle,
+            "tests/certificates/node1/".to_string(),
+            secio,
+        );
         rt.block_on(async move {
             let listen_addr = addr_receiver.await.unwrap();
             service
@@ -428,5 +442,10 @@ fn test_tls_dial() {
 
 #[test]
 fn test_tls_message_send() {
-    test_tls_dial()
+    test_tls_dial(false)
+}
+
+#[test]
+fn test_tls_with_secio_message_send() {
+    test_tls_dial(true)
 }
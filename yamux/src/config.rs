@@ -53,3 +53,40 @@ impl Default for Config {
         }
     }
 }
+
+impl Config {
+    /// Preset for the interactive traffic, such as consensus or relay messages
+    ///
+    /// Keeps the initial window, which is the smallest one both sides can assume,
+    /// and finds the broken connections early
+    pub fn low_latency() -> Config {
+        Config {
+            keepalive_interval: Duration::from_secs(10),
+            connection_write_timeout: Duration::from_secs(5),
+            ..Default::default()
+        }
+    }
+
+    /// Preset for the bulk transfer, such as block or file sync
+    ///
+    /// The window is 4MB, not over the default 8MB max frame length of tentacle,
+    /// and the writes are given more time to finish
+    pub fn high_throughput() -> Config {
+        Config {
+            connection_write_timeout: Duration::from_secs(30),
+            max_stream_window_size: 4 * 1024 * 1024,
+            ..Default::default()
+        }
+    }
+
+    /// Preset for the nodes with little memory
+    ///
+    /// Keeps the initial window, and limits the streams waiting to be accepted and opened
+    pub fn memory_constrained() -> Config {
+        Config {
+            accept_backlog: 32,
+            max_stream_count: 256,
+            ..Default::default()
+        }
+    }
+}
@@ -3,7 +3,13 @@ use futures::{
     sink::SinkExt,
 };
 
-use crate::{error::Error, stream::StreamHandle};
+use std::sync::Arc;
+
+use crate::{
+    error::Error,
+    stats::{Stats, StatsCounter},
+    stream::StreamHandle,
+};
 
 pub(crate) enum Command {
     OpenStream(oneshot::Sender<Result<StreamHandle, Error>>),
@@ -12,11 +18,17 @@ pub(crate) enum Command {
 
 /// A session control is used to open the stream or close the session
 #[derive(Clone)]
-pub struct Control(mpsc::Sender<Command>);
+pub struct Control(mpsc::Sender<Command>, Arc<StatsCounter>);
 
 impl Control {
-    pub(crate) fn new(sender: mpsc::Sender<Command>) -> Self {
-        Control(sender)
+    pub(crate) fn new(sender: mpsc::Sender<Command>, stats: Arc<StatsCounter>) -> Self {
+        Control(sender, stats)
+    }
+
+    /// Current flow control state of the session, the windows move as the streams
+    /// consume and return the credits
+    pub fn stats(&self) -> Stats {
+        self.1.snapshot()
     }
 
     /// Open a new stream to remote session
@@ -14,13 +14,16 @@ pub mod frame;
 pub mod session;
 // Stream module
 mod control;
+// Statistics module
+mod stats;
 pub mod stream;
 
 // Stream ID type
 pub(crate) type StreamId = u32;
 
 pub use crate::{
-    config::Config, control::Control, error::Error, session::Session, stream::StreamHandle,
+    config::Config, control::Control, error::Error, session::Session, stats::Stats,
+    stream::StreamHandle,
 };
 
 // Latest Protocol Version
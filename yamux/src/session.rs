@@ -6,6 +6,7 @@ use std::{
     collections::{hash_map::Entry, BTreeMap, HashMap, HashSet, VecDeque},
     io,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
     time::Duration,
 };
@@ -26,6 +27,7 @@ use crate::{
     control::{Command, Control},
     error::Error,
     frame::{Flag, Flags, Frame, FrameCodec, GoAwayCode, Type},
+    stats::StatsCounter,
     stream::{StreamEvent, StreamHandle, StreamState},
     StreamId,
 };
@@ -109,6 +111,9 @@ pub struct Session<T> {
     control_receiver: Receiver<Command>,
 
     keepalive: Option<Interval>,
+
+    // Flow control statistics shared with the streams and the controls
+    stats: Arc<StatsCounter>,
 }
 
 /// Session type, client or server
@@ -173,6 +178,7 @@ where
             control_sender,
             control_receiver,
             keepalive,
+            stats: Arc::new(StatsCounter::default()),
         }
     }
 
@@ -263,7 +269,7 @@ where
 
     /// Return a control to async open stream/close session
     pub fn control(&self) -> Control {
-        Control::new(self.control_sender.clone())
+        Control::new(self.control_sender.clone(), self.stats.clone())
     }
 
     fn keep_alive(&mut self, cx: &mut Context, ping_at: Instant) -> Result<(), io::Error> {
@@ -311,6 +317,7 @@ where
             state,
             self.config.max_stream_window_size,
             self.config.max_stream_window_size,
+            self.stats.clone(),
         );
         if let Err(err) = stream.send_window_update() {
             debug!("[{:?}] stream.send_window_update error={:?}", self.ty, err);
@@ -895,7 +902,7 @@ mod test {
         task::{Context, Poll},
         time::Duration,
     };
-    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
     use tokio_util::codec::Framed;
 
     struct MockSocket {
@@ -1040,6 +1047,40 @@ mod test {
         })
     }
 
+    #[test]
+    fn test_stats_follow_window_credits() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let (_remote, local) = MockSocket::new();
+            let config = Config {
+                enable_keepalive: false,
+                ..Default::default()
+            };
+            let window = u64::from(config.max_stream_window_size);
+
+            let mut session = Session::new_client(local, config);
+            let control = session.control();
+            assert_eq!(control.stats().streams, 0);
+
+            let mut stream = session.open_stream().unwrap();
+            let other = session.open_stream().unwrap();
+            let stats = control.stats();
+            assert_eq!(stats.streams, 2);
+            assert_eq!(stats.send_window, 2 * window);
+            assert_eq!(stats.recv_window, 2 * window);
+
+            stream.write_all(&[0; 10]).await.unwrap();
+            assert_eq!(control.stats().send_window, 2 * window - 10);
+
+            drop(other);
+            let stats = control.stats();
+            assert_eq!(stats.streams, 1);
+            assert_eq!(stats.send_window, window - 10);
+            assert_eq!(stats.recv_window, window);
+        })
+    }
+
     // issue: https://github.com/nervosnetwork/tentacle/issues/259
     // The reason for the problem is that when the session is closed,
     // all stream states are not set to `RemoteClosed`
@@ -0,0 +1,62 @@
+//! Flow control statistics of the session
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Snapshot of the flow control state of a session
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of the open streams
+    pub streams: usize,
+    /// Sum of the send windows of the open streams, the bytes can be sent before
+    /// the remote returns the credits
+    pub send_window: u64,
+    /// Sum of the receive windows of the open streams, the bytes the remote can send
+    /// before we return the credits
+    pub recv_window: u64,
+}
+
+/// Counters shared by the session and its streams
+#[derive(Debug, Default)]
+pub(crate) struct StatsCounter {
+    streams: AtomicUsize,
+    send_window: AtomicU64,
+    recv_window: AtomicU64,
+}
+
+impl StatsCounter {
+    pub(crate) fn open_stream(&self, recv_window: u32, send_window: u32) {
+        self.streams.fetch_add(1, Ordering::Relaxed);
+        self.incr_recv_window(recv_window);
+        self.incr_send_window(send_window);
+    }
+
+    pub(crate) fn close_stream(&self, recv_window: u32, send_window: u32) {
+        self.streams.fetch_sub(1, Ordering::Relaxed);
+        self.decr_recv_window(recv_window);
+        self.decr_send_window(send_window);
+    }
+
+    pub(crate) fn incr_recv_window(&self, n: u32) {
+        self.recv_window.fetch_add(u64::from(n), Ordering::Relaxed);
+    }
+
+    pub(crate) fn decr_recv_window(&self, n: u32) {
+        self.recv_window.fetch_sub(u64::from(n), Ordering::Relaxed);
+    }
+
+    pub(crate) fn incr_send_window(&self, n: u32) {
+        self.send_window.fetch_add(u64::from(n), Ordering::Relaxed);
+    }
+
+    pub(crate) fn decr_send_window(&self, n: u32) {
+        self.send_window.fetch_sub(u64::from(n), Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Stats {
+        Stats {
+            streams: self.streams.load(Ordering::Relaxed),
+            send_window: self.send_window.load(Ordering::Relaxed),
+            recv_window: self.recv_window.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -11,6 +11,7 @@ use futures::{
 use std::{
     io,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
 
@@ -20,6 +21,7 @@ use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use crate::{
     error::Error,
     frame::{Flag, Flags, Frame, Type},
+    stats::StatsCounter,
     StreamId,
 };
 
@@ -43,6 +45,9 @@ pub struct StreamHandle {
 
     // when the cache is sent, a writable notification is issued
     writeable_wake: Option<Waker>,
+
+    // Flow control statistics of the parent session
+    stats: Arc<StatsCounter>,
 }
 
 impl StreamHandle {
@@ -54,8 +59,10 @@ impl StreamHandle {
         state: StreamState,
         recv_window_size: u32,
         send_window_size: u32,
+        stats: Arc<StatsCounter>,
     ) -> StreamHandle {
         assert!(state == StreamState::Init || state == StreamState::SynReceived);
+        stats.open_stream(recv_window_size, send_window_size);
         StreamHandle {
             id,
             state,
@@ -66,6 +73,7 @@ impl StreamHandle {
             unbound_event_sender,
             frame_receiver,
             writeable_wake: None,
+            stats,
         }
     }
 
@@ -101,8 +109,9 @@ impl StreamHandle {
                 let event = StreamEvent::Closed(self.id);
                 self.unbound_send_event(event)?;
             }
-            StreamState::Reset | StreamState::Closed => {
-                self.state = StreamState::Closed;
+            // The session has been told when the reset frame arrived
+            StreamState::Reset => (),
+            StreamState::Closed => {
                 let event = StreamEvent::Closed(self.id);
                 self.unbound_send_event(event)?;
             }
@@ -152,6 +161,7 @@ impl StreamHandle {
         }
         // Update our window
         self.recv_window += delta;
+        self.stats.incr_recv_window(delta);
         let frame = Frame::new_window_update(flags, self.id, delta);
         self.unbound_event_sender
             .unbounded_send(StreamEvent::Frame(frame))
@@ -192,8 +202,10 @@ impl StreamHandle {
             }
         }
         if flags.contains(Flag::Rst) {
+            // Keep the reset state so the reader can tell an abort from a clean close,
+            // the session only needs to forget this stream
             self.state = StreamState::Reset;
-            close_stream = true;
+            return self.unbound_send_event(StreamEvent::Closed(self.id));
         }
 
         if close_stream {
@@ -243,6 +255,7 @@ impl StreamHandle {
             .send_window
             .checked_add(frame.length())
             .ok_or(Error::InvalidMsgType)?;
+        self.stats.incr_send_window(frame.length());
         // wake writer continue
         if let Some(waker) = self.writeable_wake.take() {
             waker.wake()
@@ -263,6 +276,7 @@ impl StreamHandle {
             self.read_buf = data;
         }
         self.recv_window -= length;
+        self.stats.decr_recv_window(length);
         Ok(())
     }
 
@@ -319,7 +333,6 @@ impl StreamHandle {
                 }
                 StreamState::Reset => {
                     debug!("connection reset");
-                    let _ignore = self.send_close();
                     Err(io::ErrorKind::ConnectionReset.into())
                 }
                 StreamState::Closed => Err(io::ErrorKind::BrokenPipe.into()),
@@ -391,9 +404,10 @@ impl AsyncWrite for StreamHandle {
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
         match self.state {
-            StreamState::RemoteClosing | StreamState::Reset => {
+            StreamState::RemoteClosing => {
                 return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
             }
+            StreamState::Reset => return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into())),
             StreamState::LocalClosing | StreamState::Closed => {
                 return Poll::Ready(Err(io::Error::new(
                     io::ErrorKind::BrokenPipe,
@@ -422,6 +436,7 @@ impl AsyncWrite for StreamHandle {
         match self.send_data(data) {
             Ok(_) => {
                 self.send_window -= n as u32;
+                self.stats.decr_send_window(n as u32);
 
                 Poll::Ready(Ok(n))
             }
@@ -445,7 +460,11 @@ impl AsyncWrite for StreamHandle {
 
 impl Drop for StreamHandle {
     fn drop(&mut self) {
-        if !self.unbound_event_sender.is_closed() && self.state != StreamState::Closed {
+        self.stats.close_stream(self.recv_window, self.send_window);
+        if !self.unbound_event_sender.is_closed()
+            && self.state != StreamState::Closed
+            && self.state != StreamState::Reset
+        {
             let event = StreamEvent::Closed(self.id);
             if self.state == StreamState::LocalClosing {
                 // LocalClosing means that local have sent Fin to the remote and waiting for a response.
@@ -523,6 +542,7 @@ mod test {
                 StreamState::Init,
                 INITIAL_STREAM_WINDOW,
                 INITIAL_STREAM_WINDOW,
+                Default::default(),
             );
 
             drop(stream);
@@ -552,6 +572,7 @@ mod test {
                 StreamState::Init,
                 INITIAL_STREAM_WINDOW,
                 INITIAL_STREAM_WINDOW,
+                Default::default(),
             );
 
             let mut flags = Flags::from(Flag::Syn);
@@ -563,7 +584,12 @@ mod test {
             // try poll stream handle, then it will recv RST frame and set self state to reset
             assert_eq!(
                 stream.read(&mut b).await.unwrap_err().kind(),
-                ErrorKind::BrokenPipe
+                ErrorKind::ConnectionReset
+            );
+            // the reset is kept, not turned into a normal close
+            assert_eq!(
+                stream.read(&mut b).await.unwrap_err().kind(),
+                ErrorKind::ConnectionReset
             );
 
             drop(stream);
@@ -588,6 +614,7 @@ mod test {
                 StreamState::Init,
                 INITIAL_STREAM_WINDOW,
                 INITIAL_STREAM_WINDOW,
+                Default::default(),
             );
 
             let _ignore = stream.shutdown().await;
@@ -623,6 +650,7 @@ mod test {
                 StreamState::Init,
                 2,
                 INITIAL_STREAM_WINDOW,
+                Default::default(),
             );
 
             let flags = Flags::from(Flag::Syn);
@@ -665,6 +693,7 @@ mod test {
                 StreamState::Init,
                 2,
                 INITIAL_STREAM_WINDOW,
+                Default::default(),
             );
 
             let data = [0; 8];